@@ -0,0 +1,14 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (bool, &[u8])| {
+    let (with_profile, config) = input;
+    // the file is loaded by path, as given with --config
+    let path = std::env::temp_dir().join(format!("cdu-fuzz-{}.yaml", std::process::id()));
+    std::fs::write(&path, config).unwrap();
+    let mut args = vec!["cdu".into(), "--config".into(), path.into_os_string()];
+    if with_profile {
+        args.extend(["--profile".into(), "fuzz".into()]);
+    }
+    let _ = cdu::Opts::from_iter_with_config(args);
+});
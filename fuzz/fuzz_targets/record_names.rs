@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(records) = std::str::from_utf8(data) {
+        for name in cdu::parse_record_names(records) {
+            assert!(!name.is_empty());
+            assert!(!name.contains(','));
+            assert_eq!(name, cdu::normalize_name(&name));
+        }
+    }
+});
@@ -0,0 +1,25 @@
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+
+use serde::Deserialize;
+
+/// Where the address published by a record comes from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressSource {
+    /// Public IP address as seen from the internet, the WAN side
+    #[default]
+    Public,
+    /// Address of this host on the local network, the LAN side
+    Local,
+}
+
+/// IPv4 address of the interface which routes to the internet. Connecting a UDP socket only
+/// picks the route, nothing is sent.
+pub(crate) fn local_ipv4() -> anyhow::Result<Ipv4Addr> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect("192.0.2.1:53")?;
+    match socket.local_addr()? {
+        SocketAddr::V4(addr) => Ok(*addr.ip()),
+        SocketAddr::V6(addr) => anyhow::bail!("unexpected local IPv6 address: {}", addr),
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use cloudflare::framework::endpoint::{Endpoint, Method};
+use cloudflare::framework::response::{ApiErrors, ApiFailure, ApiResponse, ApiResult};
+use cloudflare::framework::{Environment, HttpApiClientConfig};
+use hyper::client::connect::dns::Name;
+use log::warn;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use secrecy::{ExposeSecret, SecretString};
+use serde::Serialize;
+
+use crate::opts::ApiIpVersion;
+use crate::socks::ProxyUrl;
+
+/// How to reach the Cloudflare API
+#[derive(Clone)]
+pub(crate) struct Network {
+    pub(crate) proxy: Option<ProxyUrl>,
+    pub(crate) ip_version: ApiIpVersion,
+    pub(crate) rate_limit: RateLimit,
+    pub(crate) url: reqwest::Url,
+}
+
+/// Instant no request should be sent before once rate limited, shared by clients across runs
+#[derive(Clone, Default)]
+pub(crate) struct RateLimit(Arc<Mutex<Option<Instant>>>);
+
+impl RateLimit {
+    /// Hold requests back for the delay, or longer if already held back longer
+    fn hold_back(&self, delay: Duration) {
+        let until = Instant::now() + delay;
+        let mut rate_limited_until = self.0.lock().unwrap();
+        *rate_limited_until = Some(rate_limited_until.map_or(until, |u| u.max(until)));
+    }
+
+    /// Time left before requests may be sent again, none if they may be sent right away
+    pub(crate) fn remaining(&self) -> Option<Duration> {
+        let until = (*self.0.lock().unwrap())?;
+        Some(until.saturating_duration_since(Instant::now())).filter(|d| !d.is_zero())
+    }
+}
+
+/// Resolver of host names to addresses of one family only, so connections aren't attempted over
+/// the other one
+struct FamilyResolver(ApiIpVersion);
+
+impl Resolve for FamilyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let version = self.0;
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| match version {
+                    ApiIpVersion::V4 => addr.is_ipv4(),
+                    ApiIpVersion::V6 => addr.is_ipv6(),
+                    ApiIpVersion::Auto => true,
+                })
+                .collect();
+            if addrs.is_empty() {
+                let family = if version == ApiIpVersion::V6 { 6 } else { 4 };
+                return Err(format!("no IPv{} address of {}", family, name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Credentials of the Cloudflare API, rather than those of the cloudflare crate which hold plain
+/// strings. Each client keeps its own copy, wiped from memory once the client is dropped, and
+/// exposes it only to build the headers of a request.
+pub(crate) enum ApiCredentials {
+    /// API token
+    Token(SecretString),
+    /// Global API key of the account
+    Key { email: String, key: SecretString },
+}
+
+/// Client of the Cloudflare API as the one of the cloudflare crate, which keeps the delay asked
+/// for by rate limited responses rather than discarding their headers
+pub(crate) struct Client {
+    environment: Environment,
+    credentials: ApiCredentials,
+    http_client: reqwest::Client,
+    rate_limit: RateLimit,
+}
+
+impl Client {
+    pub(crate) fn new(
+        credentials: ApiCredentials,
+        config: HttpApiClientConfig,
+        environment: Environment,
+        network: &Network,
+    ) -> anyhow::Result<Client> {
+        let mut builder = reqwest::Client::builder()
+            .default_headers(config.default_headers)
+            .timeout(config.http_timeout);
+        if let Some(ref proxy) = network.proxy {
+            builder = builder.proxy(proxy.reqwest_proxy()?);
+        }
+        if network.ip_version != ApiIpVersion::Auto {
+            builder = builder.dns_resolver(Arc::new(FamilyResolver(network.ip_version)));
+        }
+        let http_client = builder.build()?;
+        Ok(Client {
+            environment,
+            credentials,
+            http_client,
+            rate_limit: network.rate_limit.clone(),
+        })
+    }
+
+    pub(crate) async fn request<R, Q, B>(
+        &self,
+        endpoint: &(dyn Endpoint<R, Q, B> + Send + Sync),
+    ) -> ApiResponse<R>
+    where
+        R: ApiResult,
+        Q: Serialize,
+        B: Serialize,
+    {
+        let method = match endpoint.method() {
+            Method::Get => reqwest::Method::GET,
+            Method::Post => reqwest::Method::POST,
+            Method::Put => reqwest::Method::PUT,
+            Method::Delete => reqwest::Method::DELETE,
+            Method::Patch => reqwest::Method::PATCH,
+        };
+        let mut request = self
+            .http_client
+            .request(method, endpoint.url(&self.environment))
+            .query(&endpoint.query());
+        if let Some(body) = endpoint.body() {
+            request = request
+                .body(serde_json::to_string(&body).unwrap_or_default())
+                .header(reqwest::header::CONTENT_TYPE, endpoint.content_type());
+        }
+        request = match self.credentials {
+            // marked sensitive, so left out of debug output of the request
+            ApiCredentials::Token(ref token) => request.bearer_auth(token.expose_secret()),
+            ApiCredentials::Key { ref email, ref key } => request
+                .header("X-Auth-Email", email)
+                .header("X-Auth-Key", key.expose_secret()),
+        };
+        let res = request.send().await?;
+        let status = res.status();
+        if status.is_success() {
+            return res.json().await.map_err(ApiFailure::Invalid);
+        }
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            if let Some(delay) = retry_after(res.headers(), Utc::now()) {
+                warn!(
+                    "rate limited by Cloudflare, wait {}s before the next request",
+                    delay.as_secs()
+                );
+                self.rate_limit.hold_back(delay);
+            }
+        }
+        let errors: ApiErrors = res.json().await.unwrap_or_default();
+        Err(ApiFailure::Error(status, errors))
+    }
+
+    /// Wait out the delay asked for by the last rate limited response, if any
+    pub(crate) async fn wait_rate_limit(&self) {
+        if let Some(remaining) = self.rate_limit.remaining() {
+            tokio::time::sleep(remaining).await;
+        }
+    }
+}
+
+/// Clients of the Cloudflare API, of zones with tokens of their own and of --token otherwise
+pub(crate) struct Clients {
+    pub(crate) default: Option<Arc<Client>>,
+    pub(crate) zones: BTreeMap<String, Arc<Client>>,
+}
+
+impl Clients {
+    /// Client for records in the zone
+    pub(crate) fn zone(&self, zone: &str) -> anyhow::Result<Arc<Client>> {
+        match self.zones.get(zone).or(self.default.as_ref()) {
+            Some(client) => Ok(client.clone()),
+            None => bail!("Cloudflare token is required for zone {}", zone),
+        }
+    }
+}
+
+/// Clients of the Cloudflare API of --token and --fallback-token in order, and of tokens of zones,
+/// built once rather than on every run so tokens aren't copied over and over
+pub(crate) struct ApiClients {
+    pub(crate) tokens: Vec<Option<Arc<Client>>>,
+    pub(crate) zones: BTreeMap<String, Arc<Client>>,
+}
+
+impl ApiClients {
+    /// Clients with the token of the index for zones without tokens of their own
+    pub(crate) fn with_token(&self, token: Option<usize>) -> Clients {
+        Clients {
+            default: token.and_then(|index| self.tokens.get(index).cloned().flatten()),
+            zones: self.zones.clone(),
+        }
+    }
+}
+
+/// Delay asked for by a rate limited response, by Retry-After in seconds or as HTTP date, or
+/// else by the reset of the RateLimit header e.g. `"default";r=0;t=30` or `limit=1200, remaining=0,
+/// reset=30`
+pub fn retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    if let Some(value) = headers.get(RETRY_AFTER).and_then(|v| v.to_str().ok()) {
+        let value = value.trim();
+        if let Ok(seconds) = value.parse::<u64>() {
+            return Some(Duration::from_secs(seconds));
+        }
+        if let Ok(datetime) = DateTime::parse_from_rfc2822(value) {
+            return Some(
+                (datetime.with_timezone(&Utc) - now)
+                    .to_std()
+                    .unwrap_or_default(),
+            );
+        }
+    }
+    let value = headers.get("ratelimit")?.to_str().ok()?;
+    value
+        .split([';', ','])
+        .filter_map(|param| param.trim().split_once('='))
+        .find(|(key, _)| *key == "t" || *key == "reset")
+        .and_then(|(_, seconds)| seconds.trim().parse().ok())
+        .map(Duration::from_secs)
+}
@@ -0,0 +1,49 @@
+use std::net::IpAddr;
+
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+
+/// Outcome of the runs so far, shown by the badge
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Outcome {
+    pub(crate) succeeded_at: Option<DateTime<Utc>>,
+    /// Whether the last run failed
+    pub(crate) failed: bool,
+}
+
+/// Badge of the endpoint format of Shields.io with the IP address and how long ago it was last
+/// confirmed, green while runs succeed and red once one fails, e.g. for homelab dashboards
+pub fn badge(
+    ip_address: Option<IpAddr>,
+    succeeded_at: Option<DateTime<Utc>>,
+    failed: bool,
+    now: DateTime<Utc>,
+) -> Value {
+    let ip_address = ip_address.map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+    let message = match succeeded_at {
+        Some(at) => format!("{}, {} ago", ip_address, ago((now - at).num_seconds())),
+        None => ip_address,
+    };
+    let color = match (succeeded_at, failed) {
+        (_, true) => "red",
+        (Some(_), false) => "brightgreen",
+        (None, false) => "lightgrey",
+    };
+    json!({
+        "schemaVersion": 1,
+        "label": "cdu",
+        "message": message,
+        "color": color,
+    })
+}
+
+/// Coarse age e.g. 5m
+fn ago(seconds: i64) -> String {
+    let seconds = seconds.max(0);
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m", seconds / 60),
+        3600..=86399 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
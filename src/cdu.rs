@@ -1,40 +1,266 @@
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-use anyhow::bail;
+use anyhow::{bail, Context};
+use chrono::{DateTime, Utc};
 use cloudflare::endpoints::dns::{
-    DnsContent, DnsRecord, ListDnsRecords, ListDnsRecordsParams, UpdateDnsRecord,
-    UpdateDnsRecordParams,
+    CreateDnsRecord, CreateDnsRecordParams, DeleteDnsRecord, DeleteDnsRecordResponse, DnsContent,
+    DnsRecord, ListDnsRecords, ListDnsRecordsParams, UpdateDnsRecord, UpdateDnsRecordParams,
 };
 use cloudflare::endpoints::zone::{ListZones, ListZonesParams, Zone};
-use cloudflare::framework::async_api::{ApiClient, Client};
-use cloudflare::framework::auth::Credentials;
-use cloudflare::framework::response::ApiSuccess;
+use cloudflare::framework::endpoint::{Endpoint, Method};
+use cloudflare::framework::response::{ApiFailure, ApiResponse, ApiResult, ApiSuccess};
 use cloudflare::framework::{Environment, HttpApiClientConfig};
-use log::{debug, info};
-use tokio::task::JoinHandle;
+use cron::Schedule;
+use log::{debug, info, warn};
+use once_cell::sync::OnceCell;
+use public_ip::Version;
+use secrecy::{ExposeSecret, SecretString};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinSet;
 use ttl_cache::TtlCache;
 
-use crate::{Opts, PublicIPError};
+use crate::address::local_ipv4;
+use crate::api::{ApiClients, ApiCredentials, Client, Clients, Network, RateLimit};
+use crate::badge::Outcome;
+use crate::config::{self, RecordType};
+use crate::daemon::{is_transient, RetriesSpent, RetryBudget, RetryPolicy};
+use crate::detect::{detect, DetectPolicy};
+use crate::digest;
+use crate::event::{EventBus, Subscriber};
+use crate::history::HistoryFormat;
+use crate::ip_file::IpFile;
+use crate::lint::{runs_per_window, runs_per_window_every, RATE_LIMIT};
+use crate::metrics::{RunCounter, METRICS};
+use crate::migration::MigrationReport;
+use crate::notifier::WebhookNotifier;
+use crate::opts::DEFAULT_CRON;
+use crate::output::Output;
+use crate::park::{ParkSwitch, ParkTarget};
+use crate::pipeline::{Phase, RecordContext, RunContext, Stage};
+use crate::plan::{Plan, PlannedRecord};
+use crate::probe::Probe;
+use crate::provider::{self, record_type, Provider, Providers};
+use crate::record::{check_problems, invalid_names, validate_name};
+use crate::report;
+#[cfg(feature = "sqlite")]
+use crate::sqlite::SqliteStore;
+use crate::state::{self, RunState};
+use crate::store::{HistoryEntry, JsonStore, MemoryStore, StateRecorder, Store, StoreKind};
+use crate::trigger::{normalize_cron, split_cron_timezone, Fired};
+use crate::zone::pick_zone;
+use crate::{
+    normalize_name, AddressSource, Command, CronTimezone, Event, IpVersion, LogTarget,
+    LogTimestamps, Opts, Proxied, PublicIPError, Trigger,
+};
 
 const HTTP_TIMEOUT: u64 = 30;
+/// Runs per 5 minutes beyond which a schedule is more frequent than useful, every 30 seconds
+const FREQUENT_RUNS: usize = 10;
+const SCHEDULE_HINT: &str =
+    "on change instead with --watch-network, --watch-file, --webhook-listen, or `cdu trigger` from PPP or DHCP hooks";
+
+/// Zones listed per request when listing zones of the account
+const ZONES_PER_PAGE: u32 = 50;
+
+/// DNS records listed per request when matching --records-pattern
+const RECORDS_PER_PAGE: u32 = 100;
+
+/// TTL Cloudflare takes as automatic
+pub(crate) const AUTOMATIC_TTL: u32 = 1;
+/// Range of TTL in seconds Cloudflare accepts other than automatic
+const TTL_RANGE: std::ops::RangeInclusive<u32> = 60..=86400;
+
+const PROXIED_CAVEAT: &str =
+    "DNS lookups return addresses of Cloudflare instead of its content, which is expected";
 
 const ZONE: u8 = 1;
 const RECORD: u8 = 2;
+const PROXIED: u8 = 3;
+const RECORD_TYPE: u8 = 4;
+
+/// IP addresses, IPv6 address in dual-stack mode, and when they were first detected
+type Detection = (IpAddr, Option<Ipv6Addr>, Instant);
 
 pub struct Cdu {
-    opts: Opts,
+    /// Shared with instances updating groups of records
+    opts: Arc<Opts>,
+    /// Group whose records are updated, all of them if none
+    group: Option<String>,
     cache: Arc<Mutex<TtlCache<(u8, String), String>>>,
+    output: Output,
+    /// Shared with the instance reloaded from this one
+    last_run: Arc<Mutex<Option<RunState>>>,
+    /// Index of token in use, 0 for primary and 1 for fallback
+    active_token: AtomicUsize,
+    bus: EventBus,
+    stages: Vec<(Phase, Arc<dyn Stage>)>,
+    /// Maintenance destination while parked
+    park: ParkSwitch,
+    /// IP addresses not yet published to every record, and when they were first detected
+    first_detected: Arc<Mutex<Option<Detection>>>,
+    /// Zone identifiers fetched before and when, to fall back to if looking them up fails
+    known_zones: Arc<Mutex<BTreeMap<String, (String, Instant)>>>,
+    /// Where the state of the last run and the history of IP addresses are persisted
+    store: Arc<dyn Store>,
+    /// Update on the next run even if up to date as of the last run
+    forced: Arc<AtomicBool>,
+    /// When runs last succeeded and whether the last one failed, shown by the badge
+    outcome: Arc<Mutex<Outcome>>,
+    /// Delay asked for by Cloudflare, outliving the clients of each run
+    rate_limit: RateLimit,
+    /// Clients of the Cloudflare API, built on the first run
+    api_clients: Arc<OnceCell<ApiClients>>,
 }
 
 impl Cdu {
     pub fn new(opts: Opts) -> Self {
         let capacity = opts.record_name_list().len();
+        let output = Output::new(opts.no_color);
+        let mut bus = EventBus::default();
+        bus.set_quiet_hours(opts.notify_quiet_hours);
+        let (ipv4_file, ipv6_file) = match opts.ip_version() {
+            IpVersion::V6 => (None, opts.ip6_file.clone().or_else(|| opts.ip_file.clone())),
+            _ => (opts.ip_file.clone(), opts.ip6_file.clone()),
+        };
+        if ipv4_file.is_some() || ipv6_file.is_some() {
+            bus.add_subscriber(Box::new(IpFile::new(ipv4_file, ipv6_file)));
+        }
+        let store: Arc<dyn Store> = match opts.store {
+            StoreKind::Json => Arc::new(JsonStore {
+                status_file: opts.status_file.clone(),
+                history_file: opts.history_file.clone(),
+            }),
+            StoreKind::Memory => Arc::new(MemoryStore::default()),
+            #[cfg(feature = "sqlite")]
+            StoreKind::Sqlite => Arc::new(SqliteStore::new(opts.database_file.clone())),
+        };
+        // loaded again on the first run if it fails, once logged
+        let last_run = store.load().ok().flatten();
+        // parked with `cdu park` before a restart
+        let park = opts
+            .park
+            .clone()
+            .or_else(|| last_run.as_ref().and_then(|state| state.park.clone()));
+        let park = Arc::new(Mutex::new(park));
+        let last_run = Arc::new(Mutex::new(last_run));
+        bus.add_subscriber(Box::new(StateRecorder {
+            store: store.clone(),
+            last_run: last_run.clone(),
+        }));
+        bus.add_subscriber(Box::new(RunCounter));
+        if let Some(ref url) = opts.notify_url {
+            let notifier = WebhookNotifier::new(url.clone());
+            match opts.notify_digest {
+                Some(window) => {
+                    bus.add_subscriber(Box::new(digest::Digest::new(notifier, window.into())))
+                }
+                None => bus.add_subscriber(Box::new(notifier)),
+            }
+        }
+        Self {
+            opts: Arc::new(opts),
+            group: None,
+            output,
+            last_run,
+            active_token: AtomicUsize::new(0),
+            bus,
+            stages: vec![],
+            park,
+            first_detected: Arc::new(Mutex::new(None)),
+            known_zones: Arc::new(Mutex::new(BTreeMap::new())),
+            store,
+            forced: Arc::new(AtomicBool::new(false)),
+            outcome: Arc::new(Mutex::new(Outcome::default())),
+            rate_limit: RateLimit::default(),
+            api_clients: Arc::new(OnceCell::new()),
+            // zone identifier, and record identifiers, proxied status, and types of A and AAAA records
+            cache: Arc::new(Mutex::new(TtlCache::new(capacity * 6 + 1))),
+        }
+    }
+
+    /// Instance with the given options, e.g. re-read on SIGHUP, keeping the state of the last
+    /// run, parked records, subscribers, and stages. The cache starts empty for the records of
+    /// the new options.
+    pub fn reload(&self, opts: Opts) -> Self {
+        let mut cdu = Self::new(opts);
+        cdu.output = self.output;
+        cdu.last_run = self.last_run.clone();
+        cdu.active_token = AtomicUsize::new(self.active_token.load(Ordering::Relaxed));
+        cdu.bus = self.bus.clone();
+        cdu.stages = self.stages.clone();
+        cdu.park = self.park.clone();
+        cdu.first_detected = self.first_detected.clone();
+        cdu.known_zones = self.known_zones.clone();
+        cdu.forced = self.forced.clone();
+        cdu.outcome = self.outcome.clone();
+        cdu.rate_limit = self.rate_limit.clone();
+        // history in memory is lost otherwise
+        if cdu.opts.store == StoreKind::Memory && self.opts.store == StoreKind::Memory {
+            cdu.store = self.store.clone();
+        }
+        cdu
+    }
+
+    /// Instance updating the records of the group only, sharing the state, cache, and options
+    /// of this one
+    pub(crate) fn for_group(&self, group: &str) -> Self {
         Self {
-            opts,
-            // zone identifier and record identifiers
-            cache: Arc::new(Mutex::new(TtlCache::new(capacity + 1))),
+            opts: self.opts.clone(),
+            group: Some(group.to_string()),
+            cache: self.cache.clone(),
+            output: self.output,
+            last_run: self.last_run.clone(),
+            active_token: AtomicUsize::new(self.active_token.load(Ordering::Relaxed)),
+            bus: self.bus.clone(),
+            stages: self.stages.clone(),
+            park: self.park.clone(),
+            first_detected: self.first_detected.clone(),
+            known_zones: self.known_zones.clone(),
+            store: self.store.clone(),
+            forced: self.forced.clone(),
+            outcome: self.outcome.clone(),
+            rate_limit: self.rate_limit.clone(),
+            api_clients: self.api_clients.clone(),
+        }
+    }
+
+    /// Unique record names in configured order, of the group if the instance updates one
+    fn record_name_list(&self) -> Vec<String> {
+        let names = self.opts.record_name_list();
+        match self.group {
+            Some(ref group) => names
+                .into_iter()
+                .filter(|name| self.opts.record_group(name) == Some(group.as_str()))
+                .collect(),
+            None => names,
+        }
+    }
+
+    /// Record names with views of their further entries, see [`Opts::record_views`]
+    fn record_entries(&self) -> Vec<(String, Option<String>)> {
+        let mut entries = vec![];
+        for name in self.record_name_list() {
+            let views = self.opts.record_views(&name);
+            entries.push((name.clone(), None));
+            entries.extend(views.into_iter().map(|view| (name.clone(), Some(view))));
         }
+        entries
+    }
+
+    /// Any record is published on Cloudflare rather than with other providers
+    fn any_on_cloudflare(&self) -> bool {
+        self.record_entries()
+            .iter()
+            .any(|(name, view)| self.opts.record_provider(name, view.as_deref()).is_none())
     }
 
     pub fn cache_ttl(&self) -> Option<Duration> {
@@ -49,6 +275,476 @@ impl Cdu {
         &self.opts.cron
     }
 
+    /// Interval between runs given by --every instead of cron
+    pub fn every(&self) -> Option<Duration> {
+        self.opts.every.map(Into::into)
+    }
+
+    /// How long the run in progress may take to finish on shutdown, given by --shutdown-timeout
+    pub fn shutdown_timeout(&self) -> Duration {
+        self.opts.shutdown_timeout.into()
+    }
+
+    /// Most delay of scheduled runs given by --jitter
+    pub fn jitter(&self) -> Duration {
+        self.opts.jitter.map(Into::into).unwrap_or_default()
+    }
+
+    /// Schedule of runs for logs e.g. `every 5m`
+    pub fn schedule_description(&self) -> String {
+        match self.opts.every {
+            Some(every) => format!("every {}", every),
+            None => format!("cron {}", self.opts.cron),
+        }
+    }
+
+    /// Triggers which start an update cycle in daemon mode
+    pub fn triggers(&self) -> anyhow::Result<Vec<Trigger>> {
+        let mut triggers = vec![self.schedule()?];
+        for path in &self.opts.watch_file {
+            triggers.push(Trigger::File(path.clone()));
+        }
+        if self.opts.watch_network {
+            #[cfg(any(target_os = "linux", target_os = "macos"))]
+            triggers.push(Trigger::Network);
+            #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+            bail!("watching network interfaces is not supported on this platform");
+        }
+        if let Some(addr) = self.opts.webhook_listen {
+            match self.opts.webhook_token {
+                Some(ref token) => triggers.push(Trigger::Webhook(addr, token.clone())),
+                None => bail!("webhook token is required to listen for webhooks"),
+            }
+        }
+        if let Some(ref path) = self.opts.control_socket {
+            #[cfg(unix)]
+            triggers.push(Trigger::Control(path.clone(), self.park.clone()));
+            #[cfg(not(unix))]
+            bail!(
+                "control socket is not supported on this platform: {}",
+                path.display()
+            );
+        }
+        Ok(triggers)
+    }
+
+    /// Retries of runs, and of each record within the budget of its run
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_delay_millis: self.opts.retry_max_delay_millis,
+            ..Default::default()
+        }
+    }
+
+    /// Trigger firing on the cron schedule or every interval, and on the schedules of groups of
+    /// records if any, rebuilt on reload
+    pub fn schedule(&self) -> anyhow::Result<Trigger> {
+        let schedule = self.main_schedule()?;
+        let groups = self.opts.record_groups();
+        if groups.is_empty() {
+            return Ok(schedule);
+        }
+        let mut schedules = vec![schedule];
+        for (name, group) in groups {
+            let trigger = match (&group.cron, group.every) {
+                (Some(_), Some(_)) => bail!("group {}: every and cron can't be both given", name),
+                (None, None) => bail!("group {}: either every or cron is required", name),
+                (None, Some(every)) if every.is_zero() => {
+                    bail!("group {}: every must be longer than zero", name)
+                }
+                (None, Some(every)) => Trigger::Interval(every),
+                (Some(cron), None) => {
+                    let schedule = Schedule::from_str(&normalize_cron(cron))
+                        .with_context(|| format!("group {}: invalid cron {}", name, cron))?;
+                    Trigger::Cron(Box::new(schedule), self.cron_timezone()?)
+                }
+            };
+            let records = self.for_group(name).record_name_list();
+            if records.is_empty() {
+                warn!("group {} has no DNS records, its schedule is ignored", name);
+                continue;
+            }
+            debug!(
+                "update {} on the schedule of group {}",
+                records.join(", "),
+                name
+            );
+            schedules.push(Trigger::Group(name.clone(), Box::new(trigger)));
+        }
+        Ok(Trigger::Schedules(schedules))
+    }
+
+    fn main_schedule(&self) -> anyhow::Result<Trigger> {
+        match self.every() {
+            // cron from environment variable isn't rejected by the parser
+            Some(_) if self.opts.cron != DEFAULT_CRON => {
+                bail!("--every and --cron can't be both given")
+            }
+            Some(every) if every.is_zero() => bail!("--every must be longer than zero"),
+            Some(every) => Ok(Trigger::Interval(every)),
+            None => {
+                let schedule = Schedule::from_str(&self.cron_expression())?;
+                Ok(Trigger::Cron(Box::new(schedule), self.cron_timezone()?))
+            }
+        }
+    }
+
+    /// Cron expression in the 7 fields the cron crate expects, without the `CRON_TZ=` prefix
+    fn cron_expression(&self) -> String {
+        normalize_cron(split_cron_timezone(&self.opts.cron).1)
+    }
+
+    /// Log the cron expression parsed if normalized from 5 or 6 fields
+    pub fn log_cron_normalization(&self) {
+        let (_, expression) = split_cron_timezone(&self.opts.cron);
+        let normalized = self.cron_expression();
+        if self.opts.every.is_none() && normalized != expression {
+            info!("cron {} normalized to {}", expression, normalized);
+        }
+    }
+
+    /// Timezone of the cron schedule, by --cron-timezone or the `CRON_TZ=` prefix of --cron
+    pub fn cron_timezone(&self) -> anyhow::Result<CronTimezone> {
+        match split_cron_timezone(&self.opts.cron) {
+            (Some(timezone), _) if self.opts.cron_timezone != CronTimezone::Utc => bail!(
+                "CRON_TZ={} and --cron-timezone {} can't be both given",
+                timezone,
+                self.opts.cron_timezone
+            ),
+            (Some(timezone), _) => timezone.parse(),
+            (None, _) => Ok(self.opts.cron_timezone.clone()),
+        }
+    }
+
+    /// Set the process timezone to the named timezone of the cron schedule, as chrono only knows
+    /// the local timezone. Call before the runtime starts its threads
+    pub fn set_cron_timezone(&self) -> anyhow::Result<()> {
+        if let CronTimezone::Named(name) = self.cron_timezone()? {
+            std::env::set_var("TZ", name);
+        }
+        Ok(())
+    }
+
+    pub fn command(&self) -> Option<&Command> {
+        self.opts.command.as_ref()
+    }
+
+    /// Lock the PID file given by --pid-file for updates, failing if another instance holds it.
+    /// Call before detaching, which writes the process ID of the detached process instead.
+    #[cfg(unix)]
+    pub fn lock_pid_file(&self) -> anyhow::Result<()> {
+        match self.opts.pid_file {
+            Some(ref path) if self.opts.command.is_none() => crate::pidfile::lock(path),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fork into the background if --detach is given. Call before the runtime starts its threads.
+    #[cfg(unix)]
+    pub fn detach(&mut self) -> anyhow::Result<()> {
+        if !self.opts.detach {
+            return Ok(());
+        }
+        if !self.opts.daemon {
+            bail!("--detach is only in effect in daemon mode");
+        }
+        crate::detach::detach(self.opts.log_file.as_deref())?;
+        // standard output is no longer the terminal
+        self.output = Output::new(self.opts.no_color);
+        Ok(())
+    }
+
+    /// Switch to the user and group given by --user and --group, if any
+    #[cfg(unix)]
+    pub fn drop_privileges(&self) -> anyhow::Result<()> {
+        if self.opts.user.is_none() && self.opts.group.is_none() {
+            return Ok(());
+        }
+        crate::privileges::drop_privileges(self.opts.user.as_deref(), self.opts.group.as_deref())
+    }
+
+    /// Restrict the process to network and the files cdu reads and writes, with landlock and
+    /// seccomp on Linux, or pledge and unveil on OpenBSD. Only updates are sandboxed, not other
+    /// commands. Call before the runtime starts its threads on Linux, and once listeners are bound
+    /// and privileges are dropped on OpenBSD.
+    #[cfg(feature = "sandbox")]
+    pub fn sandbox(&self) -> anyhow::Result<()> {
+        if self.opts.command.is_some() {
+            return Ok(());
+        }
+        let opts = &self.opts;
+        let mut paths = crate::sandbox::Paths {
+            read: vec![],
+            write: vec![],
+            socket: opts.control_socket.as_deref(),
+        };
+        paths.read.extend(opts.config.as_deref());
+        paths.read.extend(opts.gcp_credentials.as_deref());
+        paths.read.extend(opts.tls_cert.as_deref());
+        paths.read.extend(opts.tls_key.as_deref());
+        paths
+            .read
+            .extend(opts.watch_file.iter().map(|p| p.as_path()));
+        if opts.user.is_some() || opts.group.is_some() {
+            paths.read.push(std::path::Path::new("/etc/passwd"));
+            paths.read.push(std::path::Path::new("/etc/group"));
+        }
+        // timezones are loaded once the schedule is first computed
+        if let Ok(CronTimezone::Local | CronTimezone::Named(_)) = self.cron_timezone() {
+            paths.read.push(std::path::Path::new("/etc/localtime"));
+            paths
+                .read
+                .push(std::path::Path::new(crate::trigger::ZONEINFO));
+        }
+        paths.write.extend(opts.ip_file.as_deref());
+        paths.write.extend(opts.ip6_file.as_deref());
+        paths.write.extend(opts.status_file.as_deref());
+        paths.write.extend(opts.history_file.as_deref());
+        #[cfg(feature = "sqlite")]
+        paths.write.extend(opts.database_file.as_deref());
+        paths.write.extend(opts.migration_report.as_deref());
+        crate::sandbox::restrict(&paths)
+    }
+
+    /// Install or uninstall scheduled task with Windows Task Scheduler
+    #[cfg(windows)]
+    pub fn task(&self, command: &crate::TaskCommand) -> anyhow::Result<()> {
+        match command {
+            crate::TaskCommand::Install {
+                name,
+                interval_minutes,
+            } => crate::task::install(name, *interval_minutes),
+            crate::TaskCommand::Uninstall { name } => crate::task::uninstall(name),
+        }
+    }
+
+    /// Install or uninstall Windows service, which is run by `cdu::run_service` instead
+    #[cfg(windows)]
+    pub fn service(&self, command: &crate::ServiceCommand) -> anyhow::Result<()> {
+        match command {
+            crate::ServiceCommand::Install { name } => crate::service::install(name),
+            crate::ServiceCommand::Uninstall { name } => crate::service::uninstall(name),
+            crate::ServiceCommand::Run { .. } => bail!("service is run by cdu::run_service"),
+        }
+    }
+
+    /// Create a token limited to the zones with the global API key, and print it
+    pub async fn create_token(&self, email: &str, api_key: &str, name: &str) -> anyhow::Result<()> {
+        let http_timeout = Duration::from_secs(HTTP_TIMEOUT);
+        let client =
+            crate::token::global_key_client(email, api_key, http_timeout, &self.api_network())?;
+        if self.opts.zone.is_empty() {
+            self.list_zones(&client).await?;
+        }
+        let zones = self.opts.zone_names();
+        let mut zone_ids = vec![];
+        for zone in &zones {
+            zone_ids.push(self.get_zone_identifier(client.clone(), zone).await?.1);
+        }
+        let token = crate::token::create_token(&client, name, &zone_ids).await?;
+        info!(
+            "token created for zones {}: {} ({})",
+            zones.join(", "),
+            name,
+            fingerprint(&token)
+        );
+        println!("{}", token);
+        Ok(())
+    }
+
+    /// Print how often the existing DDNS tool agreed with cdu in observe mode
+    pub fn print_migration_report(&self) -> anyhow::Result<()> {
+        match self.opts.migration_report {
+            Some(ref path) if path.exists() => {
+                println!("{}", MigrationReport::load(path)?.render());
+                Ok(())
+            }
+            Some(ref path) => bail!("migration report not found: {}", path.display()),
+            None => bail!("migration report is not configured"),
+        }
+    }
+
+    /// Print providers compiled in and what they support
+    pub fn print_providers(&self) {
+        println!("{}", provider::render_capabilities());
+    }
+
+    /// Bug report with version, options, configuration file, state of the last run, and the last
+    /// lines of the log file. Secrets are redacted, and parts which fail to load hold the error.
+    pub fn report(&self, log: Option<&Path>, lines: usize) -> serde_json::Value {
+        let features: Vec<&str> = vec![
+            #[cfg(feature = "grpc")]
+            "grpc",
+            #[cfg(feature = "sqlite")]
+            "sqlite",
+        ];
+        let providers = self
+            .opts
+            .provider_configs()
+            .map(|configs| configs.into_keys().collect::<Vec<_>>());
+        let options = json!({
+            "zones": self.opts.zone_names(),
+            "records": self.record_name_list(),
+            "disabled_records": self.opts.disabled_record_names(),
+            "ip_version": format!("{:?}", self.opts.ip_version()),
+            "proxied": format!("{:?}", self.opts.proxied),
+            "ttl": self.opts.ttl,
+            "daemon": self.opts.daemon,
+            "cron": self.opts.cron,
+            "every": self.opts.every.map(|every| every.to_string()),
+            "cache_seconds": self.opts.cache_seconds,
+            "batch_size": self.opts.batch_size,
+            "dry_run": self.opts.dry_run,
+            "observe": self.opts.observe,
+            "plan": self.opts.plan,
+            "providers": or_error(providers),
+            "token_fingerprint": self.token_fingerprint(),
+            "fallback_token_fingerprint": self.fallback_token_fingerprint(),
+        });
+        let config = self.opts.config.as_deref().map(report::redacted_config);
+        // nothing to load from files not given
+        let status = (self.opts.status_file.is_some() || self.opts.store != StoreKind::Json)
+            .then(|| self.store.load());
+        let log = log.map(|path| report::tail(path, lines, &self.opts.secrets()));
+        json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "os": std::env::consts::OS,
+            "arch": std::env::consts::ARCH,
+            "features": features,
+            "options": options,
+            "config": config.map(or_error),
+            "status": status.map(or_error),
+            "log": log.map(or_error),
+        })
+    }
+
+    /// Print bug report, see [`Cdu::report`]
+    pub fn print_report(&self, log: Option<&Path>, lines: usize) -> anyhow::Result<()> {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&self.report(log, lines))?
+        );
+        Ok(())
+    }
+
+    /// Print changes of the public IP address within the duration if given, see
+    /// [`crate::export_history`]
+    pub fn print_history_export(
+        &self,
+        format: HistoryFormat,
+        since: Option<Duration>,
+    ) -> anyhow::Result<()> {
+        if self.opts.store == StoreKind::Memory {
+            bail!("history is only kept by the running daemon with --store memory");
+        }
+        let since = match since {
+            Some(since) => Some(Utc::now() - chrono::Duration::from_std(since)?),
+            None => None,
+        };
+        let history = self.history(since)?;
+        print!("{}", crate::export_history(&history, format)?);
+        Ok(())
+    }
+
+    /// Print JSON Schema of the configuration file
+    pub fn print_config_schema(&self) -> anyhow::Result<()> {
+        println!("{}", serde_json::to_string_pretty(&config::schema())?);
+        Ok(())
+    }
+
+    /// Print or write configuration file with the current options, see [`crate::starter_config`]
+    pub fn print_config_init(&self, output: Option<&Path>) -> anyhow::Result<()> {
+        use std::io::Write;
+
+        let content = crate::starter_config(std::env::args_os())?;
+        match output {
+            Some(path) => {
+                let mut file = std::fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(path)
+                    .with_context(|| format!("failed to create {}", path.display()))?;
+                file.write_all(content.as_bytes())?;
+                info!("configuration written to {}", path.display());
+            }
+            None => print!("{}", content),
+        }
+        Ok(())
+    }
+
+    fn token(&self) -> anyhow::Result<&SecretString> {
+        match self.opts.token {
+            Some(ref token) => Ok(token),
+            None => bail!("Cloudflare token is required"),
+        }
+    }
+
+    /// Ask running daemon to update DNS records over the control socket.
+    /// Fall back to update once if no daemon is listening.
+    pub async fn trigger(&self, ip_address: Option<IpAddr>) -> anyhow::Result<()> {
+        #[cfg(unix)]
+        if let Some(ref path) = self.opts.control_socket {
+            let command = crate::ControlCommand::Trigger(ip_address);
+            match crate::control::send_command(path, &command).await {
+                Ok(_) => {
+                    info!("daemon triggered via {}", path.display());
+                    return Ok(());
+                }
+                Err(e) => debug!("failed to trigger daemon via {}: {}", path.display(), e),
+            }
+        }
+        self.run_with_ip_address(ip_address).await
+    }
+
+    /// Park DNS records of the running daemon at the target, or unpark them if none
+    pub async fn park(&self, target: Option<ParkTarget>) -> anyhow::Result<()> {
+        let path = match self.opts.control_socket {
+            Some(ref path) => path,
+            None => bail!("control socket is required to park or unpark the daemon"),
+        };
+        #[cfg(unix)]
+        {
+            let command = match target {
+                Some(target) => crate::ControlCommand::Park(target),
+                None => crate::ControlCommand::Unpark,
+            };
+            crate::control::send_command(path, &command).await?;
+            info!("sent {} to daemon via {}", command, path.display());
+            Ok(())
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = target;
+            bail!(
+                "control socket is not supported on this platform: {}",
+                path.display()
+            )
+        }
+    }
+
+    /// Short non-reversible fingerprint of the token, to tell credentials apart in logs
+    pub fn token_fingerprint(&self) -> Option<String> {
+        self.opts
+            .token
+            .as_ref()
+            .map(|token| fingerprint(token.expose_secret().as_str()))
+    }
+
+    pub fn fallback_token_fingerprint(&self) -> Option<String> {
+        self.opts
+            .fallback_token
+            .as_ref()
+            .map(|token| fingerprint(token.expose_secret().as_str()))
+    }
+
+    pub fn log_timestamps(&self) -> &LogTimestamps {
+        &self.opts.log_timestamps
+    }
+
+    pub fn log_target(&self) -> LogTarget {
+        self.opts.log_target
+    }
+
     pub fn is_debug(&self) -> bool {
         self.opts.debug
     }
@@ -57,145 +753,2084 @@ impl Cdu {
         self.opts.daemon
     }
 
-    async fn get_zone_identifier(&self, client: Arc<Client>) -> anyhow::Result<(Duration, String)> {
-        if let Some(id) = self
-            .cache
-            .lock()
-            .unwrap()
-            .get(&(ZONE, self.opts.zone.clone()))
-        {
-            debug!("zone found in cache: {} ({})", &self.opts.zone, &id);
+    async fn get_zone_identifier(
+        &self,
+        client: Arc<Client>,
+        zone: &str,
+    ) -> anyhow::Result<(Duration, String)> {
+        let zone = zone.to_string();
+        if let Some(id) = self.cache.lock().unwrap().get(&(ZONE, zone.clone())) {
+            debug!("zone found in cache: {} ({})", &zone, &id);
             return Ok((Duration::from_millis(0), id.clone()));
         }
 
         let params = ListZones {
             params: ListZonesParams {
-                name: Some(self.opts.zone.clone()),
+                name: Some(zone.clone()),
                 ..Default::default()
             },
         };
 
         let instant = Instant::now();
-        let res: ApiSuccess<Vec<Zone>> = client.request(&params).await?;
+        let res: ApiResponse<Vec<Zone>> = request(&client, "ListZones", &params).await;
         let duration = Instant::now() - instant;
-        debug!("took {}ms to fetch zone identifier", duration.as_millis());
-
-        let id = match res.result.first() {
-            Some(zone) => zone.id.to_string(),
-            None => bail!("zone not found: {}", self.opts.zone),
+        let res = match res {
+            Ok(res) => res,
+            Err(e) => {
+                let e = anyhow::Error::from(e);
+                return match self.stale_zone_identifier(&zone) {
+                    Some((id, age)) if is_retryable(&e) => {
+                        warn!(
+                            "failed to look up zone {}, use identifier fetched {}s ago: {}",
+                            &zone,
+                            age.as_secs(),
+                            e
+                        );
+                        Ok((duration, id))
+                    }
+                    _ => Err(e),
+                };
+            }
         };
+
+        let zones: Vec<_> = res
+            .result
+            .iter()
+            .map(|zone| (zone.id.as_str(), &zone.status))
+            .collect();
+        let id = pick_zone(&zone, &zones)?.to_string();
+        self.remember_zone(&zone, &id);
+        debug!("zone fetched from Cloudflare: {} ({})", &zone, &id);
+        Ok((duration, id))
+    }
+
+    /// Cache the zone identifier, and keep it to fall back to
+    fn remember_zone(&self, zone: &str, id: &str) {
         if let Some(ttl) = self.cache_ttl() {
             let mut cache = self.cache.lock().unwrap();
-            cache.insert((ZONE, self.opts.zone.clone()), id.clone(), ttl);
+            cache.insert((ZONE, zone.to_string()), id.to_string(), ttl);
         }
-        debug!(
-            "zone fetched from Cloudflare: {} ({})",
-            &self.opts.zone, &id
-        );
-        Ok((duration, id))
+        let mut known_zones = self.known_zones.lock().unwrap();
+        known_zones.insert(zone.to_string(), (id.to_string(), Instant::now()));
     }
 
-    pub async fn run(&self) -> anyhow::Result<()> {
-        let ip_address = public_ip::addr_v4().await.ok_or(PublicIPError)?;
-
-        let credentials = Credentials::UserAuthToken {
-            token: self.opts.token.clone(),
-        };
-        let config = HttpApiClientConfig {
-            http_timeout: Duration::from_secs(HTTP_TIMEOUT),
-            ..Default::default()
-        };
-        let client = Arc::new(Client::new(credentials, config, Environment::Production)?);
-
-        debug!("public IPv4 address: {}", &ip_address);
+    /// Zone identifier fetched before and its age, unless older than --stale-zone-seconds
+    fn stale_zone_identifier(&self, zone: &str) -> Option<(String, Duration)> {
+        let known_zones = self.known_zones.lock().unwrap();
+        let (id, fetched_at) = known_zones.get(zone)?;
+        let age = fetched_at.elapsed();
+        if age > Duration::from_secs(self.opts.stale_zone_seconds) {
+            return None;
+        }
+        Some((id.clone(), age))
+    }
 
-        let (duration1, zone_id) = self.get_zone_identifier(client.clone()).await?;
+    /// List zones of the account once if --zone is omitted, so records are matched against them
+    async fn list_account_zones(&self) -> anyhow::Result<()> {
+        let on_cloudflare = self.opts.records_pattern.is_some() || self.any_on_cloudflare();
+        if !self.opts.zone.is_empty() || !on_cloudflare || self.opts.account_zones.get().is_some() {
+            return Ok(());
+        }
+        let client = client(self.token()?, &self.api_network())?;
+        self.list_zones(&client).await
+    }
 
-        let mut tasks = vec![];
-        for record_name in self.opts.record_name_list() {
-            let client = client.clone();
-            let zone_id = zone_id.clone();
-            let cache = self.cache.clone();
-            let cache_ttl = self.cache_ttl();
-            tasks.push(tokio::spawn(async move {
-                if let Some(id) = cache.lock().unwrap().get(&(RECORD, record_name.clone())) {
-                    debug!("record found in cache: {} ({})", &record_name, &id);
-                    return Ok((id.clone(), record_name));
-                }
+    /// List A and AAAA records of the zones once, and select those matching --records-pattern
+    async fn list_pattern_records(&self) -> anyhow::Result<()> {
+        let pattern = match self.opts.records_pattern {
+            Some(ref pattern) => pattern,
+            None => return Ok(()),
+        };
+        if self.opts.pattern_records.get().is_some() {
+            return Ok(());
+        }
+        let clients = self.clients(self.opts.token.as_ref().map(|_| 0))?;
+        let mut names: Vec<String> = vec![];
+        for zone in self.opts.zone_names() {
+            let client = clients.zone(&zone)?;
+            let (_, zone_id) = self.get_zone_identifier(client.clone(), &zone).await?;
+            let mut page = 1;
+            loop {
                 let params = ListDnsRecords {
                     zone_identifier: &zone_id,
                     params: ListDnsRecordsParams {
-                        name: Some(record_name.clone()),
+                        page: Some(page),
+                        per_page: Some(RECORDS_PER_PAGE),
                         ..Default::default()
                     },
                 };
-                let res: ApiSuccess<Vec<DnsRecord>> = client.request(&params).await?;
-                let id = match res.result.first() {
-                    Some(dns_record) => dns_record.id.clone(),
-                    None => bail!("DNS record not found: {}", record_name),
-                };
-                if let Some(ttl) = cache_ttl {
-                    cache
-                        .lock()
-                        .unwrap()
-                        .insert((RECORD, record_name.clone()), id.clone(), ttl);
+                let res: ApiSuccess<Vec<DnsRecord>> =
+                    request(&client, "ListDnsRecords", &params).await?;
+                let count = res.result.len();
+                for dns_record in res.result {
+                    let name = normalize_name(&dns_record.name);
+                    let address = matches!(
+                        dns_record.content,
+                        DnsContent::A { .. } | DnsContent::AAAA { .. }
+                    );
+                    if address && pattern.is_match(&name) && !names.contains(&name) {
+                        names.push(name);
+                    }
                 }
-                debug!("record fetched from Cloudflare: {} ({})", &record_name, &id);
-                Ok((id, record_name))
-            }));
+                if count < RECORDS_PER_PAGE as usize {
+                    break;
+                }
+                page += 1;
+            }
+        }
+        if names.is_empty() {
+            warn!("no DNS records match {}", pattern);
+        } else {
+            info!("DNS records matching {}: {}", pattern, names.join(", "));
         }
+        // listed concurrently at worst, either result will do
+        let _ = self.opts.pattern_records.set(names);
+        Ok(())
+    }
 
-        let mut dns_record_ids = vec![];
-        let instant = Instant::now();
-        for task in futures::future::join_all(tasks).await {
-            let (dns_record_id, record_name) = task??;
-            dns_record_ids.push((dns_record_id, record_name));
+    /// List zones of the account, and cache their identifiers. Zones of the same name are left to
+    /// be looked up by name again, which tells why if the active one can't be picked.
+    async fn list_zones(&self, client: &Client) -> anyhow::Result<()> {
+        let mut zones = vec![];
+        let mut candidates: BTreeMap<String, Vec<Zone>> = BTreeMap::new();
+        let mut page = 1;
+        loop {
+            let params = ListZones {
+                params: ListZonesParams {
+                    page: Some(page),
+                    per_page: Some(ZONES_PER_PAGE),
+                    ..Default::default()
+                },
+            };
+            let res: ApiSuccess<Vec<Zone>> = request(client, "ListZones", &params).await?;
+            let count = res.result.len();
+            for zone in res.result {
+                let name = normalize_name(&zone.name);
+                if !zones.contains(&name) {
+                    zones.push(name.clone());
+                }
+                candidates.entry(name).or_default().push(zone);
+            }
+            if count < ZONES_PER_PAGE as usize {
+                break;
+            }
+            page += 1;
         }
-        let duration2 = Instant::now() - instant;
-        debug!(
-            "took {}ms to fetch record identifiers",
-            duration2.as_millis()
-        );
+        for (name, candidates) in &candidates {
+            let candidates: Vec<_> = candidates
+                .iter()
+                .map(|zone| (zone.id.as_str(), &zone.status))
+                .collect();
+            if let Ok(id) = pick_zone(name, &candidates) {
+                self.remember_zone(name, id);
+            }
+        }
+        debug!("zones of the account: {}", zones.join(", "));
+        // listed concurrently at worst, either result will do
+        let _ = self.opts.account_zones.set(zones);
+        Ok(())
+    }
 
-        let mut tasks: Vec<JoinHandle<anyhow::Result<(String, String, String)>>> = vec![];
-        for (dns_record_id, record_name) in dns_record_ids {
-            let client = client.clone();
-            let zone_id = zone_id.clone();
-            tasks.push(tokio::spawn(async move {
-                let params = UpdateDnsRecord {
-                    zone_identifier: &zone_id,
-                    identifier: &dns_record_id,
-                    params: UpdateDnsRecordParams {
-                        name: &record_name,
-                        content: DnsContent::A {
-                            content: ip_address,
-                        },
-                        proxied: None,
-                        ttl: None,
-                    },
+    /// Identifiers of zones which records on Cloudflare belong to, by name
+    async fn get_zone_identifiers(
+        &self,
+        clients: &Clients,
+    ) -> anyhow::Result<(Duration, BTreeMap<String, String>)> {
+        let mut duration = Duration::default();
+        let mut zone_ids = BTreeMap::new();
+        for (name, view) in self.record_entries() {
+            let zone = self.opts.record_zone(&name, view.as_deref());
+            if self.opts.record_provider(&name, view.as_deref()).is_some()
+                || zone_ids.contains_key(&zone)
+            {
+                continue;
+            }
+            let (elapsed, zone_id) = self
+                .get_zone_identifier(clients.zone(&zone)?, &zone)
+                .await?;
+            duration += elapsed;
+            zone_ids.insert(zone, zone_id);
+        }
+        Ok((duration, zone_ids))
+    }
+
+    /// Notify subscribers of the event e.g. from the daemon loop
+    pub async fn emit(&self, event: Event) {
+        if let Err(e) = self.publish(event).await {
+            warn!("{:#}", e);
+        }
+    }
+
+    /// Notify subscribers of the event of the run, failed if a required subscriber fails
+    async fn publish(&self, event: Event) -> anyhow::Result<()> {
+        match event {
+            Event::RunSucceeded => {
+                *self.outcome.lock().unwrap() = Outcome {
+                    succeeded_at: Some(Utc::now()),
+                    failed: false,
+                }
+            }
+            Event::RunFailed { .. } => self.outcome.lock().unwrap().failed = true,
+            _ => {}
+        }
+        self.bus.emit(event).await
+    }
+
+    /// Wait until events emitted so far are delivered to subscribers, or given up on after the
+    /// timeout of each, e.g. before exiting
+    pub async fn flush_events(&self) {
+        self.bus.flush().await;
+    }
+
+    /// Add sink notified of every event
+    pub fn add_subscriber(&mut self, subscriber: Box<dyn Subscriber>) {
+        self.bus.add_subscriber(subscriber);
+    }
+
+    /// Listen to events asynchronously
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.bus.listen()
+    }
+
+    /// Address to serve Prometheus metrics on
+    pub fn metrics_listen(&self) -> Option<std::net::SocketAddr> {
+        self.opts.metrics_listen
+    }
+
+    /// Serve metrics and health on the address of --metrics-listen, over TLS with --tls-cert and
+    /// --tls-key. Runs are fired to the channel by POST /trigger with the webhook token. The
+    /// address is bound right away, requests are served by the returned future.
+    pub fn serve_metrics(
+        &self,
+        addr: std::net::SocketAddr,
+        tx: UnboundedSender<Fired>,
+    ) -> anyhow::Result<impl std::future::Future<Output = anyhow::Result<()>>> {
+        let tls = match (&self.opts.tls_cert, &self.opts.tls_key) {
+            (Some(cert), Some(key)) => Some(crate::tls::server_config(cert, key)?),
+            _ => None,
+        };
+        if self.opts.webhook_token.is_some() && tls.is_none() && !addr.ip().is_loopback() {
+            warn!(
+                "token of POST /trigger on {} is sent in clear text, serve over TLS with --tls-cert and --tls-key",
+                addr
+            );
+        }
+        let control = self.opts.webhook_token.clone().map(|token| (token, tx));
+        let (last_run, outcome) = (self.last_run.clone(), self.outcome.clone());
+        let badge = Arc::new(move || {
+            let ip_address = last_run
+                .lock()
+                .unwrap()
+                .as_ref()
+                .and_then(|state| state.ip_address);
+            let outcome = *outcome.lock().unwrap();
+            crate::badge(ip_address, outcome.succeeded_at, outcome.failed, Utc::now())
+        });
+        crate::metrics::serve_metrics(addr, tls, control, badge)
+    }
+
+    /// Announce the metrics listener on the address by mDNS with --mdns, at the local IPv4
+    /// address if it listens on all of them. The socket is bound right away, queries are answered
+    /// by the returned future.
+    pub fn announce_mdns(
+        &self,
+        addr: std::net::SocketAddr,
+    ) -> anyhow::Result<Option<impl std::future::Future<Output = anyhow::Result<()>>>> {
+        if !self.opts.mdns {
+            return Ok(None);
+        }
+        let ip = match addr.ip() {
+            IpAddr::V4(ip) if ip.is_unspecified() => local_ipv4()?,
+            IpAddr::V4(ip) if ip.is_loopback() => {
+                bail!(
+                    "metrics listener on {} can't be reached from the local network",
+                    addr
+                )
+            }
+            IpAddr::V4(ip) => ip,
+            IpAddr::V6(_) => bail!("mDNS announcement is only supported for IPv4 listeners"),
+        };
+        let mut txt = vec![
+            format!("version={}", env!("CARGO_PKG_VERSION")),
+            "path=/health".to_string(),
+            "metrics=/metrics".to_string(),
+        ];
+        if self.opts.tls_cert.is_some() {
+            txt.push("scheme=https".to_string());
+        }
+        let host = crate::mdns::hostname();
+        let announcement = crate::mdns::Announcement {
+            instance: host.clone(),
+            host,
+            addr: ip,
+            port: addr.port(),
+            txt,
+        };
+        Ok(Some(crate::mdns::announce(announcement)?))
+    }
+
+    /// Address to serve gRPC control and status service on
+    #[cfg(feature = "grpc")]
+    pub fn grpc_listen(&self) -> Option<std::net::SocketAddr> {
+        self.opts.grpc_listen
+    }
+
+    #[cfg(feature = "grpc")]
+    pub(crate) fn last_run_state(&self) -> Option<RunState> {
+        self.last_run.lock().unwrap().clone()
+    }
+
+    /// State of the last run, loaded from the store if not run yet
+    fn previous_run_state(&self) -> Option<RunState> {
+        let mut last_run = self.last_run.lock().unwrap();
+        if last_run.is_none() {
+            match self.store.load() {
+                Ok(state) => *last_run = state,
+                Err(e) => warn!("failed to load state of the last run: {:#}", e),
+            }
+        }
+        last_run.clone()
+    }
+
+    /// Log what changed since the last run, then remember the current state, persisted by the
+    /// subscriber of the store
+    async fn record_run_state(&self, previous: &RunState, current: RunState) -> anyhow::Result<()> {
+        let changes = current.diff(previous);
+        if changes.is_empty() {
+            info!("no changes since last run");
+        } else {
+            info!("changes since last run: {}", changes.join(", "));
+        }
+        let now = Utc::now();
+        let addresses = [
+            (previous.ip_address, current.ip_address),
+            (
+                previous.ipv6_address.map(IpAddr::V6),
+                current.ipv6_address.map(IpAddr::V6),
+            ),
+        ];
+        let history = addresses
+            .iter()
+            .filter_map(|&(previous, current)| {
+                let current = current.filter(|current| previous != Some(*current))?;
+                Some(HistoryEntry {
+                    at: now,
+                    previous,
+                    current,
+                })
+            })
+            .collect();
+        *self.last_run.lock().unwrap() = Some(current);
+        self.publish(Event::RunRecorded { changes, history }).await
+    }
+
+    /// Changes of the public IP address published to records at or after the time if given,
+    /// oldest first
+    pub fn history(&self, since: Option<DateTime<Utc>>) -> anyhow::Result<Vec<HistoryEntry>> {
+        self.store.history(since)
+    }
+
+    /// Time left before requests may be sent to Cloudflare again once rate limited
+    pub fn rate_limited_for(&self) -> Option<Duration> {
+        self.rate_limit.remaining()
+    }
+
+    /// Forget cached identifiers of zones and records, and update on the next run even if up to
+    /// date, e.g. after records were changed elsewhere
+    pub fn clear_cache(&self) {
+        self.cache.lock().unwrap().clear();
+        self.forced.store(true, Ordering::Relaxed);
+    }
+
+    /// Write the state of the last run to the store once more, e.g. before shutting down
+    pub fn flush_state(&self) -> anyhow::Result<()> {
+        match *self.last_run.lock().unwrap() {
+            Some(ref state) => self.store.save(state),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn run(&self) -> anyhow::Result<()> {
+        self.run_with_ip_address(None).await
+    }
+
+    /// Update DNS records with the given public IP address, detect it if not given
+    pub async fn run_with_ip_address(&self, ip_address: Option<IpAddr>) -> anyhow::Result<()> {
+        let providers = self.providers()?;
+        self.list_account_zones().await?;
+        self.list_pattern_records().await?;
+        self.validate(&providers)?;
+        let disabled = self.opts.disabled_record_names();
+        for name in &disabled {
+            debug!("DNS record disabled: {}", name);
+        }
+        if !disabled.is_empty() && self.record_name_list().is_empty() {
+            info!("all DNS records are disabled, nothing to update");
+            return Ok(());
+        }
+        for name in self.opts.duplicate_record_names() {
+            warn!("duplicate record ignored: {}", name);
+            self.output.duplicate(&name);
+        }
+        let heartbeat = ip_address.is_some();
+        let mut ctx = match (self.opts.ip_version(), ip_address) {
+            (IpVersion::V4, Some(ip_address @ IpAddr::V4(_)))
+            | (IpVersion::V6, Some(ip_address @ IpAddr::V6(_))) => {
+                RunContext::new(ip_address, heartbeat)
+            }
+            (IpVersion::V4, Some(ip_address)) => bail!("{} is not an IPv4 address", ip_address),
+            (IpVersion::V6, Some(ip_address)) => bail!("{} is not an IPv6 address", ip_address),
+            (IpVersion::V4, None) => RunContext::new(IpAddr::V4(self.detect_ipv4().await?), false),
+            (IpVersion::V6, None) => RunContext::new(IpAddr::V6(self.detect_ipv6().await?), false),
+            // address given by trigger is used for its family, the other one is detected
+            (IpVersion::Both, ip_address) => {
+                let ipv4_address = match ip_address {
+                    Some(IpAddr::V4(ip_address)) => ip_address,
+                    _ => self.detect_ipv4().await?,
                 };
-                let res: ApiSuccess<DnsRecord> = client.request(&params).await?;
-                let dns_record = res.result;
-                let content = match dns_record.content {
-                    DnsContent::A { content } => content.to_string(),
-                    _ => "(not an A record)".into(),
+                let ipv6_address = match ip_address {
+                    Some(IpAddr::V6(ip_address)) => ip_address,
+                    _ => self.detect_ipv6().await?,
                 };
+                let mut ctx = RunContext::new(IpAddr::V4(ipv4_address), heartbeat);
+                ctx.ipv6_address = Some(ipv6_address);
+                ctx
+            }
+        };
+        self.track_detection(&mut ctx);
+        // typed records are known before the check below whether they're up to date
+        let (any_ipv4, any_ipv6) = families(&self.record_contexts());
+        self.detect_other_family(&mut ctx, any_ipv4, any_ipv6)
+            .await?;
+        ctx.park = self.park.lock().unwrap().clone();
+        if self.record_entries().iter().any(|(name, view)| {
+            self.opts.record_address(name, view.as_deref()) == AddressSource::Local
+        }) {
+            let local_ip_address = local_ipv4()?;
+            debug!("local IPv4 address: {}", local_ip_address);
+            ctx.local_ip_address = Some(local_ip_address);
+        }
+        if let Some(ref target) = ctx.park {
+            info!("DNS records parked at {}", target);
+        }
+        self.run_stages(Phase::Detect, &mut ctx).await?;
 
-                Ok((record_name, dns_record_id, content))
-            }));
+        debug!("public IP address: {}", &ctx.ip_address);
+        if let Some(ipv6_address) = ctx.ipv6_address {
+            debug!("public IPv6 address: {}", ipv6_address);
+        }
+        let ipv6_address = ctx.ipv6_address.map(IpAddr::V6);
+        for current in std::iter::once(ctx.ip_address).chain(ipv6_address) {
+            self.publish(Event::IpDetected { current }).await?;
         }
 
-        let instant = Instant::now();
-        for task in futures::future::join_all(tasks).await {
-            let (r, d, c) = task??;
-            debug!("DNS record updated: {} ({}) -> {}", &r, &d, &c);
+        let forced = self.forced.swap(false, Ordering::Relaxed);
+        if !self.opts.force
+            && !forced
+            && !self.opts.dry_run
+            && !self.opts.observe
+            && !self.opts.plan
+        {
+            let previous = self.previous_run_state().unwrap_or_default();
+            if self.unchanged(&previous, &ctx) {
+                *self.first_detected.lock().unwrap() = None;
+                info!("DNS records are up to date since last run, skip update");
+                if self.opts.explain {
+                    for record in self.record_contexts() {
+                        let key = record.key();
+                        let content = content_of(&ctx.desired_content(&record));
+                        info!("{}: skipped, {} since last run", key, content);
+                    }
+                }
+                return Ok(());
+            }
+        } else if self.opts.explain && (self.opts.force || forced) {
+            info!("update forced, DNS records are updated even if up to date since last run");
         }
-        let duration3 = Instant::now() - instant;
-        debug!("took {}ms to update DNS records", duration3.as_millis());
 
-        info!("took {}ms to fetch zone record, {}ms to fetch DNS records, and {}ms to update DNS records", duration1.as_millis(),
-        duration2.as_millis(),duration3.as_millis());
+        // maintenance destination isn't the address of this link
+        if ctx.park.is_none() && !self.opts.observe {
+            self.probe(&ctx).await?;
+        }
 
-        Ok(())
+        // once per run, not once per token
+        self.emit_ip_changes(&ctx).await;
+
+        if !self.any_on_cloudflare() {
+            // Cloudflare client is left unused without records on Cloudflare
+            return self.update(&mut ctx, &providers, None).await;
+        }
+        // records are left to tokens of their zones without --token
+        let mut tokens = match self.opts.token {
+            Some(ref token) => vec![token.expose_secret().as_str()],
+            None => return self.update(&mut ctx, &providers, None).await,
+        };
+        if let Some(ref token) = self.opts.fallback_token {
+            tokens.push(token.expose_secret().as_str());
+        }
+        let active = self.active_token.load(Ordering::Relaxed) % tokens.len();
+        let res = self.update(&mut ctx, &providers, Some(active)).await;
+        match res {
+            Err(e) if tokens.len() > 1 && is_auth_error(&e) => {
+                let other = (active + 1) % tokens.len();
+                warn!(
+                    "token {} rejected ({}), fail over to token {}",
+                    fingerprint(tokens[active]),
+                    e,
+                    fingerprint(tokens[other])
+                );
+                self.active_token.store(other, Ordering::Relaxed);
+                self.update(&mut ctx, &providers, Some(other)).await
+            }
+            res => res,
+        }
+    }
+
+    /// Notify subscribers of public IP addresses which differ from the last run
+    async fn emit_ip_changes(&self, ctx: &RunContext) {
+        let previous = self.previous_run_state().unwrap_or_default();
+        let now = Utc::now();
+        if previous.ip_address != Some(ctx.ip_address) {
+            self.emit(Event::IpChanged {
+                previous: previous.ip_address,
+                current: ctx.ip_address,
+                stable_for: state::stable_for(previous.ip_address_since, now),
+            })
+            .await;
+        }
+        if let Some(ipv6_address) = ctx.ipv6_address {
+            if previous.ipv6_address != ctx.ipv6_address {
+                self.emit(Event::IpChanged {
+                    previous: previous.ipv6_address.map(IpAddr::V6),
+                    current: IpAddr::V6(ipv6_address),
+                    stable_for: state::stable_for(previous.ipv6_address_since, now),
+                })
+                .await;
+            }
+        }
+    }
+
+    /// Make sure the public IP addresses are reachable from outside before they're published, if
+    /// a probe is given
+    async fn probe(&self, ctx: &RunContext) -> anyhow::Result<()> {
+        let url = match self.opts.reachability_probe {
+            Some(ref url) => url,
+            None => return Ok(()),
+        };
+        let probe = Probe::new(url, Duration::from_secs(HTTP_TIMEOUT))?;
+        let ipv6_address = ctx.ipv6_address.map(IpAddr::V6);
+        for ip_address in std::iter::once(ctx.ip_address).chain(ipv6_address) {
+            probe.check(ip_address).await?;
+            debug!("{} is reachable from outside", ip_address);
+        }
+        Ok(())
+    }
+
+    /// Providers other than Cloudflare, configured on command line or in the configuration file
+    fn providers(&self) -> anyhow::Result<Providers> {
+        let timeout = Duration::from_secs(HTTP_TIMEOUT);
+        let mut providers = Providers::new();
+        for (name, config) in self.opts.provider_configs()? {
+            let provider = provider::build(&config, timeout)
+                .with_context(|| format!("failed to set up provider {}", name))?;
+            providers.insert(name, provider);
+        }
+        Ok(providers)
+    }
+
+    /// Print DNS records with their current type and content, explaining proxied ones
+    pub async fn list(&self) -> anyhow::Result<()> {
+        let providers = self.providers()?;
+        self.list_account_zones().await?;
+        self.list_pattern_records().await?;
+        self.validate(&providers)?;
+        for record in self.list_records(&providers).await? {
+            let mut line = format!(
+                "{} {} {}",
+                record.name,
+                record.record_type,
+                record.content.as_deref().unwrap_or("(none)")
+            );
+            if let Some(ref provider) = record.provider {
+                line.push_str(&format!(" ({})", provider));
+            }
+            if record.proxied {
+                line.push_str(&format!(" (proxied, {})", PROXIED_CAVEAT));
+            }
+            println!("{}", line);
+        }
+        Ok(())
+    }
+
+    /// Check configuration and DNS records without updating them, and explain proxied records
+    pub async fn check(&self) -> anyhow::Result<()> {
+        let providers = self.providers()?;
+        self.list_account_zones().await?;
+        self.list_pattern_records().await?;
+        self.validate(&providers)?;
+        let records = self.list_records(&providers).await?;
+        for record in records.iter().filter(|record| record.proxied) {
+            println!("{} is proxied, {}", record.name, PROXIED_CAVEAT);
+        }
+        println!("{} DNS records are valid", self.record_name_list().len());
+        Ok(())
+    }
+
+    /// Current DNS records of each name, from Cloudflare or other providers
+    async fn list_records(&self, providers: &Providers) -> anyhow::Result<Vec<ListedRecord>> {
+        let (clients, zone_ids) = if self.any_on_cloudflare() {
+            let clients = self.clients(self.opts.token.as_ref().map(|_| 0))?;
+            let (_, zone_ids) = self.get_zone_identifiers(&clients).await?;
+            (Some(clients), zone_ids)
+        } else {
+            (None, BTreeMap::new())
+        };
+        let mut records = vec![];
+        for (name, view) in self.record_entries() {
+            let zone = self.opts.record_zone(&name, view.as_deref());
+            if let Some(provider_name) = self.opts.record_provider(&name, view.as_deref()) {
+                // validated to be configured
+                let provider = &providers[&provider_name];
+                let types: &[&str] = match self.opts.ip_version() {
+                    IpVersion::V4 => &["A"],
+                    IpVersion::V6 => &["AAAA"],
+                    IpVersion::Both => &["A", "AAAA"],
+                };
+                for record_type in types {
+                    records.push(ListedRecord {
+                        content: provider.get(&zone, &name, record_type).await?,
+                        name: name.clone(),
+                        record_type,
+                        provider: Some(provider_name.clone()),
+                        proxied: false,
+                    });
+                }
+                continue;
+            }
+            let clients = clients.as_ref().expect("clients for records on Cloudflare");
+            let client = clients.zone(&zone)?;
+            let params = ListDnsRecords {
+                zone_identifier: &zone_ids[&zone],
+                params: ListDnsRecordsParams {
+                    name: Some(name.clone()),
+                    ..Default::default()
+                },
+            };
+            let res: ApiSuccess<Vec<DnsRecord>> =
+                request(&client, "ListDnsRecords", &params).await?;
+            let found: Vec<_> = res
+                .result
+                .iter()
+                .filter(|dns_record| normalize_name(&dns_record.name) == name)
+                .collect();
+            if found.is_empty() {
+                records.push(ListedRecord {
+                    name: name.clone(),
+                    record_type: "-",
+                    content: None,
+                    provider: None,
+                    proxied: false,
+                });
+            }
+            for dns_record in found {
+                records.push(ListedRecord {
+                    name: name.clone(),
+                    record_type: record_type(&dns_record.content),
+                    content: Some(content_of(&dns_record.content)),
+                    provider: None,
+                    proxied: dns_record.proxied,
+                });
+            }
+        }
+        Ok(records)
+    }
+
+    /// Check names, zones, and providers of records before making API calls
+    fn validate(&self, providers: &Providers) -> anyhow::Result<()> {
+        check_problems(self.problems(providers))
+    }
+
+    /// Describe every record with invalid name, zone, or provider
+    fn problems(&self, providers: &Providers) -> Vec<String> {
+        let cloudflare_zones = self.opts.zone_names();
+        let mut problems = vec![];
+        let mut zones: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for zone in &cloudflare_zones {
+            zones.entry(zone.clone()).or_default();
+        }
+        for (name, view) in self.record_entries() {
+            if let Some(group) = self.opts.record_group(&name).filter(|_| view.is_none()) {
+                if !self.opts.record_groups().contains_key(group) {
+                    problems.push(format!("record {}: group {} is unknown", name, group));
+                }
+            }
+            let record_zone = self.opts.record_zone(&name, view.as_deref());
+            match self.opts.record_provider(&name, view.as_deref()) {
+                Some(provider) if !providers.contains_key(&provider) => problems.push(format!(
+                    "record {}: provider {} is unknown or not configured",
+                    name, provider
+                )),
+                // providers replace every address record of a name on update
+                Some(provider) if self.opts.ip_version() == IpVersion::Both => {
+                    problems.push(format!(
+                        "record {}: provider {} doesn't support dual-stack mode",
+                        name, provider
+                    ))
+                }
+                None if !record_zone.is_empty() && !cloudflare_zones.contains(&record_zone) => {
+                    problems.push(format!(
+                        "record {}: zone {} is not managed on Cloudflare, give it with --zone",
+                        name, record_zone
+                    ))
+                }
+                _ => {}
+            }
+            zones.entry(record_zone).or_default().push(name);
+        }
+        for (zone, names) in zones {
+            if !zone.is_empty() {
+                problems.extend(invalid_names(&zone, &names));
+                continue;
+            }
+            // without --zone, records outside zones of the account, or zones aren't listed yet
+            // e.g. in lint
+            for name in names {
+                let reason = match self.opts.account_zones.get() {
+                    Some(_) => Some("not in any zone of the account".to_string()),
+                    None => validate_name(&name, true).err(),
+                };
+                if let Some(reason) = reason {
+                    problems.push(format!("record {}: {}", name, reason));
+                }
+            }
+        }
+        for (name, view) in self.record_entries() {
+            match self.opts.record_ttl(&name, view.as_deref()) {
+                Some(ttl) if ttl != AUTOMATIC_TTL && !TTL_RANGE.contains(&ttl) => {
+                    problems.push(format!(
+                        "record {}: TTL {} is out of range, give 1 for automatic or {} to {}",
+                        name,
+                        ttl,
+                        TTL_RANGE.start(),
+                        TTL_RANGE.end()
+                    ))
+                }
+                Some(_) if self.opts.record_provider(&name, view.as_deref()).is_some() => problems
+                    .push(format!(
+                        "record {}: TTL only applies to records on Cloudflare",
+                        name
+                    )),
+                _ => {}
+            }
+        }
+        // unknown options are rejected as the command line is parsed
+        for (name, _) in self.opts.record_options() {
+            if self.opts.record_provider(&name, None).is_some() {
+                problems.push(format!(
+                    "record {}: only records on Cloudflare can be proxied",
+                    name
+                ));
+            }
+        }
+        problems
+    }
+
+    /// Warn about a schedule running into Cloudflare rate limits, or running far more often than
+    /// public IP addresses change. Called as the daemon starts and on reload
+    pub fn warn_schedule(&self) {
+        let (runs, requests) = match self.schedule_load() {
+            Ok(load) => load,
+            Err(_) => return,
+        };
+        let description = self.schedule_description();
+        let hint = match self.opts.cache_seconds {
+            0 => format!(
+                "Cache lookups with --cache-seconds, or run {}",
+                SCHEDULE_HINT
+            ),
+            _ => format!("Run {}", SCHEDULE_HINT),
+        };
+        if requests > RATE_LIMIT / 2 {
+            warn!(
+                "{}: up to {} requests to Cloudflare per 5 minutes and {} per hour, {} the rate limit of {}. {}",
+                description,
+                requests,
+                requests * 12,
+                if requests > RATE_LIMIT { "over" } else { "close to" },
+                RATE_LIMIT,
+                hint
+            );
+        } else if runs > FREQUENT_RUNS {
+            warn!(
+                "{}: {} runs per 5 minutes, far more often than public IP addresses change. {}",
+                description, runs, hint
+            );
+        }
+    }
+
+    /// Most runs and requests to Cloudflare the schedule makes within any window of rate limits
+    fn schedule_load(&self) -> anyhow::Result<(usize, usize)> {
+        let runs = match self.schedule()? {
+            Trigger::Cron(schedule, _) => runs_per_window(&schedule),
+            Trigger::Interval(period) => runs_per_window_every(period),
+            _ => 0,
+        };
+        let on_cloudflare = self.records_on_cloudflare().len();
+        // zone and records are looked up unless cached, then records are updated
+        let lookups = match self.opts.cache_seconds {
+            0 => 1 + on_cloudflare,
+            _ => 0,
+        };
+        Ok((runs, runs * (lookups + on_cloudflare)))
+    }
+
+    fn records_on_cloudflare(&self) -> Vec<(String, Option<String>)> {
+        self.record_entries()
+            .into_iter()
+            .filter(|(name, view)| self.opts.record_provider(name, view.as_deref()).is_none())
+            .collect()
+    }
+
+    /// Check configuration for common mistakes, failing with every problem found
+    pub fn lint(&self) -> anyhow::Result<()> {
+        let mut problems = vec![];
+        match self.providers() {
+            Ok(providers) => problems.extend(self.problems(&providers)),
+            Err(e) => problems.push(format!("{:#}", e)),
+        }
+        for name in self.opts.duplicate_record_names() {
+            problems.push(format!("record {}: listed more than once", name));
+        }
+        let on_cloudflare = self.records_on_cloudflare();
+        let proxied: Vec<_> = on_cloudflare
+            .iter()
+            .filter(|(name, view)| self.opts.record_proxied(name, view.as_deref()) == Proxied::True)
+            .collect();
+        for (name, view) in &proxied {
+            if self.opts.record_address(name, view.as_deref()) == AddressSource::Local {
+                problems.push(format!(
+                    "record {}: local address can't be proxied through Cloudflare",
+                    name
+                ));
+            }
+        }
+        if let Some(ref url) = self.opts.reachability_probe {
+            if let Err(e) = Probe::new(url, Duration::from_secs(HTTP_TIMEOUT)) {
+                problems.push(format!("reachability probe: {:#}", e));
+            }
+        }
+        let verified = self.stages.iter().any(|(phase, _)| *phase == Phase::Verify);
+        if verified && !proxied.is_empty() && !self.opts.skip_verify_proxied {
+            problems.push(format!(
+                "verify stages look up proxied records, {}, leave them out with \
+                 --skip-verify-proxied",
+                PROXIED_CAVEAT
+            ));
+        }
+        match self.schedule_load() {
+            Ok((_, requests)) if requests > RATE_LIMIT => problems.push(format!(
+                "{}: up to {} requests to Cloudflare per 5 minutes, over the rate limit of {}",
+                self.schedule_description(),
+                requests,
+                RATE_LIMIT
+            )),
+            Ok(_) => {}
+            Err(e) => problems.push(format!("{}: {}", self.schedule_description(), e)),
+        }
+        if !problems.is_empty() {
+            bail!(
+                "{} problems found:\n  {}",
+                problems.len(),
+                problems.join("\n  ")
+            );
+        }
+        println!("no problems found");
+        Ok(())
+    }
+
+    /// Add custom stage which runs after the built-in phase
+    pub fn add_stage(&mut self, phase: Phase, stage: Box<dyn Stage>) {
+        self.stages.push((phase, Arc::from(stage)));
+    }
+
+    async fn run_stages(&self, phase: Phase, ctx: &mut RunContext) -> anyhow::Result<()> {
+        for (p, stage) in &self.stages {
+            if *p == phase {
+                stage.run(ctx).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run verify stages, on records other than proxied ones with --skip-verify-proxied
+    async fn verify(&self, ctx: &mut RunContext) -> anyhow::Result<()> {
+        if !self.opts.skip_verify_proxied {
+            return self.run_stages(Phase::Verify, ctx).await;
+        }
+        // left out and put back in place afterwards
+        let (proxied, verified): (Vec<_>, Vec<_>) = std::mem::take(&mut ctx.records)
+            .into_iter()
+            .enumerate()
+            .partition(|(_, record)| record.proxied == Some(true));
+        for (_, record) in &proxied {
+            debug!("{} is proxied, verify stages skip it", record.name);
+        }
+        let (indexes, records): (Vec<_>, Vec<_>) = verified.into_iter().unzip();
+        ctx.records = records;
+        let res = self.run_stages(Phase::Verify, ctx).await;
+        let mut records: Vec<_> = indexes
+            .into_iter()
+            .zip(std::mem::take(&mut ctx.records))
+            .chain(proxied)
+            .collect();
+        records.sort_by_key(|(index, _)| *index);
+        ctx.records = records.into_iter().map(|(_, record)| record).collect();
+        res
+    }
+
+    async fn update(
+        &self,
+        ctx: &mut RunContext,
+        providers: &Providers,
+        token: Option<usize>,
+    ) -> anyhow::Result<()> {
+        let clients = self.clients(token)?;
+
+        let previous = self.previous_run_state().unwrap_or_default();
+
+        let (duration1, duration2) = self.resolve(&clients, providers, ctx).await?;
+        self.run_stages(Phase::Resolve, ctx).await?;
+
+        self.expire(&previous, ctx);
+        displace(ctx.park.as_ref(), &mut ctx.records);
+        self.run_stages(Phase::Diff, ctx).await?;
+
+        let duration3 = self.apply(&clients, providers, ctx).await?;
+        self.run_stages(Phase::Apply, ctx).await?;
+
+        self.verify(ctx).await?;
+        self.observe_propagation(ctx);
+
+        self.notify(&previous, ctx).await?;
+        self.run_stages(Phase::Notify, ctx).await?;
+
+        if let Some(e) = ctx.records.iter_mut().find_map(|r| r.error.take()) {
+            return Err(e);
+        }
+
+        info!("took {}ms to fetch zone record, {}ms to fetch DNS records, and {}ms to update DNS records", duration1.as_millis(),
+        duration2.as_millis(),duration3.as_millis());
+
+        Ok(())
+    }
+
+    /// Date detection back to the run which first detected the IP addresses, if records have
+    /// failed to update since
+    fn track_detection(&self, ctx: &mut RunContext) {
+        let mut first_detected = self.first_detected.lock().unwrap();
+        match *first_detected {
+            Some((ip_address, ipv6_address, instant))
+                if ip_address == ctx.ip_address && ipv6_address == ctx.ipv6_address =>
+            {
+                ctx.detected_at = instant
+            }
+            _ => *first_detected = Some((ctx.ip_address, ctx.ipv6_address, ctx.detected_at)),
+        }
+    }
+
+    /// Measure how long the change took since the IP address was first detected, once every
+    /// record is updated and verified if verify stages are added
+    fn observe_propagation(&self, ctx: &RunContext) {
+        if self.opts.dry_run || self.opts.observe || self.opts.plan {
+            return;
+        }
+        if ctx.records.iter().any(|record| record.error.is_some()) {
+            return;
+        }
+        if ctx
+            .records
+            .iter()
+            .all(|record| record.new_content.is_none())
+        {
+            return;
+        }
+        let verified = self.stages.iter().any(|(phase, _)| *phase == Phase::Verify);
+        let latency = ctx.detected_at.elapsed();
+        debug!(
+            "took {}ms from detection to propagation",
+            latency.as_millis()
+        );
+        METRICS
+            .propagation_seconds
+            .with_label_values(&[if verified { "true" } else { "false" }])
+            .observe(latency.as_secs_f64());
+        *self.first_detected.lock().unwrap() = None;
+    }
+
+    /// Look up zone identifier, then identifiers and contents of DNS records
+    async fn resolve(
+        &self,
+        clients: &Clients,
+        providers: &Providers,
+        ctx: &mut RunContext,
+    ) -> anyhow::Result<(Duration, Duration)> {
+        if self.record_name_list().is_empty() {
+            bail!("DNS records are required");
+        }
+        let (duration1, zone_ids) = if self.any_on_cloudflare() {
+            self.get_zone_identifiers(clients).await?
+        } else {
+            (Duration::default(), BTreeMap::new())
+        };
+        ctx.zone_ids = zone_ids;
+
+        let records = self.record_contexts();
+        let batch_size = self.batch_size(records.len());
+        let mut pending = records.into_iter().enumerate().peekable();
+        let mut records = vec![];
+        let instant = Instant::now();
+        let mut batch = 0;
+        while pending.peek().is_some() {
+            self.pause_between_batches(batch).await;
+            let chunk = pending.by_ref().take(batch_size).collect();
+            records.extend(
+                self.resolve_batch(clients, providers, &ctx.zone_ids, chunk)
+                    .await?,
+            );
+            batch += 1;
+        }
+        ctx.records = in_order(records);
+        let (any_ipv4, any_ipv6) = families(&ctx.records);
+        self.detect_other_family(ctx, any_ipv4, any_ipv6).await?;
+        let duration2 = Instant::now() - instant;
+        debug!(
+            "took {}ms to fetch record identifiers",
+            duration2.as_millis()
+        );
+        Ok((duration1, duration2))
+    }
+
+    /// Detect the public IP address of the other family if records of it were found in place of
+    /// records of the configured one, or are typed so in the configuration file
+    async fn detect_other_family(
+        &self,
+        ctx: &mut RunContext,
+        any_ipv4: bool,
+        any_ipv6: bool,
+    ) -> anyhow::Result<()> {
+        match ctx.ip_address {
+            IpAddr::V4(_) if any_ipv6 && ctx.ipv6_address.is_none() => {
+                let ipv6_address = self.detect_ipv6().await?;
+                debug!("public IPv6 address: {}", ipv6_address);
+                ctx.ipv6_address = Some(ipv6_address);
+            }
+            // IPv4 address goes first as in dual-stack mode
+            IpAddr::V6(ipv6_address) if any_ipv4 => {
+                let ipv4_address = self.detect_ipv4().await?;
+                debug!("public IP address: {}", ipv4_address);
+                ctx.ip_address = IpAddr::V4(ipv4_address);
+                ctx.ipv6_address = Some(ipv6_address);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Records to update with their settings, A and AAAA records of each name in dual-stack mode
+    fn record_contexts(&self) -> Vec<RecordContext> {
+        let families: &[bool] = match self.opts.ip_version() {
+            IpVersion::V4 => &[false],
+            IpVersion::V6 => &[true],
+            IpVersion::Both => &[false, true],
+        };
+        let mut records = vec![];
+        for (name, view) in self.record_entries() {
+            let families = match self.opts.record_type(&name, view.as_deref()) {
+                Some(RecordType::A) => &[false],
+                Some(RecordType::Aaaa) => &[true],
+                None => families,
+            };
+            for ipv6 in families {
+                let mut record = RecordContext::new(name.clone());
+                record.ipv6 = *ipv6;
+                record.expires_after = self.opts.record_expiry(&name, view.as_deref());
+                record.address = self.opts.record_address(&name, view.as_deref());
+                record.zone = self.opts.record_zone(&name, view.as_deref());
+                record.provider = self.opts.record_provider(&name, view.as_deref());
+                record.view = view.clone();
+                records.push(record);
+            }
+        }
+        records
+    }
+
+    /// Every record already has the content it should have as of the last run, so the update can
+    /// be skipped. Expiring records are checked on every run.
+    fn unchanged(&self, previous: &RunState, ctx: &RunContext) -> bool {
+        let mut records = self.record_contexts();
+        displace(ctx.park.as_ref(), &mut records);
+        !records.is_empty()
+            && previous.park == self.persisted_park(ctx)
+            && records.iter().all(|record| {
+                let content = match record.displaced {
+                    true => None,
+                    false => Some(content_of(&ctx.desired_content(record))),
+                };
+                record.expires_after.is_none()
+                    && previous.records.get(&record.key()) == content.as_ref()
+                    && (record.provider.is_some() || self.settings_unchanged(previous, record))
+            })
+    }
+
+    /// Record has the proxy status and TTL given by options or the configuration file as of the
+    /// last run, or they are left as they are
+    fn settings_unchanged(&self, previous: &RunState, record: &RecordContext) -> bool {
+        let key = record.key();
+        let view = record.view.as_deref();
+        let proxied = self.opts.record_proxied(&record.name, view).resolve(None);
+        let ttl = self.opts.record_ttl(&record.name, view);
+        (proxied.is_none() || previous.proxied.get(&key) == proxied.as_ref())
+            && (ttl.is_none() || previous.ttl.get(&key) == ttl.as_ref())
+    }
+
+    /// Maintenance destination to keep in the state of the run, if parked with `cdu park` rather
+    /// than by --park
+    fn persisted_park(&self, ctx: &RunContext) -> Option<ParkTarget> {
+        ctx.park
+            .clone()
+            .filter(|target| self.opts.park.as_ref() != Some(target))
+    }
+
+    /// Mark records whose host hasn't reported back in time as expired, or park them with
+    /// --park-expired until they have been expired for --park-expired-for
+    fn expire(&self, previous: &RunState, ctx: &mut RunContext) {
+        if ctx.heartbeat || self.opts.observe {
+            return;
+        }
+        let now = Utc::now();
+        for record in &mut ctx.records {
+            let (expires_after, confirmed) =
+                match (record.expires_after, previous.confirmed.get(&record.key())) {
+                    (Some(expires_after), Some(confirmed)) => (expires_after, confirmed),
+                    _ => continue,
+                };
+            let elapsed = (now - *confirmed).to_std().unwrap_or_default();
+            if elapsed <= expires_after {
+                continue;
+            }
+            match self.opts.park_expired {
+                Some(ref target) if elapsed <= expires_after + *self.opts.park_expired_for => {
+                    debug!(
+                        "DNS record expired: {}, host last reported at {}, park at {}",
+                        &record.name, confirmed, target
+                    );
+                    record.explain(format!(
+                        "host last reported at {}, parked at {} until deleted",
+                        confirmed, target
+                    ));
+                    record.parked = Some(target.clone());
+                }
+                _ => {
+                    debug!(
+                        "DNS record expired: {}, host last reported at {}",
+                        &record.name, confirmed
+                    );
+                    record.explain(format!("host last reported at {}, expired", confirmed));
+                    record.expired = true;
+                }
+            }
+        }
+    }
+
+    /// Clients of the Cloudflare API with the token of the index, 0 for --token and 1 for
+    /// --fallback-token, and with tokens of zones in the configuration file
+    fn clients(&self, token: Option<usize>) -> anyhow::Result<Clients> {
+        let clients = self.api_clients.get_or_try_init(|| {
+            let network = self.api_network();
+            let tokens = [&self.opts.token, &self.opts.fallback_token]
+                .iter()
+                .map(|token| {
+                    token
+                        .as_ref()
+                        .map(|token| client(token, &network))
+                        .transpose()
+                })
+                .collect::<anyhow::Result<_>>()?;
+            let mut zones = BTreeMap::new();
+            for (zone, token) in self.opts.zone_tokens() {
+                zones.insert(zone, client(token, &network)?);
+            }
+            Ok::<_, anyhow::Error>(ApiClients { tokens, zones })
+        })?;
+        Ok(clients.with_token(token))
+    }
+
+    /// Proxy and address family to reach the Cloudflare API with
+    fn api_network(&self) -> Network {
+        Network {
+            proxy: self.opts.api_proxy.clone(),
+            ip_version: self.opts.api_ip_version,
+            rate_limit: self.rate_limit.clone(),
+            url: self.opts.api_url.clone(),
+        }
+    }
+
+    fn detect_policy(&self) -> DetectPolicy {
+        DetectPolicy {
+            timeout: Duration::from_millis(self.opts.ip_source_timeout_millis),
+            retries: self.opts.ip_source_retries,
+            proxy: self.opts.detect_proxy.clone(),
+        }
+    }
+
+    async fn detect_ipv4(&self) -> anyhow::Result<Ipv4Addr> {
+        match detect(Version::V4, self.detect_policy()).await {
+            Some(IpAddr::V4(ip_address)) => Ok(ip_address),
+            _ => Err(PublicIPError.into()),
+        }
+    }
+
+    async fn detect_ipv6(&self) -> anyhow::Result<Ipv6Addr> {
+        match detect(Version::V6, self.detect_policy()).await {
+            Some(IpAddr::V6(ip_address)) => Ok(ip_address),
+            _ => Err(PublicIPError.into()),
+        }
+    }
+
+    /// Number of records handled at a time
+    fn batch_size(&self, len: usize) -> usize {
+        match self.opts.batch_size {
+            0 => len.max(1),
+            n => n,
+        }
+    }
+
+    async fn pause_between_batches(&self, batch: usize) {
+        if batch > 0 {
+            let delay = Duration::from_millis(self.opts.batch_delay_millis);
+            debug!("wait {}ms before batch {}", delay.as_millis(), batch + 1);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn resolve_batch(
+        &self,
+        clients: &Clients,
+        providers: &Providers,
+        zone_ids: &BTreeMap<String, String>,
+        chunk: Vec<(usize, RecordContext)>,
+    ) -> anyhow::Result<Vec<(usize, RecordContext)>> {
+        // tasks are aborted if the run is dropped e.g. on timeout or shutdown
+        let mut tasks = JoinSet::new();
+        for (index, mut record) in chunk {
+            let provider = record
+                .provider
+                .as_ref()
+                .and_then(|provider| providers.get(provider))
+                .cloned();
+            let client = match provider {
+                Some(_) => None,
+                None => Some(clients.zone(&record.zone)?),
+            };
+            let zone_id = zone_ids.get(&record.zone).cloned().unwrap_or_default();
+            let cache = self.cache.clone();
+            let cache_ttl = self.cache_ttl();
+            // observe mode and plan need current content and TTL which are not cached
+            let fresh = self.opts.observe || self.opts.plan;
+            // records of the other family are managed on their own in dual-stack mode
+            let detect_type = self.opts.ip_version() != IpVersion::Both;
+            let key = record.key();
+            tasks.spawn(async move {
+                if let Some(provider) = provider {
+                    record.old_content = provider
+                        .get(&record.zone, &record.name, record.record_type())
+                        .await?;
+                    record.explain(format!(
+                        "fetched from {} as {}",
+                        record.provider.as_deref().unwrap_or_default(),
+                        record.old_content.as_deref().unwrap_or("none")
+                    ));
+                    debug!(
+                        "record fetched from {}: {} ({})",
+                        record.provider.as_deref().unwrap_or_default(),
+                        &record.name,
+                        record.old_content.as_deref().unwrap_or("none")
+                    );
+                    return Ok((index, record));
+                }
+                {
+                    let cache = cache.lock().unwrap();
+                    let id = cache.get(&(RECORD, key.clone()));
+                    let proxied = cache.get(&(PROXIED, key.clone()));
+                    let record_type = cache.get(&(RECORD_TYPE, key.clone()));
+                    if let (Some(id), Some(proxied), Some(record_type), false) =
+                        (id, proxied, record_type, fresh)
+                    {
+                        debug!("record found in cache: {} ({})", &key, &id);
+                        record.explain("found in cache, content not fetched");
+                        record.id = Some(id.clone());
+                        record.proxied = Some(proxied == "true");
+                        record.ipv6 = record_type == "AAAA";
+                        return Ok((index, record));
+                    }
+                }
+                let params = ListDnsRecords {
+                    zone_identifier: &zone_id,
+                    params: ListDnsRecordsParams {
+                        name: Some(record.name.clone()),
+                        ..Default::default()
+                    },
+                };
+                let client = client.expect("client for records on Cloudflare");
+                let res: ApiSuccess<Vec<DnsRecord>> =
+                    request(&client, "ListDnsRecords", &params).await?;
+                let named: Vec<_> = res
+                    .result
+                    .iter()
+                    .filter(|dns_record| normalize_name(&dns_record.name) == record.name)
+                    .collect();
+                // A and AAAA records of the same name are told apart by the address family
+                let found = named.iter().find(|dns_record| match dns_record.content {
+                    DnsContent::A { .. } => !record.ipv6,
+                    DnsContent::AAAA { .. } => record.ipv6,
+                    _ => true,
+                });
+                // otherwise the existing record keeps its type, e.g. AAAA while migrating to IPv6
+                let found = found.or_else(|| {
+                    named.iter().find(|dns_record| {
+                        detect_type
+                            && matches!(
+                                dns_record.content,
+                                DnsContent::AAAA { .. } | DnsContent::A { .. }
+                            )
+                    })
+                });
+                let (id, content) = match found {
+                    Some(dns_record) => {
+                        let ipv6 = matches!(dns_record.content, DnsContent::AAAA { .. });
+                        if ipv6 != record.ipv6 {
+                            debug!(
+                                "{} record found, updated with the address of its family: {}",
+                                record_type(&dns_record.content),
+                                &record.name
+                            );
+                            record.explain(format!(
+                                "{} record found, updated with the address of its family",
+                                record_type(&dns_record.content)
+                            ));
+                            record.ipv6 = ipv6;
+                        }
+                        record.proxied = Some(dns_record.proxied);
+                        record.ttl = Some(dns_record.ttl);
+                        (dns_record.id.clone(), content_of(&dns_record.content))
+                    }
+                    // expiring records come and go with their hosts
+                    None if record.expires_after.is_some() => {
+                        debug!("record not found, to be created: {}", record.key());
+                        record.explain("not found, to be created as it expires");
+                        return Ok((index, record));
+                    }
+                    None => bail!("DNS record not found: {}", record.key()),
+                };
+                if let Some(ttl) = cache_ttl {
+                    let mut cache = cache.lock().unwrap();
+                    cache.insert((RECORD, key.clone()), id.clone(), ttl);
+                    let proxied = record.proxied.unwrap_or_default().to_string();
+                    cache.insert((PROXIED, key.clone()), proxied, ttl);
+                    cache.insert((RECORD_TYPE, key), record.record_type().to_string(), ttl);
+                }
+                debug!("record fetched from Cloudflare: {} ({})", record.key(), &id);
+                record.explain(format!("fetched from Cloudflare as {}", content));
+                record.id = Some(id);
+                record.old_content = Some(content);
+                Ok((index, record))
+            });
+        }
+
+        let mut records = vec![];
+        while let Some(task) = tasks.join_next().await {
+            records.push(task??);
+        }
+        Ok(records)
+    }
+
+    /// Update DNS records unless skipped, or in dry run, observe mode, or plan
+    async fn apply(
+        &self,
+        clients: &Clients,
+        providers: &Providers,
+        ctx: &mut RunContext,
+    ) -> anyhow::Result<Duration> {
+        let batch_size = self.batch_size(ctx.records.len());
+        // displaced records go first, a name parked at a CNAME record can't have others
+        let (displaced, others): (Vec<_>, Vec<_>) = std::mem::take(&mut ctx.records)
+            .into_iter()
+            .enumerate()
+            .partition(|(_, record)| record.displaced);
+        let mut records = vec![];
+        let budget = Arc::new(RetryBudget::new(self.opts.retry_budget));
+        let instant = Instant::now();
+        let mut batch = 0;
+        for group in [displaced, others] {
+            let mut pending = group.into_iter().peekable();
+            while pending.peek().is_some() {
+                self.pause_between_batches(batch).await;
+                let chunk = pending.by_ref().take(batch_size).collect();
+                records.extend(
+                    self.apply_batch(clients, providers, ctx, chunk, &budget)
+                        .await?,
+                );
+                batch += 1;
+            }
+        }
+        ctx.records = in_order(records);
+        let duration3 = Instant::now() - instant;
+        debug!("took {}ms to update DNS records", duration3.as_millis());
+        Ok(duration3)
+    }
+
+    async fn apply_batch(
+        &self,
+        clients: &Clients,
+        providers: &Providers,
+        ctx: &RunContext,
+        chunk: Vec<(usize, RecordContext)>,
+        budget: &Arc<RetryBudget>,
+    ) -> anyhow::Result<Vec<(usize, RecordContext)>> {
+        let mut tasks = JoinSet::new();
+        for (index, record) in chunk {
+            let zone_id = ctx.zone_ids.get(&record.zone).cloned().unwrap_or_default();
+            let cache = self.cache.clone();
+            let content = ctx.desired_content(&record);
+            let provider = record
+                .provider
+                .as_ref()
+                .and_then(|provider| providers.get(provider))
+                .cloned();
+            let client = match provider {
+                Some(_) => None,
+                None => Some(clients.zone(&record.zone)?),
+            };
+            let proxied = self
+                .opts
+                .record_proxied(&record.name, record.view.as_deref())
+                .resolve(record.proxied);
+            let ttl = self.opts.record_ttl(&record.name, record.view.as_deref());
+            // plans log the payload of each record as dry runs do
+            let dry_run = self.opts.dry_run || self.opts.plan;
+            let observe = self.opts.observe;
+            let detected_at = ctx.detected_at;
+            let budget = budget.clone();
+            let mut delays = self.retry_policy().strategy();
+            tasks.spawn(async move {
+                if record.skip || observe {
+                    return (index, record);
+                }
+                let mut record = record;
+                loop {
+                    record = match provider {
+                        Some(ref provider) => {
+                            let content = content.clone();
+                            apply_provider_record(provider.as_ref(), content, record, dry_run).await
+                        }
+                        None => {
+                            let target = Target {
+                                content: content.clone(),
+                                proxied,
+                                ttl,
+                            };
+                            let cache = cache.clone();
+                            let client =
+                                client.as_deref().expect("client for records on Cloudflare");
+                            apply_record(client, &zone_id, target, record, dry_run, cache).await
+                        }
+                    };
+                    // created again after a server error, the record may be duplicated if the
+                    // first request went through
+                    let creating = provider.is_none() && record.id.is_none() && !record.deleted();
+                    let (retryable, delay) = match record.error {
+                        Some(ref e) if creating && is_server_error(e) => (false, None),
+                        Some(ref e) if is_retryable(e) => (true, delays.next()),
+                        _ => (false, None),
+                    };
+                    match delay {
+                        Some(delay) if budget.take() => {
+                            debug!("retry {} in {}ms", record.name, delay.as_millis());
+                            if let Some(ref e) = record.error {
+                                record.explain(format!("retried after {}", e));
+                            }
+                            record.error = None;
+                            tokio::time::sleep(delay).await;
+                        }
+                        Some(_) => {
+                            debug!("retry budget spent, {} is not retried", record.name);
+                            record.explain("not retried, retry budget spent");
+                            record.error = record.error.map(spent);
+                            break;
+                        }
+                        None if retryable => {
+                            record.error = record.error.map(spent);
+                            break;
+                        }
+                        None => break,
+                    }
+                }
+                if record.new_content.is_some() {
+                    record.latency = Some(detected_at.elapsed());
+                }
+                (index, record)
+            });
+        }
+
+        let mut records = vec![];
+        while let Some(task) = tasks.join_next().await {
+            records.push(task?);
+        }
+        Ok(records)
+    }
+
+    /// Report outcome of each record, then remember state of this run
+    async fn notify(&self, previous: &RunState, ctx: &RunContext) -> anyhow::Result<()> {
+        let mut report = match self.opts.migration_report {
+            Some(ref path) if self.opts.observe => Some(MigrationReport::load(path)?),
+            _ => None,
+        };
+        let now = Utc::now();
+        let mut current = RunState {
+            ip_address: Some(ctx.ip_address),
+            ip_address_since: state::since(
+                previous.ip_address,
+                previous.ip_address_since,
+                Some(ctx.ip_address),
+                now,
+            ),
+            ipv6_address: ctx.ipv6_address,
+            ipv6_address_since: state::since(
+                previous.ipv6_address,
+                previous.ipv6_address_since,
+                ctx.ipv6_address,
+                now,
+            ),
+            park: self.persisted_park(ctx),
+            ..Default::default()
+        };
+        let mut plan = vec![];
+        for record in &ctx.records {
+            let key = record.key();
+            let name = &key;
+            if self.opts.explain {
+                self.explain(ctx, record);
+            }
+            if let Some(ref e) = record.error {
+                if let Some(content) = previous.records.get(name) {
+                    current.records.insert(name.clone(), content.clone());
+                }
+                self.output.failed(name, e);
+            } else if self.opts.plan {
+                plan.push(self.plan_record(ctx, record));
+            } else if record.expired {
+                debug!("DNS record deleted: {}", name);
+                self.output.expired(name, record.old_content.as_deref());
+            } else if record.displaced {
+                debug!("DNS record deleted while parked: {}", name);
+                self.output.displaced(name, record.old_content.as_deref());
+            } else if let Some(ref new_content) = record.new_content {
+                current.records.insert(name.clone(), new_content.clone());
+                info!(
+                    record = record.name.as_str(),
+                    zone = record.zone.as_str(),
+                    old_content = record.old_content.as_deref().unwrap_or_default(),
+                    new_content = new_content.as_str(),
+                    duration_ms = record.duration.as_millis() as u64;
+                    "DNS record updated: {} ({}) -> {}",
+                    name,
+                    record.id.as_deref().unwrap_or_default(),
+                    new_content
+                );
+                if record.parked.is_some() {
+                    self.output
+                        .parked(name, record.old_content.as_deref(), new_content);
+                } else {
+                    self.output.updated(
+                        name,
+                        record.old_content.as_deref(),
+                        new_content,
+                        record.duration,
+                    );
+                }
+                self.emit(Event::RecordUpdated {
+                    record_name: record.name.clone(),
+                    old_content: record.old_content.clone(),
+                    new_content: new_content.clone(),
+                    latency: record.latency.unwrap_or_default(),
+                })
+                .await;
+            } else if record.skip {
+                if let Some(content) = record
+                    .old_content
+                    .as_ref()
+                    .or_else(|| previous.records.get(name))
+                {
+                    current.records.insert(name.clone(), content.clone());
+                }
+                debug!("DNS record skipped: {}", name);
+                self.output.skipped(name, record.old_content.as_deref());
+            } else if self.opts.observe {
+                let content = record.old_content.clone().unwrap_or_default();
+                let expected = content_of(&ctx.desired_content(record));
+                if content != expected {
+                    warn!(
+                        "drift detected: {} is {} but should be {}",
+                        name, &content, &expected
+                    );
+                    METRICS.drift_detected.with_label_values(&[name]).inc();
+                    self.emit(Event::DriftDetected {
+                        record_name: record.name.clone(),
+                        content: content.clone(),
+                        expected: expected.clone(),
+                    })
+                    .await;
+                } else {
+                    debug!("DNS record in sync: {} ({})", name, &content);
+                }
+                self.output.observed(name, &content, &expected);
+                if let Some(ref mut report) = report {
+                    report.observe(name, &content, &expected);
+                }
+                current.records.insert(name.clone(), content);
+            } else {
+                let new_content = content_of(&ctx.desired_content(record));
+                self.output
+                    .dry_run(name, record.old_content.as_deref(), &new_content);
+            }
+        }
+
+        // host reporting its IP address confirms it's alive, otherwise the clock keeps ticking
+        for record in ctx.records.iter().filter(|r| r.expires_after.is_some()) {
+            let key = record.key();
+            let confirmed = match previous.confirmed.get(&key) {
+                Some(confirmed) if !ctx.heartbeat || record.error.is_some() => *confirmed,
+                _ => now,
+            };
+            current.confirmed.insert(key, confirmed);
+        }
+
+        // proxy status and TTL of records on Cloudflare, so records are updated once either is
+        // changed
+        for record in ctx.records.iter().filter(|r| r.provider.is_none()) {
+            let key = record.key();
+            if !current.records.contains_key(&key) {
+                continue;
+            }
+            let (proxied, ttl) = match record.new_content {
+                Some(_) => (
+                    record.proxied,
+                    self.opts.record_ttl(&record.name, record.view.as_deref()),
+                ),
+                None => (
+                    previous.proxied.get(&key).copied(),
+                    previous.ttl.get(&key).copied(),
+                ),
+            };
+            if let Some(proxied) = proxied {
+                current.proxied.insert(key.clone(), proxied);
+            }
+            if let Some(ttl) = ttl {
+                current.ttl.insert(key, ttl);
+            }
+        }
+
+        // runs of a group leave the state of records of the others as it was
+        if self.group.is_some() {
+            let keys: Vec<String> = ctx.records.iter().map(|record| record.key()).collect();
+            for (key, content) in &previous.records {
+                if !keys.contains(key) {
+                    current.records.insert(key.clone(), content.clone());
+                }
+            }
+            for (key, proxied) in &previous.proxied {
+                if !keys.contains(key) {
+                    current.proxied.insert(key.clone(), *proxied);
+                }
+            }
+            for (key, ttl) in &previous.ttl {
+                if !keys.contains(key) {
+                    current.ttl.insert(key.clone(), *ttl);
+                }
+            }
+            for (key, confirmed) in &previous.confirmed {
+                if !keys.contains(key) {
+                    current.confirmed.insert(key.clone(), *confirmed);
+                }
+            }
+        }
+
+        if self.opts.plan {
+            println!("{}", Plan(plan));
+        } else if !self.opts.dry_run {
+            self.record_run_state(previous, current).await?;
+        }
+        if let (Some(report), Some(path)) = (report, &self.opts.migration_report) {
+            report.save(path)?;
+            info!("{}", report.summary());
+        }
+        Ok(())
+    }
+
+    /// Log why the record was updated, skipped, or failed, with --explain
+    fn explain(&self, ctx: &RunContext, record: &RecordContext) {
+        let mut reasons = record.explanation.clone();
+        if let Some(ref target) = ctx.park {
+            reasons.push(format!("parked at {}", target));
+        }
+        if record.address == AddressSource::Local {
+            reasons.push("address on the local network".to_string());
+        }
+        if record.proxied == Some(true) {
+            reasons.push(format!("proxied, {}", PROXIED_CAVEAT));
+        }
+        let outcome = if let Some(ref e) = record.error {
+            format!("failed, {}", e)
+        } else if self.opts.plan {
+            "planned".to_string()
+        } else if record.deleted() {
+            "deleted".to_string()
+        } else if let Some(ref new_content) = record.new_content {
+            match record.old_content {
+                Some(ref old_content) if old_content == new_content => {
+                    format!("updated, {} already", new_content)
+                }
+                Some(ref old_content) => format!("updated from {} to {}", old_content, new_content),
+                None => format!("updated to {}", new_content),
+            }
+        } else if record.skip {
+            "skipped by a stage".to_string()
+        } else if self.opts.observe {
+            "observed".to_string()
+        } else {
+            "not updated in dry run".to_string()
+        };
+        reasons.push(outcome);
+        info!("{}: {}", record.key(), reasons.join(", "));
+    }
+
+    /// Record as it is and as it would be after the run
+    fn plan_record(&self, ctx: &RunContext, record: &RecordContext) -> PlannedRecord {
+        let content = ctx.desired_content(record);
+        let name = record.key();
+        if record.deleted() {
+            return PlannedRecord::delete(name, record_type(&content), record.old_content.clone());
+        }
+        let (ttl, proxied) = match record.provider {
+            Some(_) => (None, None),
+            None => (
+                self.opts.record_ttl(&record.name, record.view.as_deref()),
+                self.opts
+                    .record_proxied(&record.name, record.view.as_deref())
+                    .resolve(record.proxied),
+            ),
+        };
+        PlannedRecord::new(
+            name,
+            record_type(&content),
+            (record.old_content.clone(), content_of(&content)),
+            (record.ttl, ttl),
+            (record.proxied, proxied),
+        )
+    }
+}
+
+/// Mark records of the other family of parked names as displaced in dual-stack mode, so each
+/// name keeps one record pointing to the maintenance destination, the A record if it has one
+fn displace(park: Option<&ParkTarget>, records: &mut [RecordContext]) {
+    let parked: Vec<_> = records
+        .iter()
+        .filter(|record| !record.ipv6 && (park.is_some() || record.parked.is_some()))
+        .map(|record| (record.name.clone(), record.view.clone()))
+        .collect();
+    for record in records.iter_mut().filter(|record| record.ipv6) {
+        if (park.is_some() || record.parked.is_some())
+            && parked.contains(&(record.name.clone(), record.view.clone()))
+        {
+            record.displaced = true;
+            record.explain("parked at the A record of the name");
+        }
+    }
+}
+
+/// DNS record as listed by `cdu list`
+struct ListedRecord {
+    name: String,
+    record_type: &'static str,
+    content: Option<String>,
+    provider: Option<String>,
+    proxied: bool,
+}
+
+/// Forget cached identifiers, proxy status, and types of records of the name. Both families are
+/// forgotten since a record may have been looked up as the other one.
+fn forget(cache: &Mutex<TtlCache<(u8, String), String>>, name: &str) {
+    let mut cache = cache.lock().unwrap();
+    for key in &[name.to_string(), format!("{}/AAAA", name)] {
+        for kind in &[RECORD, PROXIED, RECORD_TYPE] {
+            cache.remove(&(*kind, key.clone()));
+        }
+    }
+}
+
+/// Content and proxy status to set on a record on Cloudflare
+struct Target {
+    content: DnsContent,
+    /// Left as is on update if none
+    proxied: Option<bool>,
+    /// Left as is on update if none, automatic on create
+    ttl: Option<u32>,
+}
+
+/// Update, create, or delete DNS record, or log the payload in dry run
+async fn apply_record(
+    client: &Client,
+    zone_id: &str,
+    target: Target,
+    mut record: RecordContext,
+    dry_run: bool,
+    cache: Arc<Mutex<TtlCache<(u8, String), String>>>,
+) -> RecordContext {
+    let instant = Instant::now();
+    let res = match (record.id.clone(), record.deleted()) {
+        // already deleted
+        (None, true) => Ok(None),
+        (Some(id), true) => {
+            let params = DeleteDnsRecord {
+                zone_identifier: zone_id,
+                identifier: &id,
+            };
+            forget(&cache, &record.name);
+            send(client, "DeleteDnsRecord", &params, &record.name, dry_run)
+                .await
+                .map(|_: Option<DeleteDnsRecordResponse>| None)
+        }
+        (None, false) => {
+            let params = CreateDnsRecord {
+                zone_identifier: zone_id,
+                params: CreateDnsRecordParams {
+                    name: &record.name,
+                    content: target.content,
+                    proxied: target.proxied,
+                    ttl: target.ttl,
+                    priority: None,
+                },
+            };
+            send(client, "CreateDnsRecord", &params, &record.name, dry_run).await
+        }
+        (Some(id), false) => {
+            let params = UpdateDnsRecord {
+                zone_identifier: zone_id,
+                identifier: &id,
+                params: UpdateDnsRecordParams {
+                    name: &record.name,
+                    content: target.content,
+                    // re-sent as fetched unless set, otherwise Cloudflare may turn proxying off
+                    proxied: target.proxied,
+                    ttl: target.ttl,
+                },
+            };
+            send(client, "UpdateDnsRecord", &params, &record.name, dry_run).await
+        }
+    };
+    match res {
+        Ok(Some(dns_record)) => {
+            if record.proxied != Some(dns_record.proxied) {
+                forget(&cache, &record.name);
+            }
+            record.id = Some(dns_record.id.clone());
+            record.new_content = Some(content_of(&dns_record.content));
+            record.proxied = Some(dns_record.proxied);
+        }
+        Ok(None) => {}
+        Err(e) => record.error = Some(e),
+    }
+    record.duration = Instant::now() - instant;
+    record
+}
+
+/// Set or delete DNS record with provider other than Cloudflare, or log the change in dry run
+async fn apply_provider_record(
+    provider: &dyn Provider,
+    content: DnsContent,
+    mut record: RecordContext,
+    dry_run: bool,
+) -> RecordContext {
+    let instant = Instant::now();
+    let provider_name = record.provider.clone().unwrap_or_default();
+    let new_content = content_of(&content);
+    let res = match (record.old_content.is_some(), record.deleted()) {
+        // already deleted
+        (false, true) => Ok(()),
+        (true, true) if dry_run => {
+            info!(
+                "dry run: {} would be deleted on {}",
+                &record.name, provider_name
+            );
+            Ok(())
+        }
+        (true, true) => {
+            provider
+                .delete(&record.zone, &record.name, record.record_type())
+                .await
+        }
+        (_, false) if dry_run => {
+            info!(
+                "dry run: {} would be set to {} on {}",
+                &record.name, new_content, provider_name
+            );
+            Ok(())
+        }
+        (_, false) => provider.set(&record.zone, &record.name, &content).await,
+    };
+    match res {
+        Ok(_) if !dry_run && !record.deleted() => record.new_content = Some(new_content),
+        Ok(_) => {}
+        Err(e) => record.error = Some(e),
+    }
+    record.duration = Instant::now() - instant;
+    record
+}
+
+/// Send request, or log its payload in dry run and return none
+async fn send<E, R, B>(
+    client: &Client,
+    endpoint: &'static str,
+    params: &E,
+    record_name: &str,
+    dry_run: bool,
+) -> anyhow::Result<Option<R>>
+where
+    E: Endpoint<R, (), B> + Send + Sync,
+    R: ApiResult,
+    B: serde::Serialize,
+{
+    if dry_run {
+        // payload carries no credentials, the token only travels in headers
+        let body = serde_json::to_string(&params.body())?;
+        info!(
+            "dry run: {} would {} {} {}",
+            record_name,
+            method_name(params.method()),
+            params.path(),
+            body
+        );
+        return Ok(None);
+    }
+    let res: ApiSuccess<R> = request(client, endpoint, params).await?;
+    Ok(Some(res.result))
+}
+
+/// Send request to Cloudflare once the delay asked for by rate limited responses has passed,
+/// recording its round trip time by endpoint e.g. ListZones
+async fn request<E, R, Q, B>(client: &Client, endpoint: &'static str, params: &E) -> ApiResponse<R>
+where
+    E: Endpoint<R, Q, B> + Send + Sync,
+    R: ApiResult,
+    Q: serde::Serialize,
+    B: serde::Serialize,
+{
+    client.wait_rate_limit().await;
+    let instant = Instant::now();
+    let res = client.request(params).await;
+    let duration = instant.elapsed();
+    debug!("{} took {}ms", endpoint, duration.as_millis());
+    METRICS
+        .api_latency_seconds
+        .with_label_values(&[endpoint])
+        .observe(duration.as_secs_f64());
+    res
+}
+
+fn method_name(method: Method) -> &'static str {
+    match method {
+        Method::Get => "GET",
+        Method::Post => "POST",
+        Method::Put => "PUT",
+        Method::Delete => "DELETE",
+        Method::Patch => "PATCH",
+    }
+}
+
+/// Whether any of the records is an A record, and whether any is an AAAA record
+fn families(records: &[RecordContext]) -> (bool, bool) {
+    let any_ipv4 = records.iter().any(|record| !record.ipv6);
+    let any_ipv6 = records.iter().any(|record| record.ipv6);
+    (any_ipv4, any_ipv6)
+}
+
+/// Restore configured order of records which tasks finished in arbitrary order
+fn in_order(mut records: Vec<(usize, RecordContext)>) -> Vec<RecordContext> {
+    records.sort_by_key(|(index, _)| *index);
+    records.into_iter().map(|(_, record)| record).collect()
+}
+
+/// Whether a record update may succeed when retried, unlike requests Cloudflare rejected
+fn is_retryable(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<ApiFailure>() {
+        Some(ApiFailure::Error(status, _)) => status.is_server_error() || status.as_u16() == 429,
+        _ => is_transient(e),
+    }
+}
+
+fn is_server_error(e: &anyhow::Error) -> bool {
+    matches!(e.downcast_ref::<ApiFailure>(), Some(ApiFailure::Error(status, _)) if status.is_server_error())
+}
+
+/// Failure the run already retried as far as its retries and budget allow, so the daemon
+/// doesn't retry the whole run with a budget of its own
+fn spent(e: anyhow::Error) -> anyhow::Error {
+    anyhow::Error::new(RetriesSpent(e))
+}
+
+/// Whether Cloudflare rejected the token itself rather than the request
+fn is_auth_error(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<ApiFailure>() {
+        Some(ApiFailure::Error(status, _)) => status.as_u16() == 401 || status.as_u16() == 403,
+        _ => false,
+    }
+}
+
+/// Value to report, or the error which kept it from loading
+fn or_error<T: serde::Serialize>(res: anyhow::Result<T>) -> serde_json::Value {
+    match res.and_then(|value| Ok(serde_json::to_value(value)?)) {
+        Ok(value) => value,
+        Err(e) => json!({ "error": format!("{:#}", e) }),
+    }
+}
+
+fn fingerprint(token: &str) -> String {
+    let digest = Sha256::digest(token.as_bytes());
+    digest[..3].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn client(token: &SecretString, network: &Network) -> anyhow::Result<Arc<Client>> {
+    let credentials = ApiCredentials::Token(token.clone());
+    let config = HttpApiClientConfig {
+        http_timeout: Duration::from_secs(HTTP_TIMEOUT),
+        ..Default::default()
+    };
+    Ok(Arc::new(Client::new(
+        credentials,
+        config,
+        Environment::Custom(network.url.clone()),
+        network,
+    )?))
+}
+
+fn content_of(content: &DnsContent) -> String {
+    match content {
+        DnsContent::A { content } => content.to_string(),
+        DnsContent::AAAA { content } => content.to_string(),
+        DnsContent::CNAME { content } => content.clone(),
+        DnsContent::NS { content } => content.clone(),
+        DnsContent::MX { content, .. } => content.clone(),
+        DnsContent::TXT { content } => content.clone(),
+        DnsContent::SRV { content } => content.clone(),
     }
 }
@@ -0,0 +1,54 @@
+use std::iter;
+
+/// Arguments cdu was run with before the subcommand e.g. `service install`, so services and
+/// scheduled tasks run cdu as it was configured. `--daemon` is left to the caller.
+pub(crate) fn forwarded_args(subcommand: &[&str]) -> Vec<String> {
+    let args: Vec<String> = std::env::args_os()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let end = args
+        .windows(subcommand.len())
+        .position(|window| window == subcommand)
+        .unwrap_or(args.len());
+    args[..end]
+        .iter()
+        .filter(|arg| *arg != "--daemon" && *arg != "-d")
+        .cloned()
+        .collect()
+}
+
+/// Arguments quoted and joined into a Windows command line
+pub(crate) fn join_args(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quote the argument as CommandLineToArgvW parses it back, if it has to be
+fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(&[' ', '\t', '"'][..]) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        // backslashes are literal unless followed by a quote
+        let escaped = if c == '"' {
+            backslashes * 2 + 1
+        } else {
+            backslashes
+        };
+        quoted.extend(iter::repeat_n('\\', escaped));
+        quoted.push(c);
+        backslashes = 0;
+    }
+    quoted.extend(iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
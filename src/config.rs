@@ -0,0 +1,428 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use secrecy::SecretString;
+use serde::Deserialize;
+use serde_json::json;
+use serde_yaml::{Mapping, Value};
+use toml_edit::{DocumentMut, Item};
+
+use crate::{AddressSource, TsigAlgorithm};
+
+/// Configuration file in YAML or TOML, complementing command line options
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct Config {
+    /// Providers by name with their credentials, `${VAR}` in values is replaced with the
+    /// environment variable
+    #[serde(default)]
+    pub(crate) providers: BTreeMap<String, ProviderConfig>,
+    /// Records, also given as `[[record]]` tables in TOML
+    #[serde(default, alias = "record")]
+    pub(crate) records: Vec<RecordConfig>,
+    /// Cloudflare tokens by zone, instead of --token for records in the zone, `${VAR}` in values
+    /// is replaced with the environment variable
+    #[serde(default)]
+    pub(crate) tokens: BTreeMap<String, SecretString>,
+    /// Groups of records by name, updated on schedules of their own besides --cron or --every
+    #[serde(default)]
+    pub(crate) groups: BTreeMap<String, GroupConfig>,
+    /// Command line options by long name e.g. `cron` or `cache_seconds`, used unless given on
+    /// command line. Unknown ones are rejected when parsed as options.
+    #[serde(flatten)]
+    pub(crate) options: BTreeMap<String, Value>,
+}
+
+/// DNS hosting service records can refer to by name
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub(crate) enum ProviderConfig {
+    Powerdns {
+        url: String,
+        api_key: String,
+        #[serde(default = "powerdns_server_id")]
+        server_id: String,
+    },
+    Rfc2136 {
+        server: SocketAddr,
+        key_name: String,
+        key_secret: String,
+        #[serde(default = "tsig_algorithm")]
+        key_algorithm: TsigAlgorithm,
+    },
+    Gcp {
+        #[serde(default)]
+        credentials: Option<PathBuf>,
+        #[serde(default)]
+        project: Option<String>,
+    },
+    Azure {
+        subscription_id: String,
+        resource_group: String,
+        tenant_id: String,
+        client_id: String,
+        client_secret: String,
+    },
+    Ovh {
+        #[serde(default = "ovh_endpoint")]
+        endpoint: String,
+        application_key: String,
+        application_secret: String,
+        consumer_key: String,
+    },
+}
+
+fn powerdns_server_id() -> String {
+    "localhost".to_string()
+}
+
+fn tsig_algorithm() -> TsigAlgorithm {
+    TsigAlgorithm::HmacSha256
+}
+
+fn ovh_endpoint() -> String {
+    "https://eu.api.ovh.com/1.0".to_string()
+}
+
+/// DNS record in the configuration file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RecordConfig {
+    pub(crate) name: String,
+    /// Publish only an A or only an AAAA record for the name, overriding --ip-version
+    #[serde(default, rename = "type")]
+    pub(crate) record_type: Option<RecordType>,
+    /// Give false to leave the record alone without deleting its configuration
+    #[serde(default = "enabled")]
+    pub(crate) enabled: bool,
+    /// Delete the record if the host hasn't reported its IP address within this long e.g. 2h,
+    /// or park it first with --park-expired, and create it again once the host reports back.
+    /// Meant for short-lived hosts.
+    #[serde(default, with = "humantime_serde")]
+    pub(crate) expires_after: Option<Duration>,
+    /// Publish the public address, or the address on the local network e.g. for the internal
+    /// view of split-horizon DNS
+    #[serde(default)]
+    pub(crate) address: AddressSource,
+    /// Publish with the provider e.g. powerdns, or one named in `providers`, instead of Cloudflare
+    #[serde(default)]
+    pub(crate) provider: Option<String>,
+    /// Zone of the record at the provider, the zone given on command line if omitted
+    #[serde(default)]
+    pub(crate) zone: Option<String>,
+    /// Proxy through Cloudflare or not, overriding --proxied
+    #[serde(default)]
+    pub(crate) proxied: Option<bool>,
+    /// TTL in seconds on Cloudflare, overriding --ttl
+    #[serde(default)]
+    pub(crate) ttl: Option<u32>,
+    /// Group in `groups` whose schedule updates the record too
+    #[serde(default)]
+    pub(crate) group: Option<String>,
+}
+
+/// Records updated on a schedule of their own e.g. critical ones every minute
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GroupConfig {
+    /// Cron expression in the timezone of --cron
+    #[serde(default)]
+    pub(crate) cron: Option<String>,
+    /// Interval between updates e.g. 1m, instead of cron
+    #[serde(default, with = "humantime_serde")]
+    pub(crate) every: Option<Duration>,
+}
+
+/// Type of DNS record published for a name
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub(crate) enum RecordType {
+    /// Public IPv4 address or the address on the local network
+    #[serde(alias = "a")]
+    A,
+    /// Public IPv6 address
+    #[serde(rename = "AAAA", alias = "aaaa")]
+    Aaaa,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Config {
+    /// Load configuration file with the profile merged in, if given
+    pub(crate) fn load(path: &Path, profile: Option<&str>) -> anyhow::Result<Self> {
+        let mut value = read(path)?;
+        merge_profile(&mut value, profile)
+            .and_then(|_| Self::from_value(value))
+            .with_context(|| format!("failed to parse config file {}", path.display()))
+    }
+
+    fn from_value(mut value: Value) -> anyhow::Result<Self> {
+        // records hold no credentials
+        if let Value::Mapping(ref mut mapping) = value {
+            for (key, value) in mapping.iter_mut() {
+                if key.as_str() != Some("records") {
+                    interpolate(value)?;
+                }
+            }
+        }
+        Ok(serde_yaml::from_value(value)?)
+    }
+}
+
+/// Take profiles out of the configuration file, then merge the named one into it. Options,
+/// providers, tokens, and groups of the profile take precedence, and its records are added to the others.
+fn merge_profile(value: &mut Value, name: Option<&str>) -> anyhow::Result<()> {
+    let root = match value {
+        Value::Mapping(root) => root,
+        _ => return Ok(()),
+    };
+    let mut profiles = match root.remove("profile") {
+        Some(Value::Mapping(profiles)) => profiles,
+        Some(_) => bail!("profile must be a table of profiles by name"),
+        None => Mapping::new(),
+    };
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+    let profile = match profiles.remove(name) {
+        Some(Value::Mapping(profile)) => profile,
+        Some(_) => bail!("profile {} must be a table", name),
+        None => {
+            let names: Vec<_> = profiles.keys().filter_map(Value::as_str).collect();
+            bail!("profile {} not found, profiles: {}", name, names.join(", "))
+        }
+    };
+    for (key, value) in profile {
+        match (key.as_str(), value) {
+            (Some("records" | "record"), Value::Sequence(records)) => {
+                let key = if root.contains_key("record") {
+                    "record"
+                } else {
+                    "records"
+                };
+                match root
+                    .entry(Value::String(key.to_string()))
+                    .or_insert_with(|| Value::Sequence(vec![]))
+                {
+                    Value::Sequence(others) => others.extend(records),
+                    _ => bail!("{} must be a list", key),
+                }
+            }
+            (Some(table @ ("providers" | "groups" | "tokens")), Value::Mapping(entries)) => {
+                let table = table.to_string();
+                match root
+                    .entry(key)
+                    .or_insert_with(|| Value::Mapping(Mapping::new()))
+                {
+                    Value::Mapping(others) => others.extend(entries),
+                    _ => bail!("{} must be a table", table),
+                }
+            }
+            (_, value) => {
+                root.insert(key, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Configuration file as it is, in TOML if named *.toml or else in YAML
+pub(crate) fn read(path: &Path) -> anyhow::Result<Value> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("failed to read config file {}", path.display()))?;
+    let value = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("toml") => content
+            .parse::<DocumentMut>()
+            .map(|document| toml_table(document.as_table()))
+            .map_err(anyhow::Error::from),
+        _ => serde_yaml::from_str(&content).map_err(anyhow::Error::from),
+    };
+    value.with_context(|| format!("failed to parse config file {}", path.display()))
+}
+
+fn toml_table<'a>(entries: impl IntoIterator<Item = (&'a str, &'a Item)>) -> Value {
+    let mut mapping = Mapping::new();
+    for (key, item) in entries {
+        let value = match item {
+            Item::None => continue,
+            Item::Value(value) => toml_value(value),
+            Item::Table(table) => toml_table(table),
+            Item::ArrayOfTables(tables) => Value::Sequence(tables.iter().map(toml_table).collect()),
+        };
+        mapping.insert(Value::String(key.to_string()), value);
+    }
+    Value::Mapping(mapping)
+}
+
+fn toml_value(value: &toml_edit::Value) -> Value {
+    match value {
+        toml_edit::Value::String(s) => Value::String(s.value().clone()),
+        toml_edit::Value::Integer(i) => Value::Number((*i.value()).into()),
+        toml_edit::Value::Float(f) => Value::Number((*f.value()).into()),
+        toml_edit::Value::Boolean(b) => Value::Bool(*b.value()),
+        toml_edit::Value::Datetime(d) => Value::String(d.value().to_string()),
+        toml_edit::Value::Array(values) => Value::Sequence(values.iter().map(toml_value).collect()),
+        toml_edit::Value::InlineTable(table) => {
+            let mapping = table
+                .iter()
+                .map(|(key, value)| (Value::String(key.to_string()), toml_value(value)))
+                .collect();
+            Value::Mapping(mapping)
+        }
+    }
+}
+
+/// JSON Schema of the configuration file for completion and validation in editors. Keep in sync
+/// with [`Config`], other properties are taken as command line options.
+pub fn schema() -> serde_json::Value {
+    let string = json!({ "type": "string" });
+    let provider = |name: &str, properties: serde_json::Value, required: &[&str]| {
+        let mut properties = properties;
+        properties["type"] = json!({ "const": name });
+        let mut required = required.to_vec();
+        required.insert(0, "type");
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+            "additionalProperties": false,
+        })
+    };
+    let records = json!({
+        "type": "array",
+        "items": {
+            "type": "object",
+            "properties": {
+                "name": string,
+                "type": { "enum": ["A", "AAAA"], "description": "Publish only an A or only an AAAA record for the name, overriding --ip-version" },
+                "enabled": { "type": "boolean", "default": true, "description": "Give false to leave the record alone without deleting its configuration" },
+                "expires_after": { "type": "string", "description": "Delete the record if the host hasn't reported its IP address within this long e.g. 2h" },
+                "address": { "enum": ["public", "local"], "default": "public" },
+                "provider": { "type": "string", "description": "Provider type e.g. powerdns, or name in providers, instead of Cloudflare" },
+                "zone": { "type": "string", "description": "Zone of the record at the provider, the zone given on command line if omitted" },
+                "proxied": { "type": "boolean", "description": "Proxy through Cloudflare or not, overriding --proxied" },
+                "ttl": { "anyOf": [{ "const": 1 }, { "type": "integer", "minimum": 60, "maximum": 86400 }], "description": "TTL in seconds on Cloudflare, 1 for automatic, overriding --ttl" },
+                "group": { "type": "string", "description": "Group in groups whose schedule updates the record too" },
+            },
+            "required": ["name"],
+            "additionalProperties": false,
+        },
+    });
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "cdu configuration",
+        "type": "object",
+        "properties": {
+            "providers": {
+                "description": "Providers by name with their credentials, ${VAR} in values is replaced with the environment variable",
+                "type": "object",
+                "additionalProperties": {
+                    "oneOf": [
+                        provider("powerdns", json!({
+                            "url": { "type": "string", "description": "URL of PowerDNS Authoritative HTTP API e.g. http://127.0.0.1:8081" },
+                            "api_key": string,
+                            "server_id": { "type": "string", "default": "localhost" },
+                        }), &["url", "api_key"]),
+                        provider("rfc2136", json!({
+                            "server": { "type": "string", "description": "Address of the primary name server e.g. 192.0.2.53:53" },
+                            "key_name": string,
+                            "key_secret": { "type": "string", "description": "Base64 secret of TSIG key" },
+                            "key_algorithm": { "enum": ["hmac-sha256", "hmac-sha512"], "default": "hmac-sha256" },
+                        }), &["server", "key_name", "key_secret"]),
+                        provider("gcp", json!({
+                            "credentials": { "type": "string", "description": "Service account key file, the service account of the instance if omitted" },
+                            "project": string,
+                        }), &[]),
+                        provider("azure", json!({
+                            "subscription_id": string,
+                            "resource_group": string,
+                            "tenant_id": string,
+                            "client_id": string,
+                            "client_secret": string,
+                        }), &["subscription_id", "resource_group", "tenant_id", "client_id", "client_secret"]),
+                        provider("ovh", json!({
+                            "endpoint": { "type": "string", "default": ovh_endpoint() },
+                            "application_key": string,
+                            "application_secret": string,
+                            "consumer_key": string,
+                        }), &["application_key", "application_secret", "consumer_key"]),
+                    ],
+                },
+            },
+            "tokens": {
+                "description": "Cloudflare tokens by zone, instead of --token for records in the zone, ${VAR} in values is replaced with the environment variable",
+                "type": "object",
+                "additionalProperties": string,
+            },
+            "groups": {
+                "description": "Groups of records by name, updated on schedules of their own besides --cron or --every",
+                "type": "object",
+                "additionalProperties": {
+                    "type": "object",
+                    "properties": {
+                        "cron": { "type": "string", "description": "Cron expression in the timezone of --cron" },
+                        "every": { "type": "string", "description": "Interval between updates e.g. 1m, instead of cron" },
+                    },
+                    "oneOf": [{ "required": ["cron"] }, { "required": ["every"] }],
+                    "additionalProperties": false,
+                },
+            },
+            // [[record]] tables read better than [[records]] in TOML
+            "records": records,
+            "record": records,
+            "profile": {
+                "description": "Profiles by name selected with --profile, whose options and providers take precedence and whose records are added to the others",
+                "type": "object",
+                "additionalProperties": { "type": "object" },
+            },
+        },
+        "additionalProperties": {
+            "description": "Command line option by long name e.g. cron or cache_seconds, used unless given on command line",
+            "type": ["string", "number", "boolean", "array"],
+        },
+    })
+}
+
+/// Replace `${VAR}` in strings with environment variables, so credentials stay out of the file
+fn interpolate(value: &mut Value) -> anyhow::Result<()> {
+    match value {
+        Value::String(s) => *s = interpolate_str(s)?,
+        Value::Sequence(values) => {
+            for value in values {
+                interpolate(value)?;
+            }
+        }
+        Value::Mapping(mapping) => {
+            for (_, value) in mapping.iter_mut() {
+                interpolate(value)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn interpolate_str(s: &str) -> anyhow::Result<String> {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => bail!("unterminated variable in {:?}", s),
+        };
+        let name = &rest[start + 2..end];
+        match std::env::var(name) {
+            Ok(value) => result.push_str(&value),
+            Err(_) => bail!("environment variable {} is not set", name),
+        }
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
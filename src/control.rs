@@ -0,0 +1,173 @@
+use std::fs::Permissions;
+use std::future::Future;
+use std::net::IpAddr;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
+use std::time::Duration;
+use std::{fmt, io};
+
+use anyhow::bail;
+use log::{debug, info, warn};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::time::timeout;
+
+use crate::park::{ParkSwitch, ParkTarget};
+use crate::trigger::Fired;
+
+/// Command accepted on the control socket
+#[derive(Debug, PartialEq, Eq)]
+pub enum ControlCommand {
+    /// Update DNS records, with the IPv4 address if given
+    Trigger(Option<IpAddr>),
+    /// Switch records to maintenance destination
+    Park(ParkTarget),
+    /// Switch records back to the public IP address
+    Unpark,
+}
+
+impl fmt::Display for ControlCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlCommand::Trigger(Some(ip_address)) => write!(f, "trigger {}", ip_address),
+            ControlCommand::Trigger(None) => write!(f, "trigger"),
+            ControlCommand::Park(target) => write!(f, "park {}", target),
+            ControlCommand::Unpark => write!(f, "unpark"),
+        }
+    }
+}
+
+/// Time a client is given to send its command before it's dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Accept commands on the control socket and forward them to the daemon loop.
+///
+/// The protocol is line-based: a client sends `trigger`, `trigger <IPv4 address>`,
+/// `park <IPv4 address or name>`, or `unpark`, and the daemon answers with `ok` or
+/// `error <reason>`. Parking and unparking update DNS records right away. The socket is bound
+/// right away and only accessible by its owner, commands are accepted by the returned future.
+pub(crate) fn listen(
+    path: &Path,
+    tx: UnboundedSender<Fired>,
+    park: ParkSwitch,
+) -> anyhow::Result<impl Future<Output = anyhow::Result<()>>> {
+    // remove socket left behind by previous process, but nothing else
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => bail!(
+            "control socket path exists and is not a socket: {}",
+            path.display()
+        ),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, Permissions::from_mode(0o600))?;
+    debug!("listen on control socket {}", path.display());
+    Ok(accept(listener, tx, park))
+}
+
+async fn accept(
+    listener: UnixListener,
+    tx: UnboundedSender<Fired>,
+    park: ParkSwitch,
+) -> anyhow::Result<()> {
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("failed to accept client on control socket: {}", e);
+                    continue;
+                }
+            },
+            // daemon loop is gone
+            _ = tx.closed() => return Ok(()),
+        };
+        let (tx, park) = (tx.clone(), park.clone());
+        // a slow or broken client holds neither the listener nor other clients
+        tokio::spawn(async move {
+            if let Err(e) = serve(stream, tx, park).await {
+                warn!("control socket client failed: {}", e);
+            }
+        });
+    }
+}
+
+async fn serve(
+    stream: UnixStream,
+    tx: UnboundedSender<Fired>,
+    park: ParkSwitch,
+) -> anyhow::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    match timeout(READ_TIMEOUT, BufReader::new(reader).read_line(&mut line)).await {
+        Ok(read) => read?,
+        Err(_) => bail!(
+            "no command within {}",
+            humantime::format_duration(READ_TIMEOUT)
+        ),
+    };
+    let reply = match parse_command(line.trim()) {
+        Ok(command) => {
+            let (reason, ip_address) = match command {
+                ControlCommand::Trigger(ip_address) => ("control socket".to_string(), ip_address),
+                ControlCommand::Park(target) => {
+                    info!("park DNS records at {}", target);
+                    let reason = format!("park at {} via control socket", target);
+                    *park.lock().unwrap() = Some(target);
+                    (reason, None)
+                }
+                ControlCommand::Unpark => {
+                    info!("unpark DNS records");
+                    *park.lock().unwrap() = None;
+                    ("unpark via control socket".to_string(), None)
+                }
+            };
+            let fired = Fired {
+                reason,
+                ip_address,
+                group: None,
+            };
+            match fired.enqueue(&tx) {
+                Ok(_) => "ok".to_string(),
+                Err(_) => "error daemon is shutting down".to_string(),
+            }
+        }
+        Err(e) => {
+            warn!("invalid command on control socket: {}", e);
+            format!("error {}", e)
+        }
+    };
+    writer.write_all(format!("{}\n", reply).as_bytes()).await?;
+    Ok(())
+}
+
+/// Send command to the daemon listening on the control socket
+pub(crate) async fn send_command(path: &Path, command: &ControlCommand) -> anyhow::Result<()> {
+    let mut stream = UnixStream::connect(path).await?;
+    stream
+        .write_all(format!("{}\n", command).as_bytes())
+        .await?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).await?;
+    match reply.trim() {
+        "ok" => Ok(()),
+        reply => bail!("daemon rejected {}: {}", command, reply),
+    }
+}
+
+/// Parse control socket command
+pub fn parse_command(line: &str) -> anyhow::Result<ControlCommand> {
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some("trigger"), None, None) => Ok(ControlCommand::Trigger(None)),
+        (Some("trigger"), Some(ip_address), None) => {
+            Ok(ControlCommand::Trigger(Some(ip_address.parse()?)))
+        }
+        (Some("park"), Some(target), None) => Ok(ControlCommand::Park(target.parse()?)),
+        (Some("unpark"), None, None) => Ok(ControlCommand::Unpark),
+        _ => bail!("unknown command: {}", line),
+    }
+}
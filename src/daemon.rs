@@ -0,0 +1,291 @@
+use std::fmt;
+use std::future::Future;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use cloudflare::framework::response::ApiFailure;
+use futures::future::BoxFuture;
+use log::{debug, error, info, warn};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::Instant;
+use tokio_retry::strategy::{jitter, ExponentialBackoff};
+
+use crate::metrics::METRICS;
+use crate::{Cdu, ErrorDedup, Event, Fired, PublicIPError};
+
+/// Something the daemon runs each time a trigger fires
+pub trait Runner: Send + Sync {
+    /// Update DNS records with the given public IPv4 address, detect it if not given
+    fn run(&self, ip_address: Option<IpAddr>) -> BoxFuture<'_, anyhow::Result<()>>;
+
+    /// Update DNS records of the group only, all of them if none
+    fn run_group(
+        &self,
+        ip_address: Option<IpAddr>,
+        _group: Option<String>,
+    ) -> BoxFuture<'_, anyhow::Result<()>> {
+        self.run(ip_address)
+    }
+
+    /// Time to wait before running again once rate limited, none if not rate limited
+    fn rate_limited_for(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Notify subscribers of the event
+    fn emit(&self, _event: Event) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+
+    /// Deliver pending events and persist what's left to persist before the daemon exits
+    fn flush(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl Runner for Cdu {
+    fn run(&self, ip_address: Option<IpAddr>) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(self.run_with_ip_address(ip_address))
+    }
+
+    fn run_group(
+        &self,
+        ip_address: Option<IpAddr>,
+        group: Option<String>,
+    ) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async move {
+            match group {
+                Some(group) => self.for_group(&group).run_with_ip_address(ip_address).await,
+                None => self.run_with_ip_address(ip_address).await,
+            }
+        })
+    }
+
+    fn rate_limited_for(&self) -> Option<Duration> {
+        Cdu::rate_limited_for(self)
+    }
+
+    fn emit(&self, event: Event) -> BoxFuture<'_, ()> {
+        Box::pin(Cdu::emit(self, event))
+    }
+
+    fn flush(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async move {
+            self.flush_events().await;
+            self.flush_state()
+        })
+    }
+}
+
+/// Retries of a run or a record failed with transient errors
+#[derive(Clone, Copy)]
+pub struct RetryPolicy {
+    /// First delay in milliseconds, delays grow as its powers
+    pub base_millis: u64,
+    pub retries: usize,
+    /// Ceiling of delays in milliseconds
+    pub max_delay_millis: u64,
+    /// Randomize delays, disable for deterministic tests
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            base_millis: 10,
+            retries: 3,
+            max_delay_millis: 30_000,
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub(crate) fn strategy(&self) -> impl Iterator<Item = Duration> {
+        let with_jitter = self.jitter;
+        ExponentialBackoff::from_millis(self.base_millis)
+            .max_delay(Duration::from_millis(self.max_delay_millis))
+            .map(move |d| if with_jitter { jitter(d) } else { d })
+            .take(self.retries)
+    }
+}
+
+/// Retries shared by the records of a run, so many records failing at once don't multiply into
+/// many more requests
+pub(crate) struct RetryBudget(AtomicUsize);
+
+impl RetryBudget {
+    pub(crate) fn new(retries: usize) -> Self {
+        Self(AtomicUsize::new(retries))
+    }
+
+    /// Take a retry from the budget, false once it's spent
+    pub(crate) fn take(&self) -> bool {
+        self.0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok()
+    }
+}
+
+/// Failure retried within the run as far as it allows, not worth retrying the run for
+#[derive(Debug)]
+pub(crate) struct RetriesSpent(pub(crate) anyhow::Error);
+
+impl fmt::Display for RetriesSpent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#}", self.0)
+    }
+}
+
+impl std::error::Error for RetriesSpent {}
+
+/// Errors worth retrying e.g. network failures, as opposed to misconfiguration
+pub fn is_transient(e: &anyhow::Error) -> bool {
+    e.is::<ApiFailure>() || e.is::<PublicIPError>()
+}
+
+/// Run whenever a trigger fires, until every trigger is gone or shutdown is requested
+pub struct Daemon {
+    runner: Arc<dyn Runner>,
+    retry: RetryPolicy,
+    /// How long a run in progress may take to finish on shutdown
+    shutdown_timeout: Duration,
+}
+
+impl Daemon {
+    pub fn new(runner: Arc<dyn Runner>) -> Self {
+        Self {
+            runner,
+            retry: RetryPolicy::default(),
+            shutdown_timeout: Duration::from_secs(30),
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = shutdown_timeout;
+        self
+    }
+
+    pub async fn run(&self, rx: UnboundedReceiver<Fired>) -> anyhow::Result<()> {
+        self.run_until(rx, futures::future::pending()).await
+    }
+
+    /// Run until every trigger is gone, or the shutdown future completes e.g. on SIGTERM. Runs
+    /// aren't started after shutdown is requested, and the run in progress is given the shutdown
+    /// timeout to finish before the runner is flushed.
+    pub async fn run_until(
+        &self,
+        mut rx: UnboundedReceiver<Fired>,
+        shutdown: impl Future<Output = ()>,
+    ) -> anyhow::Result<()> {
+        tokio::pin!(shutdown);
+        let mut errors = ErrorDedup::default();
+        loop {
+            let mut fired = tokio::select! {
+                fired = rx.recv() => match fired {
+                    Some(fired) => fired,
+                    None => break,
+                },
+                _ = &mut shutdown => break,
+            };
+            // at most one run is pending, triggers fired meanwhile join it
+            let mut coalesced = 0;
+            while let Ok(next) = rx.try_recv() {
+                fired = coalesce(fired, next);
+                coalesced += 1;
+            }
+            // queued with `Fired::enqueue`, picked up by this run
+            METRICS.queue_depth.sub(coalesced as i64 + 1);
+            if coalesced > 0 {
+                debug!("coalesced {} pending triggers", coalesced);
+                METRICS.triggers_coalesced.inc_by(coalesced);
+            }
+
+            info!("update DNS records, triggered by {}", fired.reason);
+            self.runner
+                .emit(Event::RunStarted {
+                    reason: fired.reason.clone(),
+                })
+                .await;
+
+            let instant = Instant::now();
+            let run = tokio_retry::RetryIf::start(
+                self.retry.strategy(),
+                || async {
+                    // retries wait out the rate limit too, besides their own delays
+                    if let Some(delay) = self.runner.rate_limited_for() {
+                        debug!("rate limited, run in {}s", delay.as_secs());
+                        tokio::time::sleep(delay).await;
+                    }
+                    self.runner
+                        .run_group(fired.ip_address, fired.group.clone())
+                        .await
+                },
+                is_transient,
+            );
+            tokio::pin!(run);
+            tokio::select! {
+                res = &mut run => self.finish(res, instant, &mut errors).await,
+                _ = &mut shutdown => {
+                    info!(
+                        "shutting down once the run in progress finishes, within {}s",
+                        self.shutdown_timeout.as_secs()
+                    );
+                    match tokio::time::timeout(self.shutdown_timeout, run).await {
+                        Ok(res) => self.finish(res, instant, &mut errors).await,
+                        Err(_) => warn!(
+                            "run in progress abandoned after {}s",
+                            self.shutdown_timeout.as_secs()
+                        ),
+                    }
+                    break;
+                }
+            }
+        }
+        if let Err(e) = self.runner.flush().await {
+            error!("failed to flush state: {:#}", e);
+        }
+        info!("shut down");
+        Ok(())
+    }
+
+    /// Count and report the outcome of a run
+    async fn finish(&self, res: anyhow::Result<()>, instant: Instant, errors: &mut ErrorDedup) {
+        match res {
+            Ok(_) => {
+                self.runner.emit(Event::RunSucceeded).await;
+                errors.success();
+                let duration = Instant::now() - instant;
+                info!("done in {}ms", duration.as_millis());
+            }
+            Err(e) => {
+                self.runner
+                    .emit(Event::RunFailed {
+                        error: format!("{:#}", e),
+                    })
+                    .await;
+                METRICS.errors.inc();
+                errors.error(&e);
+            }
+        }
+    }
+}
+
+/// Merge two triggers into one run, the later IP address wins
+fn coalesce(pending: Fired, next: Fired) -> Fired {
+    Fired {
+        reason: format!("{}, {}", pending.reason, next.reason),
+        ip_address: next.ip_address.or(pending.ip_address),
+        // all records unless both are for the same group
+        group: pending
+            .group
+            .filter(|group| next.group.as_ref() == Some(group)),
+    }
+}
@@ -0,0 +1,70 @@
+use std::time::{Duration, Instant};
+
+use log::{error, info};
+
+const SUMMARY_WINDOW: u64 = 3600;
+
+/// Collapse the same error repeating every cycle into periodic summaries
+pub struct ErrorDedup {
+    window: Duration,
+    last: Option<String>,
+    since: Instant,
+    repeated: u64,
+}
+
+impl Default for ErrorDedup {
+    fn default() -> Self {
+        Self::with_window(Duration::from_secs(SUMMARY_WINDOW))
+    }
+}
+
+impl ErrorDedup {
+    /// Summarize repeated errors once per window instead of once an hour
+    pub fn with_window(window: Duration) -> Self {
+        Self {
+            window,
+            last: None,
+            since: Instant::now(),
+            repeated: 0,
+        }
+    }
+
+    /// Log the error unless it repeats the previous one, in which case it's counted and
+    /// summarized once per window
+    pub fn error(&mut self, e: &anyhow::Error) {
+        let message = format!("{:#}", e);
+        if self.last.as_ref() == Some(&message) {
+            self.repeated += 1;
+            if self.since.elapsed() >= self.window {
+                self.summarize();
+            }
+            return;
+        }
+        self.summarize();
+        error!("{}", message);
+        self.last = Some(message);
+    }
+
+    /// Close the current streak of repeated errors
+    pub fn success(&mut self) {
+        self.summarize();
+        if self.last.take().is_some() {
+            info!("recovered from error");
+        }
+    }
+
+    fn summarize(&mut self) {
+        if let Some(ref message) = self.last {
+            if self.repeated > 0 {
+                error!(
+                    "error repeated {} times in the last {}m: {}",
+                    self.repeated,
+                    self.since.elapsed().as_secs() / 60,
+                    message
+                );
+            }
+        }
+        self.repeated = 0;
+        self.since = Instant::now();
+    }
+}
@@ -0,0 +1,48 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use anyhow::bail;
+
+/// Fork into the background and return in the child, detached from the terminal in a new
+/// session. Standard input reads nothing and output is appended to the log file, or discarded.
+/// The PID file, if locked, is written before the parent exits, so it's there once the command
+/// returns, and stays locked by the child.
+pub(crate) fn detach(log_file: Option<&Path>) -> anyhow::Result<()> {
+    // opened before forking so errors are reported on the terminal
+    let null = File::open("/dev/null")?;
+    let log = match log_file {
+        Some(path) => OpenOptions::new().create(true).append(true).open(path)?,
+        None => OpenOptions::new().write(true).open("/dev/null")?,
+    };
+
+    // SAFETY: called before the runtime starts any threads
+    let pid = unsafe { libc::fork() };
+    if pid == -1 {
+        bail!("failed to fork: {}", io::Error::last_os_error());
+    }
+    if pid > 0 {
+        if let Err(e) = crate::pidfile::replace(pid as u32) {
+            // SAFETY: no memory is involved
+            unsafe { libc::kill(pid, libc::SIGTERM) };
+            bail!("failed to write PID file: {}", e);
+        }
+        std::process::exit(0);
+    }
+
+    // SAFETY: no memory is involved
+    if unsafe { libc::setsid() } == -1 {
+        bail!(
+            "failed to start new session: {}",
+            io::Error::last_os_error()
+        );
+    }
+    for &(file, fd) in &[(&null, 0), (&log, 1), (&log, 2)] {
+        // SAFETY: both file descriptors are open
+        if unsafe { libc::dup2(file.as_raw_fd(), fd) } == -1 {
+            bail!("failed to redirect output: {}", io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
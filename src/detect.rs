@@ -0,0 +1,136 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use log::debug;
+use public_ip::http::{self, ExtractMethod};
+use public_ip::{Resolver, Version};
+
+use crate::socks::ProxyUrl;
+
+/// HTTP echo services telling the public IP address, tried in order. The address family is
+/// picked by connecting over it.
+const SOURCES: &[(&str, &dyn Resolver<'static>)] = &[
+    ("ipify", http::HTTP_IPIFY_ORG),
+    (
+        "icanhazip",
+        &http::Resolver::new_static("http://icanhazip.com", ExtractMethod::PlainText),
+    ),
+    (
+        "ident.me",
+        &http::Resolver::new_static("http://ident.me", ExtractMethod::PlainText),
+    ),
+];
+
+/// Echo services over HTTPS for IPv4 and IPv6 respectively, tried in order through proxies. The
+/// address family is picked by host name, as the proxy connects rather than this host.
+const PROXIED_SOURCES: &[(&str, &str, &str)] = &[
+    ("ipify", "https://api4.ipify.org", "https://api6.ipify.org"),
+    (
+        "icanhazip",
+        "https://ipv4.icanhazip.com",
+        "https://ipv6.icanhazip.com",
+    ),
+    ("ident.me", "https://v4.ident.me", "https://v6.ident.me"),
+];
+
+/// How long to wait for each source of the public IP address, and how often to retry it before
+/// moving on to the next one, so one slow source doesn't hold up detection, and the proxy to
+/// reach them through if any
+#[derive(Clone)]
+pub(crate) struct DetectPolicy {
+    pub(crate) timeout: Duration,
+    pub(crate) retries: u32,
+    pub(crate) proxy: Option<ProxyUrl>,
+}
+
+/// Public IP address of the version from the first source telling it, none if all failed
+pub(crate) async fn detect(version: Version, policy: DetectPolicy) -> Option<IpAddr> {
+    if let Some(ref proxy) = policy.proxy {
+        return detect_through(proxy, version, &policy).await;
+    }
+    for &(name, resolver) in SOURCES {
+        for attempt in 1..=policy.retries + 1 {
+            let lookup = public_ip::addr_with(resolver, version);
+            match tokio::time::timeout(policy.timeout, lookup).await {
+                Ok(Some(ip_address)) => {
+                    debug!("public IP address from {}: {}", name, ip_address);
+                    return Some(ip_address);
+                }
+                Ok(None) => debug!(
+                    "{} failed to tell public IP address (attempt {})",
+                    name, attempt
+                ),
+                Err(_) => debug!(
+                    "{} timed out after {}ms (attempt {})",
+                    name,
+                    policy.timeout.as_millis(),
+                    attempt
+                ),
+            }
+        }
+    }
+    None
+}
+
+/// Public IP address of the version as seen by echo services through the proxy
+async fn detect_through(
+    proxy: &ProxyUrl,
+    version: Version,
+    policy: &DetectPolicy,
+) -> Option<IpAddr> {
+    let client = match proxy
+        .reqwest_proxy()
+        .and_then(|proxy| Ok(reqwest::Client::builder().proxy(proxy).build()?))
+    {
+        Ok(client) => client,
+        Err(e) => {
+            debug!(
+                "failed to detect public IP address through {}: {:#}",
+                proxy, e
+            );
+            return None;
+        }
+    };
+    for &(name, ipv4, ipv6) in PROXIED_SOURCES {
+        let url = match version {
+            Version::V6 => ipv6,
+            _ => ipv4,
+        };
+        for attempt in 1..=policy.retries + 1 {
+            let lookup = async {
+                let body = client
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await?;
+                Ok::<_, anyhow::Error>(body.trim().parse::<IpAddr>()?)
+            };
+            match tokio::time::timeout(policy.timeout, lookup).await {
+                Ok(Ok(ip_address)) if ip_address.is_ipv6() == matches!(version, Version::V6) => {
+                    debug!(
+                        "public IP address from {} through {}: {}",
+                        name, proxy, ip_address
+                    );
+                    return Some(ip_address);
+                }
+                Ok(Ok(ip_address)) => debug!(
+                    "{} told public IP address of the other family: {}",
+                    name, ip_address
+                ),
+                Ok(Err(e)) => debug!(
+                    "{} failed to tell public IP address through {} (attempt {}): {:#}",
+                    name, proxy, attempt, e
+                ),
+                Err(_) => debug!(
+                    "{} timed out after {}ms (attempt {})",
+                    name,
+                    policy.timeout.as_millis(),
+                    attempt
+                ),
+            }
+        }
+    }
+    None
+}
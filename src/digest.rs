@@ -0,0 +1,53 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::event::{Event, Subscriber};
+
+/// Subscriber wrapper collecting events over a window e.g. an hour, and passing them on as one
+/// [`Event::Digest`], against alert fatigue from many records or a flappy link.
+/// The digest is sent with the first event after the window closes.
+pub struct Digest<S> {
+    inner: S,
+    window: Duration,
+    pending: Mutex<Pending>,
+}
+
+struct Pending {
+    since: Option<Instant>,
+    events: Vec<Event>,
+}
+
+impl<S: Subscriber> Digest<S> {
+    pub fn new(inner: S, window: Duration) -> Self {
+        Self {
+            inner,
+            window,
+            pending: Mutex::new(Pending {
+                since: None,
+                events: vec![],
+            }),
+        }
+    }
+}
+
+impl<S: Subscriber> Subscriber for Digest<S> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn notify(&self, event: &Event) -> anyhow::Result<()> {
+        let events = {
+            let mut pending = self.pending.lock().unwrap();
+            let since = *pending.since.get_or_insert_with(Instant::now);
+            pending.events.push(event.clone());
+            if since.elapsed() < self.window {
+                return Ok(());
+            }
+            pending.since = None;
+            std::mem::take(&mut pending.events)
+        };
+        self.inner.notify(&Event::Digest { events })
+    }
+}
@@ -0,0 +1,368 @@
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use log::{debug, warn};
+use once_cell::sync::OnceCell;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::sync::{broadcast, oneshot};
+use tokio::task::spawn_blocking;
+use tokio::time::timeout;
+
+use crate::metrics::METRICS;
+use crate::quiet_hours::local_time;
+use crate::{HistoryEntry, QuietHours};
+
+const BROADCAST_CAPACITY: usize = 16;
+
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Something that happened in cdu, published on the event bus
+#[derive(Clone, Debug)]
+pub enum Event {
+    RunStarted {
+        reason: String,
+    },
+    /// Public IP address detected or given by the trigger, on every run
+    IpDetected {
+        current: IpAddr,
+    },
+    /// Public IP address differs from the last run, or there was no last run
+    IpChanged {
+        previous: Option<IpAddr>,
+        current: IpAddr,
+        /// How long the previous address had been in use, unknown without a previous run
+        stable_for: Option<Duration>,
+    },
+    RecordUpdated {
+        record_name: String,
+        old_content: Option<String>,
+        new_content: String,
+        /// Time from detecting the IP address to the record updated
+        latency: Duration,
+    },
+    /// Record differs from the public IP address in observe mode
+    DriftDetected {
+        record_name: String,
+        content: String,
+        expected: String,
+    },
+    /// State of the run remembered for the next run, along with changes of the public IP
+    /// addresses published
+    RunRecorded {
+        changes: Vec<String>,
+        history: Vec<HistoryEntry>,
+    },
+    RunSucceeded,
+    RunFailed {
+        error: String,
+    },
+    /// Events collected over a window, see [`crate::Digest`]
+    Digest {
+        events: Vec<Event>,
+    },
+}
+
+impl Event {
+    /// Short machine-readable name
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::RunStarted { .. } => "run_started",
+            Event::IpDetected { .. } => "ip_detected",
+            Event::IpChanged { .. } => "ip_changed",
+            Event::RecordUpdated { .. } => "record_updated",
+            Event::DriftDetected { .. } => "drift_detected",
+            Event::RunRecorded { .. } => "run_recorded",
+            Event::RunSucceeded => "run_succeeded",
+            Event::RunFailed { .. } => "run_failed",
+            Event::Digest { .. } => "digest",
+        }
+    }
+
+    /// Delivered even during quiet hours
+    pub fn is_critical(&self) -> bool {
+        matches!(self, Event::RunFailed { .. })
+    }
+
+    /// Human-readable details
+    pub fn message(&self) -> String {
+        match self {
+            Event::RunStarted { reason } => format!("triggered by {}", reason),
+            Event::IpDetected { current } => current.to_string(),
+            Event::IpChanged {
+                previous,
+                current,
+                stable_for,
+            } => match (previous, stable_for) {
+                (Some(previous), Some(stable_for)) => {
+                    // to the second, as detection runs on schedule anyway
+                    let stable_for = Duration::from_secs(stable_for.as_secs());
+                    format!(
+                        "{} → {}, previous address stable for {}",
+                        previous,
+                        current,
+                        humantime::format_duration(stable_for)
+                    )
+                }
+                (Some(previous), None) => format!("{} → {}", previous, current),
+                (None, _) => current.to_string(),
+            },
+            Event::RecordUpdated {
+                record_name,
+                old_content,
+                new_content,
+                latency,
+            } => format!(
+                "{} {} → {}, {}ms after detection",
+                record_name,
+                old_content.as_deref().unwrap_or("?"),
+                new_content,
+                latency.as_millis()
+            ),
+            Event::DriftDetected {
+                record_name,
+                content,
+                expected,
+            } => format!("{} is {} but should be {}", record_name, content, expected),
+            Event::RunRecorded { changes, .. } if changes.is_empty() => "no changes".to_string(),
+            Event::RunRecorded { changes, .. } => changes.join(", "),
+            Event::RunSucceeded => String::new(),
+            Event::RunFailed { error } => error.clone(),
+            Event::Digest { events } => {
+                let count = |kind| events.iter().filter(|e| e.kind() == kind).count();
+                let mut lines = vec![format!(
+                    "{} runs succeeded, {} failed",
+                    count("run_succeeded"),
+                    count("run_failed")
+                )];
+                // events of every run are only counted
+                lines.extend(
+                    events
+                        .iter()
+                        .filter(|e| {
+                            !matches!(
+                                e,
+                                Event::RunStarted { .. }
+                                    | Event::IpDetected { .. }
+                                    | Event::RunRecorded { .. }
+                                    | Event::RunSucceeded
+                            )
+                        })
+                        .map(|e| format!("{}: {}", e.kind(), e.message())),
+                );
+                lines.join("\n")
+            }
+        }
+    }
+}
+
+/// Sink notified of every event e.g. notifier, metrics, hook. Each subscriber is notified in
+/// order by a task of its own, so runs never wait for it. Each notification runs in a blocking
+/// task with a timeout, failures are retried once and timeouts aren't. Failures are logged and
+/// counted, they never fail the run unless the subscriber is required. Panics are caught only
+/// where panics unwind, release builds abort on panic.
+pub trait Subscriber: Send + Sync {
+    /// Name in logs and metrics
+    fn name(&self) -> &str {
+        "subscriber"
+    }
+
+    /// Notified in place, failing the run if it fails to handle an event of the run, e.g. files
+    /// other programs or the next run read
+    fn required(&self) -> bool {
+        false
+    }
+
+    /// Non-critical events are held back during quiet hours, never for required subscribers
+    fn deferrable(&self) -> bool {
+        !self.required()
+    }
+
+    fn notify(&self, event: &Event) -> anyhow::Result<()>;
+}
+
+/// Deliver events to subscribers in order, and to async listeners over a broadcast channel.
+/// Clones deliver to the same subscribers and listeners.
+#[derive(Clone)]
+pub(crate) struct EventBus {
+    subscribers: Vec<Arc<Queue>>,
+    broadcast: broadcast::Sender<Event>,
+    quiet_hours: Option<QuietHours>,
+    /// Non-critical events held back during quiet hours, delivered with the first event after
+    deferred: Arc<Mutex<Vec<Event>>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self {
+            subscribers: vec![],
+            broadcast: broadcast::channel(BROADCAST_CAPACITY).0,
+            quiet_hours: None,
+            deferred: Arc::new(Mutex::new(vec![])),
+        }
+    }
+}
+
+impl EventBus {
+    pub(crate) fn add_subscriber(&mut self, subscriber: Box<dyn Subscriber>) {
+        self.subscribers.push(Arc::new(Queue {
+            subscriber: Arc::from(subscriber),
+            tx: OnceCell::new(),
+        }));
+    }
+
+    pub(crate) fn set_quiet_hours(&mut self, quiet_hours: Option<QuietHours>) {
+        self.quiet_hours = quiet_hours;
+    }
+
+    /// Notify subscribers of the event, failed if a required subscriber fails to handle it.
+    /// Other subscribers are notified in the background.
+    pub(crate) async fn emit(&self, event: Event) -> anyhow::Result<()> {
+        let now = local_time();
+        let quiet = matches!(self.quiet_hours, Some(quiet_hours) if quiet_hours.contains(now));
+        let defer = quiet
+            && !event.is_critical()
+            && self.subscribers.iter().any(|q| q.subscriber.deferrable());
+        let deferred = if quiet {
+            vec![]
+        } else {
+            std::mem::take(&mut *self.deferred.lock().unwrap())
+        };
+        if !deferred.is_empty() {
+            debug!("deliver {} events deferred in quiet hours", deferred.len());
+        }
+        let mut res = Ok(());
+        for queue in &self.subscribers {
+            let subscriber = &queue.subscriber;
+            if subscriber.required() {
+                if let Err(e) = subscriber.notify(&event) {
+                    let e = e.context(format!(
+                        "{} failed to handle {}",
+                        subscriber.name(),
+                        event.kind()
+                    ));
+                    res = res.and(Err(e));
+                }
+                continue;
+            }
+            if subscriber.deferrable() {
+                for event in &deferred {
+                    queue.push(Queued::Event(event.clone()));
+                }
+                if defer {
+                    continue;
+                }
+            }
+            queue.push(Queued::Event(event.clone()));
+        }
+        if defer {
+            debug!("defer {} in quiet hours", event.kind());
+            self.deferred.lock().unwrap().push(event.clone());
+        }
+        // no async listener is not an error
+        let _ = self.broadcast.send(event);
+        res
+    }
+
+    /// Wait until events emitted so far are delivered to subscribers or given up on, e.g. before
+    /// exiting
+    pub(crate) async fn flush(&self) {
+        let mut delivered = vec![];
+        for queue in &self.subscribers {
+            let (tx, rx) = oneshot::channel();
+            if queue.started() {
+                queue.push(Queued::Flush(tx));
+                delivered.push(rx);
+            }
+        }
+        for rx in delivered {
+            let _ = rx.await;
+        }
+    }
+
+    /// Listen asynchronously, lagging listeners miss events
+    pub(crate) fn listen(&self) -> broadcast::Receiver<Event> {
+        self.broadcast.subscribe()
+    }
+}
+
+enum Queued {
+    Event(Event),
+    /// Answered once events queued before are delivered
+    Flush(oneshot::Sender<()>),
+}
+
+/// Events of a subscriber delivered in order by a task of its own
+struct Queue {
+    subscriber: Arc<dyn Subscriber>,
+    /// Task is started with the first event, as subscribers may be added outside of the runtime
+    tx: OnceCell<UnboundedSender<Queued>>,
+}
+
+impl Queue {
+    fn started(&self) -> bool {
+        self.tx.get().is_some()
+    }
+
+    fn push(&self, queued: Queued) {
+        let tx = self.tx.get_or_init(|| {
+            let (tx, mut rx) = unbounded_channel();
+            let subscriber = self.subscriber.clone();
+            tokio::spawn(async move {
+                while let Some(queued) = rx.recv().await {
+                    match queued {
+                        Queued::Event(event) => deliver(subscriber.clone(), &event).await,
+                        Queued::Flush(tx) => {
+                            let _ = tx.send(());
+                        }
+                    }
+                }
+            });
+            tx
+        });
+        // task is gone only along with the runtime
+        let _ = tx.send(queued);
+    }
+}
+
+/// Notify the subscriber isolated from the run, so a failing or hanging subscriber e.g. a
+/// notifier whose webhook is down never fails or stalls DNS updates
+async fn deliver(subscriber: Arc<dyn Subscriber>, event: &Event) {
+    for attempt in 1..=2 {
+        let task = {
+            let subscriber = subscriber.clone();
+            let event = event.clone();
+            spawn_blocking(move || subscriber.notify(&event))
+        };
+        // task keeps running in background after timeout, its result is ignored
+        let (res, retry) = match timeout(NOTIFY_TIMEOUT, task).await {
+            Ok(Ok(res)) => (res, attempt == 1),
+            Ok(Err(e)) => (Err(anyhow!("{}", e)), attempt == 1),
+            // likely to hang again, and still busy with this one
+            Err(_) => (
+                Err(anyhow!("timed out after {}s", NOTIFY_TIMEOUT.as_secs())),
+                false,
+            ),
+        };
+        match res {
+            Ok(_) => return,
+            Err(e) if retry => {
+                debug!("retry {} on {}: {:#}", subscriber.name(), event.kind(), e)
+            }
+            Err(e) => {
+                warn!(
+                    "{} failed to handle {}: {:#}",
+                    subscriber.name(),
+                    event.kind(),
+                    e
+                );
+                METRICS
+                    .notifier_failures
+                    .with_label_values(&[subscriber.name()])
+                    .inc();
+                return;
+            }
+        }
+    }
+}
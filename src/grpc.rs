@@ -0,0 +1,100 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::{Stream, StreamExt};
+use log::debug;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_stream::wrappers::BroadcastStream;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Request, Response, Status};
+
+use crate::trigger::Fired;
+use crate::Cdu;
+
+mod proto {
+    tonic::include_proto!("cdu");
+}
+
+use proto::cdu_server::CduServer;
+use proto::{Event, EventsRequest, StatusReply, StatusRequest, TriggerReply, TriggerRequest};
+
+struct Service {
+    cdu: Arc<Cdu>,
+    tx: UnboundedSender<Fired>,
+}
+
+#[tonic::async_trait]
+impl proto::cdu_server::Cdu for Service {
+    async fn status(&self, _: Request<StatusRequest>) -> Result<Response<StatusReply>, Status> {
+        let state = self.cdu.last_run_state().unwrap_or_default();
+        Ok(Response::new(StatusReply {
+            ip_address: state
+                .ip_address
+                .map(|ip| ip.to_string())
+                .unwrap_or_default(),
+            records: state.records.into_iter().collect(),
+        }))
+    }
+
+    async fn trigger(
+        &self,
+        request: Request<TriggerRequest>,
+    ) -> Result<Response<TriggerReply>, Status> {
+        let ip_address = match request.into_inner().ip_address.as_str() {
+            "" => None,
+            s => Some(
+                s.parse()
+                    .map_err(|e| Status::invalid_argument(format!("{}", e)))?,
+            ),
+        };
+        let fired = Fired {
+            reason: "gRPC".to_string(),
+            ip_address,
+            group: None,
+        };
+        fired
+            .enqueue(&self.tx)
+            .map_err(|_| Status::unavailable("shutting down"))?;
+        Ok(Response::new(TriggerReply {}))
+    }
+
+    type EventsStream = Pin<Box<dyn Stream<Item = Result<Event, Status>> + Send>>;
+
+    // tonic dictates Status as the error type
+    #[allow(clippy::result_large_err)]
+    async fn events(
+        &self,
+        _: Request<EventsRequest>,
+    ) -> Result<Response<Self::EventsStream>, Status> {
+        let stream = BroadcastStream::new(self.cdu.subscribe()).filter_map(|event| async move {
+            // lagging subscribers skip missed events
+            event.ok().map(|event| {
+                Ok(Event {
+                    kind: event.kind().to_string(),
+                    message: event.message(),
+                })
+            })
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// Serve gRPC control and status service. The address is bound right away, requests are served
+/// by the returned future.
+pub fn serve_grpc(
+    addr: SocketAddr,
+    cdu: Arc<Cdu>,
+    tx: UnboundedSender<Fired>,
+) -> anyhow::Result<impl Future<Output = anyhow::Result<()>>> {
+    let incoming = TcpIncoming::new(addr, true, None).map_err(|e| anyhow::anyhow!(e))?;
+    debug!("serve gRPC on {}", addr);
+    Ok(async move {
+        tonic::transport::Server::builder()
+            .add_service(CduServer::new(Service { cdu, tx }))
+            .serve_with_incoming(incoming)
+            .await?;
+        Ok(())
+    })
+}
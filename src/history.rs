@@ -0,0 +1,53 @@
+use std::str::FromStr;
+
+use anyhow::bail;
+
+use crate::store::HistoryEntry;
+
+/// Format of exported history
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistoryFormat {
+    /// Comma-separated values with a header, e.g. for spreadsheets
+    Csv,
+    /// Array of entries
+    Json,
+}
+
+impl FromStr for HistoryFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(HistoryFormat::Csv),
+            "json" => Ok(HistoryFormat::Json),
+            s => bail!("unknown format {}, expect csv or json", s),
+        }
+    }
+}
+
+/// Changes of the public IP address in the format. Each row tells how long the previous address
+/// of the same family had been in use, if it's known from an earlier entry.
+pub fn export_history(entries: &[HistoryEntry], format: HistoryFormat) -> anyhow::Result<String> {
+    match format {
+        HistoryFormat::Json => Ok(serde_json::to_string_pretty(entries)? + "\n"),
+        HistoryFormat::Csv => {
+            let mut csv = "time,previous,current,previous_in_use_seconds\n".to_string();
+            for (i, entry) in entries.iter().enumerate() {
+                let in_use = entries[..i]
+                    .iter()
+                    .rev()
+                    .find(|earlier| Some(earlier.current) == entry.previous)
+                    .map(|earlier| (entry.at - earlier.at).num_seconds().to_string());
+                let previous = entry.previous.map(|ip| ip.to_string());
+                csv.push_str(&format!(
+                    "{},{},{},{}\n",
+                    entry.at.to_rfc3339(),
+                    previous.unwrap_or_default(),
+                    entry.current,
+                    in_use.unwrap_or_default()
+                ));
+            }
+            Ok(csv)
+        }
+    }
+}
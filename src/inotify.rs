@@ -0,0 +1,107 @@
+use std::ffi::{CString, OsString};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use tokio::io::unix::AsyncFd;
+
+/// Written and closed, attributes e.g. mtime changed, renamed, or removed
+const MASK: u32 = libc::IN_CLOSE_WRITE
+    | libc::IN_ATTRIB
+    | libc::IN_MOVED_TO
+    | libc::IN_MOVED_FROM
+    | libc::IN_DELETE;
+
+/// Changes of a file told by inotify. The directory is watched rather than the file, so the file
+/// is still watched once replaced e.g. by editors renaming a new file over it.
+pub(crate) struct FileWatch {
+    fd: AsyncFd<OwnedFd>,
+    name: OsString,
+    buf: Vec<u8>,
+}
+
+impl FileWatch {
+    pub(crate) fn new(path: &Path) -> io::Result<Self> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no file name"))?
+            .to_os_string();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let dir = CString::new(dir.as_os_str().as_bytes())?;
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), MASK) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+            name,
+            buf: vec![0u8; 4096],
+        })
+    }
+
+    /// Wait for the file to change. Fails once the directory is removed, as nothing is told
+    /// afterwards.
+    pub(crate) async fn changed(&mut self) -> io::Result<()> {
+        let Self { fd, name, buf } = self;
+        loop {
+            let mut guard = fd.readable().await?;
+            let res = guard.try_io(|fd| {
+                let n = unsafe {
+                    libc::read(
+                        fd.as_raw_fd(),
+                        buf.as_mut_ptr() as *mut libc::c_void,
+                        buf.len(),
+                    )
+                };
+                if n < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(n as usize)
+            });
+            match res {
+                Ok(Ok(n)) => {
+                    if is_change(&buf[..n], name.as_bytes())? {
+                        return Ok(());
+                    }
+                }
+                Ok(Err(e)) => return Err(e),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+/// Whether any event is about the file, or events were dropped which may be about it
+fn is_change(events: &[u8], name: &[u8]) -> io::Result<bool> {
+    let mut changed = false;
+    let mut pos = 0;
+    // wd, mask, cookie, and length of the name padded with NULs
+    while let Some(header) = events.get(pos..pos + 16) {
+        let mask = u32::from_ne_bytes([header[4], header[5], header[6], header[7]]);
+        let len = u32::from_ne_bytes([header[12], header[13], header[14], header[15]]) as usize;
+        let event_name = events.get(pos + 16..pos + 16 + len).unwrap_or_default();
+        if mask & libc::IN_IGNORED != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "watched directory removed",
+            ));
+        }
+        let trimmed = match event_name.iter().position(|&b| b == 0) {
+            Some(end) => &event_name[..end],
+            None => event_name,
+        };
+        if mask & libc::IN_Q_OVERFLOW != 0 || trimmed == name {
+            changed = true;
+        }
+        pos += 16 + len;
+    }
+    Ok(changed)
+}
@@ -0,0 +1,45 @@
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use log::debug;
+
+use crate::event::{Event, Subscriber};
+use crate::state::write_atomic;
+
+/// Keep files updated with the current public IP addresses for other programs, one per family
+pub(crate) struct IpFile {
+    ipv4: Option<PathBuf>,
+    ipv6: Option<PathBuf>,
+}
+
+impl IpFile {
+    pub(crate) fn new(ipv4: Option<PathBuf>, ipv6: Option<PathBuf>) -> Self {
+        Self { ipv4, ipv6 }
+    }
+}
+
+impl Subscriber for IpFile {
+    fn name(&self) -> &str {
+        "ip file"
+    }
+
+    fn required(&self) -> bool {
+        true
+    }
+
+    fn notify(&self, event: &Event) -> anyhow::Result<()> {
+        if let Event::IpDetected { current } = event {
+            let path = match current {
+                IpAddr::V4(_) => &self.ipv4,
+                IpAddr::V6(_) => &self.ipv6,
+            };
+            if let Some(path) = path {
+                write_atomic(path, format!("{}\n", current).as_bytes())
+                    .with_context(|| format!("failed to write {}", path.display()))?;
+                debug!("public IP address written to {}", path.display());
+            }
+        }
+        Ok(())
+    }
+}
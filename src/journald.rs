@@ -0,0 +1,123 @@
+use std::os::unix::net::UnixDatagram;
+use std::sync::Mutex;
+
+use env_logger::filter::{self, Filter};
+use log::kv::{self, Key, Value, VisitSource};
+use log::{Log, Metadata, Record};
+
+use crate::syslog::severity;
+
+/// Socket of the native protocol of journald
+const SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Logger sending records to journald with fields of their own, filtered by RUST_LOG
+struct Journald {
+    socket: Mutex<UnixDatagram>,
+    filter: Filter,
+}
+
+impl Log for Journald {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.filter.matches(record) {
+            return;
+        }
+        let message = journal_message(record);
+        let mut socket = self.socket.lock().unwrap();
+        if socket.send(&message).is_ok() {
+            return;
+        }
+        // journald may have been restarted, logs are dropped if it's still gone
+        if let Ok(reconnected) = connect() {
+            *socket = reconnected;
+            let _ = socket.send(&message);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Entry sent to journald for the log record. Key-values of the record e.g. `record` and `zone`
+/// of updated records become fields e.g. RECORD and ZONE.
+pub fn journal_message(record: &Record) -> Vec<u8> {
+    let mut message = vec![];
+    field(&mut message, "MESSAGE", &record.args().to_string());
+    field(
+        &mut message,
+        "PRIORITY",
+        &severity(record.level()).to_string(),
+    );
+    field(&mut message, "SYSLOG_IDENTIFIER", "cdu");
+    field(&mut message, "TARGET", record.target());
+    if let Some(file) = record.file() {
+        field(&mut message, "CODE_FILE", file);
+    }
+    if let Some(line) = record.line() {
+        field(&mut message, "CODE_LINE", &line.to_string());
+    }
+    let _ = record.key_values().visit(&mut Fields(&mut message));
+    message
+}
+
+struct Fields<'a>(&'a mut Vec<u8>);
+
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let name = field_name(key.as_str());
+        let value = value.to_string();
+        if !name.is_empty() && !value.is_empty() {
+            field(self.0, &name, &value);
+        }
+        Ok(())
+    }
+}
+
+/// Field names of journald are uppercase letters, digits, and underscores, starting with a letter
+fn field_name(key: &str) -> String {
+    key.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase(),
+            _ => '_',
+        })
+        .skip_while(|c| !c.is_ascii_alphabetic())
+        .collect()
+}
+
+fn field(message: &mut Vec<u8>, name: &str, value: &str) {
+    message.extend_from_slice(name.as_bytes());
+    // values with newlines are prefixed with their length instead
+    if value.contains('\n') {
+        message.push(b'\n');
+        message.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        message.push(b'=');
+    }
+    message.extend_from_slice(value.as_bytes());
+    message.push(b'\n');
+}
+
+fn connect() -> anyhow::Result<UnixDatagram> {
+    let socket = UnixDatagram::unbound()?;
+    socket.connect(SOCKET)?;
+    Ok(socket)
+}
+
+/// Send logs to journald instead of standard error. Connected right away, so the socket is
+/// reachable once sandboxed.
+pub(crate) fn init() -> anyhow::Result<()> {
+    let mut builder = filter::Builder::new();
+    if let Ok(s) = std::env::var("RUST_LOG") {
+        builder.parse(&s);
+    }
+    let filter = builder.build();
+    let socket = connect().map_err(|e| anyhow::anyhow!("journald isn't listening: {}", e))?;
+    log::set_max_level(filter.filter());
+    log::set_boxed_logger(Box::new(Journald {
+        socket: Mutex::new(socket),
+        filter,
+    }))?;
+    Ok(())
+}
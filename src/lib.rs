@@ -1,7 +1,113 @@
+mod address;
+mod api;
+mod badge;
 mod cdu;
+#[cfg(windows)]
+mod command_line;
+mod config;
+#[cfg(unix)]
+mod control;
+mod daemon;
+mod dedup;
+#[cfg(unix)]
+mod detach;
+mod detect;
+mod digest;
 mod error;
+mod event;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod history;
+#[cfg(target_os = "linux")]
+mod inotify;
+mod ip_file;
+#[cfg(target_os = "linux")]
+mod journald;
+mod lint;
+mod logger;
+mod mdns;
+mod metrics;
+mod migration;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod netwatch;
+mod notifier;
 mod opts;
+mod output;
+mod park;
+#[cfg(unix)]
+mod pidfile;
+mod pipeline;
+mod plan;
+#[cfg(unix)]
+mod privileges;
+mod probe;
+mod provider;
+mod quiet_hours;
+mod record;
+mod reload;
+mod report;
+#[cfg(feature = "sandbox")]
+mod sandbox;
+#[cfg(windows)]
+mod service;
+mod signal;
+mod socks;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod starter;
+mod state;
+mod store;
+#[cfg(unix)]
+mod syslog;
+#[cfg(windows)]
+mod task;
+mod tls;
+mod token;
+mod trigger;
+mod webhook;
+mod zone;
 
+pub use crate::address::AddressSource;
+pub use crate::api::retry_after;
+pub use crate::badge::badge;
 pub use crate::cdu::Cdu;
+pub use crate::config::schema as config_schema;
+#[cfg(unix)]
+pub use crate::control::{parse_command as parse_control_command, ControlCommand};
+pub use crate::daemon::{is_transient, Daemon, RetryPolicy, Runner};
+pub use crate::dedup::ErrorDedup;
+pub use crate::digest::Digest;
 pub use crate::error::PublicIPError;
-pub use crate::opts::Opts;
+pub use crate::event::{Event, Subscriber};
+#[cfg(feature = "grpc")]
+pub use crate::grpc::serve_grpc;
+pub use crate::history::{export_history, HistoryFormat};
+#[cfg(target_os = "linux")]
+pub use crate::journald::journal_message;
+pub use crate::logger::init_logger;
+pub use crate::metrics::render_metrics;
+pub use crate::opts::{
+    ApiIpVersion, Command, ConfigCommand, HistoryCommand, IpVersion, LogTarget, LogTimestamps,
+    Opts, Proxied, TokenCommand,
+};
+#[cfg(windows)]
+pub use crate::opts::{ServiceCommand, TaskCommand};
+pub use crate::park::{ParkSwitch, ParkTarget};
+pub use crate::pipeline::{Phase, RecordContext, RunContext, Stage};
+pub use crate::provider::{tsig_sign, Provider, TsigAlgorithm};
+pub use crate::quiet_hours::QuietHours;
+pub use crate::record::{normalize_name, parse_record_names, validate_record_names};
+pub use crate::reload::Reloadable;
+#[cfg(windows)]
+pub use crate::service::run as run_service;
+#[cfg(unix)]
+pub use crate::signal::signals;
+pub use crate::signal::Signal;
+pub use crate::socks::ProxyUrl;
+pub use crate::starter::starter_config;
+pub use crate::store::{HistoryEntry, StoreKind};
+#[cfg(unix)]
+pub use crate::syslog::syslog_message;
+pub use crate::trigger::{normalize_cron, CronTimer, CronTimezone, Fired, Trigger};
+pub use crate::webhook::parse_payload;
+pub use crate::zone::pick_zone;
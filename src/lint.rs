@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use cron::Schedule;
+
+/// Requests Cloudflare allows per client within the window
+pub(crate) const RATE_LIMIT: usize = 1200;
+/// Window of Cloudflare rate limits
+const RATE_WINDOW: Duration = Duration::from_secs(300);
+/// Upcoming runs to look at, enough to find the busiest window of most schedules
+const LOOKAHEAD: usize = 1000;
+
+/// Most runs the interval fires within any window of Cloudflare rate limits
+pub(crate) fn runs_per_window_every(period: Duration) -> usize {
+    let period = period.as_nanos().max(1);
+    // runs at the start of the window and every period after, before it ends
+    RATE_WINDOW.as_nanos().div_ceil(period) as usize
+}
+
+/// Most runs the schedule fires within any window of Cloudflare rate limits
+pub(crate) fn runs_per_window(schedule: &Schedule) -> usize {
+    let times: Vec<_> = schedule.upcoming(Utc).take(LOOKAHEAD).collect();
+    let window = chrono::Duration::from_std(RATE_WINDOW).expect("window in range");
+    times
+        .iter()
+        .enumerate()
+        .map(|(start, time)| {
+            times[start..]
+                .iter()
+                .take_while(|other| **other < *time + window)
+                .count()
+        })
+        .max()
+        .unwrap_or(0)
+}
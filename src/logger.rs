@@ -0,0 +1,46 @@
+use std::io::Write;
+
+use chrono::{Local, SecondsFormat, Utc};
+
+use crate::{LogTarget, LogTimestamps};
+
+/// Initialize logger with pretty_env_logger's format, prefixed with timestamps as configured, or
+/// sending to syslog or journald
+pub fn init_logger(timestamps: &LogTimestamps, target: LogTarget) -> anyhow::Result<()> {
+    match target {
+        LogTarget::Stderr => {}
+        #[cfg(unix)]
+        LogTarget::Syslog => return crate::syslog::init(),
+        #[cfg(target_os = "linux")]
+        LogTarget::Journald => return crate::journald::init(),
+    }
+    if let LogTimestamps::None = timestamps {
+        pretty_env_logger::init();
+        return Ok(());
+    }
+
+    let timestamps = timestamps.clone();
+    let mut builder = pretty_env_logger::formatted_builder();
+    builder.format(move |f, record| {
+        let timestamp = match timestamps {
+            LogTimestamps::Local => Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+            LogTimestamps::Utc => Utc::now().format("%Y-%m-%d %H:%M:%S%.3f UTC").to_string(),
+            LogTimestamps::Rfc3339 => Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            LogTimestamps::Custom(ref format) => Local::now().format(format).to_string(),
+            LogTimestamps::None => unreachable!(),
+        };
+        writeln!(
+            f,
+            " {} {:<5} {} > {}",
+            timestamp,
+            f.default_styled_level(record.level()),
+            record.target(),
+            record.args()
+        )
+    });
+    if let Ok(s) = std::env::var("RUST_LOG") {
+        builder.parse_filters(&s);
+    }
+    builder.init();
+    Ok(())
+}
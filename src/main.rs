@@ -1,23 +1,29 @@
 #![forbid(unsafe_code)]
 
 use std::env;
-use std::str::FromStr;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
 
-use cloudflare::framework::response::ApiFailure;
-use cron::Schedule;
-use log::info;
-use structopt::StructOpt;
-use tokio_retry::strategy::{jitter, ExponentialBackoff};
+use log::{error, info, warn};
+use tokio::sync::{mpsc, oneshot};
 
-use cdu::{Cdu, Opts, PublicIPError};
+#[cfg(windows)]
+use cdu::ServiceCommand;
+use cdu::{
+    init_logger, Cdu, Command, ConfigCommand, Daemon, Fired, HistoryCommand, Opts, Reloadable,
+    Signal, TokenCommand, Trigger,
+};
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let opts: Opts = Opts::from_args();
+fn main() -> anyhow::Result<()> {
+    let opts = Opts::from_args_with_config()?;
+
+    #[allow(unused_mut)]
+    let mut cdu = Cdu::new(opts);
+    #[cfg(unix)]
+    cdu.lock_pid_file()?;
+    // forked before the runtime starts its threads
+    #[cfg(unix)]
+    cdu.detach()?;
 
-    let cdu = Cdu::new(opts);
     if env::var_os("RUST_LOG").is_none() {
         if cdu.is_debug() {
             env::set_var("RUST_LOG", "cdu=debug");
@@ -25,44 +31,215 @@ async fn main() -> anyhow::Result<()> {
             env::set_var("RUST_LOG", "cdu=info");
         }
     }
+    if cdu.is_daemon() {
+        cdu.set_cron_timezone()?;
+    }
+    init_logger(cdu.log_timestamps(), cdu.log_target())?;
 
-    pretty_env_logger::init();
+    // landlock and seccomp apply to threads started afterwards
+    #[cfg(all(feature = "sandbox", target_os = "linux"))]
+    cdu.sandbox()?;
+    // as is the signal mask
+    #[cfg(unix)]
+    let signals = match cdu.command() {
+        None if cdu.is_daemon() => Some(cdu::signals()?),
+        _ => None,
+    };
+    #[cfg(not(unix))]
+    let signals = None;
+    // the service control dispatcher takes over this thread until the service is stopped
+    #[cfg(windows)]
+    if let Some(Command::Service(ServiceCommand::Run { name })) = cdu.command() {
+        let name = name.clone();
+        let shutdown_timeout = cdu.shutdown_timeout();
+        return cdu::run_service(&name, shutdown_timeout, move |stop| {
+            tokio::runtime::Runtime::new()?.block_on(run_daemon(cdu, None, Some(stop)))
+        });
+    }
+    tokio::runtime::Runtime::new()?.block_on(run(cdu, signals))
+}
 
-    if cdu.is_daemon() {
-        run_daemon(cdu).await?;
-    } else {
-        cdu.run().await?;
+async fn run(cdu: Cdu, signals: Option<mpsc::UnboundedReceiver<Signal>>) -> anyhow::Result<()> {
+    if let Some(fingerprint) = cdu.token_fingerprint() {
+        info!("token fingerprint: {}", fingerprint);
+    }
+    if let Some(fingerprint) = cdu.fallback_token_fingerprint() {
+        info!("fallback token fingerprint: {}", fingerprint);
+    }
+
+    match cdu.command() {
+        Some(Command::Trigger { ip }) => cdu.trigger(*ip).await?,
+        Some(Command::Park { target }) => cdu.park(Some(target.clone())).await?,
+        Some(Command::Unpark) => cdu.park(None).await?,
+        Some(Command::List) => cdu.list().await?,
+        Some(Command::Validate) => cdu.check().await?,
+        Some(Command::Lint) => cdu.lint()?,
+        Some(Command::Providers) => cdu.print_providers(),
+        Some(Command::MigrationReport) => cdu.print_migration_report()?,
+        Some(Command::Config(ConfigCommand::Schema)) => cdu.print_config_schema()?,
+        Some(Command::Config(ConfigCommand::Init { output })) => {
+            cdu.print_config_init(output.as_deref())?
+        }
+        Some(Command::History(HistoryCommand::Export { format, since })) => {
+            cdu.print_history_export(*format, since.map(Into::into))?
+        }
+        Some(Command::Report { log, lines }) => cdu.print_report(log.as_deref(), *lines)?,
+        Some(Command::Token(TokenCommand::Create {
+            email,
+            api_key,
+            name,
+        })) => cdu.create_token(email, api_key, name).await?,
+        #[cfg(windows)]
+        Some(Command::Task(command)) => cdu.task(command)?,
+        #[cfg(windows)]
+        Some(Command::Service(command)) => cdu.service(command)?,
+        None if cdu.is_daemon() => run_daemon(cdu, signals, None).await?,
+        None => {
+            #[cfg(all(feature = "sandbox", target_os = "openbsd"))]
+            cdu.sandbox()?;
+            let res = cdu.run().await;
+            cdu.flush_events().await;
+            res?
+        }
     }
 
     Ok(())
 }
 
-async fn run_daemon(cdu: Cdu) -> anyhow::Result<()> {
+async fn run_daemon(
+    cdu: Cdu,
+    signals: Option<mpsc::UnboundedReceiver<Signal>>,
+    stop: Option<oneshot::Receiver<()>>,
+) -> anyhow::Result<()> {
     let cdu = Arc::new(cdu);
-    let schedule = Schedule::from_str(cdu.cron())?;
-    for datetime in schedule.upcoming(chrono::Utc) {
-        info!("update DNS records at {}", datetime);
-
-        loop {
-            if chrono::Utc::now() > datetime {
-                break;
-            } else {
-                tokio::time::sleep(Duration::from_millis(999)).await;
+    cdu.log_cron_normalization();
+    cdu.warn_schedule();
+    let (tx, rx) = mpsc::unbounded_channel();
+    let mut schedule = None;
+    for trigger in cdu.triggers()? {
+        if matches!(
+            trigger,
+            Trigger::Cron(..) | Trigger::Interval(_) | Trigger::Schedules(_)
+        ) {
+            schedule = Some(trigger.spawn_with_jitter(tx.clone(), cdu.jitter()));
+        } else {
+            trigger.spawn(tx.clone());
+        }
+    }
+    let runner = Arc::new(Reloadable::new(cdu.clone()));
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let mut shutdown_tx = Some(shutdown_tx);
+    // stopped as a service
+    if let (Some(stop), Some(shutdown_tx)) = (stop, shutdown_tx.take()) {
+        tokio::spawn(async move {
+            if stop.await.is_ok() {
+                let _ = shutdown_tx.send(());
+            }
+        });
+    }
+    if let Some(mut signals) = signals {
+        let (runner, tx) = (runner.clone(), tx.clone());
+        let mut shutdown_tx = shutdown_tx.take();
+        tokio::spawn(async move {
+            while let Some(signal) = signals.recv().await {
+                match signal {
+                    Signal::Hangup => {}
+                    Signal::User1 | Signal::User2 => {
+                        if signal == Signal::User2 {
+                            runner.current().clear_cache();
+                        }
+                        let fired = Fired {
+                            reason: signal.to_string(),
+                            ip_address: None,
+                            group: None,
+                        };
+                        if fired.enqueue(&tx).is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                    Signal::Interrupt | Signal::Terminate => {
+                        match shutdown_tx.take() {
+                            Some(shutdown_tx) => {
+                                info!("{} received, shut down", signal);
+                                let _ = shutdown_tx.send(());
+                            }
+                            None => {
+                                warn!("{} received again, exit right away", signal);
+                                std::process::exit(1);
+                            }
+                        }
+                        continue;
+                    }
+                }
+                info!("SIGHUP received, reload configuration");
+                match runner.reload() {
+                    Ok(trigger) => {
+                        if let Some(handle) = schedule.take() {
+                            handle.abort();
+                        }
+                        let jitter = runner.current().jitter();
+                        schedule = Some(trigger.spawn_with_jitter(tx.clone(), jitter));
+                    }
+                    Err(e) => error!("failed to reload configuration, keep it as is: {:#}", e),
+                }
+            }
+        });
+    }
+    // listeners are left as they are, bound before privileges are dropped
+    if let Some(addr) = cdu.metrics_listen() {
+        let server = cdu.serve_metrics(addr, tx.clone());
+        tokio::spawn(async move {
+            let res = match server {
+                Ok(server) => server.await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                log::error!("metrics listener on {} stopped: {}", addr, e);
             }
+        });
+        match cdu.announce_mdns(addr) {
+            Ok(Some(announcement)) => {
+                tokio::spawn(async move {
+                    if let Err(e) = announcement.await {
+                        log::error!("mDNS announcement stopped: {}", e);
+                    }
+                });
+            }
+            Ok(None) => {}
+            Err(e) => log::error!("failed to announce metrics listener by mDNS: {}", e),
         }
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = cdu.grpc_listen() {
+        let server = cdu::serve_grpc(addr, cdu.clone(), tx.clone());
+        tokio::spawn(async move {
+            let res = match server {
+                Ok(server) => server.await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                log::error!("gRPC service on {} stopped: {}", addr, e);
+            }
+        });
+    }
+    drop(tx);
+    // listeners are bound by now
+    #[cfg(unix)]
+    cdu.drop_privileges()?;
+    #[cfg(all(feature = "sandbox", target_os = "openbsd"))]
+    cdu.sandbox()?;
 
-        let strategy = ExponentialBackoff::from_millis(10).map(jitter).take(3);
-        let cdu = cdu.clone();
-        let instant = Instant::now();
-        tokio_retry::RetryIf::spawn(
-            strategy,
-            || cdu.run(),
-            |e: &anyhow::Error| e.is::<ApiFailure>() || e.is::<PublicIPError>(),
-        )
+    Daemon::new(runner)
+        .with_retry(cdu.retry_policy())
+        .with_shutdown_timeout(cdu.shutdown_timeout())
+        .run_until(rx, async {
+            // without signals or the service stopped, the daemon runs until it's killed
+            if shutdown_rx.await.is_err() {
+                futures::future::pending::<()>().await;
+            }
+        })
         .await?;
-        let duration = Instant::now() - instant;
-        info!("done in {}ms", duration.as_millis());
-    }
 
     Ok(())
 }
@@ -0,0 +1,192 @@
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+
+use log::{debug, warn};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+
+use crate::provider::{read_name, skip_name, write_name};
+
+const GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const PORT: u16 = 5353;
+
+/// Type of service the metrics listener is announced as
+const SERVICE: &str = "_cdu._tcp.local";
+/// Name browsers enumerate types of services by, RFC 6763 section 9
+const SERVICES: &str = "_services._dns-sd._udp.local";
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// Records other responders should replace rather than add to, RFC 6762 section 10.2
+const CACHE_FLUSH: u16 = 0x8000;
+
+/// TTL of records named after the host, RFC 6762 section 10
+const HOST_TTL: u32 = 120;
+const OTHER_TTL: u32 = 4500;
+
+/// Service announced on the local network by multicast DNS
+pub(crate) struct Announcement {
+    /// Instance name e.g. the host name
+    pub(crate) instance: String,
+    pub(crate) host: String,
+    pub(crate) addr: Ipv4Addr,
+    pub(crate) port: u16,
+    /// Key-value pairs of the TXT record e.g. path=/health
+    pub(crate) txt: Vec<String>,
+}
+
+impl Announcement {
+    fn instance_name(&self) -> String {
+        format!("{}.{}", self.instance, SERVICE)
+    }
+
+    fn host_name(&self) -> String {
+        format!("{}.local", self.host)
+    }
+
+    /// Whether the question asks for any of the records of the service
+    fn answers(&self, name: &str, rr_type: u16) -> bool {
+        let instance = self.instance_name().to_ascii_lowercase();
+        let host = self.host_name().to_ascii_lowercase();
+        match rr_type {
+            TYPE_PTR => name == SERVICE || name == SERVICES,
+            TYPE_SRV | TYPE_TXT => name == instance,
+            TYPE_A => name == host,
+            TYPE_ANY => [SERVICE, SERVICES, &instance, &host].contains(&name),
+            _ => false,
+        }
+    }
+
+    /// Unsolicited response with every record of the service
+    fn response(&self) -> Vec<u8> {
+        let (instance, host) = (self.instance_name(), self.host_name());
+        let mut bytes = vec![0, 0, 0x84, 0, 0, 0, 0, 5, 0, 0, 0, 0];
+        let mut ptr = vec![];
+        write_name(&mut ptr, SERVICE);
+        record(&mut bytes, SERVICES, TYPE_PTR, CLASS_IN, OTHER_TTL, &ptr);
+        let mut ptr = vec![];
+        write_name(&mut ptr, &instance);
+        record(&mut bytes, SERVICE, TYPE_PTR, CLASS_IN, OTHER_TTL, &ptr);
+        // priority and weight
+        let mut srv = vec![0, 0, 0, 0];
+        srv.extend_from_slice(&self.port.to_be_bytes());
+        write_name(&mut srv, &host);
+        let class = CLASS_IN | CACHE_FLUSH;
+        record(&mut bytes, &instance, TYPE_SRV, class, HOST_TTL, &srv);
+        let mut txt = vec![];
+        for pair in &self.txt {
+            txt.push(pair.len().min(255) as u8);
+            txt.extend_from_slice(&pair.as_bytes()[..pair.len().min(255)]);
+        }
+        record(&mut bytes, &instance, TYPE_TXT, class, OTHER_TTL, &txt);
+        record(
+            &mut bytes,
+            &host,
+            TYPE_A,
+            class,
+            HOST_TTL,
+            &self.addr.octets(),
+        );
+        bytes
+    }
+}
+
+fn record(bytes: &mut Vec<u8>, name: &str, rr_type: u16, class: u16, ttl: u32, rdata: &[u8]) {
+    write_name(bytes, name);
+    bytes.extend_from_slice(&rr_type.to_be_bytes());
+    bytes.extend_from_slice(&class.to_be_bytes());
+    bytes.extend_from_slice(&ttl.to_be_bytes());
+    bytes.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(rdata);
+}
+
+/// Questions of a query as lowercase names and types, none if the message is a response
+fn questions(message: &[u8]) -> anyhow::Result<Vec<(String, u16)>> {
+    if message.len() < 12 || message[2] & 0x80 != 0 {
+        return Ok(vec![]);
+    }
+    let count = u16::from_be_bytes([message[4], message[5]]);
+    let mut pos = 12;
+    let mut questions = vec![];
+    for _ in 0..count {
+        let name = read_name(message, pos)?;
+        pos = skip_name(message, pos)?;
+        let rr_type = message
+            .get(pos..pos + 2)
+            .map(|t| u16::from_be_bytes([t[0], t[1]]))
+            .ok_or_else(|| anyhow::anyhow!("truncated DNS message"))?;
+        questions.push((name, rr_type));
+        pos += 4;
+    }
+    Ok(questions)
+}
+
+/// Join the multicast group of mDNS alongside other responders on the host e.g. Avahi. The socket
+/// is bound right away, the service is announced and queries answered by the returned future.
+pub(crate) fn announce(
+    announcement: Announcement,
+) -> anyhow::Result<impl std::future::Future<Output = anyhow::Result<()>>> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, PORT)).into())?;
+    socket.join_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_ttl_v4(255)?;
+    socket.set_nonblocking(true)?;
+    let socket = UdpSocket::from_std(socket.into())?;
+    let group = SocketAddr::V4(SocketAddrV4::new(GROUP, PORT));
+    debug!(
+        "announce {}.{} on {}:{} by mDNS",
+        announcement.instance, SERVICE, announcement.addr, announcement.port
+    );
+    Ok(async move {
+        let response = announcement.response();
+        // announced twice a second apart, RFC 6762 section 8.3
+        for i in 0..2 {
+            if i > 0 {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            socket.send_to(&response, group).await?;
+        }
+        let mut buf = vec![0; 9000];
+        loop {
+            let (len, peer) = socket.recv_from(&mut buf).await?;
+            let questions = match questions(&buf[..len]) {
+                Ok(questions) => questions,
+                Err(e) => {
+                    debug!("invalid mDNS query from {}: {}", peer, e);
+                    continue;
+                }
+            };
+            if questions
+                .iter()
+                .any(|(name, rr_type)| announcement.answers(name, *rr_type))
+            {
+                if let Err(e) = socket.send_to(&response, group).await {
+                    warn!("failed to answer mDNS query from {}: {}", peer, e);
+                }
+            }
+        }
+    })
+}
+
+/// Name of this host, the first label only
+pub(crate) fn hostname() -> String {
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } == 0 {
+            let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+            let name = String::from_utf8_lossy(&buf[..len]);
+            if let Some(label) = name.split('.').next().filter(|label| !label.is_empty()) {
+                return label.to_string();
+            }
+        }
+    }
+    std::env::var("COMPUTERNAME").unwrap_or_else(|_| "cdu".to_string())
+}
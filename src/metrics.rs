@@ -0,0 +1,229 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use log::debug;
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_rustls::rustls::ServerConfig;
+
+use crate::event::{Event, Subscriber};
+use crate::trigger::Fired;
+
+/// Prometheus metrics of this process
+pub(crate) struct Metrics {
+    registry: Registry,
+    /// Triggers waiting for the daemon to pick them up
+    pub(crate) queue_depth: IntGauge,
+    /// Triggers merged into an already pending run
+    pub(crate) triggers_coalesced: IntCounter,
+    /// Runs by result, success or failure
+    pub(crate) runs: IntCounterVec,
+    /// Errors of runs, including repeated ones collapsed in logs
+    pub(crate) errors: IntCounter,
+    /// Events subscribers failed to handle even after retry, by subscriber
+    pub(crate) notifier_failures: IntCounterVec,
+    /// Records found differing from the public IP address in observe mode, by record
+    pub(crate) drift_detected: IntCounterVec,
+    /// Seconds from first detecting a new IP address to every record updated, by whether verify
+    /// stages confirmed the records
+    pub(crate) propagation_seconds: HistogramVec,
+    /// Round trip time of requests to Cloudflare by endpoint e.g. ListZones
+    pub(crate) api_latency_seconds: HistogramVec,
+}
+
+/// Count runs by result as they finish
+pub(crate) struct RunCounter;
+
+impl Subscriber for RunCounter {
+    fn name(&self) -> &str {
+        "metrics"
+    }
+
+    fn deferrable(&self) -> bool {
+        false
+    }
+
+    fn notify(&self, event: &Event) -> anyhow::Result<()> {
+        match event {
+            Event::RunSucceeded => METRICS.runs.with_label_values(&["success"]).inc(),
+            Event::RunFailed { .. } => METRICS.runs.with_label_values(&["failure"]).inc(),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+pub(crate) static METRICS: Lazy<Metrics> = Lazy::new(|| {
+    let registry = Registry::new_custom(Some("cdu".to_string()), None).unwrap();
+    let queue_depth = IntGauge::new("trigger_queue_depth", "Triggers waiting to be run").unwrap();
+    let triggers_coalesced = IntCounter::new(
+        "triggers_coalesced_total",
+        "Triggers merged into an already pending run",
+    )
+    .unwrap();
+    let runs = IntCounterVec::new(Opts::new("runs_total", "Runs by result"), &["result"]).unwrap();
+    let errors = IntCounter::new(
+        "errors_total",
+        "Errors of runs, including repeated ones collapsed in logs",
+    )
+    .unwrap();
+    registry.register(Box::new(queue_depth.clone())).unwrap();
+    registry
+        .register(Box::new(triggers_coalesced.clone()))
+        .unwrap();
+    let notifier_failures = IntCounterVec::new(
+        Opts::new(
+            "notifier_failures_total",
+            "Events subscribers failed to handle after retry",
+        ),
+        &["subscriber"],
+    )
+    .unwrap();
+    let drift_detected = IntCounterVec::new(
+        Opts::new(
+            "drift_detected_total",
+            "Records differing from the public IP address in observe mode",
+        ),
+        &["record"],
+    )
+    .unwrap();
+    let propagation_seconds = HistogramVec::new(
+        HistogramOpts::new(
+            "propagation_seconds",
+            "Seconds from first detecting a new IP address to every record updated, or confirmed by verify stages",
+        )
+        .buckets(vec![
+            0.5, 1.0, 2.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0, 3600.0,
+        ]),
+        &["verified"],
+    )
+    .unwrap();
+    let api_latency_seconds = HistogramVec::new(
+        HistogramOpts::new(
+            "api_latency_seconds",
+            "Round trip time of requests to Cloudflare by endpoint",
+        ),
+        &["endpoint"],
+    )
+    .unwrap();
+    registry.register(Box::new(runs.clone())).unwrap();
+    registry.register(Box::new(errors.clone())).unwrap();
+    registry
+        .register(Box::new(notifier_failures.clone()))
+        .unwrap();
+    registry.register(Box::new(drift_detected.clone())).unwrap();
+    registry
+        .register(Box::new(propagation_seconds.clone()))
+        .unwrap();
+    registry
+        .register(Box::new(api_latency_seconds.clone()))
+        .unwrap();
+    Metrics {
+        registry,
+        queue_depth,
+        triggers_coalesced,
+        runs,
+        errors,
+        notifier_failures,
+        drift_detected,
+        propagation_seconds,
+        api_latency_seconds,
+    }
+});
+
+impl Metrics {
+    /// Metrics in Prometheus text format
+    pub(crate) fn render(&self) -> String {
+        let mut buffer = vec![];
+        // encoding into a vector never fails
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8_lossy(&buffer).into_owned()
+    }
+}
+
+/// Metrics of this process in Prometheus text format, as served on GET /metrics
+pub fn render_metrics() -> String {
+    METRICS.render()
+}
+
+/// Badge of the current state, see [`crate::badge`]
+pub(crate) type Badge = Arc<dyn Fn() -> serde_json::Value + Send + Sync>;
+
+/// Serve metrics on GET /metrics, health on GET /health, and a badge on GET /badge.json, over TLS
+/// if configured. Runs are started by POST /trigger with the bearer token if given. The address
+/// is bound right away, requests are served by the returned future.
+pub(crate) fn serve_metrics(
+    addr: SocketAddr,
+    tls: Option<Arc<ServerConfig>>,
+    control: Option<(String, UnboundedSender<Fired>)>,
+    badge: Badge,
+) -> anyhow::Result<BoxFuture<'static, anyhow::Result<()>>> {
+    let control = control.map(|(token, tx)| (Arc::new(token), tx));
+    let handle = move |req| handle(req, control.clone(), badge.clone());
+    match tls {
+        Some(config) => {
+            let listener = std::net::TcpListener::bind(addr)?;
+            listener.set_nonblocking(true)?;
+            let listener = tokio::net::TcpListener::from_std(listener)?;
+            debug!("serve metrics on {} over TLS", addr);
+            Ok(crate::tls::serve(listener, config, handle).boxed())
+        }
+        None => {
+            let make_service = make_service_fn(move |_| {
+                let handle = handle.clone();
+                async move { Ok::<_, Infallible>(service_fn(handle)) }
+            });
+            let server = Server::try_bind(&addr)?.serve(make_service);
+            debug!("serve metrics on {}", addr);
+            Ok(async move { Ok(server.await?) }.boxed())
+        }
+    }
+}
+
+async fn handle(
+    req: Request<Body>,
+    control: Option<(Arc<String>, UnboundedSender<Fired>)>,
+    badge: Badge,
+) -> Result<Response<Body>, Infallible> {
+    let mut res = Response::default();
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => {
+            *res.body_mut() = Body::from(METRICS.render());
+            res.headers_mut().insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("text/plain; version=0.0.4"),
+            );
+        }
+        (&Method::GET, "/health") => {
+            *res.body_mut() = Body::from(serde_json::json!({ "status": "ok" }).to_string());
+            res.headers_mut().insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("application/json"),
+            );
+        }
+        (&Method::GET, "/badge.json") => {
+            *res.body_mut() = Body::from(badge().to_string());
+            res.headers_mut().insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("application/json"),
+            );
+        }
+        (_, "/trigger") => match control {
+            Some((token, tx)) => return crate::webhook::handle(req, token, tx).await,
+            None => *res.status_mut() = StatusCode::NOT_FOUND,
+        },
+        _ => *res.status_mut() = StatusCode::NOT_FOUND,
+    }
+    Ok(res)
+}
@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::state::write_atomic;
+
+/// How often DNS records written by another DDNS tool agreed with what cdu would have written
+#[derive(Serialize, Deserialize)]
+pub(crate) struct MigrationReport {
+    since: DateTime<Utc>,
+    records: BTreeMap<String, Agreement>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Agreement {
+    observations: u64,
+    agreements: u64,
+    last_disagreement: Option<DateTime<Utc>>,
+}
+
+impl MigrationReport {
+    /// Load report from file, start a new one if the file doesn't exist yet
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self {
+                since: Utc::now(),
+                records: BTreeMap::new(),
+            });
+        }
+        let s = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&s)?)
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> anyhow::Result<()> {
+        write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    /// Count one observation of record content against the content cdu would have written
+    pub(crate) fn observe(&mut self, record_name: &str, content: &str, expected: &str) {
+        let agreement = self.records.entry(record_name.to_string()).or_default();
+        agreement.observations += 1;
+        if content == expected {
+            agreement.agreements += 1;
+        } else {
+            agreement.last_disagreement = Some(Utc::now());
+        }
+    }
+
+    /// Percentage of observations across all records in agreement
+    pub(crate) fn confidence(&self) -> f64 {
+        let (observations, agreements) = self
+            .records
+            .values()
+            .fold((0, 0), |(o, a), r| (o + r.observations, a + r.agreements));
+        if observations == 0 {
+            return 0.0;
+        }
+        agreements as f64 * 100.0 / observations as f64
+    }
+
+    pub(crate) fn summary(&self) -> String {
+        format!(
+            "{:.1}% agreement with existing DDNS tool since {}",
+            self.confidence(),
+            self.since.to_rfc3339()
+        )
+    }
+
+    /// Human-readable report with one line per record
+    pub(crate) fn render(&self) -> String {
+        let mut lines = vec![self.summary()];
+        for (name, agreement) in &self.records {
+            let last_disagreement = match agreement.last_disagreement {
+                Some(at) => at.to_rfc3339(),
+                None => "never".to_string(),
+            };
+            lines.push(format!(
+                "{}: {}/{} observations agreed, last disagreement {}",
+                name, agreement.agreements, agreement.observations, last_disagreement
+            ));
+        }
+        lines.join("\n")
+    }
+}
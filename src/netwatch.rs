@@ -0,0 +1,160 @@
+use std::future::Future;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::Duration;
+
+use log::debug;
+use tokio::io::unix::AsyncFd;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::trigger::Fired;
+
+/// Changes within this long of each other are taken as one, e.g. an address and its routes
+/// coming up as a PPP link reconnects
+const SETTLE: Duration = Duration::from_secs(2);
+
+/// Subscribe to changes of addresses and routes of network interfaces. The socket is opened right
+/// away, changes are forwarded to the daemon loop by the returned future.
+pub(crate) fn watch(tx: UnboundedSender<Fired>) -> anyhow::Result<impl Future<Output = ()>> {
+    let fd = AsyncFd::new(open()?)?;
+    debug!("watch network interfaces for changes");
+    Ok(async move {
+        let mut buf = vec![0u8; 16384];
+        loop {
+            if let Err(e) = next_change(&fd, &mut buf).await {
+                log::error!("stop watching network interfaces: {}", e);
+                return;
+            }
+            // wait for the network to settle, taking further changes meanwhile as the same one
+            while let Ok(res) = tokio::time::timeout(SETTLE, next_change(&fd, &mut buf)).await {
+                if let Err(e) = res {
+                    log::error!("stop watching network interfaces: {}", e);
+                    return;
+                }
+            }
+            if Fired::new("network changed".to_string())
+                .enqueue(&tx)
+                .is_err()
+            {
+                return;
+            }
+        }
+    })
+}
+
+/// Wait for a message telling a change of addresses or routes
+async fn next_change(fd: &AsyncFd<OwnedFd>, buf: &mut [u8]) -> io::Result<()> {
+    loop {
+        let mut guard = fd.readable().await?;
+        let res = guard.try_io(|fd| {
+            let n = unsafe {
+                libc::read(
+                    fd.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if n < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(n as usize)
+        });
+        match res {
+            Ok(Ok(n)) if is_change(&buf[..n]) => return Ok(()),
+            Ok(Ok(_)) => continue,
+            // e.g. ENOBUFS as the kernel dropped messages, which are changes all the same
+            Ok(Err(e)) if e.raw_os_error() == Some(libc::ENOBUFS) => return Ok(()),
+            Ok(Err(e)) => return Err(e),
+            Err(_would_block) => continue,
+        }
+    }
+}
+
+/// Netlink socket subscribed to addresses and routes of IPv4 and IPv6
+#[cfg(target_os = "linux")]
+fn open() -> io::Result<OwnedFd> {
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            libc::NETLINK_ROUTE,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    addr.nl_groups = (libc::RTMGRP_IPV4_IFADDR
+        | libc::RTMGRP_IPV6_IFADDR
+        | libc::RTMGRP_IPV4_ROUTE
+        | libc::RTMGRP_IPV6_ROUTE) as u32;
+    let res = unsafe {
+        libc::bind(
+            fd.as_raw_fd(),
+            &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+/// Whether any message is about a new or deleted address or route, rather than e.g. an error
+#[cfg(target_os = "linux")]
+fn is_change(messages: &[u8]) -> bool {
+    let mut pos = 0;
+    while let Some(header) = messages.get(pos..pos + 16) {
+        let len = u32::from_ne_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let kind = u16::from_ne_bytes([header[4], header[5]]);
+        if matches!(
+            kind,
+            libc::RTM_NEWADDR | libc::RTM_DELADDR | libc::RTM_NEWROUTE | libc::RTM_DELROUTE
+        ) {
+            return true;
+        }
+        if len < 16 {
+            break;
+        }
+        // messages are aligned to 4 bytes
+        pos += (len + 3) & !3;
+    }
+    false
+}
+
+/// Routing socket, which SystemConfiguration watches for changes of addresses and routes too
+#[cfg(target_os = "macos")]
+fn open() -> io::Result<OwnedFd> {
+    let fd = unsafe { libc::socket(libc::AF_ROUTE, libc::SOCK_RAW, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let flags = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GETFL) };
+    if flags < 0
+        || unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0
+        || unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } < 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+/// Whether the message is about a new or deleted address or route, by its type after length and
+/// version
+#[cfg(target_os = "macos")]
+fn is_change(message: &[u8]) -> bool {
+    matches!(
+        message.get(3).map(|&kind| kind as libc::c_int),
+        Some(
+            libc::RTM_ADD
+                | libc::RTM_DELETE
+                | libc::RTM_CHANGE
+                | libc::RTM_NEWADDR
+                | libc::RTM_DELADDR
+        )
+    )
+}
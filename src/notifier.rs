@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use log::debug;
+use reqwest::{Client, Url};
+use serde_json::json;
+use tokio::runtime::Handle;
+
+use crate::event::{Event, Subscriber};
+
+const POST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Post changes and failures as JSON to a URL e.g. a chat webhook, held back in quiet hours
+pub(crate) struct WebhookNotifier {
+    url: Url,
+    client: Client,
+}
+
+impl WebhookNotifier {
+    pub(crate) fn new(url: Url) -> Self {
+        Self {
+            url,
+            client: Client::new(),
+        }
+    }
+}
+
+impl Subscriber for WebhookNotifier {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn notify(&self, event: &Event) -> anyhow::Result<()> {
+        // events of every run would be noise
+        if matches!(
+            event,
+            Event::RunStarted { .. }
+                | Event::IpDetected { .. }
+                | Event::RunRecorded { .. }
+                | Event::RunSucceeded
+        ) {
+            return Ok(());
+        }
+        let body = json!({ "kind": event.kind(), "message": event.message() });
+        let req = self
+            .client
+            .post(self.url.clone())
+            .timeout(POST_TIMEOUT)
+            .json(&body);
+        // notified in a blocking task of the runtime
+        Handle::current()
+            .block_on(async { req.send().await?.error_for_status() })
+            .with_context(|| format!("failed to post {} to {}", event.kind(), self.url))?;
+        debug!("{} posted to {}", event.kind(), self.url);
+        Ok(())
+    }
+}
@@ -1,33 +1,1112 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::bail;
+use chrono::format::{Item, StrftimeItems};
+use once_cell::sync::OnceCell;
+use regex::Regex;
+use secrecy::{ExposeSecret, SecretString};
+use serde_yaml::Value;
+
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
+use crate::config::{Config, GroupConfig, ProviderConfig, RecordConfig, RecordType};
+use crate::history::HistoryFormat;
+use crate::park::ParkTarget;
+use crate::record::{check_record_options, dedup_record_names, parse_record_options};
+use crate::socks::ProxyUrl;
+use crate::store::StoreKind;
+use crate::{
+    normalize_name, parse_record_names, AddressSource, CronTimezone, QuietHours, TsigAlgorithm,
+};
+
+/// Every 5 minutes
+pub(crate) const DEFAULT_CRON: &str = "0 */5 * * * * *";
+
 #[derive(StructOpt)]
 #[structopt(about, author)]
 pub struct Opts {
     /// Cloudflare token
     #[structopt(short, long, env = "CLOUDFLARE_TOKEN")]
-    pub(crate) token: String,
-    /// Cloudflare zone name
-    #[structopt(short, long, env = "CLOUDFLARE_ZONE")]
-    pub(crate) zone: String,
-    /// Cloudflare records separated with comma e.g. a.x.com,b.x.com
-    #[structopt(short, long, env = "CLOUDFLARE_RECORDS")]
-    records: String,
+    pub(crate) token: Option<SecretString>,
+    /// Cloudflare token to fail over to when the primary one is rejected e.g. during rotation
+    #[structopt(long, env = "CLOUDFLARE_FALLBACK_TOKEN")]
+    pub(crate) fallback_token: Option<SecretString>,
+    /// Cloudflare zone names, repeat or separate with comma to manage records across zones e.g.
+    /// example.com,example.org. Records belong to the longest zone they're in. Zones of the
+    /// account are listed once at startup if omitted
+    #[structopt(
+        short,
+        long,
+        env = "CLOUDFLARE_ZONE",
+        number_of_values = 1,
+        use_delimiter = true
+    )]
+    pub(crate) zone: Vec<String>,
+    /// Zones of the account, listed when --zone is omitted
+    #[structopt(skip)]
+    pub(crate) account_zones: OnceCell<Vec<String>>,
+    /// Cloudflare records separated with comma e.g. a.x.com,b.x.com. Follow a name with :proxied
+    /// or :dns-only to override --proxied for it e.g. a.x.com:proxied,b.x.com:dns-only
+    #[structopt(
+        short,
+        long,
+        env = "CLOUDFLARE_RECORDS",
+        parse(try_from_str = check_record_options)
+    )]
+    pub(crate) records: Option<String>,
+    /// Regular expression selecting A and AAAA records on Cloudflare by name along with --records
+    /// e.g. '^vpn-.*\.example\.com$'. Records of the zones are listed once at startup
+    #[structopt(long, env = "CLOUDFLARE_RECORDS_PATTERN")]
+    pub(crate) records_pattern: Option<Regex>,
+    /// Record names matching --records-pattern, listed at startup
+    #[structopt(skip)]
+    pub(crate) pattern_records: OnceCell<Vec<String>>,
+    /// Update AAAA records with the public IPv6 address instead of A records with the public
+    /// IPv4 address, same as --ip-version 6
+    #[structopt(long, env = "CLOUDFLARE_IPV6")]
+    pub(crate) ipv6: bool,
+    /// Public IP address to detect and publish: 4 for A records, 6 for AAAA records, or both to
+    /// update A and AAAA records of each name
+    #[structopt(long, default_value = "4", env = "IP_VERSION")]
+    pub(crate) ip_version: IpVersion,
+    /// Proxy status of records on Cloudflare: true or false to set it on update, or keep to leave
+    /// it as is
+    #[structopt(long, default_value = "keep", env = "CLOUDFLARE_PROXIED")]
+    pub(crate) proxied: Proxied,
+    /// TTL in seconds of records on Cloudflare, 1 for automatic or 60 to 86400. Left as is on
+    /// update if omitted
+    #[structopt(long, env = "CLOUDFLARE_TTL")]
+    pub(crate) ttl: Option<u32>,
+    /// Configuration file in YAML, or TOML if named *.toml, e.g. /etc/cdu/config.yaml. Records in
+    /// it are updated along with those given by --records, and options in it by long name e.g.
+    /// `cache_seconds` apply unless given on command line
+    #[structopt(long, env = "CONFIG", parse(from_os_str))]
+    pub(crate) config: Option<PathBuf>,
+    /// Profile in the configuration file to use e.g. home for `[profile.home]`, whose options and
+    /// providers take precedence and whose records are added to the others
+    #[structopt(long, env = "PROFILE")]
+    pub(crate) profile: Option<String>,
+    #[structopt(skip)]
+    pub(crate) file: Config,
+    /// URL of PowerDNS Authoritative HTTP API e.g. http://127.0.0.1:8081, for records with
+    /// `provider: powerdns` in the configuration file
+    #[structopt(long, env = "POWERDNS_URL")]
+    pub(crate) powerdns_url: Option<String>,
+    /// PowerDNS API key
+    #[structopt(long, env = "POWERDNS_API_KEY", hide_env_values = true)]
+    pub(crate) powerdns_api_key: Option<String>,
+    /// PowerDNS server ID
+    #[structopt(long, default_value = "localhost", env = "POWERDNS_SERVER_ID")]
+    pub(crate) powerdns_server_id: String,
+    /// DNS server accepting dynamic updates (RFC 2136) e.g. 192.0.2.53:53, for records with
+    /// `provider: rfc2136` in the configuration file
+    #[structopt(long, env = "RFC2136_SERVER")]
+    pub(crate) rfc2136_server: Option<SocketAddr>,
+    /// Name of TSIG key to sign updates with
+    #[structopt(long, env = "RFC2136_KEY_NAME")]
+    pub(crate) rfc2136_key_name: Option<String>,
+    /// Secret of TSIG key in base64
+    #[structopt(long, env = "RFC2136_KEY_SECRET", hide_env_values = true)]
+    pub(crate) rfc2136_key_secret: Option<String>,
+    /// Algorithm of TSIG key: hmac-sha256 or hmac-sha512
+    #[structopt(long, default_value = "hmac-sha256", env = "RFC2136_KEY_ALGORITHM")]
+    pub(crate) rfc2136_key_algorithm: TsigAlgorithm,
+    /// Service account key of Google Cloud in JSON, for records with `provider: gcp` in the
+    /// configuration file. Without it, the service account of the instance is used on Google Cloud
+    #[structopt(long, env = "GOOGLE_APPLICATION_CREDENTIALS", parse(from_os_str))]
+    pub(crate) gcp_credentials: Option<PathBuf>,
+    /// Google Cloud project hosting the managed zones, defaults to project of the service account key
+    #[structopt(long, env = "GCP_PROJECT")]
+    pub(crate) gcp_project: Option<String>,
+    /// Azure subscription the DNS zones belong to, for records with `provider: azure` in the
+    /// configuration file
+    #[structopt(long, env = "AZURE_SUBSCRIPTION_ID")]
+    pub(crate) azure_subscription_id: Option<String>,
+    /// Azure resource group of the DNS zones
+    #[structopt(long, env = "AZURE_RESOURCE_GROUP")]
+    pub(crate) azure_resource_group: Option<String>,
+    /// Azure Active Directory tenant of the service principal
+    #[structopt(long, env = "AZURE_TENANT_ID")]
+    pub(crate) azure_tenant_id: Option<String>,
+    /// Application (client) ID of the service principal
+    #[structopt(long, env = "AZURE_CLIENT_ID")]
+    pub(crate) azure_client_id: Option<String>,
+    /// Client secret of the service principal
+    #[structopt(long, env = "AZURE_CLIENT_SECRET", hide_env_values = true)]
+    pub(crate) azure_client_secret: Option<String>,
+    /// OVH API endpoint e.g. https://ca.api.ovh.com/1.0, for records with `provider: ovh` in the
+    /// configuration file
+    #[structopt(
+        long,
+        default_value = "https://eu.api.ovh.com/1.0",
+        env = "OVH_ENDPOINT"
+    )]
+    pub(crate) ovh_endpoint: String,
+    /// OVH application key
+    #[structopt(long, env = "OVH_APPLICATION_KEY")]
+    pub(crate) ovh_application_key: Option<String>,
+    /// OVH application secret
+    #[structopt(long, env = "OVH_APPLICATION_SECRET", hide_env_values = true)]
+    pub(crate) ovh_application_secret: Option<String>,
+    /// OVH consumer key granted access to /domain/zone
+    #[structopt(long, env = "OVH_CONSUMER_KEY", hide_env_values = true)]
+    pub(crate) ovh_consumer_key: Option<String>,
+    /// Proxy to reach the Cloudflare API through, by HTTP e.g. http://proxy.lan:3128 or by
+    /// SOCKS5 e.g. socks5://127.0.0.1:1080 over an SSH tunnel or Tor
+    #[structopt(long, env = "API_PROXY", hide_env_values = true)]
+    pub(crate) api_proxy: Option<ProxyUrl>,
+    /// Address family to connect to the Cloudflare API over: 4, 6, or auto for whichever
+    /// connects first, e.g. 4 on networks where IPv6 is broken and connections time out
+    #[structopt(long, default_value = "auto", env = "API_IP_VERSION")]
+    pub(crate) api_ip_version: ApiIpVersion,
+    /// Base URL of the Cloudflare API, e.g. of a mock API in tests
+    #[structopt(
+        long,
+        default_value = "https://api.cloudflare.com/client/v4/",
+        env = "CLOUDFLARE_API_URL",
+        hidden = true
+    )]
+    pub(crate) api_url: reqwest::Url,
+    /// Proxy to detect the public IP address through, by HTTP or SOCKS5 as --api-proxy. Echo
+    /// services then see the address of the proxy
+    #[structopt(long, env = "DETECT_PROXY", hide_env_values = true)]
+    pub(crate) detect_proxy: Option<ProxyUrl>,
     /// Debug mode
     #[structopt(long)]
     pub(crate) debug: bool,
+    /// Timestamps in logs: none, local, utc, rfc3339, or strftime format in local time e.g. "%H:%M:%S"
+    #[structopt(long, default_value = "none", env = "LOG_TIMESTAMPS")]
+    pub(crate) log_timestamps: LogTimestamps,
+    /// Where logs go: stderr, syslog on Unix e.g. once detached without --log-file, or journald
+    /// on Linux with fields e.g. RECORD and ZONE of updated records
+    #[structopt(long, default_value = "stderr", env = "LOG_TARGET")]
+    pub(crate) log_target: LogTarget,
+    /// Disable colors in terminal output, also disabled if NO_COLOR is set
+    #[structopt(long)]
+    pub(crate) no_color: bool,
+    /// Dry run, log API payloads instead of updating DNS records
+    #[structopt(long)]
+    pub(crate) dry_run: bool,
+    /// Update DNS records even if they are up to date as of the last run e.g. after they were
+    /// changed elsewhere
+    #[structopt(long)]
+    pub(crate) force: bool,
+    /// Observe mode, detect and report drift between DNS records and public IP address
+    /// without ever updating DNS records
+    #[structopt(long, conflicts_with = "dry-run")]
+    pub(crate) observe: bool,
+    /// Log why each DNS record was updated, skipped, or failed, e.g. found in cache, up to date
+    /// since last run, parked, or expired
+    #[structopt(long)]
+    pub(crate) explain: bool,
+    /// Print planned changes to DNS records with their content, TTL, and proxy status, without
+    /// updating them, on every run in daemon mode. API payloads are logged as in dry run
+    #[structopt(long, conflicts_with_all = &["dry-run", "observe"])]
+    pub(crate) plan: bool,
+    /// File to accumulate agreement between existing DDNS tool and cdu in observe mode,
+    /// to gain confidence before cutting over
+    #[structopt(long, env = "MIGRATION_REPORT", parse(from_os_str))]
+    pub(crate) migration_report: Option<PathBuf>,
+    /// Park DNS records at a maintenance IPv4 address, or CNAME them to a name e.g. a status
+    /// page, instead of the public IP address. Park and unpark a running daemon with
+    /// `cdu park` and `cdu unpark`
+    #[structopt(long, env = "PARK")]
+    pub(crate) park: Option<ParkTarget>,
+    /// Park records whose host hasn't reported back within `expires_after` at an IPv4 address
+    /// e.g. 0.0.0.0, or CNAME them to a name, instead of deleting them right away
+    #[structopt(long, env = "PARK_EXPIRED")]
+    pub(crate) park_expired: Option<ParkTarget>,
+    /// How long expired records stay parked with --park-expired before they're deleted, so
+    /// hosts coming back in the meantime find them where they were
+    #[structopt(long, default_value = "24h", env = "PARK_EXPIRED_FOR")]
+    pub(crate) park_expired_for: humantime::Duration,
     /// Daemon mode
     #[structopt(short, long, env = "DAEMON")]
     pub(crate) daemon: bool,
     /// Cron. Only in effect in daemon mode
-    #[structopt(short, long, default_value = "0 */5 * * * * *", env = "CRON")]
+    #[structopt(short, long, default_value = DEFAULT_CRON, env = "CRON")]
     pub(crate) cron: String,
+    /// Delay each scheduled run by a random duration up to this e.g. 30s, so instances started
+    /// from the same image don't make requests at the same second
+    #[structopt(long, env = "JITTER")]
+    pub(crate) jitter: Option<humantime::Duration>,
+    /// How long a run in progress may take to finish once SIGTERM or SIGINT is received in daemon
+    /// mode, before exiting anyway
+    #[structopt(long, default_value = "30s", env = "SHUTDOWN_TIMEOUT")]
+    pub(crate) shutdown_timeout: humantime::Duration,
+    /// Timezone of the cron schedule: UTC, local for the timezone of the system, an offset e.g.
+    /// +02:00, or a name e.g. Europe/Berlin. Also given by prefixing the cron expression with
+    /// e.g. `CRON_TZ=Europe/Berlin`
+    #[structopt(long, default_value = "UTC", env = "CRON_TIMEZONE")]
+    pub(crate) cron_timezone: CronTimezone,
+    /// Interval between runs instead of cron e.g. 5m or 1h 30m. Only in effect in daemon mode
+    #[structopt(long, env = "EVERY", conflicts_with = "cron")]
+    pub(crate) every: Option<humantime::Duration>,
     /// Cache duration in seconds, give 0 to disable
     #[structopt(short = "s", long, default_value = "0", env = "CACHE_SECONDS")]
     pub(crate) cache_seconds: u64,
+    /// Age in seconds up to which a zone identifier fetched before is used when looking it up
+    /// fails transiently e.g. during an outage of the API, even if expired from cache. Give 0 to
+    /// fail the run instead
+    #[structopt(long, default_value = "86400", env = "STALE_ZONE_SECONDS")]
+    pub(crate) stale_zone_seconds: u64,
+    /// Records to look up or update at a time, give 0 to handle all at once. Spreads runs over
+    /// large record sets to stay under Cloudflare rate limits of 1200 requests per 5 minutes
+    #[structopt(long, default_value = "0", env = "BATCH_SIZE")]
+    pub(crate) batch_size: usize,
+    /// Pause in milliseconds between batches, see --batch-size
+    #[structopt(long, default_value = "1000", env = "BATCH_DELAY_MILLIS")]
+    pub(crate) batch_delay_millis: u64,
+    /// Time in milliseconds to wait for each source of the public IP address e.g. ipify, before
+    /// retrying it or moving on to the next one
+    #[structopt(long, default_value = "5000", env = "IP_SOURCE_TIMEOUT_MILLIS")]
+    pub(crate) ip_source_timeout_millis: u64,
+    /// Times to retry each source of the public IP address before moving on to the next one
+    #[structopt(long, default_value = "0", env = "IP_SOURCE_RETRIES")]
+    pub(crate) ip_source_retries: u32,
+    /// Ceiling of delays between retries in milliseconds, as they grow exponentially
+    #[structopt(long, default_value = "30000", env = "RETRY_MAX_DELAY_MILLIS")]
+    pub(crate) retry_max_delay_millis: u64,
+    /// Retries of record updates failed with transient errors, shared by the records of a run.
+    /// Once spent, remaining failures are reported without retry
+    #[structopt(long, default_value = "10", env = "RETRY_BUDGET")]
+    pub(crate) retry_budget: usize,
+    /// URL of a probe answering whether the public IP address is reachable from outside e.g.
+    /// https://probe.example.net/check?ip={ip}&port=443, on a host elsewhere or a probe API.
+    /// Records are left as they are unless it answers with success for each address
+    #[structopt(long, env = "REACHABILITY_PROBE")]
+    pub(crate) reachability_probe: Option<String>,
+    /// Leave records proxied through Cloudflare out of verify stages, since DNS lookups of them
+    /// return addresses of Cloudflare rather than their content
+    #[structopt(long)]
+    pub(crate) skip_verify_proxied: bool,
+    /// File to keep updated with the current public IP address e.g. /var/run/cdu/ip, the IPv4
+    /// address with --ip-version both
+    #[structopt(long, env = "IP_FILE", parse(from_os_str))]
+    pub(crate) ip_file: Option<PathBuf>,
+    /// File to keep updated with the current public IPv6 address with --ip-version both, or
+    /// instead of --ip-file with --ip-version 6 e.g. /var/run/cdu/ip6
+    #[structopt(long, env = "IP6_FILE", parse(from_os_str))]
+    pub(crate) ip6_file: Option<PathBuf>,
+    /// File to keep the state of the last run in, to tell what changed across restarts
+    #[structopt(long, env = "STATUS_FILE", parse(from_os_str))]
+    pub(crate) status_file: Option<PathBuf>,
+    /// File to append each change of the public IP address to as JSON lines
+    #[structopt(long, env = "HISTORY_FILE", parse(from_os_str))]
+    pub(crate) history_file: Option<PathBuf>,
+    /// Keep state and history in the status and history files, json, in the database file,
+    /// sqlite, or in memory only, memory, e.g. to spare flash storage of embedded devices. Files
+    /// are ignored with memory
+    #[structopt(long, default_value = "json", env = "STORE")]
+    pub(crate) store: StoreKind,
+    /// SQLite database to keep state and history in with --store sqlite
+    #[cfg(feature = "sqlite")]
+    #[structopt(long, env = "DATABASE_FILE", parse(from_os_str))]
+    pub(crate) database_file: Option<PathBuf>,
+    /// Files to watch in daemon mode, any change triggers an update e.g. /var/run/ppp0.pid. Told
+    /// by inotify on Linux, polled every second otherwise
+    #[structopt(long, parse(from_os_str))]
+    pub(crate) watch_file: Vec<PathBuf>,
+    /// Update DNS records once addresses or routes of network interfaces change in daemon mode,
+    /// on Linux and macOS, besides the schedule as a fallback
+    #[structopt(long)]
+    pub(crate) watch_network: bool,
+    /// Unix socket to accept triggers on in daemon mode e.g. /var/run/cdu/control.sock
+    #[structopt(long, env = "CONTROL_SOCKET", parse(from_os_str))]
+    pub(crate) control_socket: Option<PathBuf>,
+    /// User name or ID to switch to in daemon mode once listeners are bound, when started as
+    /// root e.g. to listen on a low port
+    #[cfg(unix)]
+    #[structopt(long)]
+    pub(crate) user: Option<String>,
+    /// Group name or ID to switch to along with --user, the primary group of the user if omitted
+    #[cfg(unix)]
+    #[structopt(long)]
+    pub(crate) group: Option<String>,
+    /// Fork into the background in daemon mode, for init systems which can't supervise
+    /// processes in the foreground
+    #[cfg(unix)]
+    #[structopt(long)]
+    pub(crate) detach: bool,
+    /// File to append logs to once detached e.g. /var/log/cdu.log, discarded if omitted
+    #[cfg(unix)]
+    #[structopt(long, env = "LOG_FILE", parse(from_os_str))]
+    pub(crate) log_file: Option<PathBuf>,
+    /// File to write the process ID to e.g. /var/run/cdu.pid, locked while DNS records are
+    /// updated so another instance, or a run by cron, fails instead of updating them at once
+    #[cfg(unix)]
+    #[structopt(long, env = "PID_FILE", parse(from_os_str))]
+    pub(crate) pid_file: Option<PathBuf>,
+    /// Address to listen for webhooks on in daemon mode e.g. 0.0.0.0:8080. POST {"ip": "..."}
+    /// with bearer token to update DNS records, the IP address is detected if omitted
+    #[structopt(long, env = "WEBHOOK_LISTEN")]
+    pub(crate) webhook_listen: Option<SocketAddr>,
+    /// Bearer token to authenticate webhooks
+    #[structopt(long, env = "WEBHOOK_TOKEN", hide_env_values = true)]
+    pub(crate) webhook_token: Option<String>,
+    /// URL to POST changes and failures to as JSON e.g. {"kind": "ip_changed", "message": "..."}
+    #[structopt(long, env = "NOTIFY_URL")]
+    pub(crate) notify_url: Option<reqwest::Url>,
+    /// Collect notifications over this window e.g. 1h, and send them as one digest with the first
+    /// event after the window
+    #[structopt(long, env = "NOTIFY_DIGEST", requires = "notify-url")]
+    pub(crate) notify_digest: Option<humantime::Duration>,
+    /// Hold back notifications of changes during this window in local time e.g. 23:00-07:00,
+    /// and send them afterwards. Failures are still notified immediately
+    #[structopt(long, env = "NOTIFY_QUIET_HOURS")]
+    pub(crate) notify_quiet_hours: Option<QuietHours>,
+    /// Address to serve Prometheus metrics on at /metrics e.g. 127.0.0.1:9090, along with health
+    /// at /health, and POST /trigger with the bearer token of webhooks to start a run
+    #[structopt(long, env = "METRICS_LISTEN")]
+    pub(crate) metrics_listen: Option<SocketAddr>,
+    /// Announce the metrics listener on the local network by mDNS as _cdu._tcp.local, named
+    /// after the host
+    #[structopt(long, requires = "metrics-listen")]
+    pub(crate) mdns: bool,
+    /// Certificate chain in PEM to serve metrics over TLS with, along with --tls-key
+    #[structopt(long, env = "TLS_CERT", parse(from_os_str), requires = "tls-key")]
+    pub(crate) tls_cert: Option<PathBuf>,
+    /// Private key in PEM to serve metrics over TLS with, along with --tls-cert
+    #[structopt(long, env = "TLS_KEY", parse(from_os_str), requires = "tls-cert")]
+    pub(crate) tls_key: Option<PathBuf>,
+    /// Address to serve gRPC control and status service on in daemon mode e.g. 127.0.0.1:50051
+    #[cfg(feature = "grpc")]
+    #[structopt(long, env = "GRPC_LISTEN")]
+    pub(crate) grpc_listen: Option<SocketAddr>,
+    #[structopt(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+#[derive(Clone)]
+pub enum LogTimestamps {
+    None,
+    Local,
+    Utc,
+    Rfc3339,
+    Custom(String),
+}
+
+impl FromStr for LogTimestamps {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(LogTimestamps::None),
+            "local" => Ok(LogTimestamps::Local),
+            "utc" => Ok(LogTimestamps::Utc),
+            "rfc3339" => Ok(LogTimestamps::Rfc3339),
+            s if s.contains('%') => {
+                if StrftimeItems::new(s).any(|item| item == Item::Error) {
+                    bail!("invalid timestamp format: {}", s);
+                }
+                Ok(LogTimestamps::Custom(s.to_string()))
+            }
+            s => bail!("unknown log timestamps: {}", s),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogTarget {
+    Stderr,
+    #[cfg(unix)]
+    Syslog,
+    #[cfg(target_os = "linux")]
+    Journald,
+}
+
+impl FromStr for LogTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stderr" => Ok(LogTarget::Stderr),
+            #[cfg(unix)]
+            "syslog" => Ok(LogTarget::Syslog),
+            #[cfg(target_os = "linux")]
+            "journald" => Ok(LogTarget::Journald),
+            s => bail!("unknown log target: {}", s),
+        }
+    }
+}
+
+/// Address family of the public IP address
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpVersion {
+    V4,
+    V6,
+    Both,
+}
+
+impl FromStr for IpVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "4" => Ok(IpVersion::V4),
+            "6" => Ok(IpVersion::V6),
+            "both" => Ok(IpVersion::Both),
+            s => bail!("unknown IP version: {}", s),
+        }
+    }
+}
+
+/// Address family to connect to the Cloudflare API over
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApiIpVersion {
+    Auto,
+    V4,
+    V6,
+}
+
+impl FromStr for ApiIpVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ApiIpVersion::Auto),
+            "4" => Ok(ApiIpVersion::V4),
+            "6" => Ok(ApiIpVersion::V6),
+            s => bail!("unknown IP version: {}", s),
+        }
+    }
+}
+
+/// Whether records on Cloudflare are proxied after update
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Proxied {
+    True,
+    False,
+    /// Leave proxy status as is, new records aren't proxied
+    Keep,
+}
+
+impl Proxied {
+    /// Proxy status to send on update, given the current one
+    pub(crate) fn resolve(self, current: Option<bool>) -> Option<bool> {
+        match self {
+            Proxied::True => Some(true),
+            Proxied::False => Some(false),
+            Proxied::Keep => current,
+        }
+    }
+}
+
+impl FromStr for Proxied {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "true" => Ok(Proxied::True),
+            "false" => Ok(Proxied::False),
+            "keep" => Ok(Proxied::Keep),
+            s => bail!("unknown proxied: {}, expected true, false, or keep", s),
+        }
+    }
+}
+
+#[derive(StructOpt)]
+pub enum Command {
+    /// Signal running daemon over the control socket, or update once if no daemon is listening.
+    /// Intended to be called from /etc/ppp/ip-up.d or dhclient hooks
+    Trigger {
+        /// Public IPv4 or IPv6 address given by the hook e.g. $PPP_LOCAL, skip detection if set
+        #[structopt(long)]
+        ip: Option<IpAddr>,
+    },
+    /// Park DNS records of the running daemon at a maintenance IPv4 address or name, over the
+    /// control socket
+    Park {
+        /// IPv4 address or name e.g. status.example.com
+        target: ParkTarget,
+    },
+    /// Switch DNS records of the running daemon back to the public IP address, over the control
+    /// socket
+    Unpark,
+    /// Print DNS records with their current type and content
+    List,
+    /// Check configuration and DNS records without updating them
+    Validate,
+    /// Check configuration for common mistakes without making API calls, exit non-zero with
+    /// every problem found e.g. in pre-commit hooks
+    Lint,
+    /// List providers compiled in and what they support e.g. AAAA or proxied records
+    Providers,
+    /// Print how often the existing DDNS tool agreed with cdu, see --migration-report
+    MigrationReport,
+    /// Manage Cloudflare tokens
+    Token(TokenCommand),
+    /// Work with the configuration file
+    Config(ConfigCommand),
+    /// Work with the history of the public IP address, see --history-file
+    History(HistoryCommand),
+    /// Print a bug report as JSON to attach to GitHub issues, with version, options,
+    /// configuration file, state of the last run, and recent logs. Secrets are redacted
+    Report {
+        /// Log file to include the last lines of e.g. /var/log/cdu.log
+        #[structopt(long, parse(from_os_str))]
+        log: Option<PathBuf>,
+        /// Number of log lines to include
+        #[structopt(long, default_value = "100")]
+        lines: usize,
+    },
+    /// Manage scheduled task which updates DNS records periodically, without a resident daemon
+    #[cfg(windows)]
+    Task(TaskCommand),
+    /// Manage Windows service which runs the daemon in the background from startup
+    #[cfg(windows)]
+    Service(ServiceCommand),
+}
+
+#[derive(StructOpt)]
+pub enum ConfigCommand {
+    /// Print JSON Schema of the configuration file for completion and validation in editors
+    Schema,
+    /// Print a commented configuration file with the options given on command line or by
+    /// environment variables filled in, e.g. to move from environment variables to a file
+    Init {
+        /// Write to the file instead, which must not exist yet
+        #[structopt(long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(StructOpt)]
+pub enum HistoryCommand {
+    /// Print changes of the public IP address e.g. for spreadsheets or complaints to the ISP
+    Export {
+        /// csv or json
+        #[structopt(long, default_value = "csv")]
+        format: HistoryFormat,
+        /// Only changes within this long e.g. 30d
+        #[structopt(long)]
+        since: Option<humantime::Duration>,
+    },
+}
+
+#[derive(StructOpt)]
+pub enum TokenCommand {
+    /// Create a token limited to reading the zone and editing its DNS records with the global
+    /// API key, and print it
+    Create {
+        /// Email address of Cloudflare account
+        #[structopt(long, env = "CLOUDFLARE_EMAIL")]
+        email: String,
+        /// Global API key of Cloudflare account
+        #[structopt(long, env = "CLOUDFLARE_API_KEY", hide_env_values = true)]
+        api_key: String,
+        /// Name of the token
+        #[structopt(long, default_value = "cdu")]
+        name: String,
+    },
+}
+
+#[cfg(windows)]
+#[derive(StructOpt)]
+pub enum TaskCommand {
+    /// Register scheduled task with Windows Task Scheduler
+    Install {
+        /// Name of scheduled task
+        #[structopt(long, default_value = "cdu")]
+        name: String,
+        /// Interval in minutes between runs
+        #[structopt(long, default_value = "5")]
+        interval_minutes: u32,
+    },
+    /// Remove scheduled task from Windows Task Scheduler
+    Uninstall {
+        /// Name of scheduled task
+        #[structopt(long, default_value = "cdu")]
+        name: String,
+    },
+}
+
+#[cfg(windows)]
+#[derive(StructOpt)]
+pub enum ServiceCommand {
+    /// Register service with the Service Control Manager, started automatically at boot
+    Install {
+        /// Name of service
+        #[structopt(long, default_value = "cdu")]
+        name: String,
+    },
+    /// Stop and remove service from the Service Control Manager
+    Uninstall {
+        /// Name of service
+        #[structopt(long, default_value = "cdu")]
+        name: String,
+    },
+    /// Run as service, only started by the Service Control Manager
+    Run {
+        /// Name of service
+        #[structopt(long, default_value = "cdu")]
+        name: String,
+    },
 }
 
 impl Opts {
+    /// Unique zone names in given order, or zones of the account if listed, normalized to match
+    /// names returned by Cloudflare
+    pub(crate) fn zone_names(&self) -> Vec<String> {
+        if self.zone.is_empty() {
+            return self.account_zones.get().cloned().unwrap_or_default();
+        }
+        let names = self.zone.iter().map(|zone| normalize_name(zone)).collect();
+        dedup_record_names(names).0
+    }
+
+    /// Unique record names in configured order
     pub(crate) fn record_name_list(&self) -> Vec<String> {
-        self.records.split(',').map(String::from).collect()
+        dedup_record_names(self.configured_record_names()).0
+    }
+
+    /// Record names configured more than once
+    pub(crate) fn duplicate_record_names(&self) -> Vec<String> {
+        dedup_record_names(self.configured_record_names()).1
+    }
+
+    /// How long the record lives without the host reporting back, see `expires_after`
+    pub(crate) fn record_expiry(&self, name: &str, view: Option<&str>) -> Option<Duration> {
+        self.record_config(name, view)
+            .and_then(|record| record.expires_after)
+    }
+
+    /// Group of the record, whose schedule updates it besides --cron or --every
+    pub(crate) fn record_group(&self, name: &str) -> Option<&str> {
+        self.record_config(name, None)
+            .and_then(|record| record.group.as_deref())
+    }
+
+    /// Cloudflare tokens of zones given in the configuration file, by normalized zone name
+    pub(crate) fn zone_tokens(&self) -> BTreeMap<String, &SecretString> {
+        self.file
+            .tokens
+            .iter()
+            .map(|(zone, token)| (normalize_name(zone), token))
+            .collect()
+    }
+
+    /// Groups of records with schedules of their own by name
+    pub(crate) fn record_groups(&self) -> &BTreeMap<String, GroupConfig> {
+        &self.file.groups
+    }
+
+    /// Where the address published by the record comes from
+    pub(crate) fn record_address(&self, name: &str, view: Option<&str>) -> AddressSource {
+        self.record_config(name, view)
+            .map(|record| record.address)
+            .unwrap_or_default()
+    }
+
+    /// Providers configured by options, named after their type, followed by those in the
+    /// configuration file which take precedence
+    pub(crate) fn provider_configs(&self) -> anyhow::Result<BTreeMap<String, ProviderConfig>> {
+        let mut configs = BTreeMap::new();
+        if let Some(ref url) = self.powerdns_url {
+            let api_key = match self.powerdns_api_key {
+                Some(ref api_key) => api_key.clone(),
+                None => bail!("PowerDNS API key is required"),
+            };
+            let config = ProviderConfig::Powerdns {
+                url: url.clone(),
+                api_key,
+                server_id: self.powerdns_server_id.clone(),
+            };
+            configs.insert("powerdns".to_string(), config);
+        }
+        if let Some(server) = self.rfc2136_server {
+            let (key_name, key_secret) = match (&self.rfc2136_key_name, &self.rfc2136_key_secret) {
+                (Some(name), Some(secret)) => (name.clone(), secret.clone()),
+                _ => bail!("TSIG key name and secret are required for RFC 2136 updates"),
+            };
+            let config = ProviderConfig::Rfc2136 {
+                server,
+                key_name,
+                key_secret,
+                key_algorithm: self.rfc2136_key_algorithm,
+            };
+            configs.insert("rfc2136".to_string(), config);
+        }
+        if self.gcp_credentials.is_some() || self.gcp_project.is_some() {
+            let config = ProviderConfig::Gcp {
+                credentials: self.gcp_credentials.clone(),
+                project: self.gcp_project.clone(),
+            };
+            configs.insert("gcp".to_string(), config);
+        }
+        if let Some(ref subscription_id) = self.azure_subscription_id {
+            let config = match (
+                &self.azure_resource_group,
+                &self.azure_tenant_id,
+                &self.azure_client_id,
+                &self.azure_client_secret,
+            ) {
+                (Some(resource_group), Some(tenant_id), Some(client_id), Some(client_secret)) => {
+                    ProviderConfig::Azure {
+                        subscription_id: subscription_id.clone(),
+                        resource_group: resource_group.clone(),
+                        tenant_id: tenant_id.clone(),
+                        client_id: client_id.clone(),
+                        client_secret: client_secret.clone(),
+                    }
+                }
+                _ => bail!(
+                    "Azure resource group, tenant ID, client ID, and client secret are required"
+                ),
+            };
+            configs.insert("azure".to_string(), config);
+        }
+        if let Some(ref application_key) = self.ovh_application_key {
+            let config = match (&self.ovh_application_secret, &self.ovh_consumer_key) {
+                (Some(application_secret), Some(consumer_key)) => ProviderConfig::Ovh {
+                    endpoint: self.ovh_endpoint.clone(),
+                    application_key: application_key.clone(),
+                    application_secret: application_secret.clone(),
+                    consumer_key: consumer_key.clone(),
+                },
+                _ => bail!("OVH application secret and consumer key are required"),
+            };
+            configs.insert("ovh".to_string(), config);
+        }
+        for (name, config) in &self.file.providers {
+            configs.insert(name.clone(), config.clone());
+        }
+        Ok(configs)
+    }
+
+    /// Proxy status the record is updated with, given after its name on command line, in the
+    /// configuration file, or by --proxied
+    pub(crate) fn record_proxied(&self, name: &str, view: Option<&str>) -> Proxied {
+        let option = self
+            .record_options()
+            .into_iter()
+            .find(|(n, _)| n == name && view.is_none());
+        match option.as_ref().map(|(_, option)| option.as_str()) {
+            Some("proxied") => return Proxied::True,
+            Some("dns-only") => return Proxied::False,
+            _ => {}
+        }
+        match self
+            .record_config(name, view)
+            .and_then(|record| record.proxied)
+        {
+            Some(true) => Proxied::True,
+            Some(false) => Proxied::False,
+            None => self.proxied,
+        }
+    }
+
+    /// Type of record in the configuration file, both or either by --ip-version if omitted
+    pub(crate) fn record_type(&self, name: &str, view: Option<&str>) -> Option<RecordType> {
+        self.record_config(name, view)
+            .and_then(|record| record.record_type)
+    }
+
+    /// TTL the record is updated with, in the configuration file or by --ttl
+    pub(crate) fn record_ttl(&self, name: &str, view: Option<&str>) -> Option<u32> {
+        self.record_config(name, view)
+            .and_then(|record| record.ttl)
+            .or(self.ttl)
+    }
+
+    /// Secrets given on command line, to be kept out of bug reports
+    pub(crate) fn secrets(&self) -> Vec<&str> {
+        let tokens = vec![&self.token, &self.fallback_token]
+            .into_iter()
+            .filter_map(|token| token.as_ref().map(|token| token.expose_secret().as_str()))
+            .chain(
+                self.file
+                    .tokens
+                    .values()
+                    .map(|token| token.expose_secret().as_str()),
+            );
+        let secrets = vec![
+            &self.powerdns_api_key,
+            &self.rfc2136_key_secret,
+            &self.azure_client_secret,
+            &self.ovh_application_key,
+            &self.ovh_application_secret,
+            &self.ovh_consumer_key,
+            &self.webhook_token,
+        ]
+        .into_iter()
+        .filter_map(|secret| secret.as_deref());
+        tokens.chain(secrets).collect()
+    }
+
+    /// Options given after record names on command line e.g. proxied, with the names
+    pub(crate) fn record_options(&self) -> Vec<(String, String)> {
+        self.records
+            .as_deref()
+            .map(parse_record_options)
+            .unwrap_or_default()
+    }
+
+    /// Provider the record is published with, none for Cloudflare
+    pub(crate) fn record_provider(&self, name: &str, view: Option<&str>) -> Option<String> {
+        self.record_config(name, view)
+            .and_then(|record| record.provider.clone())
+            .filter(|provider| provider != "cloudflare")
+    }
+
+    /// Address family to publish, --ipv6 overrides --ip-version
+    pub(crate) fn ip_version(&self) -> IpVersion {
+        if self.ipv6 {
+            IpVersion::V6
+        } else {
+            self.ip_version
+        }
+    }
+
+    /// Zone the record belongs to, in the configuration file or the longest zone given which the
+    /// record is in. Without --zone, empty if the record isn't in any zone of the account or
+    /// zones aren't listed yet
+    pub(crate) fn record_zone(&self, name: &str, view: Option<&str>) -> String {
+        if let Some(zone) = self
+            .record_config(name, view)
+            .and_then(|record| record.zone.as_deref())
+        {
+            return normalize_name(zone);
+        }
+        self.zone_names()
+            .into_iter()
+            .filter(|zone| name == zone || name.ends_with(&format!(".{}", zone)))
+            .max_by_key(|zone| zone.len())
+            .unwrap_or_else(|| match self.zone.first() {
+                Some(zone) => normalize_name(zone),
+                None => String::new(),
+            })
+    }
+
+    /// Further entries of the record in the configuration file, by zones or providers of their
+    /// own e.g. `powerdns/internal.lan`, published beside the first one e.g. for split-horizon DNS
+    pub(crate) fn record_views(&self, name: &str) -> Vec<String> {
+        let mut views: Vec<String> = vec![];
+        for record in self.record_entries(name) {
+            let view = view_of(record);
+            if !views.contains(&view) {
+                views.push(view);
+            }
+        }
+        views.into_iter().skip(1).collect()
+    }
+
+    /// Entry of the record in the configuration file, the first one or one of its views
+    fn record_config(&self, name: &str, view: Option<&str>) -> Option<&RecordConfig> {
+        let mut entries = self.record_entries(name).into_iter();
+        match view {
+            Some(view) => entries.find(|record| view_of(record) == view),
+            None => entries.next(),
+        }
+    }
+
+    fn record_entries(&self, name: &str) -> Vec<&RecordConfig> {
+        self.file
+            .records
+            .iter()
+            .filter(|record| record.enabled && normalize_name(&record.name) == name)
+            .collect()
+    }
+
+    /// Record names disabled in the configuration file
+    pub(crate) fn disabled_record_names(&self) -> Vec<String> {
+        self.file
+            .records
+            .iter()
+            .filter(|record| !record.enabled)
+            .map(|record| normalize_name(&record.name))
+            .collect()
+    }
+
+    fn configured_record_names(&self) -> Vec<String> {
+        // entries in zones or with providers of their own are views rather than duplicates
+        let enabled = self
+            .file
+            .records
+            .iter()
+            .enumerate()
+            .filter(|(_, record)| record.enabled)
+            .filter(|(index, record)| {
+                let name = normalize_name(&record.name);
+                let earlier: Vec<_> = self.file.records[..*index]
+                    .iter()
+                    .filter(|earlier| earlier.enabled && normalize_name(&earlier.name) == name)
+                    .collect();
+                earlier.is_empty()
+                    || earlier
+                        .iter()
+                        .any(|earlier| view_of(earlier) == view_of(record))
+            })
+            .map(|(_, record)| normalize_name(&record.name));
+        // disabled in the configuration file wins over command line
+        let disabled = self.disabled_record_names();
+        let mut names: Vec<String> = self
+            .records
+            .iter()
+            .flat_map(|records| parse_record_names(records))
+            .chain(enabled)
+            .filter(|name| !disabled.contains(name))
+            .collect();
+        // matched names given by name as well are not duplicates
+        let matched = self.pattern_records.get().cloned().unwrap_or_default();
+        for name in matched {
+            if !names.contains(&name) && !disabled.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// Parse command line and the configuration file if given, see [`Opts::from_iter_with_config`]
+    pub fn from_args_with_config() -> anyhow::Result<Self> {
+        Self::from_iter_with_config(std::env::args_os())
+    }
+
+    /// Parse command line and the configuration file if given. Options in the file e.g. `cron:
+    /// "0 */5 * * * *"` apply unless given on command line, and override environment variables.
+    /// Exits on invalid command line as [`StructOpt::from_iter`] does.
+    pub fn from_iter_with_config<I>(args: I) -> anyhow::Result<Self>
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString>,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let app = Self::clap();
+        let matches = app.clone().get_matches_from(&args);
+        let opts = Self::from_clap(&matches);
+        let path = match opts.config {
+            Some(ref path) => path.clone(),
+            None if opts.profile.is_some() => bail!("--profile requires --config"),
+            None => return Ok(opts),
+        };
+        let file = Config::load(&path, opts.profile.as_deref())?;
+        if file.options.is_empty() {
+            return Ok(Self { file, ..opts });
+        }
+
+        let mut file_args = vec![];
+        let mut secrets = vec![];
+        for (key, value) in &file.options {
+            let name = key.replace('_', "-");
+            if name == "config" {
+                bail!("config file {}: config can't be nested", path.display());
+            }
+            if matches.occurrences_of(name.as_str()) > 0 {
+                continue;
+            }
+            if SECRET_OPTIONS.contains(&name.as_str()) {
+                match value {
+                    Value::String(secret) => secrets.push((name, secret.clone())),
+                    _ => bail!("config file {}: {}: expected a string", path.display(), key),
+                }
+                continue;
+            }
+            // flags take no value, unlike options with default values which allow it omitted
+            let is_flag = app
+                .clone()
+                .get_matches_from_safe(&["cdu".to_string(), format!("--{}", name)])
+                .map(|matches| matches.value_of(name.as_str()).is_none())
+                .unwrap_or(false);
+            file_args.extend(
+                option_args(&name, value, is_flag).map_err(|e| {
+                    anyhow::anyhow!("config file {}: {}: {}", path.display(), key, e)
+                })?,
+            );
+        }
+        let mut args = args.into_iter();
+        let merged: Vec<OsString> = args
+            .next()
+            .into_iter()
+            .chain(file_args)
+            .chain(args)
+            .collect();
+        let matches = app
+            .setting(AppSettings::ColorNever)
+            .get_matches_from_safe(merged)
+            .map_err(|e| {
+                // first line without usage
+                let message = e.message.lines().next().unwrap_or_default();
+                let message = message.trim_start_matches("error: ");
+                anyhow::anyhow!("config file {}: {}", path.display(), message)
+            })?;
+        let mut opts = Self {
+            file,
+            ..Self::from_clap(&matches)
+        };
+        for (name, secret) in secrets {
+            opts.set_secret(&name, secret);
+        }
+        Ok(opts)
+    }
+
+    /// Set an option of [`SECRET_OPTIONS`] by long name
+    fn set_secret(&mut self, name: &str, secret: String) {
+        match name {
+            "token" => self.token = Some(SecretString::new(secret)),
+            "fallback-token" => self.fallback_token = Some(SecretString::new(secret)),
+            "powerdns-api-key" => self.powerdns_api_key = Some(secret),
+            "rfc2136-key-secret" => self.rfc2136_key_secret = Some(secret),
+            "azure-client-secret" => self.azure_client_secret = Some(secret),
+            "ovh-application-key" => self.ovh_application_key = Some(secret),
+            "ovh-application-secret" => self.ovh_application_secret = Some(secret),
+            "ovh-consumer-key" => self.ovh_consumer_key = Some(secret),
+            "webhook-token" => self.webhook_token = Some(secret),
+            _ => unreachable!("{} is not a secret option", name),
+        }
+    }
+}
+
+/// Options holding credentials, taken from the configuration file as they are rather than
+/// copied into command line arguments for clap to parse
+const SECRET_OPTIONS: &[&str] = &[
+    "token",
+    "fallback-token",
+    "powerdns-api-key",
+    "rfc2136-key-secret",
+    "azure-client-secret",
+    "ovh-application-key",
+    "ovh-application-secret",
+    "ovh-consumer-key",
+    "webhook-token",
+];
+
+/// Provider and zone of an entry of a record, whichever are given, joined by a slash
+fn view_of(record: &RecordConfig) -> String {
+    let provider = record
+        .provider
+        .as_deref()
+        .filter(|provider| *provider != "cloudflare");
+    let zone = record.zone.as_deref().map(normalize_name);
+    match (provider, zone) {
+        (Some(provider), Some(zone)) => format!("{}/{}", provider, zone),
+        (Some(provider), None) => provider.to_string(),
+        (None, Some(zone)) => zone,
+        (None, None) => String::new(),
+    }
+}
+
+/// Command line arguments of an option in the configuration file, repeated for a list
+fn option_args(name: &str, value: &Value, is_flag: bool) -> anyhow::Result<Vec<OsString>> {
+    let scalar = |value: &Value| match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => bail!("expected a string, number, or boolean"),
+    };
+    match value {
+        Value::Bool(true) if is_flag => Ok(vec![format!("--{}", name).into()]),
+        Value::Bool(false) if is_flag => Ok(vec![]),
+        _ if is_flag => bail!("expected true or false"),
+        Value::Sequence(values) => values
+            .iter()
+            .map(|value| Ok(format!("--{}={}", name, scalar(value)?).into()))
+            .collect(),
+        value => Ok(vec![format!("--{}={}", name, scalar(value)?).into()]),
     }
 }
@@ -0,0 +1,140 @@
+use std::io::IsTerminal;
+use std::time::Duration;
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Human-friendly summary printed to stdout, separate from logging
+#[derive(Clone, Copy)]
+pub(crate) struct Output {
+    enabled: bool,
+    color: bool,
+}
+
+impl Output {
+    /// Print only if stdout is a terminal, and colorize unless disabled by flag or `NO_COLOR`
+    pub(crate) fn new(no_color: bool) -> Self {
+        let enabled = std::io::stdout().is_terminal();
+        let color = enabled && !no_color && std::env::var_os("NO_COLOR").is_none();
+        Self { enabled, color }
+    }
+
+    pub(crate) fn updated(
+        &self,
+        record_name: &str,
+        old_content: Option<&str>,
+        new_content: &str,
+        duration: Duration,
+    ) {
+        self.print(
+            GREEN,
+            "✓",
+            &format!(
+                "{} {} → {} ({}ms)",
+                record_name,
+                old_content.unwrap_or("?"),
+                new_content,
+                duration.as_millis()
+            ),
+        );
+    }
+
+    pub(crate) fn dry_run(&self, record_name: &str, old_content: Option<&str>, new_content: &str) {
+        self.print(
+            YELLOW,
+            "-",
+            &format!(
+                "{} {} → {} (dry run)",
+                record_name,
+                old_content.unwrap_or("?"),
+                new_content
+            ),
+        );
+    }
+
+    pub(crate) fn skipped(&self, record_name: &str, content: Option<&str>) {
+        self.print(
+            YELLOW,
+            "-",
+            &format!("{} {} (skipped)", record_name, content.unwrap_or("?")),
+        );
+    }
+
+    pub(crate) fn observed(&self, record_name: &str, content: &str, expected: &str) {
+        if content == expected {
+            self.print(
+                GREEN,
+                "✓",
+                &format!("{} {} (in sync)", record_name, content),
+            );
+        } else {
+            self.print(
+                RED,
+                "✗",
+                &format!("{} {} ≠ {} (drift)", record_name, content, expected),
+            );
+        }
+    }
+
+    pub(crate) fn expired(&self, record_name: &str, content: Option<&str>) {
+        self.print(
+            YELLOW,
+            "-",
+            &format!(
+                "{} {} (expired, deleted)",
+                record_name,
+                content.unwrap_or("?")
+            ),
+        );
+    }
+
+    pub(crate) fn displaced(&self, record_name: &str, content: Option<&str>) {
+        self.print(
+            YELLOW,
+            "-",
+            &format!(
+                "{} {} (parked at the A record, deleted)",
+                record_name,
+                content.unwrap_or("?")
+            ),
+        );
+    }
+
+    pub(crate) fn parked(&self, record_name: &str, old_content: Option<&str>, new_content: &str) {
+        self.print(
+            YELLOW,
+            "~",
+            &format!(
+                "{} {} → {} (expired, parked)",
+                record_name,
+                old_content.unwrap_or("?"),
+                new_content
+            ),
+        );
+    }
+
+    pub(crate) fn duplicate(&self, record_name: &str) {
+        self.print(
+            YELLOW,
+            "-",
+            &format!("{} (duplicate, ignored)", record_name),
+        );
+    }
+
+    pub(crate) fn failed(&self, record_name: &str, error: &anyhow::Error) {
+        self.print(RED, "✗", &format!("{} {}", record_name, error));
+    }
+
+    fn print(&self, color: &str, mark: &str, message: &str) {
+        if !self.enabled {
+            return;
+        }
+        if self.color {
+            println!("{}{}{} {}", color, mark, RESET, message);
+        } else {
+            println!("{} {}", mark, message);
+        }
+    }
+}
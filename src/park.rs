@@ -0,0 +1,67 @@
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use cloudflare::endpoints::dns::DnsContent;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::record::{normalize_name, validate_name};
+
+/// Maintenance destination of parked records, an IPv4 address or a name e.g. a status page
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParkTarget {
+    Address(Ipv4Addr),
+    Name(String),
+}
+
+impl ParkTarget {
+    /// Content of parked records, A record for address and CNAME record for name
+    pub(crate) fn content(&self) -> DnsContent {
+        match self {
+            ParkTarget::Address(content) => DnsContent::A { content: *content },
+            ParkTarget::Name(content) => DnsContent::CNAME {
+                content: content.clone(),
+            },
+        }
+    }
+}
+
+impl FromStr for ParkTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(ip_address) = s.parse() {
+            return Ok(ParkTarget::Address(ip_address));
+        }
+        let name = normalize_name(s);
+        validate_name(&name, false).map_err(|reason| anyhow::anyhow!("{}: {}", s, reason))?;
+        Ok(ParkTarget::Name(name))
+    }
+}
+
+impl fmt::Display for ParkTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParkTarget::Address(ip_address) => ip_address.fmt(f),
+            ParkTarget::Name(name) => name.fmt(f),
+        }
+    }
+}
+
+/// Kept as in --park e.g. in the state of the last run
+impl Serialize for ParkTarget {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ParkTarget {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Park target shared between the control socket and runs, none when not parked
+pub type ParkSwitch = Arc<Mutex<Option<ParkTarget>>>;
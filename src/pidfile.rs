@@ -0,0 +1,62 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use once_cell::sync::OnceCell;
+
+/// PID file locked for the life of the process, and of the process it forks into
+static PID_FILE: OnceCell<File> = OnceCell::new();
+
+/// Lock the PID file exclusively and write the process ID to it, failing if another process holds
+/// the lock, e.g. a daemon or a run by cron updating the same records. The file is left in place
+/// on exit, since the lock rather than the file tells whether cdu is running.
+pub(crate) fn lock(path: &Path) -> anyhow::Result<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("failed to open PID file {}", path.display()))?;
+    // SAFETY: the file descriptor is open
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == -1 {
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::WouldBlock {
+            bail!("failed to lock PID file {}: {}", path.display(), e);
+        }
+        let mut pid = String::new();
+        let _ = file.read_to_string(&mut pid);
+        match pid.trim() {
+            "" => bail!(
+                "another cdu is running, PID file {} is locked",
+                path.display()
+            ),
+            pid => bail!(
+                "another cdu is running with PID {}, PID file {} is locked",
+                pid,
+                path.display()
+            ),
+        }
+    }
+    write_pid(&file, std::process::id())
+        .with_context(|| format!("failed to write PID file {}", path.display()))?;
+    let _ = PID_FILE.set(file);
+    Ok(())
+}
+
+/// Replace the process ID in the locked PID file, e.g. with the one of the forked process
+pub(crate) fn replace(pid: u32) -> io::Result<()> {
+    match PID_FILE.get() {
+        Some(file) => write_pid(file, pid),
+        None => Ok(()),
+    }
+}
+
+fn write_pid(mut file: &File, pid: u32) -> io::Result<()> {
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(format!("{}\n", pid).as_bytes())?;
+    file.sync_all()
+}
@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant};
+
+use cloudflare::endpoints::dns::DnsContent;
+use futures::future::BoxFuture;
+
+use crate::{AddressSource, ParkTarget};
+
+/// Phases of a run in order. Custom stages run right after the built-in phase they're added to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Determine public IP address
+    Detect,
+    /// Look up zone and DNS record identifiers
+    Resolve,
+    /// Decide which records need to change
+    Diff,
+    /// Update DNS records
+    Apply,
+    /// Confirm updates took effect
+    Verify,
+    /// Report outcome to terminal, subscribers, and state
+    Notify,
+}
+
+/// DNS record as it moves through the pipeline
+pub struct RecordContext {
+    pub name: String,
+    /// DNS record identifier, known after resolve
+    pub id: Option<String>,
+    /// Content before update, unknown if the record was found in cache
+    pub old_content: Option<String>,
+    /// Content after update, none if not applied e.g. dry run, observe mode, or skipped
+    pub new_content: Option<String>,
+    /// Leave the record alone in apply
+    pub skip: bool,
+    pub error: Option<anyhow::Error>,
+    /// Time spent in apply
+    pub duration: Duration,
+    /// Time from detecting the IP address to the record updated, known after apply
+    pub latency: Option<Duration>,
+    /// Delete the record if the host hasn't reported back within this long
+    pub expires_after: Option<Duration>,
+    /// Host didn't report back in time, the record is deleted in apply
+    pub expired: bool,
+    /// Host didn't report back in time, the record points here in apply until it's deleted
+    pub parked: Option<ParkTarget>,
+    /// Record of the other family of a parked name in dual-stack mode, deleted in apply so the
+    /// name has one record pointing to the maintenance destination
+    pub displaced: bool,
+    /// Publish the public or the local address
+    pub address: AddressSource,
+    /// Zone the record belongs to
+    pub zone: String,
+    /// Provider the record is published with, none for Cloudflare
+    pub provider: Option<String>,
+    /// Provider and zone of a further entry of the name e.g. the internal view of split-horizon
+    /// DNS, none for the first one
+    pub view: Option<String>,
+    /// Publish the IPv6 address as AAAA record rather than the IPv4 address as A record
+    pub ipv6: bool,
+    /// Proxied through Cloudflare, known after resolve and kept on update. DNS lookups return
+    /// addresses of Cloudflare rather than the record content, so verifying it by lookup fails.
+    pub proxied: Option<bool>,
+    /// TTL on Cloudflare, known after resolve unless the record was found in cache
+    pub ttl: Option<u32>,
+    /// Why the record was updated, skipped, or failed, logged with --explain
+    pub explanation: Vec<String>,
+}
+
+impl RecordContext {
+    pub(crate) fn new(name: String) -> Self {
+        Self {
+            name,
+            id: None,
+            old_content: None,
+            new_content: None,
+            skip: false,
+            error: None,
+            duration: Duration::default(),
+            latency: None,
+            expires_after: None,
+            expired: false,
+            parked: None,
+            displaced: false,
+            address: AddressSource::Public,
+            zone: String::new(),
+            provider: None,
+            view: None,
+            ipv6: false,
+            proxied: None,
+            ttl: None,
+            explanation: vec![],
+        }
+    }
+
+    /// Add a reason to the explanation of what happened to the record, e.g. from stages
+    pub fn explain(&mut self, reason: impl Into<String>) {
+        self.explanation.push(reason.into());
+    }
+
+    /// Record is deleted in apply, expired or displaced
+    pub fn deleted(&self) -> bool {
+        self.expired || self.displaced
+    }
+
+    /// Type of the address record, AAAA or A
+    pub fn record_type(&self) -> &'static str {
+        if self.ipv6 {
+            "AAAA"
+        } else {
+            "A"
+        }
+    }
+
+    /// Name, followed by type for AAAA records and by the view for further entries of the name,
+    /// so records of a name are told apart in state and cache
+    pub fn key(&self) -> String {
+        let key = if self.ipv6 {
+            format!("{}/AAAA", self.name)
+        } else {
+            self.name.clone()
+        };
+        match self.view {
+            Some(ref view) => format!("{}@{}", key, view),
+            None => key,
+        }
+    }
+}
+
+/// State shared by stages during one run
+pub struct RunContext {
+    /// Public IPv4 or IPv6 address, published as A or AAAA records
+    pub ip_address: IpAddr,
+    /// Public IPv6 address in dual-stack mode, beside the IPv4 address
+    pub ipv6_address: Option<Ipv6Addr>,
+    /// IP address was reported by the host through a trigger rather than detected,
+    /// confirming the host is alive
+    pub heartbeat: bool,
+    /// IPv4 address on the local network, known if any record publishes it
+    pub local_ip_address: Option<Ipv4Addr>,
+    /// When the IP address was first detected or reported, in an earlier run if records failed to
+    /// update then, to measure how long updates take
+    pub detected_at: Instant,
+    /// Maintenance destination records point to instead of the IP address while parked
+    pub park: Option<ParkTarget>,
+    /// Identifiers of zones on Cloudflare by name, known after resolve
+    pub zone_ids: BTreeMap<String, String>,
+    pub records: Vec<RecordContext>,
+}
+
+impl RunContext {
+    /// Content the record should have, the maintenance destination while parked
+    pub fn desired_content(&self, record: &RecordContext) -> DnsContent {
+        if let Some(target) = self.park.as_ref().or(record.parked.as_ref()) {
+            return target.content();
+        }
+        let ip_address = match (record.ipv6, self.ipv6_address) {
+            (true, Some(ipv6_address)) => IpAddr::V6(ipv6_address),
+            _ => self.ip_address,
+        };
+        match (record.address, self.local_ip_address, ip_address) {
+            (AddressSource::Local, Some(content), IpAddr::V4(_)) => DnsContent::A { content },
+            (_, _, IpAddr::V4(content)) => DnsContent::A { content },
+            (_, _, IpAddr::V6(content)) => DnsContent::AAAA { content },
+        }
+    }
+
+    pub(crate) fn new(ip_address: IpAddr, heartbeat: bool) -> Self {
+        Self {
+            ip_address,
+            heartbeat,
+            detected_at: Instant::now(),
+            ipv6_address: None,
+            local_ip_address: None,
+            park: None,
+            zone_ids: BTreeMap::new(),
+            records: vec![],
+        }
+    }
+}
+
+/// Custom step inserted into the run pipeline e.g. to write results to a database.
+/// Returning error fails the run.
+pub trait Stage: Send + Sync {
+    fn run<'a>(&'a self, ctx: &'a mut RunContext) -> BoxFuture<'a, anyhow::Result<()>>;
+}
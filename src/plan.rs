@@ -0,0 +1,127 @@
+use std::fmt;
+
+use crate::cdu::AUTOMATIC_TTL;
+
+/// What a run would do to a DNS record
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Create,
+    Update,
+    Delete,
+    Keep,
+}
+
+/// DNS record as it is and as it would be after a run, printed with --plan
+pub(crate) struct PlannedRecord {
+    name: String,
+    record_type: &'static str,
+    action: Action,
+    content: (Option<String>, Option<String>),
+    ttl: (Option<u32>, Option<u32>),
+    proxied: (Option<bool>, Option<bool>),
+}
+
+impl PlannedRecord {
+    /// Record to be created, updated, or left alone. TTL and proxy status are kept if not given,
+    /// and unknown for records of other providers.
+    pub(crate) fn new(
+        name: String,
+        record_type: &'static str,
+        content: (Option<String>, String),
+        ttl: (Option<u32>, Option<u32>),
+        proxied: (Option<bool>, Option<bool>),
+    ) -> Self {
+        let (old_content, new_content) = content;
+        let ttl = (ttl.0, ttl.1.or(ttl.0));
+        let proxied = (proxied.0, proxied.1.or(proxied.0));
+        let action = if old_content.is_none() {
+            Action::Create
+        } else if old_content.as_ref() != Some(&new_content)
+            || ttl.0 != ttl.1
+            || proxied.0 != proxied.1
+        {
+            Action::Update
+        } else {
+            Action::Keep
+        };
+        Self {
+            name,
+            record_type,
+            action,
+            content: (old_content, Some(new_content)),
+            ttl,
+            proxied,
+        }
+    }
+
+    /// Record to be deleted as its host hasn't reported back in time
+    pub(crate) fn delete(name: String, record_type: &'static str, content: Option<String>) -> Self {
+        Self {
+            name,
+            record_type,
+            action: Action::Delete,
+            content: (content, None),
+            ttl: (None, None),
+            proxied: (None, None),
+        }
+    }
+}
+
+/// Changes a run would make, in a format similar to `terraform plan`
+pub(crate) struct Plan(pub(crate) Vec<PlannedRecord>);
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for record in &self.0 {
+            let mark = match record.action {
+                Action::Create => "+",
+                Action::Update => "~",
+                Action::Delete => "-",
+                Action::Keep => " ",
+            };
+            writeln!(f, "{} {} {}", mark, record.name, record.record_type)?;
+            let content = |c: &Option<String>| c.clone();
+            attribute(f, "content", &record.content, content)?;
+            attribute(f, "ttl", &record.ttl, |ttl| ttl.map(ttl_of))?;
+            attribute(f, "proxied", &record.proxied, |p| p.map(|p| p.to_string()))?;
+        }
+        let count = |action| self.0.iter().filter(|r| r.action == action).count();
+        write!(
+            f,
+            "Plan: {} to create, {} to update, {} to delete, {} unchanged",
+            count(Action::Create),
+            count(Action::Update),
+            count(Action::Delete),
+            count(Action::Keep)
+        )
+    }
+}
+
+/// Print attribute as is if unchanged, or with its old and new values, omitted if unknown
+fn attribute<T>(
+    f: &mut fmt::Formatter<'_>,
+    label: &str,
+    (old, new): &(Option<T>, Option<T>),
+    show: impl Fn(&Option<T>) -> Option<String>,
+) -> fmt::Result {
+    let label = format!("{}:", label);
+    match (show(old), show(new)) {
+        (None, None) => Ok(()),
+        (Some(old), Some(new)) if old == new => writeln!(f, "    {:<8} {}", label, new),
+        (old, new) => writeln!(
+            f,
+            "    {:<8} {} → {}",
+            label,
+            old.as_deref().unwrap_or("(none)"),
+            new.as_deref().unwrap_or("(none)")
+        ),
+    }
+}
+
+fn ttl_of(ttl: u32) -> String {
+    if ttl == AUTOMATIC_TTL {
+        "auto".to_string()
+    } else {
+        ttl.to_string()
+    }
+}
@@ -0,0 +1,129 @@
+use std::ffi::CString;
+use std::io;
+
+use anyhow::bail;
+use libc::{gid_t, uid_t};
+use log::info;
+
+/// Size of buffer for entries of passwd and group databases
+const BUFFER_SIZE: usize = 16384;
+
+/// Switch to the user, and the group or else the primary group of the user, e.g. after binding
+/// listeners as root. Supplementary groups are dropped.
+pub(crate) fn drop_privileges(user: Option<&str>, group: Option<&str>) -> anyhow::Result<()> {
+    let (uid, primary_gid) = match user {
+        Some(user) => {
+            let (uid, gid) = lookup_user(user)?;
+            (Some(uid), gid)
+        }
+        None => (None, None),
+    };
+    let gid = match (group, primary_gid) {
+        (Some(group), _) => Some(lookup_group(group)?),
+        (None, Some(gid)) => Some(gid),
+        (None, None) if uid.is_some() => {
+            bail!("user {:?} has no primary group, give --group", user)
+        }
+        (None, None) => None,
+    };
+    if let Some(gid) = gid {
+        // SAFETY: the group list outlives the call
+        if unsafe { libc::setgroups(1, &gid) } != 0 {
+            bail!(
+                "failed to drop supplementary groups: {}",
+                io::Error::last_os_error()
+            );
+        }
+        // SAFETY: no memory is involved
+        if unsafe { libc::setgid(gid) } != 0 {
+            bail!(
+                "failed to switch to group {}: {}",
+                gid,
+                io::Error::last_os_error()
+            );
+        }
+    }
+    if let Some(uid) = uid {
+        // SAFETY: no memory is involved
+        if unsafe { libc::setuid(uid) } != 0 {
+            bail!(
+                "failed to switch to user {}: {}",
+                uid,
+                io::Error::last_os_error()
+            );
+        }
+        // SAFETY: no memory is involved
+        if uid != 0 && unsafe { libc::setuid(0) } == 0 {
+            bail!(
+                "root privileges could be regained after switching to user {}",
+                uid
+            );
+        }
+    }
+    let id = |id: Option<u32>| id.map_or_else(|| "(unchanged)".to_string(), |id| id.to_string());
+    info!(
+        "privileges dropped to user {} and group {}",
+        id(uid),
+        id(gid)
+    );
+    Ok(())
+}
+
+/// User ID and primary group ID by name or ID. Primary group is unknown for IDs without entry.
+fn lookup_user(user: &str) -> anyhow::Result<(uid_t, Option<gid_t>)> {
+    // SAFETY: all-zero passwd is valid, filled by the lookup
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0; BUFFER_SIZE];
+    let mut result = std::ptr::null_mut();
+    let id = user.parse::<uid_t>();
+    let rc = match id {
+        // SAFETY: buffers outlive the call and their lengths are given
+        Ok(uid) => unsafe {
+            libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result)
+        },
+        Err(_) => {
+            let name = CString::new(user)?;
+            // SAFETY: buffers outlive the call and their lengths are given
+            unsafe {
+                libc::getpwnam_r(
+                    name.as_ptr(),
+                    &mut pwd,
+                    buf.as_mut_ptr(),
+                    buf.len(),
+                    &mut result,
+                )
+            }
+        }
+    };
+    match (rc, result.is_null(), id) {
+        (0, false, _) => Ok((pwd.pw_uid, Some(pwd.pw_gid))),
+        (_, _, Ok(uid)) => Ok((uid, None)),
+        _ => bail!("user not found: {}", user),
+    }
+}
+
+/// Group ID by name or ID
+fn lookup_group(group: &str) -> anyhow::Result<gid_t> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+    let name = CString::new(group)?;
+    // SAFETY: all-zero group is valid, filled by the lookup
+    let mut grp: libc::group = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0; BUFFER_SIZE];
+    let mut result = std::ptr::null_mut();
+    // SAFETY: buffers outlive the call and their lengths are given
+    let rc = unsafe {
+        libc::getgrnam_r(
+            name.as_ptr(),
+            &mut grp,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if rc != 0 || result.is_null() {
+        bail!("group not found: {}", group);
+    }
+    Ok(grp.gr_gid)
+}
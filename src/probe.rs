@@ -0,0 +1,47 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use reqwest::Client;
+
+/// Placeholder in the URL of the probe replaced with the IP address
+const PLACEHOLDER: &str = "{ip}";
+
+/// Service outside the network e.g. a probe API or a host elsewhere, which answers whether it
+/// can reach an IP address
+pub(crate) struct Probe {
+    client: Client,
+    url: String,
+}
+
+impl Probe {
+    /// URL with `{ip}` in it e.g. https://probe.example.net/check?ip={ip}&port=443
+    pub(crate) fn new(url: &str, timeout: Duration) -> anyhow::Result<Self> {
+        if !url.contains(PLACEHOLDER) {
+            bail!("{} is missing from URL {}", PLACEHOLDER, url);
+        }
+        Ok(Self {
+            client: Client::builder().timeout(timeout).build()?,
+            url: url.to_string(),
+        })
+    }
+
+    /// Fail unless the probe answers with a successful status
+    pub(crate) async fn check(&self, ip_address: IpAddr) -> anyhow::Result<()> {
+        let url = self.url.replace(PLACEHOLDER, &ip_address.to_string());
+        let res = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .with_context(|| format!("failed to probe reachability of {}", ip_address))?;
+        if !res.status().is_success() {
+            bail!(
+                "{} is not reachable from outside, probe answered {}",
+                ip_address,
+                res.status()
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+use anyhow::bail;
+use cloudflare::endpoints::dns::DnsContent;
+use futures::future::BoxFuture;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::OnceCell;
+
+use super::{record_type, types_in_place, Provider};
+
+const TTL: u32 = 300;
+const API: &str = "https://management.azure.com";
+const API_VERSION: &str = "2018-05-01";
+
+/// Azure DNS through Azure Resource Manager, authenticated as a service principal
+pub(crate) struct Azure {
+    client: Client,
+    credentials: AzureCredentials,
+    subscription_id: String,
+    resource_group: String,
+    token: OnceCell<String>,
+}
+
+/// Service principal registered in Azure Active Directory
+pub(crate) struct AzureCredentials {
+    pub(crate) tenant_id: String,
+    pub(crate) client_id: String,
+    pub(crate) client_secret: String,
+}
+
+#[derive(Deserialize)]
+struct Token {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct RecordSet {
+    properties: Value,
+}
+
+impl Azure {
+    pub(crate) fn new(
+        credentials: AzureCredentials,
+        subscription_id: &str,
+        resource_group: &str,
+        timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        let client = Client::builder().timeout(timeout).build()?;
+        Ok(Self {
+            client,
+            credentials,
+            subscription_id: subscription_id.to_string(),
+            resource_group: resource_group.to_string(),
+            token: OnceCell::new(),
+        })
+    }
+
+    /// Access token, fetched once per run since it lasts an hour
+    async fn token(&self) -> anyhow::Result<&str> {
+        let token = self
+            .token
+            .get_or_try_init(|| async {
+                let url = format!(
+                    "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
+                    self.credentials.tenant_id
+                );
+                let res = self
+                    .client
+                    .post(url)
+                    .form(&[
+                        ("grant_type", "client_credentials"),
+                        ("client_id", &self.credentials.client_id),
+                        ("client_secret", &self.credentials.client_secret),
+                        ("scope", "https://management.azure.com/.default"),
+                    ])
+                    .send()
+                    .await?;
+                let token: Token = check(res).await?.json().await?;
+                Ok::<_, anyhow::Error>(token.access_token)
+            })
+            .await?;
+        Ok(token)
+    }
+
+    /// Response, none if the record set doesn't exist
+    async fn request(
+        &self,
+        f: impl FnOnce(&Client) -> RequestBuilder,
+    ) -> anyhow::Result<Option<Response>> {
+        let token = self.token().await?;
+        let res = f(&self.client)
+            .bearer_auth(token)
+            .query(&[("api-version", API_VERSION)])
+            .send()
+            .await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(check(res).await?))
+    }
+
+    fn url(&self, zone: &str, name: &str, record_type: &str) -> String {
+        format!(
+            "{}/subscriptions/{}/resourceGroups/{}/providers/Microsoft.Network/dnsZones/{}/{}/{}",
+            API,
+            self.subscription_id,
+            self.resource_group,
+            zone,
+            record_type,
+            relative_name(zone, name)
+        )
+    }
+}
+
+impl Provider for Azure {
+    fn get<'a>(
+        &'a self,
+        zone: &'a str,
+        name: &'a str,
+        record_type: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<String>>> {
+        Box::pin(async move {
+            for record_type in types_in_place(record_type) {
+                let url = self.url(zone, name, record_type);
+                let res = match self.request(|client| client.get(url)).await? {
+                    Some(res) => res,
+                    None => continue,
+                };
+                let record_set: RecordSet = res.json().await?;
+                let properties = &record_set.properties;
+                let content = match *record_type {
+                    "A" => properties["ARecords"][0]["ipv4Address"].as_str(),
+                    "AAAA" => properties["AAAARecords"][0]["ipv6Address"].as_str(),
+                    _ => properties["CNAMERecord"]["cname"].as_str(),
+                };
+                if let Some(content) = content {
+                    return Ok(Some(content.trim_end_matches('.').to_string()));
+                }
+            }
+            Ok(None)
+        })
+    }
+
+    fn set<'a>(
+        &'a self,
+        zone: &'a str,
+        name: &'a str,
+        content: &'a DnsContent,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let properties = match content {
+                DnsContent::A { content } => {
+                    json!({ "TTL": TTL, "ARecords": [{ "ipv4Address": content.to_string() }] })
+                }
+                DnsContent::AAAA { content } => {
+                    json!({ "TTL": TTL, "AAAARecords": [{ "ipv6Address": content.to_string() }] })
+                }
+                DnsContent::CNAME { content } => {
+                    json!({ "TTL": TTL, "CNAMERecord": { "cname": content } })
+                }
+                _ => bail!("record type {} is not supported", record_type(content)),
+            };
+            // alias can't coexist with addresses, remove record sets in its place first
+            for other in &types_in_place(record_type(content))[1..] {
+                let url = self.url(zone, name, other);
+                self.request(|client| client.delete(url)).await?;
+            }
+            let url = self.url(zone, name, record_type(content));
+            let body = json!({ "properties": properties });
+            self.request(|client| client.put(url).json(&body)).await?;
+            Ok(())
+        })
+    }
+
+    fn delete<'a>(
+        &'a self,
+        zone: &'a str,
+        name: &'a str,
+        record_type: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            for record_type in types_in_place(record_type) {
+                let url = self.url(zone, name, record_type);
+                self.request(|client| client.delete(url)).await?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Fail with the body of the response unless successful
+async fn check(res: Response) -> anyhow::Result<Response> {
+    if !res.status().is_success() {
+        let status = res.status();
+        bail!("Azure responded {}: {}", status, res.text().await?);
+    }
+    Ok(res)
+}
+
+/// Name relative to the zone, `@` for the apex
+fn relative_name(zone: &str, name: &str) -> String {
+    let name = name.trim_end_matches('.');
+    let zone = zone.trim_end_matches('.');
+    if name == zone {
+        return "@".to_string();
+    }
+    match name.strip_suffix(zone).and_then(|n| n.strip_suffix('.')) {
+        Some(relative) => relative.to_string(),
+        None => name.to_string(),
+    }
+}
@@ -0,0 +1,316 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context};
+use cloudflare::endpoints::dns::DnsContent;
+use futures::future::BoxFuture;
+use reqwest::{Client, RequestBuilder, Response};
+use ring::rand::SystemRandom;
+use ring::signature::{RsaKeyPair, RSA_PKCS1_SHA256};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::OnceCell;
+
+use super::{record_type, types_in_place, Provider};
+
+const TTL: u32 = 300;
+const API: &str = "https://dns.googleapis.com/dns/v1";
+const SCOPE: &str = "https://www.googleapis.com/auth/ndev.clouddns.readwrite";
+const METADATA: &str = "http://metadata.google.internal/computeMetadata/v1";
+
+/// Google Cloud DNS, authenticated with a service account key or, on Google Cloud, with the
+/// service account of the instance from the metadata server
+pub(crate) struct Gcp {
+    client: Client,
+    project: String,
+    key: Option<ServiceAccountKey>,
+    token: OnceCell<String>,
+}
+
+/// Service account key file downloaded from Google Cloud console
+#[derive(Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+    project_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Token {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ManagedZones {
+    managed_zones: Vec<ManagedZone>,
+}
+
+#[derive(Deserialize)]
+struct ManagedZone {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct ResourceRecordSets {
+    #[serde(default)]
+    rrsets: Vec<ResourceRecordSet>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ResourceRecordSet {
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    ttl: u32,
+    rrdatas: Vec<String>,
+}
+
+impl Gcp {
+    /// Project defaults to the one the service account key belongs to
+    pub(crate) fn new(
+        credentials: Option<&Path>,
+        project: Option<&str>,
+        timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        let client = Client::builder().timeout(timeout).build()?;
+        let key: Option<ServiceAccountKey> =
+            match credentials {
+                Some(path) => {
+                    let content = std::fs::read_to_string(path).with_context(|| {
+                        format!("failed to read service account key {}", path.display())
+                    })?;
+                    Some(serde_json::from_str(&content).with_context(|| {
+                        format!("invalid service account key {}", path.display())
+                    })?)
+                }
+                None => None,
+            };
+        let project = match (project, key.as_ref().and_then(|k| k.project_id.clone())) {
+            (Some(project), _) => project.to_string(),
+            (None, Some(project)) => project,
+            (None, None) => bail!("Google Cloud project is required"),
+        };
+        Ok(Self {
+            client,
+            project,
+            key,
+            token: OnceCell::new(),
+        })
+    }
+
+    /// Access token, fetched once per run since it lasts an hour
+    async fn token(&self) -> anyhow::Result<&str> {
+        let token = self
+            .token
+            .get_or_try_init(|| async {
+                let res = match self.key {
+                    Some(ref key) => {
+                        let assertion = jwt(key)?;
+                        self.client
+                            .post(&key.token_uri)
+                            .form(&[
+                                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                                ("assertion", &assertion),
+                            ])
+                            .send()
+                            .await?
+                    }
+                    None => {
+                        let url = format!(
+                            "{}/instance/service-accounts/default/token?scopes={}",
+                            METADATA, SCOPE
+                        );
+                        self.client
+                            .get(url)
+                            .header("Metadata-Flavor", "Google")
+                            .send()
+                            .await?
+                    }
+                };
+                let token: Token = check(res).await?.json().await?;
+                Ok::<_, anyhow::Error>(token.access_token)
+            })
+            .await?;
+        Ok(token)
+    }
+
+    async fn request(&self, f: impl FnOnce(&Client) -> RequestBuilder) -> anyhow::Result<Response> {
+        let token = self.token().await?;
+        check(f(&self.client).bearer_auth(token).send().await?).await
+    }
+
+    /// Name of managed zone serving the DNS name
+    async fn managed_zone(&self, zone: &str) -> anyhow::Result<String> {
+        let url = format!("{}/projects/{}/managedZones", API, self.project);
+        let dns_name = canonical(zone);
+        let res = self
+            .request(|client| client.get(url).query(&[("dnsName", &dns_name)]))
+            .await?;
+        let zones: ManagedZones = res.json().await?;
+        match zones.managed_zones.into_iter().next() {
+            Some(zone) => Ok(zone.name),
+            None => bail!("managed zone not found: {}", zone),
+        }
+    }
+
+    /// Record sets of the name of the types
+    async fn rrsets(
+        &self,
+        managed_zone: &str,
+        name: &str,
+        types: &[&str],
+    ) -> anyhow::Result<Vec<ResourceRecordSet>> {
+        let url = format!(
+            "{}/projects/{}/managedZones/{}/rrsets",
+            API, self.project, managed_zone
+        );
+        let name = canonical(name);
+        let res = self
+            .request(|client| client.get(url).query(&[("name", &name)]))
+            .await?;
+        let rrsets: ResourceRecordSets = res.json().await?;
+        Ok(rrsets
+            .rrsets
+            .into_iter()
+            .filter(|rrset| types.contains(&rrset.record_type.as_str()))
+            .collect())
+    }
+
+    /// Replace record sets of the name of the types with content if any
+    async fn change(
+        &self,
+        zone: &str,
+        name: &str,
+        types: &[&str],
+        content: Option<&DnsContent>,
+    ) -> anyhow::Result<()> {
+        let managed_zone = self.managed_zone(zone).await?;
+        // deletions must match existing record sets exactly
+        let deletions = self.rrsets(&managed_zone, name, types).await?;
+        let additions = match content {
+            Some(content) => {
+                let rrdata = match content {
+                    DnsContent::A { content } => content.to_string(),
+                    DnsContent::AAAA { content } => content.to_string(),
+                    DnsContent::CNAME { content } => canonical(content),
+                    _ => bail!("record type {} is not supported", record_type(content)),
+                };
+                vec![ResourceRecordSet {
+                    name: canonical(name),
+                    record_type: record_type(content).to_string(),
+                    ttl: TTL,
+                    rrdatas: vec![rrdata],
+                }]
+            }
+            None => vec![],
+        };
+        if deletions.is_empty() && additions.is_empty() {
+            return Ok(());
+        }
+        let url = format!(
+            "{}/projects/{}/managedZones/{}/changes",
+            API, self.project, managed_zone
+        );
+        let body = json!({ "additions": additions, "deletions": deletions });
+        self.request(|client| client.post(url).json(&body)).await?;
+        Ok(())
+    }
+}
+
+impl Provider for Gcp {
+    fn get<'a>(
+        &'a self,
+        zone: &'a str,
+        name: &'a str,
+        record_type: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<String>>> {
+        Box::pin(async move {
+            let managed_zone = self.managed_zone(zone).await?;
+            Ok(self
+                .rrsets(&managed_zone, name, types_in_place(record_type))
+                .await?
+                .into_iter()
+                .flat_map(|rrset| rrset.rrdatas)
+                .next()
+                .map(|rrdata| rrdata.trim_end_matches('.').to_string()))
+        })
+    }
+
+    fn set<'a>(
+        &'a self,
+        zone: &'a str,
+        name: &'a str,
+        content: &'a DnsContent,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        let types = types_in_place(record_type(content));
+        Box::pin(self.change(zone, name, types, Some(content)))
+    }
+
+    fn delete<'a>(
+        &'a self,
+        zone: &'a str,
+        name: &'a str,
+        record_type: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(self.change(zone, name, types_in_place(record_type), None))
+    }
+}
+
+/// Fail with the body of the response unless successful
+async fn check(res: Response) -> anyhow::Result<Response> {
+    if !res.status().is_success() {
+        let status = res.status();
+        bail!("Google Cloud responded {}: {}", status, res.text().await?);
+    }
+    Ok(res)
+}
+
+/// Assertion signed with the service account key to exchange for an access token
+fn jwt(key: &ServiceAccountKey) -> anyhow::Result<String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let header = json!({ "alg": "RS256", "typ": "JWT" });
+    let claims = json!({
+        "iss": key.client_email,
+        "scope": SCOPE,
+        "aud": key.token_uri,
+        "iat": now,
+        "exp": now + 3600,
+    });
+    let encode = |value: &serde_json::Value| {
+        base64::encode_config(value.to_string(), base64::URL_SAFE_NO_PAD)
+    };
+    let message = format!("{}.{}", encode(&header), encode(&claims));
+
+    let pem: String = key
+        .private_key
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+    let der = base64::decode(pem.trim()).context("invalid private key of service account")?;
+    let key_pair = RsaKeyPair::from_pkcs8(&der)
+        .map_err(|e| anyhow::anyhow!("invalid private key of service account: {}", e))?;
+    let mut signature = vec![0; key_pair.public().modulus_len()];
+    key_pair
+        .sign(
+            &RSA_PKCS1_SHA256,
+            &SystemRandom::new(),
+            message.as_bytes(),
+            &mut signature,
+        )
+        .map_err(|_| anyhow::anyhow!("failed to sign token request"))?;
+    Ok(format!(
+        "{}.{}",
+        message,
+        base64::encode_config(signature, base64::URL_SAFE_NO_PAD)
+    ))
+}
+
+/// Fully qualified name with trailing dot, as Cloud DNS expects
+fn canonical(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
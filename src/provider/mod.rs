@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cloudflare::endpoints::dns::DnsContent;
+use futures::future::BoxFuture;
+
+use crate::config::ProviderConfig;
+
+mod azure;
+mod gcp;
+mod ovh;
+mod powerdns;
+mod rfc2136;
+
+use azure::{Azure, AzureCredentials};
+use gcp::Gcp;
+use ovh::{Ovh, OvhKeys};
+use powerdns::PowerDns;
+pub(crate) use rfc2136::{read_name, skip_name, write_name};
+pub use rfc2136::{tsig_sign, TsigAlgorithm};
+use rfc2136::{Rfc2136, TsigKey};
+
+/// DNS hosting service other than Cloudflare which records can be published to
+pub trait Provider: Send + Sync {
+    /// Current content of the record of the type e.g. AAAA, or of the alias in its place, none
+    /// if neither exists
+    fn get<'a>(
+        &'a self,
+        zone: &'a str,
+        name: &'a str,
+        record_type: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<String>>>;
+
+    /// Create the record, or replace its content and records of the name it can't coexist with
+    fn set<'a>(
+        &'a self,
+        zone: &'a str,
+        name: &'a str,
+        content: &'a DnsContent,
+    ) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    /// Delete the record of the type, and the alias in its place if any
+    fn delete<'a>(
+        &'a self,
+        zone: &'a str,
+        name: &'a str,
+        record_type: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<()>>;
+}
+
+/// Providers by name, records refer to them with `provider` in the configuration file
+pub(crate) type Providers = BTreeMap<String, Arc<dyn Provider>>;
+
+/// What a provider supports, reported by `cdu providers`
+pub(crate) struct Capabilities {
+    pub(crate) name: &'static str,
+    pub(crate) create: bool,
+    pub(crate) delete: bool,
+    pub(crate) aaaa: bool,
+    pub(crate) txt: bool,
+    pub(crate) comments: bool,
+    pub(crate) proxied: bool,
+}
+
+const fn capabilities(name: &'static str, txt: bool, proxied: bool) -> Capabilities {
+    Capabilities {
+        name,
+        create: true,
+        delete: true,
+        aaaa: true,
+        txt,
+        comments: false,
+        proxied,
+    }
+}
+
+/// Providers compiled in, Cloudflare first
+pub(crate) const CAPABILITIES: [Capabilities; 6] = [
+    capabilities("cloudflare", true, true),
+    capabilities("powerdns", false, false),
+    capabilities("rfc2136", false, false),
+    capabilities("gcp", false, false),
+    capabilities("azure", false, false),
+    capabilities("ovh", false, false),
+];
+
+/// Table of providers and their capabilities
+pub(crate) fn render_capabilities() -> String {
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    let mut lines = vec![format!(
+        "{:<12}{:<8}{:<8}{:<6}{:<6}{:<10}{}",
+        "provider", "create", "delete", "AAAA", "TXT", "comments", "proxied"
+    )];
+    for c in CAPABILITIES.iter() {
+        lines.push(format!(
+            "{:<12}{:<8}{:<8}{:<6}{:<6}{:<10}{}",
+            c.name,
+            yes_no(c.create),
+            yes_no(c.delete),
+            yes_no(c.aaaa),
+            yes_no(c.txt),
+            yes_no(c.comments),
+            yes_no(c.proxied)
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Connect to the provider with its credentials
+pub(crate) fn build(
+    config: &ProviderConfig,
+    timeout: Duration,
+) -> anyhow::Result<Arc<dyn Provider>> {
+    Ok(match config {
+        ProviderConfig::Powerdns {
+            url,
+            api_key,
+            server_id,
+        } => Arc::new(PowerDns::new(url, api_key, server_id, timeout)?),
+        ProviderConfig::Rfc2136 {
+            server,
+            key_name,
+            key_secret,
+            key_algorithm,
+        } => {
+            let key = TsigKey {
+                name: key_name.clone(),
+                algorithm: *key_algorithm,
+                secret: base64::decode(key_secret.trim())
+                    .map_err(|e| anyhow::anyhow!("invalid TSIG key secret: {}", e))?,
+            };
+            Arc::new(Rfc2136::new(*server, key, timeout))
+        }
+        ProviderConfig::Gcp {
+            credentials,
+            project,
+        } => Arc::new(Gcp::new(
+            credentials.as_deref(),
+            project.as_deref(),
+            timeout,
+        )?),
+        ProviderConfig::Azure {
+            subscription_id,
+            resource_group,
+            tenant_id,
+            client_id,
+            client_secret,
+        } => {
+            let credentials = AzureCredentials {
+                tenant_id: tenant_id.clone(),
+                client_id: client_id.clone(),
+                client_secret: client_secret.clone(),
+            };
+            Arc::new(Azure::new(
+                credentials,
+                subscription_id,
+                resource_group,
+                timeout,
+            )?)
+        }
+        ProviderConfig::Ovh {
+            endpoint,
+            application_key,
+            application_secret,
+            consumer_key,
+        } => {
+            let keys = OvhKeys {
+                application_key: application_key.clone(),
+                application_secret: application_secret.clone(),
+                consumer_key: consumer_key.clone(),
+            };
+            Arc::new(Ovh::new(endpoint, keys, timeout)?)
+        }
+    })
+}
+
+/// Types of records which take the place of a record of the type at its name, its own first.
+/// An alias can't coexist with addresses, while A and AAAA records of a name are independent.
+pub(crate) fn types_in_place(record_type: &str) -> &'static [&'static str] {
+    match record_type {
+        "AAAA" => &["AAAA", "CNAME"],
+        "CNAME" => &["CNAME", "A", "AAAA"],
+        _ => &["A", "CNAME"],
+    }
+}
+
+/// Type and content of the record in zone file format
+pub(crate) fn record_type(content: &DnsContent) -> &'static str {
+    match content {
+        DnsContent::A { .. } => "A",
+        DnsContent::AAAA { .. } => "AAAA",
+        DnsContent::CNAME { .. } => "CNAME",
+        DnsContent::NS { .. } => "NS",
+        DnsContent::MX { .. } => "MX",
+        DnsContent::TXT { .. } => "TXT",
+        DnsContent::SRV { .. } => "SRV",
+    }
+}
@@ -0,0 +1,219 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::bail;
+use cloudflare::endpoints::dns::DnsContent;
+use futures::future::BoxFuture;
+use reqwest::{Client, Method, Response};
+use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{record_type, types_in_place, Provider};
+
+const TTL: u32 = 300;
+
+/// OVH domain API, requests signed with application and consumer keys
+pub(crate) struct Ovh {
+    client: Client,
+    endpoint: String,
+    keys: OvhKeys,
+}
+
+/// Application created at https://eu.api.ovh.com/createApp/ and consumer key it was granted
+pub(crate) struct OvhKeys {
+    pub(crate) application_key: String,
+    pub(crate) application_secret: String,
+    pub(crate) consumer_key: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Record {
+    id: u64,
+    field_type: String,
+    target: String,
+}
+
+impl Ovh {
+    pub(crate) fn new(endpoint: &str, keys: OvhKeys, timeout: Duration) -> anyhow::Result<Self> {
+        let client = Client::builder().timeout(timeout).build()?;
+        Ok(Self {
+            client,
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            keys,
+        })
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> anyhow::Result<Response> {
+        let url = format!("{}{}", self.endpoint, path);
+        let body = body.map(|b| b.to_string()).unwrap_or_default();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            .to_string();
+        let signature = sign(&self.keys, method.as_str(), &url, &body, &timestamp);
+        let mut req = self
+            .client
+            .request(method, &url)
+            .header("X-Ovh-Application", &self.keys.application_key)
+            .header("X-Ovh-Consumer", &self.keys.consumer_key)
+            .header("X-Ovh-Timestamp", timestamp)
+            .header("X-Ovh-Signature", signature);
+        if !body.is_empty() {
+            req = req
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body);
+        }
+        let res = req.send().await?;
+        if !res.status().is_success() {
+            let status = res.status();
+            bail!("OVH responded {}: {}", status, res.text().await?);
+        }
+        Ok(res)
+    }
+
+    async fn json<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<T> {
+        Ok(self.request(Method::GET, path, None).await?.json().await?)
+    }
+
+    /// Records of the name of the types
+    async fn records(&self, zone: &str, name: &str, types: &[&str]) -> anyhow::Result<Vec<Record>> {
+        let sub_domain = sub_domain(zone, name);
+        let mut records = vec![];
+        for record_type in types {
+            let path = format!(
+                "/domain/zone/{}/record?fieldType={}&subDomain={}",
+                zone, record_type, sub_domain
+            );
+            let ids: Vec<u64> = self.json(&path).await?;
+            for id in ids {
+                let path = format!("/domain/zone/{}/record/{}", zone, id);
+                records.push(self.json(&path).await?);
+            }
+        }
+        Ok(records)
+    }
+
+    /// Apply pending changes to the zone
+    async fn refresh(&self, zone: &str) -> anyhow::Result<()> {
+        let path = format!("/domain/zone/{}/refresh", zone);
+        self.request(Method::POST, &path, None).await?;
+        Ok(())
+    }
+}
+
+impl Provider for Ovh {
+    fn get<'a>(
+        &'a self,
+        zone: &'a str,
+        name: &'a str,
+        record_type: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<String>>> {
+        Box::pin(async move {
+            let records = self
+                .records(zone, name, types_in_place(record_type))
+                .await?;
+            Ok(records
+                .into_iter()
+                .next()
+                .map(|r| r.target.trim_end_matches('.').to_string()))
+        })
+    }
+
+    fn set<'a>(
+        &'a self,
+        zone: &'a str,
+        name: &'a str,
+        content: &'a DnsContent,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let target = match content {
+                DnsContent::A { content } => content.to_string(),
+                DnsContent::AAAA { content } => content.to_string(),
+                DnsContent::CNAME { content } => format!("{}.", content.trim_end_matches('.')),
+                _ => bail!("record type {} is not supported", record_type(content)),
+            };
+            let field_type = record_type(content);
+            let mut updated = false;
+            let types = types_in_place(field_type);
+            for record in self.records(zone, name, types).await? {
+                let path = format!("/domain/zone/{}/record/{}", zone, record.id);
+                if record.field_type == field_type && !updated {
+                    let body = json!({ "target": target, "ttl": TTL });
+                    self.request(Method::PUT, &path, Some(body)).await?;
+                    updated = true;
+                } else {
+                    self.request(Method::DELETE, &path, None).await?;
+                }
+            }
+            if !updated {
+                let path = format!("/domain/zone/{}/record", zone);
+                let body = json!({
+                    "fieldType": field_type,
+                    "subDomain": sub_domain(zone, name),
+                    "target": target,
+                    "ttl": TTL,
+                });
+                self.request(Method::POST, &path, Some(body)).await?;
+            }
+            self.refresh(zone).await
+        })
+    }
+
+    fn delete<'a>(
+        &'a self,
+        zone: &'a str,
+        name: &'a str,
+        record_type: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let records = self
+                .records(zone, name, types_in_place(record_type))
+                .await?;
+            if records.is_empty() {
+                return Ok(());
+            }
+            for record in records {
+                let path = format!("/domain/zone/{}/record/{}", zone, record.id);
+                self.request(Method::DELETE, &path, None).await?;
+            }
+            self.refresh(zone).await
+        })
+    }
+}
+
+/// `$1$` followed by SHA-1 of secret, consumer key, method, URL, body, and timestamp
+fn sign(keys: &OvhKeys, method: &str, url: &str, body: &str, timestamp: &str) -> String {
+    let message = [
+        keys.application_secret.as_str(),
+        keys.consumer_key.as_str(),
+        method,
+        url,
+        body,
+        timestamp,
+    ]
+    .join("+");
+    let hash = digest(&SHA1_FOR_LEGACY_USE_ONLY, message.as_bytes());
+    let hex: String = hash.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("$1${}", hex)
+}
+
+/// Name relative to the zone, empty for the apex
+fn sub_domain(zone: &str, name: &str) -> String {
+    let name = name.trim_end_matches('.');
+    let zone = zone.trim_end_matches('.');
+    if name == zone {
+        return String::new();
+    }
+    match name.strip_suffix(zone).and_then(|n| n.strip_suffix('.')) {
+        Some(sub_domain) => sub_domain.to_string(),
+        None => name.to_string(),
+    }
+}
@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use anyhow::bail;
+use cloudflare::endpoints::dns::DnsContent;
+use futures::future::BoxFuture;
+use reqwest::{Client, Method, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{record_type, types_in_place, Provider};
+
+const TTL: u32 = 300;
+
+/// PowerDNS Authoritative server through its HTTP API, e.g. for internal zones
+pub(crate) struct PowerDns {
+    client: Client,
+    url: String,
+    api_key: String,
+    server_id: String,
+}
+
+#[derive(Deserialize)]
+struct Zone {
+    rrsets: Vec<RRSet>,
+}
+
+#[derive(Deserialize)]
+struct RRSet {
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    records: Vec<Record>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Record {
+    content: String,
+    #[serde(default)]
+    disabled: bool,
+}
+
+impl PowerDns {
+    /// URL of the API e.g. http://127.0.0.1:8081, server ID is usually localhost
+    pub(crate) fn new(
+        url: &str,
+        api_key: &str,
+        server_id: &str,
+        timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: Client::builder().timeout(timeout).build()?,
+            url: url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            server_id: server_id.to_string(),
+        })
+    }
+
+    fn request(&self, method: Method, zone: &str) -> RequestBuilder {
+        let url = format!(
+            "{}/api/v1/servers/{}/zones/{}",
+            self.url,
+            self.server_id,
+            canonical(zone)
+        );
+        self.client
+            .request(method, url)
+            .header("X-API-Key", &self.api_key)
+    }
+
+    async fn patch(&self, zone: &str, rrsets: Vec<serde_json::Value>) -> anyhow::Result<()> {
+        let res = self
+            .request(Method::PATCH, zone)
+            .json(&json!({ "rrsets": rrsets }))
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            let status = res.status();
+            bail!("PowerDNS responded {}: {}", status, res.text().await?);
+        }
+        Ok(())
+    }
+}
+
+impl Provider for PowerDns {
+    fn get<'a>(
+        &'a self,
+        zone: &'a str,
+        name: &'a str,
+        record_type: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<String>>> {
+        Box::pin(async move {
+            let res = self.request(Method::GET, zone).send().await?;
+            if !res.status().is_success() {
+                let status = res.status();
+                bail!("PowerDNS responded {}: {}", status, res.text().await?);
+            }
+            let zone: Zone = res.json().await?;
+            let name = canonical(name);
+            let types = types_in_place(record_type);
+            Ok(zone
+                .rrsets
+                .into_iter()
+                .filter(|rrset| rrset.name == name)
+                .filter(|rrset| types.contains(&rrset.record_type.as_str()))
+                .flat_map(|rrset| rrset.records)
+                .find(|record| !record.disabled)
+                .map(|record| record.content.trim_end_matches('.').to_string()))
+        })
+    }
+
+    fn set<'a>(
+        &'a self,
+        zone: &'a str,
+        name: &'a str,
+        content: &'a DnsContent,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let value = match content {
+                DnsContent::A { content } => content.to_string(),
+                DnsContent::AAAA { content } => content.to_string(),
+                DnsContent::CNAME { content } => canonical(content),
+                _ => bail!("record type {} is not supported", record_type(content)),
+            };
+            // records the new one can't coexist with go in the same request
+            let mut rrsets = deletions(name, &types_in_place(record_type(content))[1..]);
+            rrsets.push(json!({
+                "name": canonical(name),
+                "type": record_type(content),
+                "ttl": TTL,
+                "changetype": "REPLACE",
+                "records": [Record { content: value, disabled: false }],
+            }));
+            self.patch(zone, rrsets).await
+        })
+    }
+
+    fn delete<'a>(
+        &'a self,
+        zone: &'a str,
+        name: &'a str,
+        record_type: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(self.patch(zone, deletions(name, types_in_place(record_type))))
+    }
+}
+
+/// Changes deleting record sets of the types at the name, which need not exist
+fn deletions(name: &str, types: &[&str]) -> Vec<serde_json::Value> {
+    types
+        .iter()
+        .map(|record_type| {
+            json!({
+                "name": canonical(name),
+                "type": record_type,
+                "changetype": "DELETE",
+            })
+        })
+        .collect()
+}
+
+/// Fully qualified name with trailing dot, as PowerDNS expects
+fn canonical(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
@@ -0,0 +1,408 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context};
+use cloudflare::endpoints::dns::DnsContent;
+use futures::future::BoxFuture;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Deserialize;
+use tokio::net::UdpSocket;
+
+use super::{record_type, types_in_place, Provider};
+
+const TTL: u32 = 300;
+/// Allowed clock skew between cdu and DNS server
+const FUDGE: u16 = 300;
+
+const OPCODE_QUERY: u16 = 0;
+const OPCODE_UPDATE: u16 = 5;
+
+const TYPE_A: u16 = 1;
+const TYPE_CNAME: u16 = 5;
+const TYPE_SOA: u16 = 6;
+const TYPE_AAAA: u16 = 28;
+const TYPE_TSIG: u16 = 250;
+
+const CLASS_IN: u16 = 1;
+const CLASS_ANY: u16 = 255;
+
+/// Algorithm of TSIG key
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TsigAlgorithm {
+    HmacSha256,
+    HmacSha512,
+}
+
+impl TsigAlgorithm {
+    fn name(&self) -> &'static str {
+        match self {
+            TsigAlgorithm::HmacSha256 => "hmac-sha256",
+            TsigAlgorithm::HmacSha512 => "hmac-sha512",
+        }
+    }
+
+    fn ring(&self) -> hmac::Algorithm {
+        match self {
+            TsigAlgorithm::HmacSha256 => hmac::HMAC_SHA256,
+            TsigAlgorithm::HmacSha512 => hmac::HMAC_SHA512,
+        }
+    }
+}
+
+impl FromStr for TsigAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hmac-sha256" => Ok(TsigAlgorithm::HmacSha256),
+            "hmac-sha512" => Ok(TsigAlgorithm::HmacSha512),
+            s => bail!("unsupported TSIG algorithm: {}", s),
+        }
+    }
+}
+
+/// TSIG key shared with the DNS server e.g. generated by `tsig-keygen`
+pub(crate) struct TsigKey {
+    pub(crate) name: String,
+    pub(crate) algorithm: TsigAlgorithm,
+    pub(crate) secret: Vec<u8>,
+}
+
+/// DNS server accepting dynamic updates (RFC 2136) signed with TSIG (RFC 8945) e.g. BIND, Knot,
+/// or Windows DNS. Responses aren't verified beyond their ID and RCODE.
+pub(crate) struct Rfc2136 {
+    server: SocketAddr,
+    key: TsigKey,
+    timeout: Duration,
+    random: SystemRandom,
+}
+
+impl Rfc2136 {
+    pub(crate) fn new(server: SocketAddr, key: TsigKey, timeout: Duration) -> Self {
+        Self {
+            server,
+            key,
+            timeout,
+            random: SystemRandom::new(),
+        }
+    }
+
+    fn id(&self) -> anyhow::Result<u16> {
+        let mut id = [0; 2];
+        self.random
+            .fill(&mut id)
+            .map_err(|_| anyhow::anyhow!("failed to generate message ID"))?;
+        Ok(u16::from_be_bytes(id))
+    }
+
+    /// Send message over UDP and return the response with the same ID
+    async fn exchange(&self, id: u16, message: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let bind = match self.server {
+            SocketAddr::V4(_) => "0.0.0.0:0",
+            SocketAddr::V6(_) => "[::]:0",
+        };
+        let socket = UdpSocket::bind(bind).await?;
+        socket.connect(self.server).await?;
+        socket.send(message).await?;
+        let mut buf = vec![0; 4096];
+        loop {
+            let len = tokio::time::timeout(self.timeout, socket.recv(&mut buf))
+                .await
+                .with_context(|| format!("DNS server {} timed out", self.server))??;
+            let response = &buf[..len];
+            if response.len() >= 12 && u16::from_be_bytes([response[0], response[1]]) == id {
+                check_rcode(response)?;
+                return Ok(response.to_vec());
+            }
+        }
+    }
+
+    /// Send update which deletes RRsets of the name of the types, then adds content if any
+    async fn update(
+        &self,
+        zone: &str,
+        name: &str,
+        types: &[&str],
+        content: Option<&DnsContent>,
+    ) -> anyhow::Result<()> {
+        let id = self.id()?;
+        let mut message = Message::new(id, OPCODE_UPDATE);
+        // zone section
+        message.question(zone, TYPE_SOA, CLASS_IN);
+        // update section, delete RRsets then add RR
+        let mut updates = 0;
+        for record_type in types {
+            message.record(name, rr_type(record_type)?, CLASS_ANY, 0, &[]);
+            updates += 1;
+        }
+        if let Some(content) = content {
+            let (rr_type, rdata) = rdata(content)?;
+            message.record(name, rr_type, CLASS_IN, TTL, &rdata);
+            updates += 1;
+        }
+        message.set_count(8, updates);
+        let message = message.sign(&self.key, unix_time());
+        self.exchange(id, &message).await?;
+        Ok(())
+    }
+}
+
+impl Provider for Rfc2136 {
+    fn get<'a>(
+        &'a self,
+        _zone: &'a str,
+        name: &'a str,
+        record_type: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Option<String>>> {
+        Box::pin(async move {
+            let rr_type = rr_type(record_type)?;
+            let id = self.id()?;
+            let mut message = Message::new(id, OPCODE_QUERY);
+            message.question(name, rr_type, CLASS_IN);
+            let response = self.exchange(id, &message.bytes).await?;
+            first_answer(&response, rr_type)
+        })
+    }
+
+    fn set<'a>(
+        &'a self,
+        zone: &'a str,
+        name: &'a str,
+        content: &'a DnsContent,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        let types = types_in_place(record_type(content));
+        Box::pin(self.update(zone, name, types, Some(content)))
+    }
+
+    fn delete<'a>(
+        &'a self,
+        zone: &'a str,
+        name: &'a str,
+        record_type: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(self.update(zone, name, types_in_place(record_type), None))
+    }
+}
+
+/// DNS message in wire format
+struct Message {
+    bytes: Vec<u8>,
+}
+
+impl Message {
+    fn new(id: u16, opcode: u16) -> Self {
+        let mut bytes = Vec::with_capacity(512);
+        bytes.extend_from_slice(&id.to_be_bytes());
+        // recursion desired only matters to queries
+        let flags = opcode << 11 | if opcode == OPCODE_QUERY { 0x0100 } else { 0 };
+        bytes.extend_from_slice(&flags.to_be_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+        Self { bytes }
+    }
+
+    fn set_count(&mut self, offset: usize, count: u16) {
+        self.bytes[offset..offset + 2].copy_from_slice(&count.to_be_bytes());
+    }
+
+    fn question(&mut self, name: &str, rr_type: u16, class: u16) {
+        write_name(&mut self.bytes, name);
+        self.bytes.extend_from_slice(&rr_type.to_be_bytes());
+        self.bytes.extend_from_slice(&class.to_be_bytes());
+        self.set_count(4, 1);
+    }
+
+    fn record(&mut self, name: &str, rr_type: u16, class: u16, ttl: u32, rdata: &[u8]) {
+        write_name(&mut self.bytes, name);
+        self.bytes.extend_from_slice(&rr_type.to_be_bytes());
+        self.bytes.extend_from_slice(&class.to_be_bytes());
+        self.bytes.extend_from_slice(&ttl.to_be_bytes());
+        self.bytes
+            .extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        self.bytes.extend_from_slice(rdata);
+    }
+
+    /// Append TSIG record signing the message
+    fn sign(self, key: &TsigKey, time_signed: u64) -> Vec<u8> {
+        tsig_sign(
+            self.bytes,
+            &key.name,
+            key.algorithm,
+            &key.secret,
+            time_signed,
+        )
+    }
+}
+
+/// Append TSIG record signing the DNS message in wire format with the key at the time in
+/// seconds since epoch, RFC 8945 section 4.3
+pub fn tsig_sign(
+    message: Vec<u8>,
+    key_name: &str,
+    key_algorithm: TsigAlgorithm,
+    key_secret: &[u8],
+    time_signed: u64,
+) -> Vec<u8> {
+    let mut message = Message { bytes: message };
+    let id = [message.bytes[0], message.bytes[1]];
+    let mut algorithm = vec![];
+    write_name(&mut algorithm, key_algorithm.name());
+    let time = &time_signed.to_be_bytes()[2..];
+
+    let mut signed = message.bytes.clone();
+    write_name(&mut signed, &key_name.to_ascii_lowercase());
+    signed.extend_from_slice(&CLASS_ANY.to_be_bytes());
+    signed.extend_from_slice(&0u32.to_be_bytes());
+    signed.extend_from_slice(&algorithm);
+    signed.extend_from_slice(time);
+    signed.extend_from_slice(&FUDGE.to_be_bytes());
+    // error and other length
+    signed.extend_from_slice(&[0; 4]);
+    let mac = hmac::sign(&hmac::Key::new(key_algorithm.ring(), key_secret), &signed);
+    let mac = mac.as_ref();
+
+    let mut rdata = algorithm;
+    rdata.extend_from_slice(time);
+    rdata.extend_from_slice(&FUDGE.to_be_bytes());
+    rdata.extend_from_slice(&(mac.len() as u16).to_be_bytes());
+    rdata.extend_from_slice(mac);
+    rdata.extend_from_slice(&id);
+    // error and other length
+    rdata.extend_from_slice(&[0; 4]);
+    message.record(key_name, TYPE_TSIG, CLASS_ANY, 0, &rdata);
+    message.set_count(10, 1);
+    message.bytes
+}
+
+pub(crate) fn write_name(buf: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+}
+
+fn rr_type(record_type: &str) -> anyhow::Result<u16> {
+    match record_type {
+        "A" => Ok(TYPE_A),
+        "AAAA" => Ok(TYPE_AAAA),
+        "CNAME" => Ok(TYPE_CNAME),
+        record_type => bail!("record type {} is not supported", record_type),
+    }
+}
+
+fn rdata(content: &DnsContent) -> anyhow::Result<(u16, Vec<u8>)> {
+    match content {
+        DnsContent::A { content } => Ok((TYPE_A, content.octets().to_vec())),
+        DnsContent::AAAA { content } => Ok((TYPE_AAAA, content.octets().to_vec())),
+        DnsContent::CNAME { content } => {
+            let mut rdata = vec![];
+            write_name(&mut rdata, content);
+            Ok((TYPE_CNAME, rdata))
+        }
+        _ => bail!("record type {} is not supported", record_type(content)),
+    }
+}
+
+fn check_rcode(response: &[u8]) -> anyhow::Result<()> {
+    let rcode = response[3] & 0x0f;
+    let name = match rcode {
+        0 => return Ok(()),
+        1 => "FORMERR",
+        2 => "SERVFAIL",
+        3 => "NXDOMAIN",
+        4 => "NOTIMP",
+        5 => "REFUSED",
+        9 => "NOTAUTH",
+        10 => "NOTZONE",
+        _ => "unexpected error",
+    };
+    bail!("DNS server responded {} ({})", name, rcode)
+}
+
+/// Content of the first record of the type or alias in the answer section, none if there is
+/// none
+fn first_answer(response: &[u8], wanted: u16) -> anyhow::Result<Option<String>> {
+    let count = |offset: usize| u16::from_be_bytes([response[offset], response[offset + 1]]);
+    let (questions, answers) = (count(4), count(6));
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(response, pos)? + 4;
+    }
+    for _ in 0..answers {
+        pos = skip_name(response, pos)?;
+        let header = slice(response, pos, 10)?;
+        let rr_type = u16::from_be_bytes([header[0], header[1]]);
+        let len = u16::from_be_bytes([header[8], header[9]]) as usize;
+        let rdata = slice(response, pos + 10, len)?;
+        match (rr_type, len) {
+            (rr_type, _) if rr_type != wanted && rr_type != TYPE_CNAME => pos += 10 + len,
+            (TYPE_A, 4) => {
+                let octets = [rdata[0], rdata[1], rdata[2], rdata[3]];
+                return Ok(Some(Ipv4Addr::from(octets).to_string()));
+            }
+            (TYPE_AAAA, 16) => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(rdata);
+                return Ok(Some(Ipv6Addr::from(octets).to_string()));
+            }
+            (TYPE_CNAME, _) => return Ok(Some(read_name(response, pos + 10)?)),
+            _ => pos += 10 + len,
+        }
+    }
+    Ok(None)
+}
+
+pub(crate) fn slice(message: &[u8], pos: usize, len: usize) -> anyhow::Result<&[u8]> {
+    message
+        .get(pos..pos + len)
+        .ok_or_else(|| anyhow::anyhow!("truncated DNS message"))
+}
+
+/// Position right after the name at pos
+pub(crate) fn skip_name(message: &[u8], mut pos: usize) -> anyhow::Result<usize> {
+    loop {
+        let len = slice(message, pos, 1)?[0];
+        match len {
+            0 => return Ok(pos + 1),
+            len if len & 0xc0 == 0xc0 => return Ok(pos + 2),
+            len => pos += 1 + len as usize,
+        }
+    }
+}
+
+/// Read possibly compressed name at pos
+pub(crate) fn read_name(message: &[u8], mut pos: usize) -> anyhow::Result<String> {
+    let mut labels = vec![];
+    // every pointer must go backwards, which rules out loops
+    let mut limit = pos;
+    loop {
+        let len = slice(message, pos, 1)?[0];
+        match len {
+            0 => return Ok(labels.join(".")),
+            len if len & 0xc0 == 0xc0 => {
+                let target =
+                    (u16::from_be_bytes([len, slice(message, pos + 1, 1)?[0]]) & 0x3fff) as usize;
+                if target >= limit {
+                    bail!("invalid compression pointer in DNS message");
+                }
+                limit = target;
+                pos = target;
+            }
+            len => {
+                let label = slice(message, pos + 1, len as usize)?;
+                labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+                pos += 1 + len as usize;
+            }
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
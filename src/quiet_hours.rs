@@ -0,0 +1,64 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use chrono::{Local, NaiveTime};
+use tokio::time::Instant;
+
+/// Daily window in local time e.g. 23:00-07:00, which may span midnight
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuietHours {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl QuietHours {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl FromStr for QuietHours {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = match s.split_once('-') {
+            Some(window) => window,
+            None => bail!("quiet hours should be like 23:00-07:00: {}", s),
+        };
+        let parse = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .with_context(|| format!("invalid time in quiet hours: {}", time))
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start == end {
+            bail!("quiet hours should not be empty: {}", s);
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+/// Local time of day, moved along with the clock of tokio while paused e.g. in tests
+pub(crate) fn local_time() -> NaiveTime {
+    let (paused, now) = (Instant::now().into_std(), std::time::Instant::now());
+    let skew = match paused.checked_duration_since(now) {
+        Some(ahead) => chrono::Duration::from_std(ahead),
+        None => chrono::Duration::from_std(now - paused).map(|behind| -behind),
+    };
+    (Local::now() + skew.unwrap_or_else(|_| chrono::Duration::zero())).time()
+}
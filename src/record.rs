@@ -0,0 +1,127 @@
+/// Parse comma-separated record names e.g. `a.x.com, b.x.com`. Surrounding whitespace is
+/// trimmed and empty entries are dropped, so the result never holds a blank name. Names are
+/// normalized, see [`normalize_name`], and options after them e.g. `:proxied` are dropped.
+pub fn parse_record_names(records: &str) -> Vec<String> {
+    records
+        .split(',')
+        .map(|entry| normalize_name(split_option(entry).0))
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Options given after record names e.g. `proxied` of `a.x.com:proxied`, with the names
+pub(crate) fn parse_record_options(records: &str) -> Vec<(String, String)> {
+    records
+        .split(',')
+        .filter_map(|entry| match split_option(entry) {
+            (name, Some(option)) => Some((normalize_name(name), option.trim().to_string())),
+            (_, None) => None,
+        })
+        .collect()
+}
+
+/// Check options after record names, so an unknown one e.g. `orange` of `a.x.com:orange` is
+/// rejected as the command line is parsed
+pub(crate) fn check_record_options(records: &str) -> Result<String, String> {
+    for (name, option) in parse_record_options(records) {
+        if option != "proxied" && option != "dns-only" {
+            return Err(format!(
+                "record {}: unknown option {}, expected proxied or dns-only",
+                name, option
+            ));
+        }
+    }
+    Ok(records.to_string())
+}
+
+fn split_option(entry: &str) -> (&str, Option<&str>) {
+    match entry.split_once(':') {
+        Some((name, option)) => (name, Some(option)),
+        None => (entry, None),
+    }
+}
+
+/// Drop repeated names keeping the first occurrence, return unique names and the duplicates
+pub(crate) fn dedup_record_names(names: Vec<String>) -> (Vec<String>, Vec<String>) {
+    let mut seen = std::collections::HashSet::new();
+    names
+        .into_iter()
+        .partition(|name| seen.insert(name.clone()))
+}
+
+/// Lowercase name and strip trailing dot, so `Host.Example.com.` matches `host.example.com`
+pub fn normalize_name(name: &str) -> String {
+    name.trim_start()
+        .trim_end_matches(|c: char| c == '.' || c.is_whitespace())
+        .to_ascii_lowercase()
+}
+
+/// Longest domain name in presentation format, RFC 1035
+const MAX_NAME_LENGTH: usize = 253;
+/// Longest label, RFC 1035
+const MAX_LABEL_LENGTH: usize = 63;
+
+/// Check zone and record names against Cloudflare limits before making API calls. Every invalid
+/// entry is listed in one error rather than failing one request after another.
+pub fn validate_record_names(zone: &str, names: &[String]) -> anyhow::Result<()> {
+    check_problems(invalid_names(zone, names))
+}
+
+/// Describe every invalid zone or record name, empty if all of them are valid
+pub(crate) fn invalid_names(zone: &str, names: &[String]) -> Vec<String> {
+    let mut problems = vec![];
+    if let Err(reason) = validate_name(zone, false) {
+        problems.push(format!("zone {}: {}", zone, reason));
+    }
+    for name in names {
+        let reason = validate_name(name, true).err().or_else(|| {
+            if name != zone && !name.ends_with(&format!(".{}", zone)) {
+                Some(format!("not in zone {}", zone))
+            } else {
+                None
+            }
+        });
+        if let Some(reason) = reason {
+            problems.push(format!("record {}: {}", name, reason));
+        }
+    }
+    problems
+}
+
+/// Fail with one error listing every problem, if any
+pub(crate) fn check_problems(problems: Vec<String>) -> anyhow::Result<()> {
+    if !problems.is_empty() {
+        anyhow::bail!("invalid names:\n  {}", problems.join("\n  "));
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_name(name: &str, wildcard: bool) -> Result<(), String> {
+    if name.len() > MAX_NAME_LENGTH {
+        return Err(format!("longer than {} characters", MAX_NAME_LENGTH));
+    }
+    for (index, label) in name.split('.').enumerate() {
+        if label.is_empty() {
+            return Err("empty label".to_string());
+        }
+        if label.len() > MAX_LABEL_LENGTH {
+            return Err(format!(
+                "label {} longer than {} characters",
+                label, MAX_LABEL_LENGTH
+            ));
+        }
+        if wildcard && index == 0 && label == "*" {
+            continue;
+        }
+        if !label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!("label {} has invalid characters", label));
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err(format!("label {} starts or ends with hyphen", label));
+        }
+    }
+    Ok(())
+}
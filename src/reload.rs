@@ -0,0 +1,72 @@
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use log::info;
+
+use crate::{Cdu, Event, Opts, Runner, Trigger};
+
+/// Runner whose configuration can be re-read while the daemon runs. A run in progress finishes
+/// with the configuration it started with.
+pub struct Reloadable {
+    current: RwLock<Arc<Cdu>>,
+}
+
+impl Reloadable {
+    pub fn new(cdu: Arc<Cdu>) -> Self {
+        Self {
+            current: RwLock::new(cdu),
+        }
+    }
+
+    pub fn current(&self) -> Arc<Cdu> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Parse command line and the configuration file again, then switch to them unless invalid.
+    /// Returns the cron schedule of the new configuration, to replace the running one.
+    pub fn reload(&self) -> anyhow::Result<Trigger> {
+        let opts = Opts::from_args_with_config()?;
+        let cdu = self.current().reload(opts);
+        let schedule = cdu.schedule()?;
+        info!("configuration reloaded, {}", cdu.schedule_description());
+        cdu.log_cron_normalization();
+        cdu.warn_schedule();
+        *self.current.write().unwrap() = Arc::new(cdu);
+        Ok(schedule)
+    }
+}
+
+impl Runner for Reloadable {
+    fn run(&self, ip_address: Option<IpAddr>) -> BoxFuture<'_, anyhow::Result<()>> {
+        let cdu = self.current();
+        Box::pin(async move { cdu.run_with_ip_address(ip_address).await })
+    }
+
+    fn run_group(
+        &self,
+        ip_address: Option<IpAddr>,
+        group: Option<String>,
+    ) -> BoxFuture<'_, anyhow::Result<()>> {
+        let cdu = self.current();
+        Box::pin(async move { cdu.run_group(ip_address, group).await })
+    }
+
+    fn rate_limited_for(&self) -> Option<Duration> {
+        self.current().rate_limited_for()
+    }
+
+    fn emit(&self, event: Event) -> BoxFuture<'_, ()> {
+        let cdu = self.current();
+        Box::pin(async move { cdu.emit(event).await })
+    }
+
+    fn flush(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        let cdu = self.current();
+        Box::pin(async move {
+            cdu.flush_events().await;
+            cdu.flush_state()
+        })
+    }
+}
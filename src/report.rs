@@ -0,0 +1,60 @@
+use std::fs;
+use std::path::Path;
+
+use serde_yaml::Value;
+
+/// Replacement of secrets in bug reports
+pub(crate) const REDACTED: &str = "[redacted]";
+
+/// Whether a setting holds a secret e.g. api_key or client_secret, by its name
+pub(crate) fn is_secret(key: &str) -> bool {
+    // options in the configuration file may be named as on command line e.g. powerdns-api-key
+    let key = key.replace('-', "_");
+    key.ends_with("_key")
+        || key.contains("secret")
+        || key.contains("token")
+        || key.contains("password")
+        // proxy URLs may have credentials in them
+        || key.ends_with("_proxy")
+}
+
+/// Replace values of secret settings, at any depth
+pub(crate) fn redact(value: &mut Value) {
+    match value {
+        Value::Sequence(values) => values.iter_mut().for_each(redact),
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping.iter_mut() {
+                match key.as_str() {
+                    Some(key) if is_secret(key) => *value = Value::String(REDACTED.to_string()),
+                    _ => redact(value),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Configuration file with secrets redacted
+pub(crate) fn redacted_config(path: &Path) -> anyhow::Result<Value> {
+    let mut value = crate::config::read(path)?;
+    redact(&mut value);
+    Ok(value)
+}
+
+/// Last lines of a log file, with secrets replaced
+pub(crate) fn tail(path: &Path, lines: usize, secrets: &[&str]) -> anyhow::Result<Vec<String>> {
+    let content = fs::read_to_string(path)?;
+    let all: Vec<_> = content.lines().collect();
+    let start = all.len().saturating_sub(lines);
+    Ok(all[start..]
+        .iter()
+        .map(|line| {
+            secrets
+                .iter()
+                .filter(|secret| !secret.is_empty())
+                .fold(line.to_string(), |line, secret| {
+                    line.replace(secret, REDACTED)
+                })
+        })
+        .collect())
+}
@@ -0,0 +1,321 @@
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::Path;
+use std::ptr;
+
+use anyhow::bail;
+use libc::{c_long, c_uint, sock_filter, sock_fprog};
+use log::{info, warn};
+
+use super::{parent, Paths};
+
+/// Files read by the C library to resolve names and load name service modules
+const RESOLVER_PATHS: &[&str] = &[
+    "/etc/resolv.conf",
+    "/etc/hosts",
+    "/etc/nsswitch.conf",
+    "/etc/host.conf",
+    "/etc/gai.conf",
+    "/lib",
+    "/lib64",
+    "/usr/lib",
+    "/usr/lib64",
+];
+
+const LANDLOCK_CREATE_RULESET_VERSION: c_uint = 1 << 0;
+const LANDLOCK_RULE_PATH_BENEATH: c_uint = 1;
+
+const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+/// Every access right of the first version, from execute to making symbolic links
+const ACCESS_FS_V1: u64 = (1 << 13) - 1;
+/// Renaming and linking across directories, since the second version
+const ACCESS_FS_REFER: u64 = 1 << 13;
+/// Truncating files, since the third version
+const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+/// System calls a DNS updater never makes, failed with EPERM once sandboxed
+const DENIED_SYSCALLS: &[c_long] = &[
+    libc::SYS_execve,
+    libc::SYS_execveat,
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_kexec_load,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_userfaultfd,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_open_by_handle_at,
+    libc::SYS_reboot,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_acct,
+];
+
+/// Architecture seccomp reports for system calls of this build, see `AUDIT_ARCH_*` of Linux
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+#[cfg(target_arch = "arm")]
+const AUDIT_ARCH: Option<u32> = Some(0x4000_0028);
+#[cfg(target_arch = "riscv64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_00f3);
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "arm",
+    target_arch = "riscv64"
+)))]
+const AUDIT_ARCH: Option<u32> = None;
+
+/// Restrict the process with landlock and seccomp. Only the given files can be read, and only
+/// files in the directories of the written ones can be created or replaced, as they are written
+/// to a temporary file beside them first. Both apply to the calling thread and threads it starts
+/// later, so call before the runtime starts its threads.
+pub(crate) fn restrict(paths: &Paths) -> anyhow::Result<()> {
+    // SAFETY: no memory is involved
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        bail!("failed to set no_new_privs: {}", io::Error::last_os_error());
+    }
+    match landlock_abi() {
+        Some(abi) => landlock(abi, paths)?,
+        None => warn!("landlock is not supported by the kernel, file access is not restricted"),
+    }
+    match AUDIT_ARCH {
+        Some(arch) => seccomp(arch)?,
+        None => warn!("seccomp filter is not supported on this architecture"),
+    }
+    Ok(())
+}
+
+/// Version of landlock supported by the kernel, none if unsupported or disabled
+fn landlock_abi() -> Option<i64> {
+    // SAFETY: null attributes with the version flag only query the version
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            ptr::null::<RulesetAttr>(),
+            0,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+    if abi < 1 {
+        None
+    } else {
+        Some(abi)
+    }
+}
+
+fn landlock(abi: i64, paths: &Paths) -> anyhow::Result<()> {
+    let mut handled = ACCESS_FS_V1;
+    let mut write_access = ACCESS_FS_READ_FILE
+        | ACCESS_FS_WRITE_FILE
+        | ACCESS_FS_READ_DIR
+        | ACCESS_FS_REMOVE_FILE
+        | ACCESS_FS_MAKE_REG;
+    if abi >= 2 {
+        handled |= ACCESS_FS_REFER;
+    }
+    if abi >= 3 {
+        handled |= ACCESS_FS_TRUNCATE;
+        write_access |= ACCESS_FS_TRUNCATE;
+    }
+    let attr = RulesetAttr {
+        handled_access_fs: handled,
+    };
+    // SAFETY: the attributes outlive the call
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const RulesetAttr,
+            mem::size_of::<RulesetAttr>(),
+            0,
+        )
+    };
+    if fd < 0 {
+        bail!(
+            "failed to create landlock ruleset: {}",
+            io::Error::last_os_error()
+        );
+    }
+    // SAFETY: the file descriptor was just created and is owned here
+    let ruleset = unsafe { File::from_raw_fd(fd as i32) };
+
+    // files yet to be created e.g. watched files can still be looked up, just not read
+    let resolver = RESOLVER_PATHS.iter().map(Path::new);
+    for path in paths
+        .read
+        .iter()
+        .copied()
+        .chain(resolver)
+        .filter(|p| p.exists())
+    {
+        let access = if path.is_dir() {
+            ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR
+        } else {
+            ACCESS_FS_READ_FILE
+        };
+        allow(&ruleset, path, access)?;
+    }
+    for path in &paths.write {
+        allow(&ruleset, parent(path), write_access)?;
+    }
+    if let Some(path) = paths.socket {
+        // socket left behind by previous process is removed before binding
+        allow(
+            &ruleset,
+            parent(path),
+            ACCESS_FS_REMOVE_FILE | ACCESS_FS_MAKE_SOCK,
+        )?;
+    }
+
+    // SAFETY: no memory is involved
+    if unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0) } != 0 {
+        bail!(
+            "failed to enforce landlock ruleset: {}",
+            io::Error::last_os_error()
+        );
+    }
+    info!("sandboxed with landlock version {}", abi);
+    Ok(())
+}
+
+fn allow(ruleset: &File, path: &Path, access: u64) -> anyhow::Result<()> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: the path outlives the call
+    let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+    if fd < 0 {
+        bail!(
+            "failed to open {} for sandbox: {}",
+            path.display(),
+            io::Error::last_os_error()
+        );
+    }
+    // SAFETY: the file descriptor was just opened and is owned here
+    let file = unsafe { File::from_raw_fd(fd) };
+    let attr = PathBeneathAttr {
+        allowed_access: access,
+        parent_fd: file.as_raw_fd(),
+    };
+    // SAFETY: the attributes outlive the call
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_add_rule,
+            ruleset.as_raw_fd(),
+            LANDLOCK_RULE_PATH_BENEATH,
+            &attr as *const PathBeneathAttr,
+            0,
+        )
+    };
+    if res != 0 {
+        bail!(
+            "failed to allow access to {}: {}",
+            path.display(),
+            io::Error::last_os_error()
+        );
+    }
+    Ok(())
+}
+
+fn seccomp(arch: u32) -> anyhow::Result<()> {
+    let statement = |code: u32, k: u32| sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    };
+    let jump = |code: u32, k: u32, jt: u8, jf: u8| sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    };
+    let deny = libc::SECCOMP_RET_ERRNO | (libc::EPERM as u32 & libc::SECCOMP_RET_DATA);
+    // offsets of arch and nr in struct seccomp_data
+    let mut filter = vec![
+        statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 4),
+        jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, arch, 1, 0),
+        statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 0),
+    ];
+    // system calls of the x32 ABI share the architecture of x86_64
+    #[cfg(target_arch = "x86_64")]
+    filter.extend(vec![
+        jump(
+            libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+            0x4000_0000,
+            0,
+            1,
+        ),
+        statement(libc::BPF_RET | libc::BPF_K, deny),
+    ]);
+    for &nr in DENIED_SYSCALLS {
+        filter.push(jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            nr as u32,
+            0,
+            1,
+        ));
+        filter.push(statement(libc::BPF_RET | libc::BPF_K, deny));
+    }
+    filter.push(statement(
+        libc::BPF_RET | libc::BPF_K,
+        libc::SECCOMP_RET_ALLOW,
+    ));
+    let program = sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_mut_ptr(),
+    };
+    // SAFETY: the program outlives the call, the kernel copies it
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            libc::SECCOMP_FILTER_FLAG_TSYNC,
+            &program as *const sock_fprog,
+        )
+    };
+    if res != 0 {
+        bail!(
+            "failed to install seccomp filter: {}",
+            io::Error::last_os_error()
+        );
+    }
+    info!(
+        "sandboxed with seccomp, {} system calls denied",
+        DENIED_SYSCALLS.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use std::path::Path;
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "openbsd")]
+mod openbsd;
+
+#[cfg(target_os = "linux")]
+pub(crate) use self::linux::restrict;
+#[cfg(target_os = "openbsd")]
+pub(crate) use self::openbsd::restrict;
+
+#[cfg(not(any(target_os = "linux", target_os = "openbsd")))]
+compile_error!("the sandbox feature is only supported on Linux and OpenBSD");
+
+/// Files the process still needs once sandboxed
+pub(crate) struct Paths<'a> {
+    /// Files to read e.g. configuration file, watched files
+    pub(crate) read: Vec<&'a Path>,
+    /// Files to write, replaced by renaming a temporary file in the same directory
+    pub(crate) write: Vec<&'a Path>,
+    /// Control socket, created in its directory on Linux. It's bound already on OpenBSD.
+    #[cfg_attr(target_os = "openbsd", allow(dead_code))]
+    pub(crate) socket: Option<&'a Path>,
+}
+
+/// Directory a file is created in, the working directory for bare file names
+fn parent(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
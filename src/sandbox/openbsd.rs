@@ -0,0 +1,53 @@
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::ptr;
+
+use anyhow::bail;
+use log::info;
+
+use super::{parent, Paths};
+
+/// Promises kept after initialization: files, network, DNS lookups, and the control socket
+const PROMISES: &str = "stdio rpath wpath cpath inet dns unix";
+
+/// Restrict the process with unveil and pledge. Only the given files can be read, and only files
+/// in the directories of the written ones can be created or replaced, as they are written to a
+/// temporary file beside them first. The control socket is bound already.
+pub(crate) fn restrict(paths: &Paths) -> anyhow::Result<()> {
+    for path in &paths.read {
+        unveil(path, "r")?;
+    }
+    for path in &paths.write {
+        unveil(parent(path), "rwc")?;
+    }
+    // SAFETY: null pointers lock the unveiled paths
+    if unsafe { libc::unveil(ptr::null(), ptr::null()) } == -1 {
+        bail!(
+            "failed to lock unveiled paths: {}",
+            io::Error::last_os_error()
+        );
+    }
+    let promises = CString::new(PROMISES)?;
+    // SAFETY: the promises outlive the call, null leaves promises on exec untouched
+    if unsafe { libc::pledge(promises.as_ptr(), ptr::null()) } == -1 {
+        bail!("failed to pledge: {}", io::Error::last_os_error());
+    }
+    info!("sandboxed with pledge: {}", PROMISES);
+    Ok(())
+}
+
+fn unveil(path: &Path, permissions: &str) -> anyhow::Result<()> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let c_permissions = CString::new(permissions)?;
+    // SAFETY: both strings outlive the call
+    if unsafe { libc::unveil(c_path.as_ptr(), c_permissions.as_ptr()) } == -1 {
+        bail!(
+            "failed to unveil {}: {}",
+            path.display(),
+            io::Error::last_os_error()
+        );
+    }
+    Ok(())
+}
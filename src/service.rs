@@ -0,0 +1,198 @@
+use std::ffi::OsString;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use log::{error, info};
+use once_cell::sync::OnceCell;
+use tokio::sync::oneshot;
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+    ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{
+    self, ServiceControlHandlerResult, ServiceStatusHandle,
+};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+
+use crate::command_line::forwarded_args;
+
+/// Daemon run by the service, given the receiver which resolves once the service is stopped
+type Daemon = Box<dyn FnOnce(oneshot::Receiver<()>) -> anyhow::Result<()> + Send>;
+
+/// Service control dispatcher calls back without context, so the state is kept here
+static SERVICE: OnceCell<Service> = OnceCell::new();
+
+struct Service {
+    name: String,
+    daemon: Mutex<Option<Daemon>>,
+    stop: Mutex<Option<oneshot::Sender<()>>>,
+    handle: OnceCell<ServiceStatusHandle>,
+    /// How long the Service Control Manager waits for the service to stop
+    wait_hint: Duration,
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Register a service with the Service Control Manager which runs the daemon at startup, with
+/// the arguments given before `service install`. Give the token as `CLOUDFLARE_TOKEN` system
+/// environment variable or in the configuration file rather than on command line, or it's kept
+/// in the configuration of the service.
+pub(crate) fn install(name: &str) -> anyhow::Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .context("failed to connect to the Service Control Manager")?;
+    let mut launch_arguments: Vec<OsString> = forwarded_args(&["service", "install"])
+        .into_iter()
+        .map(OsString::from)
+        .collect();
+    launch_arguments.extend(
+        ["--daemon", "service", "run", "--name", name]
+            .iter()
+            .map(OsString::from),
+    );
+    let info = ServiceInfo {
+        name: name.into(),
+        display_name: name.into(),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments,
+        dependencies: vec![],
+        // LocalSystem
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager
+        .create_service(&info, ServiceAccess::CHANGE_CONFIG)
+        .with_context(|| format!("failed to create service {}", name))?;
+    service
+        .set_description("Update Cloudflare DNS records with the public IP address")
+        .with_context(|| format!("failed to describe service {}", name))?;
+    info!("service installed: {}, started at next boot", name);
+    Ok(())
+}
+
+/// Stop and remove the service registered by `install`
+pub(crate) fn uninstall(name: &str) -> anyhow::Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .context("failed to connect to the Service Control Manager")?;
+    let service = manager
+        .open_service(name, ServiceAccess::STOP | ServiceAccess::DELETE)
+        .with_context(|| format!("failed to open service {}", name))?;
+    // the service may not be running
+    let _ = service.stop();
+    service
+        .delete()
+        .with_context(|| format!("failed to delete service {}", name))?;
+    info!("service uninstalled: {}", name);
+    Ok(())
+}
+
+/// Run the daemon as the service, returning once it's stopped. The daemon is given the
+/// shutdown timeout to stop. Only the Service Control Manager can start it, so it fails if run
+/// from a console.
+pub fn run(
+    name: &str,
+    shutdown_timeout: Duration,
+    daemon: impl FnOnce(oneshot::Receiver<()>) -> anyhow::Result<()> + Send + 'static,
+) -> anyhow::Result<()> {
+    let service = Service {
+        name: name.to_string(),
+        daemon: Mutex::new(Some(Box::new(daemon))),
+        stop: Mutex::new(None),
+        handle: OnceCell::new(),
+        wait_hint: shutdown_timeout,
+    };
+    if SERVICE.set(service).is_err() {
+        bail!("service is already running");
+    }
+    service_dispatcher::start(name, ffi_service_main).context(
+        "failed to connect to the Service Control Manager, install the service with `cdu service install` rather than running it",
+    )?;
+    Ok(())
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    let service = SERVICE.get().unwrap();
+    let (tx, rx) = oneshot::channel();
+    *service.stop.lock().unwrap() = Some(tx);
+    let handle = match service_control_handler::register(&service.name, control_handler) {
+        Ok(handle) => handle,
+        Err(e) => {
+            error!(
+                "failed to register service control handler: {:#}",
+                anyhow::Error::new(e)
+            );
+            return;
+        }
+    };
+    let _ = service.handle.set(handle);
+    set_status(ServiceState::Running, ServiceExitCode::NO_ERROR);
+
+    let daemon = service.daemon.lock().unwrap().take();
+    let res = match daemon {
+        Some(daemon) => daemon(rx),
+        None => Ok(()),
+    };
+    let exit_code = match res {
+        Ok(_) => ServiceExitCode::NO_ERROR,
+        Err(e) => {
+            error!("service stopped: {:#}", e);
+            ServiceExitCode::Win32(1)
+        }
+    };
+    set_status(ServiceState::Stopped, exit_code);
+}
+
+/// Stop and shutdown of the system both shut the daemon down, as signals do on Unix
+fn control_handler(control: ServiceControl) -> ServiceControlHandlerResult {
+    match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            let service = SERVICE.get().unwrap();
+            if let Some(stop) = service.stop.lock().unwrap().take() {
+                info!("service stop requested, shut down");
+                set_status(ServiceState::StopPending, ServiceExitCode::NO_ERROR);
+                let _ = stop.send(());
+            }
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    }
+}
+
+fn set_status(state: ServiceState, exit_code: ServiceExitCode) {
+    let service = SERVICE.get().unwrap();
+    let handle = match service.handle.get() {
+        Some(handle) => handle,
+        None => return,
+    };
+    let controls_accepted = match state {
+        ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+        _ => ServiceControlAccept::empty(),
+    };
+    let status = ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted,
+        exit_code,
+        checkpoint: 0,
+        // the run in progress is given --shutdown-timeout to finish
+        wait_hint: match state {
+            ServiceState::StopPending => service.wait_hint,
+            _ => Duration::ZERO,
+        },
+        process_id: None,
+    };
+    if let Err(e) = handle.set_service_status(status) {
+        error!(
+            "failed to report service status: {:#}",
+            anyhow::Error::new(e)
+        );
+    }
+}
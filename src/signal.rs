@@ -0,0 +1,77 @@
+use std::fmt;
+
+/// Signal the daemon acts on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Signal {
+    /// SIGHUP, reload configuration
+    Hangup,
+    /// SIGINT e.g. Ctrl-C, shut down
+    Interrupt,
+    /// SIGTERM, shut down
+    Terminate,
+    /// SIGUSR1, update right away
+    User1,
+    /// SIGUSR2, update right away without cached identifiers, even if up to date
+    User2,
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Signal::Hangup => write!(f, "SIGHUP"),
+            Signal::Interrupt => write!(f, "SIGINT"),
+            Signal::Terminate => write!(f, "SIGTERM"),
+            Signal::User1 => write!(f, "SIGUSR1"),
+            Signal::User2 => write!(f, "SIGUSR2"),
+        }
+    }
+}
+
+/// Receive SIGHUP, SIGINT, SIGTERM, SIGUSR1, and SIGUSR2 over the channel. The signals are blocked in the calling
+/// thread and threads it starts later, and waited for by a thread of their own, so call before the
+/// runtime starts its threads.
+#[cfg(unix)]
+pub fn signals() -> anyhow::Result<tokio::sync::mpsc::UnboundedReceiver<Signal>> {
+    use std::{io, mem, ptr};
+
+    // SAFETY: the set is initialized by sigemptyset before use
+    let set = unsafe {
+        let mut set = mem::zeroed::<libc::sigset_t>();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGHUP);
+        libc::sigaddset(&mut set, libc::SIGINT);
+        libc::sigaddset(&mut set, libc::SIGTERM);
+        libc::sigaddset(&mut set, libc::SIGUSR1);
+        libc::sigaddset(&mut set, libc::SIGUSR2);
+        set
+    };
+    // SAFETY: the set outlives the call
+    let res = unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &set, ptr::null_mut()) };
+    if res != 0 {
+        anyhow::bail!(
+            "failed to block signals: {}",
+            io::Error::from_raw_os_error(res)
+        );
+    }
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    std::thread::Builder::new()
+        .name("signals".to_string())
+        .spawn(move || loop {
+            let mut signal = 0;
+            // SAFETY: the set and the signal outlive the call
+            if unsafe { libc::sigwait(&set, &mut signal) } != 0 {
+                continue;
+            }
+            let signal = match signal {
+                libc::SIGHUP => Signal::Hangup,
+                libc::SIGINT => Signal::Interrupt,
+                libc::SIGUSR1 => Signal::User1,
+                libc::SIGUSR2 => Signal::User2,
+                _ => Signal::Terminate,
+            };
+            if tx.send(signal).is_err() {
+                break;
+            }
+        })?;
+    Ok(rx)
+}
@@ -0,0 +1,303 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, bail, Context};
+use log::debug;
+use once_cell::sync::Lazy;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Longest request of CONNECT accepted by the bridge
+const MAX_REQUEST: usize = 8192;
+
+/// Proxy to send requests through, by HTTP CONNECT or SOCKS5 e.g. over an SSH tunnel or Tor.
+/// Host names are resolved by SOCKS5 proxies rather than locally.
+#[derive(Clone, PartialEq, Eq)]
+pub enum ProxyUrl {
+    Http(String),
+    Socks5 {
+        addr: String,
+        credentials: Option<(String, String)>,
+    },
+}
+
+impl FromStr for ProxyUrl {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let url = reqwest::Url::parse(s).with_context(|| format!("invalid proxy URL {}", s))?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow!("proxy URL without host: {}", s))?;
+        match url.scheme() {
+            "http" | "https" => Ok(ProxyUrl::Http(s.to_string())),
+            "socks5" | "socks5h" => {
+                let port = url.port().unwrap_or(1080);
+                let credentials = match url.username() {
+                    "" => None,
+                    username => {
+                        let password = url.password().unwrap_or_default();
+                        Some((decode(username), decode(password)))
+                    }
+                };
+                Ok(ProxyUrl::Socks5 {
+                    addr: format!("{}:{}", host, port),
+                    credentials,
+                })
+            }
+            scheme => bail!(
+                "unsupported scheme of proxy {}, expect http, https, or socks5",
+                scheme
+            ),
+        }
+    }
+}
+
+/// Proxies are printed without credentials
+impl fmt::Display for ProxyUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxyUrl::Http(url) => match reqwest::Url::parse(url) {
+                Ok(url) => write!(
+                    f,
+                    "{}://{}",
+                    url.scheme(),
+                    url.host_str().unwrap_or_default()
+                ),
+                Err(_) => write!(f, "{}", url),
+            },
+            ProxyUrl::Socks5 { addr, .. } => write!(f, "socks5://{}", addr),
+        }
+    }
+}
+
+impl fmt::Debug for ProxyUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl ProxyUrl {
+    /// Proxy of reqwest, which only speaks HTTP to proxies of its own, so SOCKS5 proxies are
+    /// reached through a bridge on loopback started once for each
+    pub(crate) fn reqwest_proxy(&self) -> anyhow::Result<reqwest::Proxy> {
+        match self {
+            ProxyUrl::Http(url) => Ok(reqwest::Proxy::all(url.as_str())?),
+            ProxyUrl::Socks5 { .. } => {
+                let addr = bridge(self)?;
+                Ok(reqwest::Proxy::all(format!("http://{}", addr))?)
+            }
+        }
+    }
+}
+
+fn decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Address of bridges on loopback by the SOCKS5 proxy they forward to
+static BRIDGES: Lazy<Mutex<BTreeMap<String, SocketAddr>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Address of the bridge accepting HTTP CONNECT and forwarding it through the SOCKS5 proxy. The
+/// bridge runs on a thread of its own, so it outlives the runtime which first asked for it.
+fn bridge(proxy: &ProxyUrl) -> anyhow::Result<SocketAddr> {
+    let key = match proxy {
+        ProxyUrl::Socks5 { addr, credentials } => format!("{:?}@{}", credentials, addr),
+        ProxyUrl::Http(_) => bail!("{} is not a SOCKS5 proxy", proxy),
+    };
+    let mut bridges = BRIDGES.lock().unwrap();
+    if let Some(addr) = bridges.get(&key) {
+        return Ok(*addr);
+    }
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let bridged = proxy.clone();
+    std::thread::Builder::new()
+        .name("socks5-bridge".to_string())
+        .spawn(move || {
+            runtime.block_on(async move {
+                let listener = match TcpListener::from_std(listener) {
+                    Ok(listener) => listener,
+                    Err(e) => return log::error!("SOCKS5 bridge stopped: {}", e),
+                };
+                loop {
+                    let stream = match listener.accept().await {
+                        Ok((stream, _)) => stream,
+                        Err(e) => {
+                            debug!("SOCKS5 bridge failed to accept: {}", e);
+                            continue;
+                        }
+                    };
+                    let proxy = bridged.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = forward(stream, &proxy).await {
+                            debug!("failed to connect through {}: {:#}", proxy, e);
+                        }
+                    });
+                }
+            })
+        })?;
+    debug!("bridge {} on {}", proxy, addr);
+    bridges.insert(key, addr);
+    Ok(addr)
+}
+
+/// Tunnel a connection requested by HTTP CONNECT through the SOCKS5 proxy
+async fn forward(mut client: TcpStream, proxy: &ProxyUrl) -> anyhow::Result<()> {
+    let mut request = vec![];
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = client.read(&mut buf).await?;
+        if n == 0 || request.len() + n > MAX_REQUEST {
+            bail!("incomplete request of CONNECT");
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let target = match request.split_whitespace().collect::<Vec<_>>()[..] {
+        ["CONNECT", target, ..] => target.to_string(),
+        _ => {
+            client
+                .write_all(b"HTTP/1.1 405 Method Not Allowed\r\ncontent-length: 0\r\n\r\n")
+                .await?;
+            bail!(
+                "only CONNECT is forwarded, not {}",
+                request.lines().next().unwrap_or_default()
+            );
+        }
+    };
+    let (host, port) = target
+        .rsplit_once(':')
+        .and_then(|(host, port)| Some((host.trim_matches(&['[', ']'][..]), port.parse().ok()?)))
+        .ok_or_else(|| anyhow!("invalid target {}", target))?;
+    let mut upstream = match connect(proxy, host, port).await {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            client
+                .write_all(b"HTTP/1.1 502 Bad Gateway\r\ncontent-length: 0\r\n\r\n")
+                .await?;
+            return Err(e);
+        }
+    };
+    client
+        .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+        .await?;
+    tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+    Ok(())
+}
+
+/// Connect to the host through the SOCKS5 proxy, RFC 1928, with username and password if given,
+/// RFC 1929
+async fn connect(proxy: &ProxyUrl, host: &str, port: u16) -> anyhow::Result<TcpStream> {
+    let (addr, credentials) = match proxy {
+        ProxyUrl::Socks5 { addr, credentials } => (addr, credentials),
+        ProxyUrl::Http(_) => bail!("{} is not a SOCKS5 proxy", proxy),
+    };
+    let mut stream = TcpStream::connect(addr.as_str())
+        .await
+        .with_context(|| format!("failed to connect to {}", proxy))?;
+    match credentials {
+        Some(_) => stream.write_all(&[5, 2, 0, 2]).await?,
+        None => stream.write_all(&[5, 1, 0]).await?,
+    }
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply).await?;
+    match (reply, credentials) {
+        ([5, 0], _) => {}
+        ([5, 2], Some((username, password))) => {
+            if username.len() > 255 || password.len() > 255 {
+                bail!("username or password of {} is too long", proxy);
+            }
+            let mut auth = vec![1, username.len() as u8];
+            auth.extend_from_slice(username.as_bytes());
+            auth.push(password.len() as u8);
+            auth.extend_from_slice(password.as_bytes());
+            stream.write_all(&auth).await?;
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0 {
+                bail!("{} rejected username or password", proxy);
+            }
+        }
+        _ => bail!("{} accepts none of the authentication methods", proxy),
+    }
+
+    let mut request = vec![5, 1, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) if host.len() > 255 => bail!("host name {} is too long", host),
+        Err(_) => {
+            request.push(3);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0 {
+        let reason = match reply[1] {
+            1 => "general failure",
+            2 => "connection not allowed by ruleset",
+            3 => "network unreachable",
+            4 => "host unreachable",
+            5 => "connection refused",
+            6 => "TTL expired",
+            7 => "command not supported",
+            8 => "address type not supported",
+            _ => "unknown error",
+        };
+        bail!(
+            "{} failed to connect to {}:{}: {}",
+            proxy,
+            host,
+            port,
+            reason
+        );
+    }
+    // address the proxy bound, unused
+    let len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => stream.read_u8().await? as usize,
+        atyp => bail!("unexpected address type {} from {}", atyp, proxy),
+    };
+    let mut bound = vec![0; len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(stream)
+}
@@ -0,0 +1,121 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{bail, Context};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::state::RunState;
+use crate::store::{HistoryEntry, Store};
+
+/// Tables are created on first use. Times are kept in UTC with a fixed number of digits, so they
+/// compare in order as text
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS state (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    state TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS history (
+    at TEXT NOT NULL,
+    previous TEXT,
+    current TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS history_at ON history (at);
+";
+
+/// State and history in a SQLite database, e.g. for servers to query history with SQL
+pub(crate) struct SqliteStore {
+    path: Option<PathBuf>,
+    /// Opened on first use, so a database which fails to open is retried like files
+    connection: Mutex<Option<Connection>>,
+}
+
+impl SqliteStore {
+    pub(crate) fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            connection: Mutex::new(None),
+        }
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> anyhow::Result<T> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => bail!("database file is required by the SQLite store"),
+        };
+        let mut connection = self.connection.lock().unwrap();
+        if connection.is_none() {
+            let opened = Connection::open(path)
+                .and_then(|opened| opened.execute_batch(SCHEMA).map(|_| opened))
+                .with_context(|| format!("failed to open database {}", path.display()))?;
+            *connection = Some(opened);
+        }
+        // opened above
+        let res = f(connection.as_ref().unwrap())
+            .with_context(|| format!("failed to query database {}", path.display()))?;
+        Ok(res)
+    }
+}
+
+fn timestamp(at: &DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+impl Store for SqliteStore {
+    fn load(&self) -> anyhow::Result<Option<RunState>> {
+        let state: Option<String> = self.with(|connection| {
+            connection
+                .query_row("SELECT state FROM state WHERE id = 0", [], |row| row.get(0))
+                .optional()
+        })?;
+        match state {
+            Some(state) => Ok(Some(serde_json::from_str(&state)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save(&self, state: &RunState) -> anyhow::Result<()> {
+        let state = serde_json::to_string(state)?;
+        self.with(|connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO state (id, state) VALUES (0, ?1)",
+                params![state],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn append(&self, entry: &HistoryEntry) -> anyhow::Result<()> {
+        let previous = entry.previous.map(|ip| ip.to_string());
+        self.with(|connection| {
+            connection.execute(
+                "INSERT INTO history (at, previous, current) VALUES (?1, ?2, ?3)",
+                params![timestamp(&entry.at), previous, entry.current.to_string()],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn history(&self, since: Option<DateTime<Utc>>) -> anyhow::Result<Vec<HistoryEntry>> {
+        // everything is at or after the empty string
+        let since = since.as_ref().map(timestamp).unwrap_or_default();
+        let rows: Vec<(String, Option<String>, String)> = self.with(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT at, previous, current FROM history WHERE at >= ?1 ORDER BY at, rowid",
+            )?;
+            let rows = statement.query_map(params![since], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?;
+            rows.collect()
+        })?;
+        rows.into_iter()
+            .map(|(at, previous, current)| {
+                Ok(HistoryEntry {
+                    at: DateTime::parse_from_rfc3339(&at)?.with_timezone(&Utc),
+                    previous: previous.map(|ip| ip.parse()).transpose()?,
+                    current: current.parse()?,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,169 @@
+use std::ffi::OsString;
+use std::fmt::Write;
+
+use structopt::clap::App;
+use structopt::StructOpt;
+
+use crate::record::parse_record_options;
+use crate::report::is_secret;
+use crate::{parse_record_names, Opts};
+
+/// Options choosing the configuration file rather than going in it, and records written as
+/// tables of their own
+const EXCLUDED: &[&str] = &["help", "version", "config", "profile", "records"];
+
+const WIDTH: usize = 100;
+
+/// Option of the command line as written to the configuration file
+struct Entry<'a> {
+    key: String,
+    help: &'a str,
+    env: Option<String>,
+    default: Option<String>,
+    flag: bool,
+}
+
+/// Commented configuration file in TOML with options given on command line or by environment
+/// variables filled in, and the others commented out with their default values. Secrets are
+/// referred to by environment variable rather than written to the file.
+pub fn starter_config<I>(args: I) -> anyhow::Result<String>
+where
+    I: IntoIterator,
+    I::Item: Into<OsString>,
+{
+    let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+    let app = Opts::clap();
+    let matches = app.clone().get_matches_from_safe(args)?;
+    let mut entries = entries(&app);
+    entries.sort_by(|a, b| a.key.cmp(&b.key));
+
+    let mut out = String::new();
+    writeln!(out, "# Configuration file of cdu, use it with --config")?;
+    writeln!(
+        out,
+        "# Options given on command line take precedence over those below"
+    )?;
+    for entry in &entries {
+        let name = entry.key.replace('_', "-");
+        let given = matches.occurrences_of(name.as_str()) > 0
+            || entry
+                .env
+                .as_ref()
+                .is_some_and(|env| std::env::var_os(env).is_some());
+        writeln!(out)?;
+        for line in wrap(entry.help) {
+            writeln!(out, "# {}", line)?;
+        }
+        let line = if !given {
+            match entry.default {
+                _ if entry.flag => format!("# {} = false", entry.key),
+                Some(ref default) => format!("# {} = {}", entry.key, toml_value(default)),
+                None => format!("# {} =", entry.key),
+            }
+        } else if entry.flag {
+            format!("{} = true", entry.key)
+        } else if is_secret(&entry.key) && entry.env.is_some() {
+            let env = entry.env.as_deref().unwrap_or_default();
+            format!("{} = \"${{{}}}\"", entry.key, env)
+        } else {
+            let values: Vec<String> = matches
+                .values_of(name.as_str())
+                .into_iter()
+                .flatten()
+                .map(toml_value)
+                .collect();
+            if values.len() == 1 {
+                format!("{} = {}", entry.key, values[0])
+            } else {
+                format!("{} = [{}]", entry.key, values.join(", "))
+            }
+        };
+        writeln!(out, "{}", line)?;
+    }
+    writeln!(out)?;
+    writeln!(out, "# Records, each with settings of its own e.g. ttl")?;
+    match matches.value_of("records") {
+        Some(records) => {
+            let options = parse_record_options(records);
+            for (i, name) in parse_record_names(records).into_iter().enumerate() {
+                if i > 0 {
+                    writeln!(out)?;
+                }
+                writeln!(out, "[[record]]")?;
+                writeln!(out, "name = {}", toml_value(&name))?;
+                match options.iter().find(|(n, _)| *n == name) {
+                    Some((_, option)) if option == "proxied" => writeln!(out, "proxied = true")?,
+                    Some((_, option)) if option == "dns-only" => writeln!(out, "proxied = false")?,
+                    _ => {}
+                }
+            }
+        }
+        None => {
+            writeln!(out, "# [[record]]")?;
+            writeln!(out, "# name = \"home.example.com\"")?;
+            writeln!(out, "# ttl = 60")?;
+        }
+    }
+    Ok(out)
+}
+
+fn entries<'a>(app: &'a App) -> Vec<Entry<'a>> {
+    let options = app.p.opts.iter().filter_map(|o| {
+        Some(Entry {
+            key: o.s.long?.replace('-', "_"),
+            help: o.b.help.unwrap_or_default(),
+            env: o
+                .v
+                .env
+                .as_ref()
+                .map(|(name, _)| name.to_string_lossy().into_owned()),
+            default: o
+                .v
+                .default_val
+                .map(|value| value.to_string_lossy().into_owned()),
+            flag: false,
+        })
+    });
+    let flags = app.p.flags.iter().filter_map(|f| {
+        Some(Entry {
+            key: f.s.long?.replace('-', "_"),
+            help: f.b.help.unwrap_or_default(),
+            env: None,
+            default: None,
+            flag: true,
+        })
+    });
+    options
+        .chain(flags)
+        .filter(|entry| !EXCLUDED.contains(&entry.key.as_str()))
+        .collect()
+}
+
+/// Numbers and booleans as they are, anything else as a string
+fn toml_value(value: &str) -> String {
+    if value.parse::<i64>().is_ok() || value == "true" || value == "false" {
+        value.to_string()
+    } else {
+        // JSON strings are valid basic strings of TOML
+        serde_json::Value::from(value).to_string()
+    }
+}
+
+/// Break help into lines of comments
+fn wrap(help: &str) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in help.split_whitespace() {
+        if !line.is_empty() && line.len() + word.len() + 3 > WIDTH {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
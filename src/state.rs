@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::net::{IpAddr, Ipv6Addr};
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::ParkTarget;
+
+/// Detected IP address and record contents as of the end of a run
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct RunState {
+    pub(crate) ip_address: Option<IpAddr>,
+    /// When the IP address was first detected, to tell how long it was in use once it changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) ip_address_since: Option<DateTime<Utc>>,
+    /// IPv6 address in dual-stack mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) ipv6_address: Option<Ipv6Addr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) ipv6_address_since: Option<DateTime<Utc>>,
+    /// Record name, followed by type for AAAA records, to content
+    pub(crate) records: BTreeMap<String, String>,
+    /// Record name, followed by type for AAAA records, to proxy status on Cloudflare
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) proxied: BTreeMap<String, bool>,
+    /// Record name, followed by type for AAAA records, to TTL on Cloudflare if given
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) ttl: BTreeMap<String, u32>,
+    /// Record name, followed by type for AAAA records and by the view, to when its host last
+    /// reported back, only for records which expire
+    #[serde(default)]
+    pub(crate) confirmed: BTreeMap<String, DateTime<Utc>>,
+    /// Maintenance destination records were parked at with `cdu park`, restored on restart
+    /// unless --park is given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) park: Option<ParkTarget>,
+}
+
+impl RunState {
+    /// Load state from status file, none if the file doesn't exist yet
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let s = fs::read_to_string(path)?;
+        let mut state: Self = serde_json::from_str(&s)?;
+        state.migrate_confirmed();
+        Ok(Some(state))
+    }
+
+    /// Status files written before views of a name were told apart keep when hosts last
+    /// reported back by name only, which then applies to every record of the name
+    fn migrate_confirmed(&mut self) {
+        for key in self.records.keys() {
+            let name = key.split('@').next().unwrap_or_default();
+            let name = name.trim_end_matches("/AAAA");
+            if key == name || self.confirmed.contains_key(key) {
+                continue;
+            }
+            if let Some(confirmed) = self.confirmed.get(name).copied() {
+                self.confirmed.insert(key.clone(), confirmed);
+            }
+        }
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> anyhow::Result<()> {
+        write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    /// Describe changes from the previous state, one entry per changed item
+    pub(crate) fn diff(&self, previous: &RunState) -> Vec<String> {
+        let mut changes = vec![];
+        if self.ip_address != previous.ip_address {
+            changes.push(format!(
+                "IP address {} → {}",
+                display(previous.ip_address.map(|ip| ip.to_string()).as_deref()),
+                display(self.ip_address.map(|ip| ip.to_string()).as_deref())
+            ));
+        }
+        if self.ipv6_address != previous.ipv6_address {
+            changes.push(format!(
+                "IPv6 address {} → {}",
+                display(previous.ipv6_address.map(|ip| ip.to_string()).as_deref()),
+                display(self.ipv6_address.map(|ip| ip.to_string()).as_deref())
+            ));
+        }
+        if self.park != previous.park {
+            changes.push(match self.park {
+                Some(ref target) => format!("parked at {}", target),
+                None => "unparked".to_string(),
+            });
+        }
+        for (name, content) in &self.records {
+            let old = previous.records.get(name);
+            if old != Some(content) {
+                changes.push(format!(
+                    "{} {} → {}",
+                    name,
+                    display(old.map(String::as_str)),
+                    content
+                ));
+            }
+        }
+        for name in previous.records.keys() {
+            if self.confirmed.contains_key(name) && !self.records.contains_key(name) {
+                changes.push(format!("{} expired", name));
+            } else if !self.records.contains_key(name) {
+                changes.push(format!("{} no longer managed", name));
+            }
+        }
+        changes
+    }
+}
+
+/// When the address was first detected, kept while it stays the same. Unknown for addresses of
+/// status files written before it was recorded.
+pub(crate) fn since<T: PartialEq>(
+    previous: Option<T>,
+    previous_since: Option<DateTime<Utc>>,
+    current: Option<T>,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    match current {
+        Some(_) if previous == current => previous_since,
+        Some(_) => Some(now),
+        None => None,
+    }
+}
+
+/// How long the previous address had been in use until now
+pub(crate) fn stable_for(since: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Option<Duration> {
+    since.map(|since| (now - since).to_std().unwrap_or_default())
+}
+
+fn display(value: Option<&str>) -> &str {
+    value.unwrap_or("(none)")
+}
+
+/// Write contents to a temporary file beside the target, then rename it over the target,
+/// so readers never observe a partially written file.
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".tmp");
+    let tmp_path = path.with_file_name(file_name);
+    {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
@@ -0,0 +1,196 @@
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::event::{Event, Subscriber};
+use crate::state::RunState;
+
+/// Entries of history kept by the memory store, the oldest are dropped first
+const MEMORY_HISTORY: usize = 1000;
+
+/// Kind of store given by --store
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StoreKind {
+    /// Status and history files, each only if given
+    Json,
+    /// Nothing written to disk, state is lost on restart
+    Memory,
+    /// SQLite database given by --database-file, e.g. for servers to query history with SQL
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+impl FromStr for StoreKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(StoreKind::Json),
+            "memory" => Ok(StoreKind::Memory),
+            #[cfg(feature = "sqlite")]
+            "sqlite" => Ok(StoreKind::Sqlite),
+            #[cfg(not(feature = "sqlite"))]
+            "sqlite" => bail!("SQLite store requires cdu built with the sqlite feature"),
+            s => bail!("unknown store {}, expect json, sqlite or memory", s),
+        }
+    }
+}
+
+/// Change of the public IP address published to records
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub at: DateTime<Utc>,
+    /// None for the first address ever published
+    pub previous: Option<IpAddr>,
+    pub current: IpAddr,
+}
+
+/// Where the state of the last run and the history of IP addresses are persisted
+pub(crate) trait Store: Send + Sync {
+    /// State of the last run, none if nothing has run yet
+    fn load(&self) -> anyhow::Result<Option<RunState>>;
+
+    fn save(&self, state: &RunState) -> anyhow::Result<()>;
+
+    fn append(&self, entry: &HistoryEntry) -> anyhow::Result<()>;
+
+    /// Changes of IP addresses at or after the time if given, oldest first
+    fn history(&self, since: Option<DateTime<Utc>>) -> anyhow::Result<Vec<HistoryEntry>>;
+}
+
+/// Persist the state of each run as it's recorded, and the history of IP addresses
+pub(crate) struct StateRecorder {
+    pub(crate) store: Arc<dyn Store>,
+    /// State of the last run, remembered before the event
+    pub(crate) last_run: Arc<Mutex<Option<RunState>>>,
+}
+
+impl Subscriber for StateRecorder {
+    fn name(&self) -> &str {
+        "status file"
+    }
+
+    fn required(&self) -> bool {
+        true
+    }
+
+    fn notify(&self, event: &Event) -> anyhow::Result<()> {
+        if let Event::RunRecorded { history, .. } = event {
+            for entry in history {
+                self.store.append(entry)?;
+            }
+            if let Some(ref state) = *self.last_run.lock().unwrap() {
+                self.store.save(state)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// State in the status file as JSON, and history appended to the history file as JSON lines
+pub(crate) struct JsonStore {
+    pub(crate) status_file: Option<PathBuf>,
+    pub(crate) history_file: Option<PathBuf>,
+}
+
+impl Store for JsonStore {
+    fn load(&self) -> anyhow::Result<Option<RunState>> {
+        match self.status_file {
+            Some(ref path) => RunState::load(path),
+            None => Ok(None),
+        }
+    }
+
+    fn save(&self, state: &RunState) -> anyhow::Result<()> {
+        match self.status_file {
+            Some(ref path) => state.save(path),
+            None => Ok(()),
+        }
+    }
+
+    fn append(&self, entry: &HistoryEntry) -> anyhow::Result<()> {
+        let path = match self.history_file {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open history file {}", path.display()))?;
+        // one write per line, so concurrent readers never see half an entry
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    fn history(&self, since: Option<DateTime<Utc>>) -> anyhow::Result<Vec<HistoryEntry>> {
+        let path = match self.history_file {
+            Some(ref path) => path,
+            None => bail!("history file is required to read history"),
+        };
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        let file = fs::File::open(path)
+            .with_context(|| format!("failed to open history file {}", path.display()))?;
+        let mut entries = vec![];
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: HistoryEntry = serde_json::from_str(&line)
+                .with_context(|| format!("invalid line {} of {}", i + 1, path.display()))?;
+            if since.is_none_or(|since| entry.at >= since) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// State and recent history in memory only, e.g. to spare flash storage of embedded devices
+#[derive(Default)]
+pub(crate) struct MemoryStore {
+    state: Mutex<Option<RunState>>,
+    history: Mutex<VecDeque<HistoryEntry>>,
+}
+
+impl Store for MemoryStore {
+    fn load(&self) -> anyhow::Result<Option<RunState>> {
+        Ok(self.state.lock().unwrap().clone())
+    }
+
+    fn save(&self, state: &RunState) -> anyhow::Result<()> {
+        *self.state.lock().unwrap() = Some(state.clone());
+        Ok(())
+    }
+
+    fn append(&self, entry: &HistoryEntry) -> anyhow::Result<()> {
+        let mut history = self.history.lock().unwrap();
+        if history.len() == MEMORY_HISTORY {
+            history.pop_front();
+        }
+        history.push_back(entry.clone());
+        Ok(())
+    }
+
+    fn history(&self, since: Option<DateTime<Utc>>) -> anyhow::Result<Vec<HistoryEntry>> {
+        let history = self.history.lock().unwrap();
+        Ok(history
+            .iter()
+            .filter(|entry| since.is_none_or(|since| entry.at >= since))
+            .cloned()
+            .collect())
+    }
+}
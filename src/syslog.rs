@@ -0,0 +1,91 @@
+use std::fmt;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::bail;
+use env_logger::filter::{self, Filter};
+use log::{Level, Log, Metadata, Record};
+
+/// Sockets of the local syslog daemon, on Linux, macOS, and BSDs in order
+const SOCKETS: &[&str] = &["/dev/log", "/var/run/syslog", "/var/run/log"];
+
+/// Facility of system daemons
+const LOG_DAEMON: u8 = 3 << 3;
+
+/// Logger sending records to the local syslog daemon, filtered by RUST_LOG
+struct Syslog {
+    socket: Mutex<UnixDatagram>,
+    filter: Filter,
+}
+
+impl Log for Syslog {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.filter.matches(record) {
+            return;
+        }
+        let message = syslog_message(record.level(), record.target(), record.args());
+        let mut socket = self.socket.lock().unwrap();
+        if socket.send(message.as_bytes()).is_ok() {
+            return;
+        }
+        // syslog daemon may have been restarted, logs are dropped if it's still gone
+        if let Ok(reconnected) = connect() {
+            *socket = reconnected;
+            let _ = socket.send(message.as_bytes());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Severity of syslog, also the priority of journald
+pub(crate) fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// Line sent to syslog for the log record, timestamped and attributed to the host by the daemon
+pub fn syslog_message(level: Level, target: &str, message: impl fmt::Display) -> String {
+    format!(
+        "<{}>cdu[{}]: {} > {}",
+        LOG_DAEMON | severity(level),
+        std::process::id(),
+        target,
+        message
+    )
+}
+
+fn connect() -> anyhow::Result<UnixDatagram> {
+    for path in SOCKETS.iter().map(Path::new).filter(|path| path.exists()) {
+        let socket = UnixDatagram::unbound()?;
+        if socket.connect(path).is_ok() {
+            return Ok(socket);
+        }
+    }
+    bail!("no syslog daemon listening on {}", SOCKETS.join(", "))
+}
+
+/// Send logs to syslog instead of standard error. Connected right away, so the socket is
+/// reachable once sandboxed.
+pub(crate) fn init() -> anyhow::Result<()> {
+    let mut builder = filter::Builder::new();
+    if let Ok(s) = std::env::var("RUST_LOG") {
+        builder.parse(&s);
+    }
+    let filter = builder.build();
+    log::set_max_level(filter.filter());
+    log::set_boxed_logger(Box::new(Syslog {
+        socket: Mutex::new(connect()?),
+        filter,
+    }))?;
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use anyhow::Context;
+use chrono::Local;
+use log::info;
+use windows::core::{Interface, BSTR, VARIANT};
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_INPROC_SERVER, COINIT_MULTITHREADED,
+};
+use windows::Win32::System::TaskScheduler::{
+    IExecAction, ITaskFolder, ITaskService, TaskScheduler, TASK_ACTION_EXEC, TASK_CREATE_OR_UPDATE,
+    TASK_LOGON_INTERACTIVE_TOKEN, TASK_TRIGGER_TIME,
+};
+
+use crate::command_line::{forwarded_args, join_args};
+
+/// Register a scheduled task with Windows Task Scheduler which updates DNS records once
+/// every `interval_minutes`, with the arguments given before `task install`. Give the token as
+/// `CLOUDFLARE_TOKEN` user environment variable e.g. with `setx`, or in the configuration file,
+/// rather than on command line, or it's kept in the task.
+pub(crate) fn install(name: &str, interval_minutes: u32) -> anyhow::Result<()> {
+    let exe = std::env::current_exe()?;
+    // executable is set apart, so arguments aren't limited to what fits on schtasks /TR
+    let args = join_args(&forwarded_args(&["task", "install"]));
+    with_task_scheduler(|service, folder| unsafe {
+        let task = service.NewTask(0)?;
+        // starts now and repeats indefinitely, as schtasks /SC MINUTE does
+        let trigger = task.Triggers()?.Create(TASK_TRIGGER_TIME)?;
+        let start = Local::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+        trigger.SetStartBoundary(&BSTR::from(start))?;
+        trigger
+            .Repetition()?
+            .SetInterval(&BSTR::from(format!("PT{}M", interval_minutes)))?;
+        let action: IExecAction = task.Actions()?.Create(TASK_ACTION_EXEC)?.cast()?;
+        action.SetPath(&BSTR::from(exe.to_string_lossy().as_ref()))?;
+        action.SetArguments(&BSTR::from(args.as_str()))?;
+        // runs as the user who installed it, while logged on
+        folder.RegisterTaskDefinition(
+            &BSTR::from(name),
+            &task,
+            TASK_CREATE_OR_UPDATE.0,
+            &VARIANT::default(),
+            &VARIANT::default(),
+            TASK_LOGON_INTERACTIVE_TOKEN,
+            &VARIANT::default(),
+        )?;
+        Ok(())
+    })
+    .with_context(|| format!("failed to register scheduled task {}", name))?;
+    info!(
+        "scheduled task installed: {} (every {} minutes)",
+        name, interval_minutes
+    );
+    Ok(())
+}
+
+/// Remove the scheduled task registered by `install`
+pub(crate) fn uninstall(name: &str) -> anyhow::Result<()> {
+    with_task_scheduler(|_, folder| unsafe { folder.DeleteTask(&BSTR::from(name), 0) })
+        .with_context(|| format!("failed to delete scheduled task {}", name))?;
+    info!("scheduled task uninstalled: {}", name);
+    Ok(())
+}
+
+/// Call with Task Scheduler and its root folder, while COM is initialized on this thread
+fn with_task_scheduler<T>(
+    f: impl FnOnce(&ITaskService, &ITaskFolder) -> windows::core::Result<T>,
+) -> windows::core::Result<T> {
+    // SAFETY: COM is uninitialized after the objects created with it are dropped
+    unsafe {
+        CoInitializeEx(None, COINIT_MULTITHREADED).ok()?;
+        let res = (|| {
+            let service: ITaskService =
+                CoCreateInstance(&TaskScheduler, None, CLSCTX_INPROC_SERVER)?;
+            // local computer as the current user
+            service.Connect(
+                &VARIANT::default(),
+                &VARIANT::default(),
+                &VARIANT::default(),
+                &VARIANT::default(),
+            )?;
+            let folder = service.GetFolder(&BSTR::from("\\"))?;
+            f(&service, &folder)
+        })();
+        CoUninitialize();
+        res
+    }
+}
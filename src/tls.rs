@@ -0,0 +1,80 @@
+use std::convert::Infallible;
+use std::fs::File;
+use std::future::Future;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, Context};
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{Body, Request, Response};
+use log::debug;
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+/// Server configuration of TLS with the certificate chain and private key in PEM files
+pub(crate) fn server_config(cert: &Path, key: &Path) -> anyhow::Result<Arc<ServerConfig>> {
+    let mut reader = BufReader::new(
+        File::open(cert).with_context(|| format!("failed to open {}", cert.display()))?,
+    );
+    let certs: Vec<Certificate> = rustls_pemfile::certs(&mut reader)?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    if certs.is_empty() {
+        bail!("no certificate found in {}", cert.display());
+    }
+    let mut reader = BufReader::new(
+        File::open(key).with_context(|| format!("failed to open {}", key.display()))?,
+    );
+    let key = loop {
+        match rustls_pemfile::read_one(&mut reader)? {
+            Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => break PrivateKey(key),
+            Some(_) => continue,
+            None => bail!("no private key found in {}", key.display()),
+        }
+    };
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(Arc::new(config))
+}
+
+/// Serve HTTP over TLS on the listener, each request handled by the function. Connections failing
+/// the handshake are dropped.
+pub(crate) async fn serve<H, F>(
+    listener: TcpListener,
+    config: Arc<ServerConfig>,
+    handle: H,
+) -> anyhow::Result<()>
+where
+    H: Fn(Request<Body>) -> F + Clone + Send + 'static,
+    F: Future<Output = Result<Response<Body>, Infallible>> + Send + 'static,
+{
+    let acceptor = TlsAcceptor::from(config);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let (acceptor, handle) = (acceptor.clone(), handle.clone());
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    debug!("TLS handshake with {} failed: {}", peer, e);
+                    return;
+                }
+            };
+            if let Err(e) = Http::new()
+                .http1_only(true)
+                .serve_connection(stream, service_fn(handle))
+                .await
+            {
+                debug!("connection with {} failed: {}", peer, e);
+            }
+        });
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use cloudflare::framework::endpoint::{Endpoint, Method};
+use cloudflare::framework::response::{ApiResult, ApiSuccess};
+use cloudflare::framework::{Environment, HttpApiClientConfig};
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+
+use crate::api::{ApiCredentials, Client, Network};
+
+/// Permission groups a token needs to update DNS records in one zone
+const PERMISSION_GROUPS: [&str; 2] = ["Zone Read", "DNS Write"];
+
+#[derive(Deserialize, Debug)]
+struct PermissionGroup {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(transparent)]
+struct PermissionGroups(Vec<PermissionGroup>);
+
+impl ApiResult for PermissionGroups {}
+
+struct ListPermissionGroups;
+
+impl Endpoint<PermissionGroups> for ListPermissionGroups {
+    fn method(&self) -> Method {
+        Method::Get
+    }
+
+    fn path(&self) -> String {
+        "user/tokens/permission_groups".to_string()
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct PermissionGroupRef {
+    id: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct Policy {
+    effect: &'static str,
+    resources: HashMap<String, String>,
+    permission_groups: Vec<PermissionGroupRef>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct CreateTokenParams {
+    name: String,
+    policies: Vec<Policy>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CreatedToken {
+    value: String,
+}
+
+impl ApiResult for CreatedToken {}
+
+struct CreateToken {
+    params: CreateTokenParams,
+}
+
+impl Endpoint<CreatedToken, (), CreateTokenParams> for CreateToken {
+    fn method(&self) -> Method {
+        Method::Post
+    }
+
+    fn path(&self) -> String {
+        "user/tokens".to_string()
+    }
+
+    fn body(&self) -> Option<CreateTokenParams> {
+        Some(self.params.clone())
+    }
+}
+
+/// API client authenticated with the global API key
+pub(crate) fn global_key_client(
+    email: &str,
+    key: &str,
+    http_timeout: Duration,
+    network: &Network,
+) -> anyhow::Result<Arc<Client>> {
+    let credentials = ApiCredentials::Key {
+        email: email.to_string(),
+        key: SecretString::new(key.to_string()),
+    };
+    let config = HttpApiClientConfig {
+        http_timeout,
+        ..Default::default()
+    };
+    Ok(Arc::new(Client::new(
+        credentials,
+        config,
+        Environment::Production,
+        network,
+    )?))
+}
+
+/// Create a token which can only read the zones and edit their DNS records, and return its value
+pub(crate) async fn create_token(
+    client: &Client,
+    name: &str,
+    zone_ids: &[String],
+) -> anyhow::Result<String> {
+    let res: ApiSuccess<PermissionGroups> = client.request(&ListPermissionGroups).await?;
+    let mut permission_groups = vec![];
+    for wanted in PERMISSION_GROUPS.iter() {
+        let group = res
+            .result
+            .0
+            .iter()
+            .find(|group| group.name == *wanted)
+            .ok_or_else(|| anyhow!("permission group not found: {}", wanted))?;
+        permission_groups.push(PermissionGroupRef {
+            id: group.id.clone(),
+        });
+    }
+
+    let mut resources = HashMap::new();
+    for zone_id in zone_ids {
+        resources.insert(
+            format!("com.cloudflare.api.account.zone.{}", zone_id),
+            "*".to_string(),
+        );
+    }
+    let params = CreateToken {
+        params: CreateTokenParams {
+            name: name.to_string(),
+            policies: vec![Policy {
+                effect: "allow",
+                resources,
+                permission_groups,
+            }],
+        },
+    };
+    let res: ApiSuccess<CreatedToken> = client.request(&params).await?;
+    Ok(res.result.value)
+}
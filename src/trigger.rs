@@ -0,0 +1,462 @@
+use std::fmt::{self, Display};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use anyhow::bail;
+use chrono::{DateTime, FixedOffset, Local, LocalResult, NaiveDateTime, TimeZone, Utc};
+use cron::Schedule;
+use log::{debug, error, info, warn};
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
+
+use crate::metrics::METRICS;
+#[cfg(unix)]
+use crate::park::ParkSwitch;
+
+const FILE_POLL_INTERVAL: u64 = 1;
+const CRON_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Update cycle requested by a trigger
+pub struct Fired {
+    /// Short description of the cause
+    pub reason: String,
+    /// Public IPv4 address given by the trigger, skip detection if set
+    pub ip_address: Option<IpAddr>,
+    /// Update only DNS records of the group, all of them if none
+    pub group: Option<String>,
+}
+
+impl Fired {
+    pub(crate) fn new(reason: String) -> Self {
+        Self {
+            reason,
+            ip_address: None,
+            group: None,
+        }
+    }
+
+    /// Queue for the daemon, counted in the trigger queue depth until a run picks it up
+    pub fn enqueue(self, tx: &UnboundedSender<Fired>) -> Result<(), SendError<Fired>> {
+        tx.send(self)?;
+        METRICS.queue_depth.inc();
+        Ok(())
+    }
+}
+
+/// Something that starts an update cycle in daemon mode
+pub enum Trigger {
+    /// Fire on every upcoming time of a cron schedule, in the timezone
+    Cron(Box<Schedule>, CronTimezone),
+    /// Fire repeatedly with a fixed period
+    Interval(Duration),
+    /// Fire whenever the file is created or modified e.g. IP file written by router, PPP status file
+    File(PathBuf),
+    /// Fire once addresses or routes of network interfaces have changed and settled
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    Network,
+    /// Fire on authenticated POST request with optional IP address in JSON body
+    Webhook(SocketAddr, String),
+    /// Fire when requested over the control socket e.g. by `cdu trigger`, and park or unpark
+    /// DNS records on request
+    #[cfg(unix)]
+    Control(PathBuf, ParkSwitch),
+    /// Fire like the trigger, for DNS records of the group only
+    Group(String, Box<Trigger>),
+    /// Fire on any of the schedules e.g. of all DNS records and of groups with schedules of
+    /// their own
+    Schedules(Vec<Trigger>),
+}
+
+impl Trigger {
+    /// Spawn a task which sends to the channel each time the trigger fires.
+    /// The task ends once the receiving side is dropped. Listeners are bound before the task
+    /// starts, so privileges can be dropped right after spawning.
+    pub fn spawn(self, tx: UnboundedSender<Fired>) -> JoinHandle<()> {
+        match self {
+            Trigger::Cron(schedule, timezone) => match timezone {
+                CronTimezone::Utc => tokio::spawn(cron(schedule, Utc, tx)),
+                CronTimezone::Fixed(offset) => tokio::spawn(cron(schedule, offset, tx)),
+                CronTimezone::Local => tokio::spawn(cron(schedule, Local, tx)),
+                CronTimezone::Named(name) => {
+                    if std::env::var("TZ").ok().as_ref() != Some(&name) {
+                        warn!("cron timezone {} takes effect after restart", name);
+                    }
+                    tokio::spawn(cron(schedule, Local, tx))
+                }
+            },
+            Trigger::Interval(period) => tokio::spawn(interval(period, tx)),
+            Trigger::File(path) => tokio::spawn(watch_file(path, tx)),
+            #[cfg(any(target_os = "linux", target_os = "macos"))]
+            Trigger::Network => match crate::netwatch::watch(tx) {
+                Ok(watch) => tokio::spawn(watch),
+                Err(e) => {
+                    error!("failed to watch network interfaces: {}", e);
+                    tokio::spawn(async {})
+                }
+            },
+            Trigger::Webhook(addr, token) => {
+                let listener = crate::webhook::listen(addr, token, tx);
+                tokio::spawn(async move {
+                    let res = match listener {
+                        Ok(listener) => listener.await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = res {
+                        error!("webhook listener on {} stopped: {}", addr, e);
+                    }
+                })
+            }
+            #[cfg(unix)]
+            Trigger::Control(path, park) => {
+                let listener = crate::control::listen(&path, tx, park);
+                tokio::spawn(async move {
+                    let res = match listener {
+                        Ok(listener) => listener.await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = res {
+                        error!("control socket {} stopped: {}", path.display(), e);
+                    }
+                })
+            }
+            Trigger::Group(name, trigger) => {
+                let (group_tx, mut group_rx) = tokio::sync::mpsc::unbounded_channel();
+                let trigger = AbortOnDrop(trigger.spawn(group_tx));
+                tokio::spawn(async move {
+                    let _trigger = trigger;
+                    while let Some(mut fired) = group_rx.recv().await {
+                        fired.reason = format!("{} of group {}", fired.reason, name);
+                        fired.group = Some(name.clone());
+                        // counted in the queue depth once enqueued by the trigger
+                        if tx.send(fired).is_err() {
+                            break;
+                        }
+                    }
+                })
+            }
+            Trigger::Schedules(triggers) => {
+                let mut triggers: Vec<_> = triggers
+                    .into_iter()
+                    .map(|trigger| AbortOnDrop(trigger.spawn(tx.clone())))
+                    .collect();
+                tokio::spawn(async move {
+                    futures::future::join_all(triggers.iter_mut().map(|t| &mut t.0)).await;
+                })
+            }
+        }
+    }
+
+    /// Spawn like [`Trigger::spawn`], delaying each firing by a random duration up to jitter, so
+    /// instances started at once don't make requests at the same second. Aborting the task
+    /// returned aborts the trigger too.
+    pub fn spawn_with_jitter(self, tx: UnboundedSender<Fired>, jitter: Duration) -> JoinHandle<()> {
+        if jitter.is_zero() {
+            return self.spawn(tx);
+        }
+        let (jitter_tx, mut jitter_rx) = tokio::sync::mpsc::unbounded_channel();
+        let trigger = AbortOnDrop(self.spawn(jitter_tx));
+        tokio::spawn(async move {
+            let _trigger = trigger;
+            while let Some(fired) = jitter_rx.recv().await {
+                let delay = tokio_retry::strategy::jitter(jitter);
+                debug!("delay run by {}ms of jitter", delay.as_millis());
+                tokio::time::sleep(delay).await;
+                // counted in the queue depth once enqueued by the trigger
+                if tx.send(fired).is_err() {
+                    break;
+                }
+            }
+        })
+    }
+}
+
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Directory of the timezone database on Unix
+pub(crate) const ZONEINFO: &str = "/usr/share/zoneinfo";
+
+/// Timezone cron schedules are in e.g. for "daily at 04:00" in local time
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CronTimezone {
+    Utc,
+    /// Timezone of the system
+    Local,
+    /// Offset from UTC e.g. +02:00, without daylight saving time
+    Fixed(FixedOffset),
+    /// Name in the timezone database e.g. Europe/Berlin, set as TZ of the process at startup
+    /// since chrono only knows the local timezone
+    Named(String),
+}
+
+impl FromStr for CronTimezone {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("utc") {
+            return Ok(Self::Utc);
+        }
+        if s.eq_ignore_ascii_case("local") {
+            return Ok(Self::Local);
+        }
+        if s.starts_with('+') || s.starts_with('-') {
+            return match FixedOffset::from_str(s) {
+                Ok(offset) => Ok(Self::Fixed(offset)),
+                Err(e) => bail!("invalid offset {}: {}", s, e),
+            };
+        }
+        if s.is_empty() || s.starts_with('/') || s.split('/').any(|part| part == "..") {
+            bail!("invalid timezone {}", s);
+        }
+        if cfg!(unix) && !Path::new(ZONEINFO).join(s).is_file() {
+            bail!("unknown timezone {}, not in {}", s, ZONEINFO);
+        }
+        if cfg!(not(unix)) {
+            bail!(
+                "timezone {} by name is not supported on this platform, use an offset",
+                s
+            );
+        }
+        Ok(Self::Named(s.to_string()))
+    }
+}
+
+impl fmt::Display for CronTimezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Utc => write!(f, "UTC"),
+            Self::Local => write!(f, "local"),
+            Self::Fixed(offset) => write!(f, "{}", offset),
+            Self::Named(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Timezone given by the `CRON_TZ=` prefix of a cron expression as in cronie, and the rest of it
+pub(crate) fn split_cron_timezone(expression: &str) -> (Option<&str>, &str) {
+    let expression = expression.trim_start();
+    match expression.strip_prefix("CRON_TZ=") {
+        Some(rest) => match rest.split_once(char::is_whitespace) {
+            Some((timezone, rest)) => (Some(timezone), rest.trim_start()),
+            None => (Some(rest), ""),
+        },
+        None => (None, expression),
+    }
+}
+
+/// Cron expression in the 7 fields the cron crate expects, from the 5 fields of crontab without
+/// seconds, or 6 fields without years. Anything else is left for the parser to report
+pub fn normalize_cron(expression: &str) -> String {
+    let fields: Vec<&str> = expression.split_whitespace().collect();
+    match fields.len() {
+        5 => format!(
+            "0 {} {} *",
+            fields[..4].join(" "),
+            crontab_days_of_week(fields[4])
+        ),
+        6 => format!("{} *", fields.join(" ")),
+        _ => expression.to_string(),
+    }
+}
+
+/// Days of week of crontab, where Sunday is 0 or 7, as the cron crate counts them from 1 for Sunday
+fn crontab_days_of_week(field: &str) -> String {
+    let day = |s: &str| s.parse::<u32>().ok().map(|n| n % 7 + 1);
+    field
+        .split(',')
+        .map(|item| {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => (range, Some(step)),
+                None => (item, None),
+            };
+            let mapped = match range.split_once('-') {
+                // up to Sunday, which wraps around to 1
+                Some((start, "7")) if step.is_none() => day(start).map(|start| match start {
+                    1 => "1-7".to_string(),
+                    start => format!("{}-7,1", start),
+                }),
+                Some((start, end)) => match (start.parse::<u32>(), end.parse::<u32>()) {
+                    (Ok(start), Ok(end)) => Some(format!("{}-{}", start + 1, end + 1)),
+                    _ => None,
+                },
+                None => day(range).map(|day| day.to_string()),
+            };
+            match (mapped, step) {
+                (Some(mapped), Some(step)) => format!("{}/{}", mapped, step),
+                (Some(mapped), None) => mapped,
+                // names and wildcards are the same
+                (None, _) => item.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Decide when a cron schedule fires by the wall clock at each check. The next time is computed
+/// afresh after each firing rather than taken from an iterator, so times missed while the clock
+/// jumped forward or a run took long fire once rather than one after another, and a time already
+/// fired doesn't fire again when the clock jumps back.
+///
+/// Times are computed in local time of the timezone, then resolved across daylight saving time:
+/// a time repeated as clocks fall back fires once at its first occurrence, and a time skipped as
+/// clocks spring forward fires an hour later.
+pub struct CronTimer<Z: TimeZone> {
+    schedule: Schedule,
+    timezone: Z,
+    /// Next time in local time, and when it fires
+    pending: Option<(NaiveDateTime, DateTime<Z>)>,
+    /// Time fired last in local time
+    last: Option<NaiveDateTime>,
+}
+
+impl<Z: TimeZone> CronTimer<Z> {
+    pub fn new(schedule: Schedule, timezone: Z) -> Self {
+        Self {
+            schedule,
+            timezone,
+            pending: None,
+            last: None,
+        }
+    }
+
+    /// Time to fire at next, none if the schedule has no more times
+    pub fn next(&mut self, now: DateTime<Utc>) -> Option<DateTime<Z>> {
+        if self.pending.is_none() {
+            let now = now.with_timezone(&self.timezone).naive_local();
+            let after = match self.last {
+                Some(last) if last > now => last,
+                _ => now,
+            };
+            // local time computed as if it were UTC, which has no daylight saving time
+            let next = self.schedule.after(&Utc.from_utc_datetime(&after)).next();
+            self.pending = next.map(|next| {
+                let next = next.naive_utc();
+                (next, self.resolve(next))
+            });
+        }
+        self.pending.as_ref().map(|(_, datetime)| datetime.clone())
+    }
+
+    /// Time fired if due by now, then the next time is computed on the following check
+    pub fn check(&mut self, now: DateTime<Utc>) -> Option<DateTime<Z>> {
+        match self.next(now) {
+            Some(datetime) if datetime <= now => {
+                self.last = self.pending.take().map(|(local, _)| local);
+                Some(datetime)
+            }
+            _ => None,
+        }
+    }
+
+    fn resolve(&self, local: NaiveDateTime) -> DateTime<Z> {
+        match self.timezone.from_local_datetime(&local) {
+            LocalResult::Single(datetime) => datetime,
+            LocalResult::Ambiguous(a, b) => a.min(b),
+            LocalResult::None => {
+                let later = local + chrono::Duration::hours(1);
+                self.timezone
+                    .from_local_datetime(&later)
+                    .earliest()
+                    .unwrap_or_else(|| self.timezone.from_utc_datetime(&local))
+            }
+        }
+    }
+}
+
+async fn cron<Z>(schedule: Box<Schedule>, timezone: Z, tx: UnboundedSender<Fired>)
+where
+    Z: TimeZone + Send,
+    Z::Offset: Display + Send,
+{
+    let mut timer = CronTimer::new(*schedule, timezone);
+    while let Some(datetime) = timer.next(Utc::now()) {
+        info!("update DNS records at {}", datetime);
+        let datetime = loop {
+            if let Some(datetime) = timer.check(Utc::now()) {
+                break datetime;
+            }
+            // wake up at least every second, the wall clock may jump while sleeping
+            let remaining = (datetime.clone() - Utc::now().with_timezone(&datetime.timezone()))
+                .to_std()
+                .unwrap_or_default();
+            tokio::time::sleep(remaining.min(CRON_POLL_INTERVAL)).await;
+        };
+        if Fired::new(format!("cron at {}", datetime))
+            .enqueue(&tx)
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+async fn interval(period: Duration, tx: UnboundedSender<Fired>) {
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        if Fired::new(format!("interval of {}s", period.as_secs()))
+            .enqueue(&tx)
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// Watch the file by inotify on Linux, or poll its modification time otherwise, or once inotify
+/// fails
+async fn watch_file(path: PathBuf, tx: UnboundedSender<Fired>) {
+    let modified = |path: &PathBuf| -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    };
+    let mut last = modified(&path);
+    debug!("watch {} for changes", path.display());
+    #[cfg(target_os = "linux")]
+    let mut watch = crate::inotify::FileWatch::new(&path)
+        .map_err(|e| warn!("failed to watch {}, poll it instead: {}", path.display(), e))
+        .ok();
+    loop {
+        #[cfg(target_os = "linux")]
+        wait_for_change(&mut watch, &path).await;
+        #[cfg(not(target_os = "linux"))]
+        tokio::time::sleep(Duration::from_secs(FILE_POLL_INTERVAL)).await;
+        let current = modified(&path);
+        if current == last {
+            continue;
+        }
+        last = current;
+        if current.is_none() {
+            warn!("watched file removed: {}", path.display());
+            continue;
+        }
+        if Fired::new(format!("{} changed", path.display()))
+            .enqueue(&tx)
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// Wait for inotify to tell the file changed, or for the next poll without it
+#[cfg(target_os = "linux")]
+async fn wait_for_change(watch: &mut Option<crate::inotify::FileWatch>, path: &Path) {
+    if let Some(ref mut inotify) = watch {
+        match inotify.changed().await {
+            Ok(()) => return,
+            Err(e) => {
+                warn!("stop watching {}, poll it instead: {}", path.display(), e);
+                *watch = None;
+            }
+        }
+    }
+    tokio::time::sleep(Duration::from_secs(FILE_POLL_INTERVAL)).await;
+}
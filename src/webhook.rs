@@ -0,0 +1,102 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use log::{debug, warn};
+use serde::Deserialize;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::trigger::Fired;
+
+#[derive(Deserialize)]
+struct Payload {
+    ip: Option<IpAddr>,
+}
+
+/// Parse webhook body e.g. `{"ip": "1.2.3.4"}`. An empty body or missing `ip` means
+/// the public IP address should be detected.
+pub fn parse_payload(body: &[u8]) -> anyhow::Result<Option<IpAddr>> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+    let payload: Payload = serde_json::from_slice(body)?;
+    Ok(payload.ip)
+}
+
+/// Accept authenticated POST requests and forward them to the daemon loop. The address is bound
+/// right away, requests are served by the returned future.
+pub(crate) fn listen(
+    addr: SocketAddr,
+    token: String,
+    tx: UnboundedSender<Fired>,
+) -> anyhow::Result<impl Future<Output = anyhow::Result<()>>> {
+    let token = Arc::new(token);
+    let make_service = make_service_fn(move |_| {
+        let token = token.clone();
+        let tx = tx.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                handle(req, token.clone(), tx.clone())
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    debug!("listen for webhooks on {}", addr);
+    Ok(async move { Ok(server.await?) })
+}
+
+pub(crate) async fn handle(
+    req: Request<Body>,
+    token: Arc<String>,
+    tx: UnboundedSender<Fired>,
+) -> Result<Response<Body>, Infallible> {
+    if req.method() != Method::POST {
+        return Ok(reply(StatusCode::METHOD_NOT_ALLOWED, "method not allowed"));
+    }
+    let authorized = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|given| constant_time_eq(given.as_bytes(), token.as_bytes()))
+        .unwrap_or(false);
+    if !authorized {
+        warn!("unauthorized webhook request");
+        return Ok(reply(StatusCode::UNAUTHORIZED, "unauthorized"));
+    }
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(e) => return Ok(reply(StatusCode::BAD_REQUEST, &e.to_string())),
+    };
+    let ip_address = match parse_payload(&body) {
+        Ok(ip_address) => ip_address,
+        Err(e) => return Ok(reply(StatusCode::BAD_REQUEST, &e.to_string())),
+    };
+    let fired = Fired {
+        reason: "webhook".to_string(),
+        ip_address,
+        group: None,
+    };
+    if fired.enqueue(&tx).is_err() {
+        return Ok(reply(StatusCode::SERVICE_UNAVAILABLE, "shutting down"));
+    }
+    Ok(reply(StatusCode::ACCEPTED, "accepted"))
+}
+
+fn reply(status: StatusCode, message: &str) -> Response<Body> {
+    let body = serde_json::json!({ "message": message }).to_string();
+    let mut res = Response::new(Body::from(body));
+    *res.status_mut() = status;
+    res.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/json"),
+    );
+    res
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
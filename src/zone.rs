@@ -0,0 +1,40 @@
+use anyhow::bail;
+use cloudflare::endpoints::zone::Status;
+use log::warn;
+
+/// Identifier of the zone among zones of the name the token can see, by identifier and status.
+/// The active one is picked if there are several e.g. an active zone and a pending duplicate in
+/// another account, otherwise it's up to the user.
+pub fn pick_zone<'a>(name: &str, zones: &[(&'a str, &Status)]) -> anyhow::Result<&'a str> {
+    let described = || {
+        zones
+            .iter()
+            .map(|(id, status)| format!("{} ({})", id, format!("{:?}", status).to_lowercase()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let active: Vec<_> = zones
+        .iter()
+        .filter(|(_, status)| matches!(status, Status::Active))
+        .collect();
+    match (zones, active.as_slice()) {
+        ([], _) => bail!("zone not found: {}", name),
+        ([(id, _)], _) => Ok(id),
+        (_, [(id, _)]) => {
+            warn!(
+                "{} zones named {}: {}, pick the active one {}",
+                zones.len(),
+                name,
+                described(),
+                id
+            );
+            Ok(id)
+        }
+        _ => bail!(
+            "{} zones named {}, narrow the token down to one of them: {}",
+            zones.len(),
+            name,
+            described()
+        ),
+    }
+}
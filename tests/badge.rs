@@ -0,0 +1,36 @@
+use cdu::badge;
+use chrono::{Duration, Utc};
+
+#[test]
+fn shows_ip_address_and_age_of_last_success() {
+    let now = Utc::now();
+    let badge = badge(
+        Some("192.0.2.1".parse().unwrap()),
+        Some(now - Duration::minutes(5)),
+        false,
+        now,
+    );
+    assert_eq!(1, badge["schemaVersion"]);
+    assert_eq!("192.0.2.1, 5m ago", badge["message"]);
+    assert_eq!("brightgreen", badge["color"]);
+}
+
+#[test]
+fn turns_red_once_a_run_fails() {
+    let now = Utc::now();
+    let badge = badge(
+        Some("192.0.2.1".parse().unwrap()),
+        Some(now - Duration::hours(3)),
+        true,
+        now,
+    );
+    assert_eq!("192.0.2.1, 3h ago", badge["message"]);
+    assert_eq!("red", badge["color"]);
+}
+
+#[test]
+fn is_grey_before_the_first_run() {
+    let badge = badge(None, None, false, Utc::now());
+    assert_eq!("unknown", badge["message"]);
+    assert_eq!("lightgrey", badge["color"]);
+}
@@ -0,0 +1,262 @@
+use std::fs;
+use std::time::Duration;
+
+use cdu::{Cdu, Opts};
+
+/// Parse command line with a configuration file of the given name and content
+fn parse(name: &str, content: &str, args: &[&str]) -> anyhow::Result<Cdu> {
+    let path = std::env::temp_dir().join(format!("cdu-{}-{}", std::process::id(), name));
+    fs::write(&path, content).unwrap();
+    let mut argv = vec!["cdu", "--config", path.to_str().unwrap()];
+    argv.extend(args);
+    let res = Opts::from_iter_with_config(&argv);
+    fs::remove_file(&path).unwrap();
+    res.map(Cdu::new)
+}
+
+const TOML: &str = r#"
+zone = ["example.com", "example.org"]
+cron = "0 */10 * * * *"
+cache_seconds = 300
+daemon = true
+debug = true
+
+[[records]]
+name = "a.example.com"
+ttl = 60
+
+[[records]]
+name = "b.example.org"
+proxied = true
+"#;
+
+#[test]
+fn takes_options_from_toml() {
+    let cdu = parse("options.toml", TOML, &[]).unwrap();
+    assert_eq!("0 */10 * * * *", cdu.cron());
+    assert_eq!(Some(Duration::from_secs(300)), cdu.cache_ttl());
+    assert!(cdu.is_daemon());
+    assert!(cdu.is_debug());
+}
+
+#[test]
+fn prefers_command_line_over_file() {
+    let cdu = parse("override.toml", TOML, &["--cron", "0 * * * * *"]).unwrap();
+    assert_eq!("0 * * * * *", cdu.cron());
+    assert_eq!(Some(Duration::from_secs(300)), cdu.cache_ttl());
+}
+
+#[test]
+fn takes_options_from_yaml() {
+    let yaml = "cache-seconds: 60\nrecords:\n  - name: a.example.com\n";
+    let cdu = parse("options.yaml", yaml, &["--zone", "example.com"]).unwrap();
+    assert_eq!(Some(Duration::from_secs(60)), cdu.cache_ttl());
+}
+
+#[test]
+fn rejects_unknown_option() {
+    let res = parse("unknown.toml", "cache_secs = 60\n", &[]);
+    assert!(res.is_err());
+}
+
+#[test]
+fn takes_record_tables() {
+    let toml = r#"
+zone = ["example.com"]
+
+[[record]]
+name = "a.example.com"
+type = "AAAA"
+ttl = 60
+
+[[record]]
+name = "b.example.com"
+type = "A"
+proxied = true
+zone = "example.com"
+"#;
+    assert!(parse("record.toml", toml, &[]).is_ok());
+}
+
+#[test]
+fn rejects_unknown_record_type() {
+    let toml = "[[record]]\nname = \"a.example.com\"\ntype = \"CNAME\"\n";
+    assert!(parse("type.toml", toml, &["--zone", "example.com"]).is_err());
+}
+
+#[test]
+fn reloads_with_options_read_again() {
+    let cdu = parse("reload.toml", TOML, &[]).unwrap();
+    let args = ["cdu", "--zone", "example.com", "--cron", "0 0 * * * *"];
+    let reloaded = cdu.reload(Opts::from_iter_with_config(&args).unwrap());
+    assert_eq!("0 0 * * * *", reloaded.cron());
+    assert_eq!(None, reloaded.cache_ttl());
+    assert!(reloaded.schedule().is_ok());
+}
+
+const PROFILES: &str = r#"
+cron = "0 */10 * * * *"
+
+[[record]]
+name = "shared.example.com"
+
+[profile.home]
+zone = ["example.com"]
+cache_seconds = 300
+
+[[profile.home.record]]
+name = "home.example.com"
+
+[profile.office]
+zone = ["example.org"]
+cron = "0 0 * * * *"
+"#;
+
+#[test]
+fn merges_selected_profile() {
+    let cdu = parse("home.toml", PROFILES, &["--profile", "home"]).unwrap();
+    assert_eq!("0 */10 * * * *", cdu.cron());
+    assert_eq!(Some(Duration::from_secs(300)), cdu.cache_ttl());
+
+    let cdu = parse("office.toml", PROFILES, &["--profile", "office"]).unwrap();
+    assert_eq!("0 0 * * * *", cdu.cron());
+    assert_eq!(None, cdu.cache_ttl());
+}
+
+#[test]
+fn rejects_unknown_profile() {
+    let e = parse("unknown-profile.toml", PROFILES, &["--profile", "cafe"])
+        .err()
+        .unwrap();
+    assert!(
+        format!("{:#}", e).contains("profile cafe not found, profiles: home, office"),
+        "{:#}",
+        e
+    );
+}
+
+#[test]
+fn starter_config_keeps_options() {
+    let content = cdu::starter_config(&[
+        "cdu",
+        "--cron",
+        "0 0 * * * *",
+        "--cache-seconds",
+        "60",
+        "--debug",
+        "--records",
+        "a.example.com:proxied",
+        "config",
+        "init",
+    ])
+    .unwrap();
+    assert!(content.contains("\n# dry_run = false\n"));
+    let cdu = parse("starter.toml", &content, &[]).unwrap();
+    assert_eq!("0 0 * * * *", cdu.cron());
+    assert_eq!(Some(Duration::from_secs(60)), cdu.cache_ttl());
+    assert!(cdu.is_debug());
+}
+
+#[test]
+fn starter_config_refers_to_secrets_by_environment_variable() {
+    let content = cdu::starter_config(&["cdu", "--token", "secret", "config", "init"]).unwrap();
+    assert!(content.contains("\ntoken = \"${CLOUDFLARE_TOKEN}\"\n"));
+    assert!(!content.contains("secret\""));
+}
+
+#[test]
+fn takes_interval_instead_of_cron() {
+    let cdu = parse("every.toml", "every = \"1h 30m\"\n", &[]).unwrap();
+    assert_eq!(Some(Duration::from_secs(5400)), cdu.every());
+    assert!(cdu.schedule().is_ok());
+
+    let e = parse(
+        "every-cron.toml",
+        "every = \"5m\"\n",
+        &["--cron", "0 0 * * * *"],
+    )
+    .err()
+    .unwrap();
+    assert!(e.to_string().contains("cannot be used with"), "{}", e);
+}
+
+const GROUPS: &str = r#"
+zone = ["example.com"]
+cron = "0 0 * * * *"
+
+[groups.critical]
+every = "1m"
+
+[[record]]
+name = "a.example.com"
+group = "critical"
+
+[[record]]
+name = "b.example.com"
+"#;
+
+#[test]
+fn schedules_groups_of_records() {
+    let cdu = parse("groups.toml", GROUPS, &[]).unwrap();
+    assert!(
+        matches!(cdu.schedule().unwrap(), cdu::Trigger::Schedules(schedules) if schedules.len() == 2)
+    );
+
+    let content = GROUPS.replace("every = \"1m\"", "every = \"1m\"\ncron = \"* * * * *\"");
+    let cdu = parse("groups-both.toml", &content, &[]).unwrap();
+    let e = cdu.schedule().err().unwrap();
+    assert!(e.to_string().contains("can't be both given"), "{}", e);
+}
+
+const TOKENS: &str = r#"
+zone = ["example.com", "example.org"]
+
+[tokens]
+"example.org" = "${CDU_TEST_ZONE_TOKEN}"
+
+[[records]]
+name = "a.example.com"
+"#;
+
+#[tokio::test]
+async fn requires_token_of_zone_without_one_of_its_own() {
+    std::env::set_var("CDU_TEST_ZONE_TOKEN", "zone-token");
+    let cdu = parse("tokens.toml", TOKENS, &[]).unwrap();
+    let e = cdu.list().await.err().unwrap();
+    assert_eq!(
+        "Cloudflare token is required for zone example.com",
+        e.to_string()
+    );
+}
+
+const SPLIT_HORIZON: &str = r#"
+zone = "example.com"
+powerdns_url = "http://127.0.0.1:8081"
+powerdns_api_key = "key"
+
+[[records]]
+name = "nas.example.com"
+
+[[records]]
+name = "nas.example.com"
+provider = "powerdns"
+address = "local"
+"#;
+
+#[test]
+fn publishes_name_in_views_of_its_own() {
+    let cdu = parse("split-horizon.toml", SPLIT_HORIZON, &[]).unwrap();
+    cdu.lint().unwrap();
+
+    let content = format!(
+        "{}\n[[records]]\nname = \"nas.example.com\"\n",
+        SPLIT_HORIZON
+    );
+    let cdu = parse("split-horizon-duplicate.toml", &content, &[]).unwrap();
+    let e = cdu.lint().unwrap_err().to_string();
+    assert!(
+        e.contains("record nas.example.com: listed more than once"),
+        "{}",
+        e
+    );
+}
@@ -0,0 +1,39 @@
+use std::fs;
+
+use cdu::{config_schema, Opts};
+
+/// Example value of each record property in YAML
+fn example(property: &str) -> &'static str {
+    match property {
+        "name" => "a.example.com",
+        "enabled" | "proxied" => "true",
+        "expires_after" => "2h",
+        "address" => "local",
+        "provider" => "powerdns",
+        "zone" => "example.com",
+        "ttl" => "60",
+        "type" => "AAAA",
+        "group" => "critical",
+        property => panic!("no example of record property {}", property),
+    }
+}
+
+#[test]
+fn parses_every_record_property_in_schema() {
+    let schema = config_schema();
+    let properties = schema["properties"]["records"]["items"]["properties"]
+        .as_object()
+        .unwrap();
+    let record = properties
+        .keys()
+        .map(|property| format!("    {}: {}", property, example(property)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let path = std::env::temp_dir().join(format!("cdu-schema-{}.yaml", std::process::id()));
+    fs::write(&path, format!("records:\n  -\n{}\n", record)).unwrap();
+
+    let config = path.to_str().unwrap();
+    let res = Opts::from_iter_with_config(&["cdu", "--zone", "example.com", "--config", config]);
+    fs::remove_file(&path).unwrap();
+    res.unwrap();
+}
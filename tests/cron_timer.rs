@@ -0,0 +1,156 @@
+use std::str::FromStr;
+
+use cdu::{normalize_cron, Cdu, CronTimer, CronTimezone, Opts};
+use chrono::{DateTime, FixedOffset, Local, TimeZone, Utc};
+use cron::Schedule;
+use structopt::StructOpt;
+
+fn at(hour: u32, min: u32, sec: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2021, 3, 28, hour, min, sec).unwrap()
+}
+
+fn every_5_minutes() -> CronTimer<Utc> {
+    CronTimer::new(Schedule::from_str("0 */5 * * * * *").unwrap(), Utc)
+}
+
+#[test]
+fn fires_once_at_each_time() {
+    let mut timer = every_5_minutes();
+    assert_eq!(Some(at(10, 5, 0)), timer.next(at(10, 0, 30)));
+    assert_eq!(None, timer.check(at(10, 4, 59)));
+    assert_eq!(Some(at(10, 5, 0)), timer.check(at(10, 5, 0)));
+    assert_eq!(None, timer.check(at(10, 5, 1)));
+    assert_eq!(Some(at(10, 10, 0)), timer.next(at(10, 5, 1)));
+}
+
+#[test]
+fn fires_once_after_clock_jumps_forward() {
+    let mut timer = every_5_minutes();
+    timer.next(at(10, 0, 30));
+    // e.g. resumed from suspend an hour later
+    assert_eq!(Some(at(10, 5, 0)), timer.check(at(11, 2, 0)));
+    assert_eq!(None, timer.check(at(11, 2, 1)));
+    assert_eq!(Some(at(11, 5, 0)), timer.next(at(11, 2, 1)));
+}
+
+#[test]
+fn does_not_fire_again_after_clock_jumps_back() {
+    let mut timer = every_5_minutes();
+    timer.next(at(10, 0, 30));
+    assert_eq!(Some(at(10, 5, 0)), timer.check(at(10, 5, 0)));
+    // e.g. corrected by NTP
+    assert_eq!(Some(at(10, 10, 0)), timer.next(at(9, 58, 0)));
+    assert_eq!(None, timer.check(at(10, 0, 0)));
+    assert_eq!(None, timer.check(at(10, 5, 0)));
+    assert_eq!(Some(at(10, 10, 0)), timer.check(at(10, 10, 0)));
+}
+
+#[test]
+fn skips_times_passed_during_long_run() {
+    let mut timer = every_5_minutes();
+    timer.next(at(10, 0, 30));
+    assert_eq!(Some(at(10, 5, 0)), timer.check(at(10, 5, 0)));
+    // retries kept the run going past two more times
+    assert_eq!(Some(at(10, 20, 0)), timer.next(at(10, 17, 0)));
+    assert_eq!(None, timer.check(at(10, 17, 1)));
+}
+
+/// Local time in Berlin, springing forward on 2021-03-28 and falling back on 2021-10-31
+fn berlin(expression: &str) -> CronTimer<Local> {
+    std::env::set_var("TZ", "Europe/Berlin");
+    CronTimer::new(Schedule::from_str(expression).unwrap(), Local)
+}
+
+fn utc(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+}
+
+#[test]
+fn fires_time_skipped_by_daylight_saving_time_an_hour_later() {
+    let mut timer = berlin("0 30 2 * * * *");
+    let now = utc(2021, 3, 27, 12, 0);
+    // 02:30 doesn't exist as clocks spring forward from 02:00 to 03:00
+    let datetime = timer.next(now).unwrap();
+    assert_eq!("2021-03-28 03:30:00 +02:00", datetime.to_string());
+    let now = datetime.with_timezone(&Utc);
+    assert_eq!(Some(datetime), timer.check(now));
+    let next = timer.next(now).unwrap();
+    assert_eq!("2021-03-29 02:30:00 +02:00", next.to_string());
+}
+
+#[test]
+fn fires_time_repeated_by_daylight_saving_time_once() {
+    let mut timer = berlin("0 30 2 * * * *");
+    // 02:30 happens twice as clocks fall back from 03:00 to 02:00
+    let datetime = timer.next(utc(2021, 10, 30, 12, 0)).unwrap();
+    assert_eq!("2021-10-31 02:30:00 +02:00", datetime.to_string());
+    assert_eq!(Some(datetime), timer.check(utc(2021, 10, 31, 0, 30)));
+    // an hour later it's 02:30 again
+    assert_eq!(None, timer.check(utc(2021, 10, 31, 1, 30)));
+    let next = timer.next(utc(2021, 10, 31, 1, 30)).unwrap();
+    assert_eq!("2021-11-01 02:30:00 +01:00", next.to_string());
+}
+
+#[test]
+fn does_not_fire_twice_while_clocks_fall_back() {
+    let mut timer = berlin("0 */30 * * * * *");
+    let mut now = utc(2021, 10, 30, 23, 50);
+    let mut fired = vec![];
+    for _ in 0..5 {
+        let datetime = timer.next(now).unwrap();
+        now = datetime.with_timezone(&Utc);
+        fired.push(timer.check(now).unwrap().with_timezone(&Utc));
+    }
+    assert!(fired.windows(2).all(|w| w[0] < w[1]), "{:?}", fired);
+    assert_eq!(utc(2021, 10, 31, 0, 0), fired[0]);
+    assert_eq!(utc(2021, 10, 31, 3, 0), fired[4]);
+}
+
+fn cron_timezone(args: &[&str]) -> anyhow::Result<CronTimezone> {
+    Cdu::new(Opts::from_iter_safe([&["cdu"], args].concat())?).cron_timezone()
+}
+
+#[test]
+fn takes_timezone_by_option_or_prefix() {
+    assert_eq!(CronTimezone::Utc, cron_timezone(&[]).unwrap());
+    assert_eq!(
+        CronTimezone::Named("Europe/Berlin".to_string()),
+        cron_timezone(&["--cron-timezone", "Europe/Berlin"]).unwrap()
+    );
+    assert_eq!(
+        CronTimezone::Named("Europe/Berlin".to_string()),
+        cron_timezone(&["--cron", "CRON_TZ=Europe/Berlin 0 0 4 * * * *"]).unwrap()
+    );
+    assert_eq!(
+        CronTimezone::Fixed(FixedOffset::east_opt(2 * 3600).unwrap()),
+        cron_timezone(&["--cron-timezone", "+02:00"]).unwrap()
+    );
+    assert!(cron_timezone(&["--cron-timezone", "Mars/Olympus_Mons"]).is_err());
+    assert!(cron_timezone(&[
+        "--cron",
+        "CRON_TZ=Europe/Berlin 0 0 4 * * * *",
+        "--cron-timezone",
+        "local"
+    ])
+    .is_err());
+}
+
+#[test]
+fn normalizes_crontab_expressions() {
+    assert_eq!("0 */5 * * * * *", normalize_cron("*/5 * * * *"));
+    assert_eq!("0 0 4 * * 2-6 *", normalize_cron("0 4 * * 1-5"));
+    assert_eq!("0 0 4 * * 1,7 *", normalize_cron("0 4 * * 0,6"));
+    assert_eq!("0 0 4 * * 6-7,1 *", normalize_cron("0 4 * * 5-7"));
+    assert_eq!("0 0 4 * * Mon-Fri *", normalize_cron("0 4 * * Mon-Fri"));
+    assert_eq!("30 0 4 * * * *", normalize_cron("30 0 4 * * *"));
+    assert_eq!("0 */5 * * * * *", normalize_cron("0 */5 * * * * *"));
+}
+
+#[test]
+fn fires_on_crontab_days_of_week() {
+    // 2021-03-28 is a Sunday
+    let schedule = Schedule::from_str(&normalize_cron("0 4 * * 1")).unwrap();
+    let mut timer = CronTimer::new(schedule, Utc);
+    let datetime = timer.next(utc(2021, 3, 28, 12, 0)).unwrap();
+    assert_eq!(utc(2021, 3, 29, 4, 0), datetime);
+}
@@ -0,0 +1,297 @@
+mod mock;
+mod sim;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use cdu::{Daemon, Phase, Reloadable, RetryPolicy, RunContext, Stage, Trigger};
+use futures::future::BoxFuture;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+
+use mock::MockApi;
+
+use sim::{
+    deterministic_retry, fire, millis, spawn_daemon, spawn_daemon_with_retry, Outcome,
+    ScriptedRunner, RATE_LIMIT,
+};
+
+#[tokio::test(start_paused = true)]
+async fn retries_transient_failures_with_exponential_backoff() {
+    let runner = ScriptedRunner::new(&[Outcome::Transient, Outcome::Transient, Outcome::Ok]);
+    let (tx, handle) = spawn_daemon(runner.clone());
+    let start = Instant::now();
+    fire(&tx, None);
+    drop(tx);
+    handle.await.unwrap().unwrap();
+
+    let calls = runner.calls();
+    assert_eq!(3, calls.len());
+    assert_eq!(millis(10), calls[1].0 - start);
+    assert_eq!(millis(110), calls[2].0 - start);
+    assert_eq!(vec!["run_started", "run_succeeded"], runner.event_kinds());
+}
+
+#[tokio::test(start_paused = true)]
+async fn caps_delays_between_retries() {
+    let runner = ScriptedRunner::new(&[Outcome::Transient, Outcome::Transient, Outcome::Ok]);
+    let retry = RetryPolicy {
+        max_delay_millis: 50,
+        ..deterministic_retry()
+    };
+    let (tx, handle) = spawn_daemon_with_retry(runner.clone(), retry);
+    let start = Instant::now();
+    fire(&tx, None);
+    drop(tx);
+    handle.await.unwrap().unwrap();
+
+    let calls = runner.calls();
+    assert_eq!(3, calls.len());
+    assert_eq!(millis(10), calls[1].0 - start);
+    assert_eq!(millis(60), calls[2].0 - start);
+}
+
+#[tokio::test(start_paused = true)]
+async fn gives_up_after_retries_and_keeps_running() {
+    let runner = ScriptedRunner::new(&[Outcome::Transient; 4]);
+    let (tx, handle) = spawn_daemon(runner.clone());
+    fire(&tx, None);
+    // fire again once the first run has given up
+    tokio::time::sleep(Duration::from_secs(10)).await;
+    fire(&tx, None);
+    drop(tx);
+    handle.await.unwrap().unwrap();
+
+    assert_eq!(5, runner.calls().len());
+    assert_eq!(
+        vec!["run_started", "run_failed", "run_started", "run_succeeded"],
+        runner.event_kinds()
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn does_not_retry_permanent_failures() {
+    let runner = ScriptedRunner::new(&[Outcome::Permanent]);
+    let (tx, handle) = spawn_daemon(runner.clone());
+    fire(&tx, None);
+    drop(tx);
+    handle.await.unwrap().unwrap();
+
+    assert_eq!(1, runner.calls().len());
+    assert_eq!(vec!["run_started", "run_failed"], runner.event_kinds());
+}
+
+#[tokio::test(start_paused = true)]
+async fn waits_out_rate_limit_before_retrying() {
+    let runner = ScriptedRunner::new(&[Outcome::RateLimited, Outcome::Ok]);
+    let (tx, handle) = spawn_daemon(runner.clone());
+    let start = Instant::now();
+    fire(&tx, None);
+    drop(tx);
+    handle.await.unwrap().unwrap();
+
+    let calls = runner.calls();
+    assert_eq!(2, calls.len());
+    assert!(calls[1].0 - start >= RATE_LIMIT);
+}
+
+/// Stage recording when runs get past detection
+#[derive(Clone, Default)]
+struct Detected(Arc<Mutex<Vec<Instant>>>);
+
+impl Stage for Detected {
+    fn run<'a>(&'a self, _: &'a mut RunContext) -> BoxFuture<'a, anyhow::Result<()>> {
+        self.0.lock().unwrap().push(Instant::now());
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[tokio::test]
+async fn waits_out_rate_limit_of_cloudflare_with_reloadable_configuration() {
+    let api = MockApi::start();
+    api.add_zone("example.com");
+    api.add_record("a.example.com", "A", "192.0.2.1", false);
+    api.rate_limit_next("GET", 1);
+    let (mut cdu, _) = mock::cdu(&api, &["--records", "a.example.com"]);
+    let detected = Detected::default();
+    cdu.add_stage(Phase::Detect, Box::new(detected.clone()));
+    let runner = Arc::new(Reloadable::new(Arc::new(cdu)));
+    let (tx, rx) = mpsc::unbounded_channel();
+    fire(&tx, Some("198.51.100.1".parse().unwrap()));
+    drop(tx);
+    Daemon::new(runner)
+        .with_retry(deterministic_retry())
+        .run(rx)
+        .await
+        .unwrap();
+
+    // retried once the second asked for by Retry-After is over, not after the first delay
+    let detected = detected.0.lock().unwrap();
+    assert_eq!(2, detected.len());
+    assert!(detected[1] - detected[0] >= Duration::from_secs(1));
+    let record = api.record("a.example.com", "A").unwrap();
+    assert_eq!("198.51.100.1", record["content"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn passes_ip_address_from_trigger() {
+    let runner = ScriptedRunner::new(&[Outcome::Transient, Outcome::Ok]);
+    let (tx, handle) = spawn_daemon(runner.clone());
+    let ip_address = "192.0.2.1".parse().unwrap();
+    fire(&tx, Some(ip_address));
+    drop(tx);
+    handle.await.unwrap().unwrap();
+
+    let calls = runner.calls();
+    assert!(calls.iter().all(|(_, ip)| *ip == Some(ip_address)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn coalesces_triggers_fired_while_run_is_pending() {
+    let runner = ScriptedRunner::new(&[]);
+    let (tx, handle) = spawn_daemon(runner.clone());
+    let ip_address = "192.0.2.1".parse().unwrap();
+    fire(&tx, None);
+    fire(&tx, Some(ip_address));
+    fire(&tx, None);
+    drop(tx);
+    handle.await.unwrap().unwrap();
+
+    assert_eq!(vec![(Instant::now(), Some(ip_address))], runner.calls());
+    assert_eq!(vec!["run_started", "run_succeeded"], runner.event_kinds());
+}
+
+#[tokio::test(start_paused = true)]
+async fn interval_trigger_catches_up_after_stall() {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    Trigger::Interval(Duration::from_secs(60)).spawn(tx);
+    let start = Instant::now();
+
+    // first tick fires immediately
+    assert!(rx.recv().await.is_some());
+    // the daemon is busy for 3 periods, then every missed tick is still delivered
+    tokio::time::sleep(Duration::from_secs(180)).await;
+    for _ in 0..3 {
+        assert!(rx.recv().await.is_some());
+    }
+    assert_eq!(Duration::from_secs(180), Instant::now() - start);
+}
+
+#[tokio::test(start_paused = true)]
+async fn jitter_delays_scheduled_runs() {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let jitter = Duration::from_secs(30);
+    let handle = Trigger::Interval(Duration::from_secs(60)).spawn_with_jitter(tx, jitter);
+    let start = Instant::now();
+
+    for period in 0..3 {
+        assert!(rx.recv().await.is_some());
+        let delay = Instant::now() - start - Duration::from_secs(60 * period);
+        assert!(delay < jitter, "{:?}", delay);
+    }
+    // aborting the task stops the trigger as well
+    handle.abort();
+    assert!(rx.recv().await.is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn schedules_of_groups_fire_for_their_records() {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let critical = Trigger::Group(
+        "critical".to_string(),
+        Box::new(Trigger::Interval(Duration::from_secs(60))),
+    );
+    let handle =
+        Trigger::Schedules(vec![Trigger::Interval(Duration::from_secs(3600)), critical]).spawn(tx);
+
+    let mut groups = vec![];
+    for _ in 0..2 {
+        groups.push(rx.recv().await.unwrap().group);
+    }
+    groups.sort();
+    assert_eq!(vec![None, Some("critical".to_string())], groups);
+
+    let fired = rx.recv().await.unwrap();
+    assert_eq!(Some("critical".to_string()), fired.group);
+    assert!(
+        fired.reason.ends_with("of group critical"),
+        "{}",
+        fired.reason
+    );
+
+    // aborting the task stops every schedule
+    handle.abort();
+    assert!(rx.recv().await.is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn finishes_run_in_progress_on_shutdown() {
+    let runner = ScriptedRunner::new(&[Outcome::Transient, Outcome::Transient, Outcome::Ok]);
+    let daemon = Daemon::new(runner.clone()).with_retry(deterministic_retry());
+    let (tx, rx) = mpsc::unbounded_channel();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let handle = tokio::spawn(async move {
+        daemon
+            .run_until(rx, async {
+                let _ = shutdown_rx.await;
+            })
+            .await
+    });
+    fire(&tx, None);
+    tokio::time::sleep(millis(5)).await;
+    shutdown_tx.send(()).unwrap();
+    // no run starts once shutdown is requested, though triggers are still around
+    fire(&tx, None);
+    handle.await.unwrap().unwrap();
+
+    assert_eq!(3, runner.calls().len());
+    assert_eq!(vec!["run_started", "run_succeeded"], runner.event_kinds());
+}
+
+#[tokio::test(start_paused = true)]
+async fn abandons_run_in_progress_after_shutdown_timeout() {
+    let runner = ScriptedRunner::new(&[Outcome::Transient, Outcome::Transient, Outcome::Ok]);
+    let daemon = Daemon::new(runner.clone())
+        .with_retry(deterministic_retry())
+        .with_shutdown_timeout(millis(50));
+    let (tx, rx) = mpsc::unbounded_channel();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let handle = tokio::spawn(async move {
+        daemon
+            .run_until(rx, async {
+                let _ = shutdown_rx.await;
+            })
+            .await
+    });
+    let start = Instant::now();
+    fire(&tx, None);
+    tokio::time::sleep(millis(5)).await;
+    shutdown_tx.send(()).unwrap();
+    handle.await.unwrap().unwrap();
+
+    assert_eq!(millis(55), Instant::now() - start);
+    assert_eq!(2, runner.calls().len());
+    assert_eq!(vec!["run_started"], runner.event_kinds());
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn file_trigger_sees_file_replaced_without_polling() {
+    let dir = std::env::temp_dir().join(format!("cdu-watch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("ip");
+    std::fs::write(&path, "1.2.3.4").unwrap();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let handle = Trigger::File(path.clone()).spawn(tx);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // as editors save, a new file renamed over the watched one
+    let new = dir.join("ip.new");
+    std::fs::write(&new, "5.6.7.8").unwrap();
+    std::fs::rename(&new, &path).unwrap();
+    // told by inotify well before the next poll
+    let fired = tokio::time::timeout(Duration::from_millis(500), rx.recv()).await;
+    handle.abort();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(fired.unwrap().unwrap().reason.ends_with("changed"));
+}
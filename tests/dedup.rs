@@ -0,0 +1,88 @@
+mod logs;
+mod sim;
+
+use std::time::Duration;
+
+use anyhow::anyhow;
+use cdu::{render_metrics, ErrorDedup};
+
+use logs::take_logged;
+use sim::{fire, spawn_daemon, Outcome, ScriptedRunner};
+
+#[test]
+fn collapses_repeated_errors_until_recovered() {
+    take_logged();
+    let mut errors = ErrorDedup::default();
+    for _ in 0..3 {
+        errors.error(&anyhow!("DNS record not found"));
+    }
+    assert_eq!(vec!["DNS record not found"], take_logged());
+
+    errors.success();
+    assert_eq!(
+        vec![
+            "error repeated 2 times in the last 0m: DNS record not found",
+            "recovered from error",
+        ],
+        take_logged()
+    );
+    errors.success();
+    assert!(take_logged().is_empty());
+}
+
+#[test]
+fn logs_different_error_right_away() {
+    take_logged();
+    let mut errors = ErrorDedup::default();
+    errors.error(&anyhow!("DNS record not found"));
+    errors.error(&anyhow!("DNS record not found"));
+    errors.error(&anyhow!("failed to determine public IP address"));
+    assert_eq!(
+        vec![
+            "DNS record not found",
+            "error repeated 1 times in the last 0m: DNS record not found",
+            "failed to determine public IP address",
+        ],
+        take_logged()
+    );
+}
+
+#[test]
+fn summarizes_repeated_errors_once_per_window() {
+    take_logged();
+    // every repetition is past the window
+    let mut errors = ErrorDedup::with_window(Duration::ZERO);
+    for _ in 0..3 {
+        errors.error(&anyhow!("DNS record not found"));
+    }
+    let summary = "error repeated 1 times in the last 0m: DNS record not found";
+    assert_eq!(
+        vec!["DNS record not found", summary, summary],
+        take_logged()
+    );
+}
+
+/// Errors counted in metrics
+fn errors_total() -> u64 {
+    render_metrics()
+        .lines()
+        .find_map(|line| line.strip_prefix("cdu_errors_total "))
+        .map(|count| count.parse().unwrap())
+        .unwrap_or_default()
+}
+
+#[tokio::test(start_paused = true)]
+async fn counts_every_error_including_collapsed_ones() {
+    let before = errors_total();
+    let runner = ScriptedRunner::new(&[Outcome::Permanent; 3]);
+    let (tx, handle) = spawn_daemon(runner.clone());
+    for _ in 0..3 {
+        fire(&tx, None);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    drop(tx);
+    handle.await.unwrap().unwrap();
+
+    assert_eq!(3, runner.calls().len());
+    assert_eq!(before + 3, errors_total());
+}
@@ -0,0 +1,36 @@
+use cdu::{export_history, HistoryEntry, HistoryFormat};
+use chrono::{TimeZone, Utc};
+
+fn entries() -> Vec<HistoryEntry> {
+    vec![
+        HistoryEntry {
+            at: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            previous: None,
+            current: "192.0.2.1".parse().unwrap(),
+        },
+        HistoryEntry {
+            at: Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap(),
+            previous: Some("192.0.2.1".parse().unwrap()),
+            current: "192.0.2.2".parse().unwrap(),
+        },
+    ]
+}
+
+#[test]
+fn exports_csv_with_time_in_use() {
+    let csv = export_history(&entries(), HistoryFormat::Csv).unwrap();
+    assert_eq!(
+        "time,previous,current,previous_in_use_seconds\n\
+         2024-01-01T00:00:00+00:00,,192.0.2.1,\n\
+         2024-01-02T00:00:00+00:00,192.0.2.1,192.0.2.2,86400\n",
+        csv
+    );
+}
+
+#[test]
+fn exports_json() {
+    let json = export_history(&entries(), HistoryFormat::Json).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!("192.0.2.2", value[1]["current"]);
+    assert_eq!(serde_json::Value::Null, value[0]["previous"]);
+}
@@ -0,0 +1,39 @@
+#![cfg(target_os = "linux")]
+
+use cdu::journal_message;
+use log::{Level, Record};
+
+#[test]
+fn sends_key_values_as_fields() {
+    let kvs = [("record", "a.example.com"), ("new_content", "1.2.3.4")];
+    let message = journal_message(
+        &Record::builder()
+            .args(format_args!("DNS record updated"))
+            .level(Level::Info)
+            .target("cdu::cdu")
+            .key_values(&kvs)
+            .build(),
+    );
+    let message = String::from_utf8(message).unwrap();
+    for field in [
+        "MESSAGE=DNS record updated\n",
+        "PRIORITY=6\n",
+        "SYSLOG_IDENTIFIER=cdu\n",
+        "TARGET=cdu::cdu\n",
+        "RECORD=a.example.com\n",
+        "NEW_CONTENT=1.2.3.4\n",
+    ] {
+        assert!(message.contains(field), "{} not in {:?}", field, message);
+    }
+}
+
+#[test]
+fn prefixes_values_with_newlines_with_their_length() {
+    let message = journal_message(
+        &Record::builder()
+            .args(format_args!("a\nb"))
+            .level(Level::Error)
+            .build(),
+    );
+    assert!(message.starts_with(b"MESSAGE\n\x03\0\0\0\0\0\0\0a\nb\nPRIORITY=3\n"));
+}
@@ -0,0 +1,134 @@
+use cdu::{Cdu, Opts};
+use structopt::StructOpt;
+
+fn lint(args: &[&str]) -> anyhow::Result<()> {
+    let opts = Opts::from_iter([&["cdu", "--zone", "example.com"], args].concat());
+    Cdu::new(opts).lint()
+}
+
+#[test]
+fn passes_sensible_config() {
+    lint(&["--records", "a.example.com,b.example.com"]).unwrap();
+}
+
+#[test]
+fn reports_every_problem() {
+    let e = lint(&[
+        "--records",
+        "a.example.com,a.example.com,b.example.org",
+        "--cron",
+        "* * * * * * *",
+    ])
+    .unwrap_err()
+    .to_string();
+    assert!(e.starts_with("3 problems found"), "{}", e);
+    assert!(
+        e.contains("record b.example.org: not in zone example.com"),
+        "{}",
+        e
+    );
+    assert!(
+        e.contains("record a.example.com: listed more than once"),
+        "{}",
+        e
+    );
+    assert!(e.contains("over the rate limit of 1200"), "{}", e);
+}
+
+#[test]
+fn allows_frequent_schedule_with_cache() {
+    lint(&[
+        "--records",
+        "a.example.com",
+        "--cron",
+        "*/1 * * * * * *",
+        "--cache-seconds",
+        "300",
+    ])
+    .unwrap();
+}
+
+#[test]
+fn rejects_unknown_record_option() {
+    lint(&["--records", "a.example.com:proxied,b.example.com:dns-only"]).unwrap();
+    let e = match Opts::from_iter_safe([
+        "cdu",
+        "--records",
+        "a.example.com:proxied,b.example.com:orange",
+    ]) {
+        Ok(_) => panic!("unknown option accepted"),
+        Err(e) => e.message,
+    };
+    assert!(
+        e.contains("record b.example.com: unknown option orange"),
+        "{}",
+        e
+    );
+}
+
+#[test]
+fn reports_ttl_out_of_range() {
+    lint(&["--records", "a.example.com", "--ttl", "1"]).unwrap();
+    lint(&["--records", "a.example.com", "--ttl", "300"]).unwrap();
+    let e = lint(&["--records", "a.example.com", "--ttl", "30"])
+        .unwrap_err()
+        .to_string();
+    assert!(
+        e.contains("record a.example.com: TTL 30 is out of range"),
+        "{}",
+        e
+    );
+}
+
+#[test]
+fn accepts_records_across_zones() {
+    lint(&[
+        "--zone",
+        "example.org",
+        "--records",
+        "a.example.com,b.example.org",
+    ])
+    .unwrap();
+    let e = lint(&["--records", "c.example.net"])
+        .unwrap_err()
+        .to_string();
+    assert!(
+        e.contains("record c.example.net: not in zone example.com"),
+        "{}",
+        e
+    );
+}
+
+#[test]
+fn checks_names_only_without_zone() {
+    let opts = Opts::from_iter(&["cdu", "--records", "a.example.com,b..example.org"]);
+    let e = Cdu::new(opts).lint().unwrap_err().to_string();
+    assert!(e.starts_with("1 problems found"), "{}", e);
+    assert!(e.contains("record b..example.org: empty label"), "{}", e);
+}
+
+#[test]
+fn rejects_reachability_probe_without_placeholder() {
+    let e = lint(&[
+        "--records",
+        "a.example.com",
+        "--reachability-probe",
+        "https://probe.example.net/check",
+    ])
+    .unwrap_err()
+    .to_string();
+    assert!(e.contains("{ip} is missing from URL"), "{}", e);
+}
+
+#[test]
+fn reports_frequent_interval() {
+    let e = lint(&["--records", "a.example.com,b.example.com", "--every", "1s"])
+        .unwrap_err()
+        .to_string();
+    assert!(
+        e.contains("every 1s: up to 1500 requests to Cloudflare per 5 minutes"),
+        "{}",
+        e
+    );
+    lint(&["--records", "a.example.com,b.example.com", "--every", "5m"]).unwrap();
+}
@@ -0,0 +1,39 @@
+//! Logger capturing messages per thread, for tests of what is logged. Tests running in parallel
+//! each see only their own, including messages of tasks on the runtime of the test.
+
+// shared by test binaries, each using only some of it
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::sync::Once;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+thread_local! {
+    static LOGGED: RefCell<Vec<String>> = const { RefCell::new(vec![]) };
+}
+
+struct Captured;
+
+impl Log for Captured {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        let message = record.args().to_string();
+        LOGGED.with(|logged| logged.borrow_mut().push(message));
+    }
+
+    fn flush(&self) {}
+}
+
+/// Messages logged on this thread since last taken
+pub fn take_logged() -> Vec<String> {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        log::set_logger(&Captured).unwrap();
+        log::set_max_level(LevelFilter::Trace);
+    });
+    LOGGED.with(|logged| logged.take())
+}
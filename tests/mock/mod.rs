@@ -0,0 +1,388 @@
+//! Mock of the Cloudflare API: zones and DNS records kept in memory, scripted failures, and a log
+//! of requests, served on a local port for `--api-url`.
+
+// shared by test binaries, each using only some of it
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use cdu::{Cdu, Event, Opts, Subscriber};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use reqwest::Url;
+use serde_json::{json, Value};
+use structopt::StructOpt;
+
+/// Token the mock is called with by [`cdu`]
+pub const TOKEN: &str = "token";
+
+/// Request received by the mock
+#[derive(Clone, Debug)]
+pub struct Received {
+    pub method: String,
+    pub path: String,
+    /// Bearer token without the scheme
+    pub token: String,
+    pub body: Value,
+}
+
+/// Response given in place of the next request of the method, e.g. PUT
+struct Failure {
+    method: &'static str,
+    status: u16,
+    retry_after: Option<u64>,
+}
+
+#[derive(Default)]
+struct State {
+    zones: Vec<Value>,
+    records: Vec<Value>,
+    failures: VecDeque<Failure>,
+    /// Tokens answered with 403 Forbidden
+    rejected_tokens: Vec<String>,
+    received: Vec<Received>,
+    next_id: usize,
+}
+
+impl State {
+    fn id(&mut self) -> String {
+        self.next_id += 1;
+        format!("{:032x}", self.next_id)
+    }
+}
+
+#[derive(Clone)]
+pub struct MockApi {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockApi {
+    /// Serve the mock on a free local port, on the runtime of the test
+    pub fn start() -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+        let make_service = {
+            let state = state.clone();
+            make_service_fn(move |_| {
+                let state = state.clone();
+                async move { Ok::<_, Infallible>(service_fn(move |req| handle(req, state.clone()))) }
+            })
+        };
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        Self { addr, state }
+    }
+
+    /// Base URL for `--api-url`
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.addr)
+    }
+
+    pub fn add_zone(&self, name: &str) {
+        let mut state = self.state.lock().unwrap();
+        let id = state.id();
+        state.zones.push(zone(&id, name));
+    }
+
+    /// Add an A or AAAA record to the zone of the name, the last two labels of it
+    pub fn add_record(&self, name: &str, record_type: &str, content: &str, proxied: bool) {
+        let mut state = self.state.lock().unwrap();
+        let zone_name = name.splitn(name.matches('.').count(), '.').last().unwrap();
+        let zone_id = state
+            .zones
+            .iter()
+            .find(|zone| zone["name"] == zone_name)
+            .map(|zone| zone["id"].clone())
+            .expect("zone of the record");
+        let id = state.id();
+        let record = json!({
+            "id": id,
+            "zone_id": zone_id,
+            "zone_name": zone_name,
+            "name": name,
+            "type": record_type,
+            "content": content,
+            "proxied": proxied,
+            "proxiable": true,
+            "ttl": 1,
+            "locked": false,
+            "meta": { "auto_added": false },
+            "created_on": "2024-01-01T00:00:00Z",
+            "modified_on": "2024-01-01T00:00:00Z",
+        });
+        state.records.push(record);
+    }
+
+    /// Answer the next request of the method with the status
+    pub fn fail_next(&self, method: &'static str, status: u16) {
+        self.fail_next_with(method, status, None);
+    }
+
+    /// Answer the next request of the method with 429 Too Many Requests, asking to wait
+    pub fn rate_limit_next(&self, method: &'static str, retry_after: u64) {
+        self.fail_next_with(method, 429, Some(retry_after));
+    }
+
+    fn fail_next_with(&self, method: &'static str, status: u16, retry_after: Option<u64>) {
+        let failure = Failure {
+            method,
+            status,
+            retry_after,
+        };
+        self.state.lock().unwrap().failures.push_back(failure);
+    }
+
+    pub fn reject_token(&self, token: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.rejected_tokens.push(token.to_string());
+    }
+
+    /// Requests received so far, in order
+    pub fn received(&self) -> Vec<Received> {
+        self.state.lock().unwrap().received.clone()
+    }
+
+    /// Requests received so far which change records, i.e. other than GET
+    pub fn changes(&self) -> Vec<Received> {
+        self.received()
+            .into_iter()
+            .filter(|received| received.method != "GET")
+            .collect()
+    }
+
+    /// Record of the name and type as the mock has it now
+    pub fn record(&self, name: &str, record_type: &str) -> Option<Value> {
+        let state = self.state.lock().unwrap();
+        state
+            .records
+            .iter()
+            .find(|record| record["name"] == name && record["type"] == record_type)
+            .cloned()
+    }
+}
+
+/// Serve a PowerDNS API on a free local port which knows no records and fails every change,
+/// return its URL for `--powerdns-url`
+pub fn failing_powerdns() -> String {
+    let make_service = make_service_fn(|_| async {
+        Ok::<_, Infallible>(service_fn(|req: Request<Body>| async move {
+            let res = match req.method().as_str() {
+                "GET" => Response::new(Body::from(json!({ "rrsets": [] }).to_string())),
+                _ => {
+                    let mut res = Response::new(Body::from("scripted failure"));
+                    *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                    res
+                }
+            };
+            Ok::<_, Infallible>(res)
+        }))
+    });
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+    url
+}
+
+/// Cdu of zone example.com on the mock, with the options given and events recorded
+pub fn cdu(api: &MockApi, args: &[&str]) -> (Cdu, Events) {
+    let url = api.url();
+    let mut argv = vec!["cdu", "--zone", "example.com", "--api-url", &url];
+    // the token is given unless the test gives its own
+    if !args.contains(&"--token") {
+        argv.extend(["--token", TOKEN]);
+    }
+    argv.extend(args);
+    let mut cdu = Cdu::new(Opts::from_iter(argv));
+    let events = Events::default();
+    cdu.add_subscriber(Box::new(events.clone()));
+    (cdu, events)
+}
+
+/// Subscriber recording events it was notified of
+#[derive(Clone, Default)]
+pub struct Events(Arc<Mutex<Vec<Event>>>);
+
+impl Events {
+    /// Messages of events of the kind
+    pub fn messages(&self, kind: &str) -> Vec<String> {
+        let events = self.0.lock().unwrap();
+        events
+            .iter()
+            .filter(|event| event.kind() == kind)
+            .map(Event::message)
+            .collect()
+    }
+}
+
+impl Subscriber for Events {
+    fn notify(&self, event: &Event) -> anyhow::Result<()> {
+        self.0.lock().unwrap().push(event.clone());
+        Ok(())
+    }
+}
+
+fn zone(id: &str, name: &str) -> Value {
+    json!({
+        "id": id,
+        "name": name,
+        "account": { "id": "account", "name": "account" },
+        "created_on": "2024-01-01T00:00:00Z",
+        "modified_on": "2024-01-01T00:00:00Z",
+        "development_mode": 0,
+        "meta": {
+            "custom_certificate_quota": 0,
+            "page_rule_quota": 3,
+            "wildcard_proxiable": false,
+            "phishing_detected": false,
+            "multiple_railguns_allowed": false,
+        },
+        "name_servers": [],
+        "owner": { "type": "user", "id": "owner", "email": "owner@example.com" },
+        "paused": false,
+        "permissions": [],
+        "status": "active",
+        "type": "full",
+    })
+}
+
+fn success(result: Value) -> Response<Body> {
+    let body = json!({ "result": result, "messages": [], "errors": [], "success": true });
+    Response::new(Body::from(body.to_string()))
+}
+
+fn failure(status: u16, message: &str) -> Response<Body> {
+    let body = json!({
+        "result": null,
+        "messages": [],
+        "errors": [{ "code": status, "message": message }],
+        "success": false,
+    });
+    let mut res = Response::new(Body::from(body.to_string()));
+    *res.status_mut() = StatusCode::from_u16(status).unwrap();
+    res
+}
+
+async fn handle(
+    req: Request<Body>,
+    state: Arc<Mutex<State>>,
+) -> Result<Response<Body>, Infallible> {
+    let method = req.method().to_string();
+    let url = Url::parse(&format!("http://mock{}", req.uri())).unwrap();
+    let token = req
+        .headers()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .trim_start_matches("Bearer ")
+        .to_string();
+    let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    let mut state = state.lock().unwrap();
+    state.received.push(Received {
+        method: method.clone(),
+        path: url.path().to_string(),
+        token: token.clone(),
+        body: body.clone(),
+    });
+    if state.rejected_tokens.contains(&token) {
+        return Ok(failure(403, "Invalid access token"));
+    }
+    let scripted = state
+        .failures
+        .iter()
+        .position(|failure| failure.method == method);
+    if let Some(failure) = scripted.and_then(|index| state.failures.remove(index)) {
+        let mut res = self::failure(failure.status, "scripted failure");
+        if let Some(seconds) = failure.retry_after {
+            res.headers_mut()
+                .insert("retry-after", seconds.to_string().parse().unwrap());
+        }
+        return Ok(res);
+    }
+
+    let name = url
+        .query_pairs()
+        .find(|(key, _)| key == "name")
+        .map(|(_, value)| value.into_owned());
+    let segments: Vec<String> = url.path_segments().unwrap().map(String::from).collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let res = match (method.as_str(), segments.as_slice()) {
+        ("GET", ["zones"]) => {
+            let zones: Vec<Value> = state
+                .zones
+                .iter()
+                .filter(|zone| named(zone, name.as_deref()))
+                .cloned()
+                .collect();
+            success(json!(zones))
+        }
+        ("GET", ["zones", zone_id, "dns_records"]) => {
+            let records: Vec<Value> = state
+                .records
+                .iter()
+                .filter(|record| record["zone_id"] == *zone_id)
+                .filter(|record| named(record, name.as_deref()))
+                .cloned()
+                .collect();
+            success(json!(records))
+        }
+        ("POST", ["zones", zone_id, "dns_records"]) => {
+            let zone_name = state
+                .zones
+                .iter()
+                .find(|zone| zone["id"] == *zone_id)
+                .map(|zone| zone["name"].clone())
+                .unwrap_or_default();
+            let id = state.id();
+            let mut record = json!({
+                "id": id,
+                "zone_id": zone_id,
+                "zone_name": zone_name,
+                "proxied": false,
+                "proxiable": true,
+                "ttl": 1,
+                "locked": false,
+                "meta": { "auto_added": false },
+                "created_on": "2024-01-01T00:00:00Z",
+                "modified_on": "2024-01-01T00:00:00Z",
+            });
+            merge(&mut record, &body);
+            state.records.push(record.clone());
+            success(record)
+        }
+        ("PUT", ["zones", _, "dns_records", id]) => {
+            match state.records.iter_mut().find(|record| record["id"] == *id) {
+                Some(record) => {
+                    merge(record, &body);
+                    success(record.clone())
+                }
+                None => failure(404, "Record not found"),
+            }
+        }
+        ("DELETE", ["zones", _, "dns_records", id]) => {
+            state.records.retain(|record| record["id"] != *id);
+            success(json!({ "id": id }))
+        }
+        _ => failure(404, "Not found"),
+    };
+    Ok(res)
+}
+
+/// Whether the zone or record has the name, if the request asked for one
+fn named(value: &Value, name: Option<&str>) -> bool {
+    name.map(|name| value["name"] == name).unwrap_or(true)
+}
+
+/// Set fields of the record given in the body of the request
+fn merge(record: &mut Value, body: &Value) {
+    if let (Some(record), Some(body)) = (record.as_object_mut(), body.as_object()) {
+        for (key, value) in body {
+            record.insert(key.clone(), value.clone());
+        }
+    }
+}
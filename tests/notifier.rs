@@ -0,0 +1,379 @@
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::bail;
+use cdu::{
+    render_metrics, Cdu, Daemon, Digest, Event, Fired, Opts, QuietHours, Reloadable, Subscriber,
+};
+use chrono::{Local, NaiveTime};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use serde_json::Value;
+use structopt::StructOpt;
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::time::Instant;
+
+/// Subscriber recording events it was notified of, failing as scripted
+#[derive(Clone, Default)]
+struct Recorder {
+    calls: Arc<Mutex<Vec<&'static str>>>,
+    fail: bool,
+    panic: bool,
+    /// Takes 100ms to handle each event
+    slow: bool,
+    /// Hangs until released, well past the timeout on a paused clock
+    hang: Option<Arc<Semaphore>>,
+    required: bool,
+}
+
+impl Subscriber for Recorder {
+    fn name(&self) -> &str {
+        match self.hang {
+            Some(_) => "hanging",
+            None => "recorder",
+        }
+    }
+
+    fn required(&self) -> bool {
+        self.required
+    }
+
+    fn notify(&self, event: &Event) -> anyhow::Result<()> {
+        self.calls.lock().unwrap().push(event.kind());
+        if self.slow {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        if let Some(ref release) = self.hang {
+            let _ = Handle::current().block_on(release.acquire());
+        }
+        if self.panic {
+            panic!("notifier panicked");
+        }
+        if self.fail {
+            bail!("webhook is down");
+        }
+        Ok(())
+    }
+}
+
+fn cdu_with(subscribers: &[&Recorder]) -> Cdu {
+    let opts = Opts::from_iter(&["cdu", "--zone", "example.com"]);
+    let mut cdu = Cdu::new(opts);
+    for subscriber in subscribers {
+        cdu.add_subscriber(Box::new((*subscriber).clone()));
+    }
+    cdu
+}
+
+#[tokio::test]
+async fn retries_failing_subscriber_once_and_notifies_the_rest() {
+    let failing = Recorder {
+        fail: true,
+        ..Default::default()
+    };
+    let healthy = Recorder::default();
+    let cdu = cdu_with(&[&failing, &healthy]);
+    cdu.emit(Event::RunSucceeded).await;
+    cdu.flush_events().await;
+
+    assert_eq!(2, failing.calls.lock().unwrap().len());
+    assert_eq!(vec!["run_succeeded"], *healthy.calls.lock().unwrap());
+}
+
+#[tokio::test]
+async fn notifies_required_subscriber_in_place_without_retry() {
+    let required = Recorder {
+        fail: true,
+        required: true,
+        ..Default::default()
+    };
+    let cdu = cdu_with(&[&required]);
+    cdu.emit(Event::RunSucceeded).await;
+
+    assert_eq!(vec!["run_succeeded"], *required.calls.lock().unwrap());
+}
+
+// release builds abort on panic, subscribers must not panic there
+#[cfg(panic = "unwind")]
+#[tokio::test]
+async fn survives_panicking_subscriber_where_panics_unwind() {
+    let panicking = Recorder {
+        panic: true,
+        ..Default::default()
+    };
+    let healthy = Recorder::default();
+    let cdu = cdu_with(&[&panicking, &healthy]);
+    cdu.emit(Event::RunSucceeded).await;
+    cdu.flush_events().await;
+
+    assert_eq!(2, panicking.calls.lock().unwrap().len());
+    assert_eq!(vec!["run_succeeded"], *healthy.calls.lock().unwrap());
+}
+
+#[tokio::test(start_paused = true)]
+async fn gives_up_on_hanging_subscriber() {
+    let release = Arc::new(Semaphore::new(0));
+    let hanging = Recorder {
+        hang: Some(release.clone()),
+        ..Default::default()
+    };
+    let healthy = Recorder::default();
+    let cdu = cdu_with(&[&hanging, &healthy]);
+    let failures = hanging_failures();
+    let start = Instant::now();
+    cdu.emit(Event::RunSucceeded).await;
+    cdu.emit(Event::RunFailed {
+        error: "DNS record not found".to_string(),
+    })
+    .await;
+    // the clock isn't advanced on its own while the subscriber blocks, advance it once each
+    // event reached the subscriber
+    for delivered in 1..=2 {
+        while hanging.calls.lock().unwrap().len() < delivered {
+            tokio::task::yield_now().await;
+        }
+        tokio::time::advance(Duration::from_secs(10)).await;
+    }
+    cdu.flush_events().await;
+    // released before asserting, the runtime waits for blocked subscribers when dropped
+    release.add_permits(2);
+
+    // each event timed out once and wasn't retried, and later events are still delivered
+    assert_eq!(Duration::from_secs(20), Instant::now() - start);
+    let kinds = vec!["run_succeeded", "run_failed"];
+    assert_eq!(kinds, *hanging.calls.lock().unwrap());
+    assert_eq!(kinds, *healthy.calls.lock().unwrap());
+    assert_eq!(failures + 2, hanging_failures());
+}
+
+/// Events the hanging subscriber failed to handle, as counted in metrics
+fn hanging_failures() -> u64 {
+    render_metrics()
+        .lines()
+        .find_map(|line| line.strip_prefix("cdu_notifier_failures_total{subscriber=\"hanging\"} "))
+        .map(|count| count.parse().unwrap())
+        .unwrap_or_default()
+}
+
+#[tokio::test]
+async fn delivers_pending_events_before_daemon_exits() {
+    let slow = Recorder {
+        slow: true,
+        ..Default::default()
+    };
+    let runner = Arc::new(Reloadable::new(Arc::new(cdu_with(&[&slow]))));
+    let (tx, rx) = mpsc::unbounded_channel();
+    let fired = Fired {
+        reason: "test".to_string(),
+        ip_address: None,
+        group: None,
+    };
+    tx.send(fired).unwrap();
+    drop(tx);
+    // the run fails without records, after which the daemon exits
+    Daemon::new(runner).run(rx).await.unwrap();
+
+    assert_eq!(
+        vec!["run_started", "run_failed"],
+        *slow.calls.lock().unwrap()
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn sends_events_of_window_as_one_digest() {
+    let recorder = Recorder::default();
+    let digest = Digest::new(recorder.clone(), Duration::from_secs(3600));
+    digest.notify(&Event::RunSucceeded).unwrap();
+    tokio::time::advance(Duration::from_secs(1800)).await;
+    digest.notify(&Event::RunSucceeded).unwrap();
+    assert!(recorder.calls.lock().unwrap().is_empty());
+
+    tokio::time::advance(Duration::from_secs(1800)).await;
+    let failed = Event::RunFailed {
+        error: "DNS record not found".to_string(),
+    };
+    digest.notify(&failed).unwrap();
+    assert_eq!(vec!["digest"], *recorder.calls.lock().unwrap());
+
+    // next window starts with the next event
+    digest.notify(&Event::RunSucceeded).unwrap();
+    assert_eq!(1, recorder.calls.lock().unwrap().len());
+}
+
+#[test]
+fn summarizes_digest() {
+    let digest = Event::Digest {
+        events: vec![
+            Event::IpDetected {
+                current: "1.2.3.4".parse().unwrap(),
+            },
+            Event::RunRecorded {
+                changes: vec![],
+                history: vec![],
+            },
+            Event::RunSucceeded,
+            Event::RunFailed {
+                error: "DNS record not found".to_string(),
+            },
+        ],
+    };
+    assert_eq!(
+        "1 runs succeeded, 1 failed\nrun_failed: DNS record not found",
+        digest.message()
+    );
+}
+
+#[test]
+fn tells_how_long_previous_address_was_stable() {
+    let changed = Event::IpChanged {
+        previous: Some("1.2.3.4".parse().unwrap()),
+        current: "5.6.7.8".parse().unwrap(),
+        stable_for: Some(Duration::from_millis(93_784_500)),
+    };
+    assert_eq!(
+        "1.2.3.4 → 5.6.7.8, previous address stable for 1day 2h 3m 4s",
+        changed.message()
+    );
+    let updated = Event::RecordUpdated {
+        record_name: "a.example.com".to_string(),
+        old_content: Some("1.2.3.4".to_string()),
+        new_content: "5.6.7.8".to_string(),
+        latency: Duration::from_millis(842),
+    };
+    assert_eq!(
+        "a.example.com 1.2.3.4 → 5.6.7.8, 842ms after detection",
+        updated.message()
+    );
+}
+
+#[test]
+fn tells_drift_in_digest() {
+    let digest = Event::Digest {
+        events: vec![
+            Event::DriftDetected {
+                record_name: "a.example.com".to_string(),
+                content: "1.2.3.4".to_string(),
+                expected: "5.6.7.8".to_string(),
+            },
+            Event::RunSucceeded,
+        ],
+    };
+    assert_eq!(
+        "1 runs succeeded, 0 failed\ndrift_detected: a.example.com is 1.2.3.4 but should be 5.6.7.8",
+        digest.message()
+    );
+}
+
+#[test]
+fn quiet_hours_span_midnight() {
+    let quiet_hours: QuietHours = "23:00-07:00".parse().unwrap();
+    let at = |time| NaiveTime::parse_from_str(time, "%H:%M").unwrap();
+    assert!(quiet_hours.contains(at("23:00")));
+    assert!(quiet_hours.contains(at("03:00")));
+    assert!(!quiet_hours.contains(at("07:00")));
+    assert!(!quiet_hours.contains(at("12:00")));
+
+    let quiet_hours: QuietHours = "12:00-13:00".parse().unwrap();
+    assert!(quiet_hours.contains(at("12:30")));
+    assert!(!quiet_hours.contains(at("23:00")));
+
+    assert!("23:00".parse::<QuietHours>().is_err());
+    assert!("07:00-07:00".parse::<QuietHours>().is_err());
+    assert!("25:00-07:00".parse::<QuietHours>().is_err());
+}
+
+/// Serve a webhook on a free local port, return its URL and the kinds of events posted to it
+fn receive_webhooks() -> (String, Arc<Mutex<Vec<String>>>) {
+    let posted = Arc::new(Mutex::new(vec![]));
+    let make_service = {
+        let posted = posted.clone();
+        make_service_fn(move |_| {
+            let posted = posted.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let posted = posted.clone();
+                    async move {
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        let body: Value = serde_json::from_slice(&body).unwrap();
+                        let kind = body["kind"].as_str().unwrap().to_string();
+                        posted.lock().unwrap().push(kind);
+                        Ok::<_, Infallible>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        })
+    };
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+    let url = format!("http://{}/", server.local_addr());
+    tokio::spawn(server);
+    (url, posted)
+}
+
+#[tokio::test(start_paused = true)]
+async fn holds_back_changes_in_quiet_hours_but_not_failures() {
+    let (url, posted) = receive_webhooks();
+    // quiet for an hour from this minute on
+    let now = Local::now();
+    let quiet_hours = format!(
+        "{}-{}",
+        now.format("%H:%M"),
+        (now + chrono::Duration::hours(1)).format("%H:%M")
+    );
+    let argv = [
+        "cdu",
+        "--zone",
+        "example.com",
+        "--notify-url",
+        &url,
+        "--notify-quiet-hours",
+        &quiet_hours,
+    ];
+    let cdu = Cdu::new(Opts::from_iter(argv));
+    cdu.emit(Event::IpChanged {
+        previous: Some("1.2.3.4".parse().unwrap()),
+        current: "5.6.7.8".parse().unwrap(),
+        stable_for: None,
+    })
+    .await;
+    cdu.emit(Event::RunFailed {
+        error: "DNS record not found".to_string(),
+    })
+    .await;
+    cdu.flush_events().await;
+    assert_eq!(vec!["run_failed"], *posted.lock().unwrap());
+
+    // changes are sent along with the first event once quiet hours are over
+    tokio::time::sleep(Duration::from_secs(61 * 60)).await;
+    cdu.emit(Event::RunSucceeded).await;
+    cdu.flush_events().await;
+    assert_eq!(vec!["run_failed", "ip_changed"], *posted.lock().unwrap());
+}
+
+#[tokio::test(start_paused = true)]
+async fn posts_digest_of_window_to_webhook() {
+    let (url, posted) = receive_webhooks();
+    let argv = [
+        "cdu",
+        "--zone",
+        "example.com",
+        "--notify-url",
+        &url,
+        "--notify-digest",
+        "1h",
+    ];
+    let cdu = Cdu::new(Opts::from_iter(argv));
+    cdu.emit(Event::RunFailed {
+        error: "DNS record not found".to_string(),
+    })
+    .await;
+    cdu.emit(Event::RunSucceeded).await;
+    cdu.flush_events().await;
+    assert!(posted.lock().unwrap().is_empty());
+
+    tokio::time::sleep(Duration::from_secs(3600)).await;
+    cdu.emit(Event::RunSucceeded).await;
+    cdu.flush_events().await;
+    assert_eq!(vec!["digest"], *posted.lock().unwrap());
+}
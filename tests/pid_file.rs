@@ -0,0 +1,29 @@
+#![cfg(unix)]
+
+use std::fs;
+
+use cdu::{Cdu, Opts};
+
+#[test]
+fn refuses_to_run_while_pid_file_is_locked() {
+    let path = std::env::temp_dir().join(format!("cdu-{}.pid", std::process::id()));
+    let args = ["cdu", "--pid-file", path.to_str().unwrap()];
+    let first = Cdu::new(Opts::from_iter_with_config(&args).unwrap());
+    first.lock_pid_file().unwrap();
+    assert_eq!(
+        format!("{}\n", std::process::id()),
+        fs::read_to_string(&path).unwrap()
+    );
+
+    let second = Cdu::new(Opts::from_iter_with_config(&args).unwrap());
+    let e = second.lock_pid_file().err().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(
+        e.to_string().contains(&format!(
+            "another cdu is running with PID {}",
+            std::process::id()
+        )),
+        "{}",
+        e
+    );
+}
@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use cdu::retry_after;
+use chrono::{TimeZone, Utc};
+use reqwest::header::{HeaderMap, HeaderValue};
+
+fn headers(name: &'static str, value: &'static str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(name, HeaderValue::from_static(value));
+    headers
+}
+
+#[test]
+fn waits_as_asked_by_retry_after() {
+    let now = Utc.with_ymd_and_hms(2021, 3, 28, 10, 0, 0).unwrap();
+    assert_eq!(
+        Some(Duration::from_secs(120)),
+        retry_after(&headers("retry-after", "120"), now)
+    );
+    assert_eq!(
+        Some(Duration::from_secs(90)),
+        retry_after(
+            &headers("retry-after", "Sun, 28 Mar 2021 10:01:30 GMT"),
+            now
+        )
+    );
+    assert_eq!(
+        Some(Duration::ZERO),
+        retry_after(
+            &headers("retry-after", "Sun, 28 Mar 2021 09:59:00 GMT"),
+            now
+        )
+    );
+}
+
+#[test]
+fn waits_until_rate_limit_resets() {
+    let now = Utc::now();
+    assert_eq!(
+        Some(Duration::from_secs(30)),
+        retry_after(&headers("ratelimit", "\"default\";r=0;t=30"), now)
+    );
+    assert_eq!(
+        Some(Duration::from_secs(45)),
+        retry_after(
+            &headers("ratelimit", "limit=1200, remaining=0, reset=45"),
+            now
+        )
+    );
+    assert_eq!(None, retry_after(&HeaderMap::new(), now));
+}
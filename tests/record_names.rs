@@ -0,0 +1,76 @@
+use cdu::{normalize_name, parse_record_names, validate_record_names};
+use proptest::prelude::*;
+
+/// Hostname-like record name e.g. vpn-1.example.com
+fn record_name() -> impl Strategy<Value = String> {
+    proptest::collection::vec("[a-z0-9]([a-z0-9-]{0,10}[a-z0-9])?", 1..5)
+        .prop_map(|labels| labels.join("."))
+}
+
+proptest! {
+    #[test]
+    fn round_trips_through_comma_separated_list(names in proptest::collection::vec(record_name(), 0..10)) {
+        prop_assert_eq!(names.clone(), parse_record_names(&names.join(",")));
+    }
+
+    #[test]
+    fn tolerates_whitespace_and_empty_entries(names in proptest::collection::vec(record_name(), 0..10), padding in "[ \t]{0,3}") {
+        let records = names
+            .iter()
+            .map(|name| format!("{}{}{}", padding, name, padding))
+            .collect::<Vec<_>>()
+            .join(",,");
+        prop_assert_eq!(names, parse_record_names(&records));
+    }
+
+    #[test]
+    fn never_yields_blank_names(records in "\\PC*") {
+        for name in parse_record_names(&records) {
+            prop_assert!(!name.is_empty());
+            prop_assert_eq!(name.trim(), name.as_str());
+            prop_assert!(!name.contains(','));
+        }
+    }
+
+    #[test]
+    fn accepts_names_in_zone(labels in proptest::collection::vec(record_name(), 0..10)) {
+        let names: Vec<_> = labels.iter().map(|label| format!("{}.example.com", label)).collect();
+        prop_assert!(validate_record_names("example.com", &names).is_ok());
+    }
+
+    #[test]
+    fn lists_every_invalid_name(names in proptest::collection::vec(record_name(), 1..10)) {
+        let names: Vec<_> = names.iter().map(|name| format!("{}.example.org", name)).collect();
+        let message = validate_record_names("example.com", &names).unwrap_err().to_string();
+        for name in &names {
+            prop_assert!(message.contains(name.as_str()));
+        }
+    }
+
+    #[test]
+    fn ignores_case_and_trailing_dot(name in record_name(), dot in "\\.?") {
+        let configured = format!("{}{}", name.to_uppercase(), dot);
+        prop_assert_eq!(&name, &normalize_name(&configured));
+        prop_assert_eq!(vec![name], parse_record_names(&configured));
+    }
+
+    #[test]
+    fn drops_options_after_names(names in proptest::collection::vec(record_name(), 0..10), option in "proxied|dns-only") {
+        let records = names
+            .iter()
+            .map(|name| format!("{}:{}", name, option))
+            .collect::<Vec<_>>()
+            .join(",");
+        prop_assert_eq!(names, parse_record_names(&records));
+    }
+}
+
+#[test]
+fn rejects_invalid_records_pattern() {
+    use structopt::StructOpt;
+    assert!(
+        cdu::Opts::from_iter_safe(&["cdu", "--records-pattern", "^vpn-.*\\.example\\.com$"])
+            .is_ok()
+    );
+    assert!(cdu::Opts::from_iter_safe(&["cdu", "--records-pattern", "^vpn-(.*"]).is_err());
+}
@@ -0,0 +1,43 @@
+use std::fs;
+
+use cdu::{Cdu, Opts};
+use structopt::StructOpt;
+
+#[test]
+fn redacts_secrets() {
+    let dir = std::env::temp_dir().join(format!("cdu-report-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let config = dir.join("config.yaml");
+    fs::write(
+        &config,
+        "providers:\n  office:\n    type: powerdns\n    url: http://10.0.0.53:8081\n    api_key: pdns-secret\nrecords:\n  - name: a.example.com\n",
+    )
+    .unwrap();
+    let log = dir.join("cdu.log");
+    fs::write(
+        &log,
+        "started\nrequest with token cf-secret failed\nstopped\n",
+    )
+    .unwrap();
+
+    let opts = Opts::from_iter(&[
+        "cdu",
+        "--zone",
+        "example.com",
+        "--token",
+        "cf-secret",
+        "--config",
+        config.to_str().unwrap(),
+    ]);
+    let report = Cdu::new(opts).report(Some(&log), 2);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let config = &report["config"]["providers"]["office"];
+    assert_eq!("[redacted]", config["api_key"]);
+    assert_eq!("http://10.0.0.53:8081", config["url"]);
+    assert_eq!(
+        serde_json::json!(["request with token [redacted] failed", "stopped"]),
+        report["log"]
+    );
+    assert!(!report.to_string().contains("secret"), "{}", report);
+}
@@ -0,0 +1,138 @@
+//! Deterministic simulation harness: tokio's paused clock stands in for wall time, a channel
+//! stands in for the scheduler, and a scripted runner stands in for IP detection and the provider.
+
+// shared by test binaries, each using only some of it
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use cdu::{Daemon, Event, Fired, PublicIPError, RetryPolicy, Runner};
+use futures::future::BoxFuture;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// Scripted result of one run
+#[derive(Clone, Copy, Debug)]
+pub enum Outcome {
+    Ok,
+    /// Worth retrying e.g. IP detection failed
+    Transient,
+    /// Not worth retrying e.g. DNS record not found
+    Permanent,
+    /// Worth retrying once the rate limit of 30 seconds is over
+    RateLimited,
+}
+
+/// Delay asked for by rate limited runs
+pub const RATE_LIMIT: Duration = Duration::from_secs(30);
+
+/// Runner which replays scripted outcomes and records calls and events
+#[derive(Default)]
+pub struct ScriptedRunner {
+    script: Mutex<VecDeque<Outcome>>,
+    calls: Mutex<Vec<(Instant, Option<IpAddr>)>>,
+    events: Mutex<Vec<Event>>,
+    rate_limited_until: Mutex<Option<Instant>>,
+}
+
+impl ScriptedRunner {
+    pub fn new(script: &[Outcome]) -> Arc<Self> {
+        Arc::new(Self {
+            script: Mutex::new(script.iter().copied().collect()),
+            ..Default::default()
+        })
+    }
+
+    /// Virtual times and IP addresses the runner was called with
+    pub fn calls(&self) -> Vec<(Instant, Option<IpAddr>)> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    pub fn event_kinds(&self) -> Vec<&'static str> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(Event::kind)
+            .collect()
+    }
+}
+
+impl Runner for ScriptedRunner {
+    fn run(&self, ip_address: Option<IpAddr>) -> BoxFuture<'_, anyhow::Result<()>> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push((Instant::now(), ip_address));
+        // an exhausted script keeps succeeding
+        let outcome = self
+            .script
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or(Outcome::Ok);
+        if let Outcome::RateLimited = outcome {
+            *self.rate_limited_until.lock().unwrap() = Some(Instant::now() + RATE_LIMIT);
+        }
+        Box::pin(async move {
+            match outcome {
+                Outcome::Ok => Ok(()),
+                Outcome::Transient | Outcome::RateLimited => Err(PublicIPError.into()),
+                Outcome::Permanent => Err(anyhow!("DNS record not found")),
+            }
+        })
+    }
+
+    fn rate_limited_for(&self) -> Option<Duration> {
+        let until = (*self.rate_limited_until.lock().unwrap())?;
+        Some(until - Instant::now()).filter(|d| !d.is_zero())
+    }
+
+    fn emit(&self, event: Event) -> BoxFuture<'_, ()> {
+        self.events.lock().unwrap().push(event);
+        Box::pin(async {})
+    }
+}
+
+/// Retries without jitter so delays are exact
+pub fn deterministic_retry() -> RetryPolicy {
+    RetryPolicy {
+        jitter: false,
+        ..Default::default()
+    }
+}
+
+/// Spawn daemon around runner, return the fake scheduler to fire triggers with
+pub fn spawn_daemon(
+    runner: Arc<ScriptedRunner>,
+) -> (UnboundedSender<Fired>, JoinHandle<anyhow::Result<()>>) {
+    spawn_daemon_with_retry(runner, deterministic_retry())
+}
+
+pub fn spawn_daemon_with_retry(
+    runner: Arc<ScriptedRunner>,
+    retry: RetryPolicy,
+) -> (UnboundedSender<Fired>, JoinHandle<anyhow::Result<()>>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let daemon = Daemon::new(runner).with_retry(retry);
+    let handle = tokio::spawn(async move { daemon.run(rx).await });
+    (tx, handle)
+}
+
+pub fn fire(tx: &UnboundedSender<Fired>, ip_address: Option<IpAddr>) {
+    let fired = Fired {
+        reason: "test".to_string(),
+        ip_address,
+        group: None,
+    };
+    tx.send(fired).unwrap();
+}
+
+pub fn millis(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
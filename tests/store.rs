@@ -0,0 +1,191 @@
+mod mock;
+
+use std::fs;
+
+use cdu::{Cdu, Opts};
+use chrono::{TimeZone, Utc};
+
+use mock::MockApi;
+
+fn cdu(args: &[&str]) -> Cdu {
+    let mut argv = vec!["cdu", "--zone", "example.com", "--records", "a.example.com"];
+    argv.extend(args);
+    Cdu::new(Opts::from_iter_with_config(&argv).unwrap())
+}
+
+#[test]
+fn reads_history_file_since() {
+    let path = std::env::temp_dir().join(format!("cdu-history-{}.jsonl", std::process::id()));
+    fs::write(
+        &path,
+        concat!(
+            r#"{"at":"2024-01-01T00:00:00Z","previous":null,"current":"192.0.2.1"}"#,
+            "\n",
+            r#"{"at":"2024-02-01T00:00:00Z","previous":"192.0.2.1","current":"192.0.2.2"}"#,
+            "\n",
+        ),
+    )
+    .unwrap();
+    let cdu = cdu(&["--history-file", path.to_str().unwrap()]);
+
+    let history = cdu.history(None).unwrap();
+    assert_eq!(2, history.len());
+    assert_eq!(None, history[0].previous);
+
+    let since = Utc.with_ymd_and_hms(2024, 1, 15, 0, 0, 0).unwrap();
+    let history = cdu.history(Some(since)).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(1, history.len());
+    assert_eq!("192.0.2.2".parse().ok(), Some(history[0].current));
+}
+
+#[test]
+fn ignores_files_in_memory() {
+    let path = std::env::temp_dir().join(format!("cdu-memory-{}.jsonl", std::process::id()));
+    fs::write(
+        &path,
+        r#"{"at":"2024-01-01T00:00:00Z","previous":null,"current":"192.0.2.1"}"#,
+    )
+    .unwrap();
+    let cdu = cdu(&[
+        "--store",
+        "memory",
+        "--history-file",
+        path.to_str().unwrap(),
+    ]);
+    let history = cdu.history(None).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(history.is_empty());
+}
+
+#[test]
+fn keeps_park_target_in_status_file() {
+    let path = std::env::temp_dir().join(format!("cdu-status-park-{}.json", std::process::id()));
+    fs::write(
+        &path,
+        r#"{"ip_address":"192.0.2.1","records":{"a.example.com":"status.example.net"},"park":"status.example.net"}"#,
+    )
+    .unwrap();
+    let cdu = cdu(&["--status-file", path.to_str().unwrap()]);
+    let report = cdu.report(None, 0);
+    fs::remove_file(&path).unwrap();
+    assert_eq!("status.example.net", report["status"]["park"]);
+}
+
+/// Options of the store of the kind, with files named after the test
+fn store_args(kind: &str, test: &str) -> Vec<String> {
+    let path = |extension: &str| {
+        let name = format!("cdu-{}-{}-{}.{}", test, kind, std::process::id(), extension);
+        std::env::temp_dir()
+            .join(name)
+            .to_str()
+            .unwrap()
+            .to_string()
+    };
+    match kind {
+        "json" => vec![
+            "--status-file".to_string(),
+            path("json"),
+            "--history-file".to_string(),
+            path("jsonl"),
+        ],
+        _ => vec![
+            "--store".to_string(),
+            kind.to_string(),
+            "--database-file".to_string(),
+            path("sqlite"),
+        ],
+    }
+}
+
+fn remove_files(args: &[String]) {
+    for path in args
+        .iter()
+        .filter(|arg| arg.contains(std::path::MAIN_SEPARATOR))
+    {
+        let _ = fs::remove_file(path);
+    }
+}
+
+fn mock_cdu(api: &MockApi, store: &[String]) -> Cdu {
+    let mut args = vec!["--records", "a.example.com"];
+    args.extend(store.iter().map(String::as_str));
+    mock::cdu(api, &args).0
+}
+
+/// Changes of the public IP address are kept in history, and read back since a time
+async fn keeps_history_since(kind: &str) {
+    let store = store_args(kind, "history");
+    let api = MockApi::start();
+    api.add_zone("example.com");
+    api.add_record("a.example.com", "A", "192.0.2.1", false);
+    let cdu = mock_cdu(&api, &store);
+    cdu.run_with_ip_address("198.51.100.1".parse().ok())
+        .await
+        .unwrap();
+    cdu.flush_events().await;
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    let between = Utc::now();
+    cdu.run_with_ip_address("198.51.100.2".parse().ok())
+        .await
+        .unwrap();
+    cdu.flush_events().await;
+
+    // read back by another instance, as by `cdu history export`
+    let history = mock_cdu(&api, &store).history(None);
+    let since = mock_cdu(&api, &store).history(Some(between));
+    remove_files(&store);
+    let history = history.unwrap();
+    assert_eq!(2, history.len());
+    assert_eq!(None, history[0].previous);
+    assert_eq!("198.51.100.1".parse().ok(), history[1].previous);
+    let since = since.unwrap();
+    assert_eq!(1, since.len());
+    assert_eq!("198.51.100.2".parse().ok(), Some(since[0].current));
+}
+
+/// State of the last run is loaded on restart, so records up to date aren't updated again
+async fn keeps_state_across_restarts(kind: &str) {
+    let store = store_args(kind, "state");
+    let api = MockApi::start();
+    api.add_zone("example.com");
+    api.add_record("a.example.com", "A", "192.0.2.1", false);
+    let cdu = mock_cdu(&api, &store);
+    cdu.run_with_ip_address("198.51.100.1".parse().ok())
+        .await
+        .unwrap();
+    cdu.flush_events().await;
+
+    let restarted = mock_cdu(&api, &store);
+    let received = api.received().len();
+    let res = restarted
+        .run_with_ip_address("198.51.100.1".parse().ok())
+        .await;
+    let report = restarted.report(None, 0);
+    remove_files(&store);
+    res.unwrap();
+    assert_eq!(received, api.received().len());
+    assert_eq!("198.51.100.1", report["status"]["ip_address"]);
+}
+
+#[tokio::test]
+async fn keeps_history_since_in_json_files() {
+    keeps_history_since("json").await;
+}
+
+#[tokio::test]
+async fn keeps_state_across_restarts_in_json_files() {
+    keeps_state_across_restarts("json").await;
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn keeps_history_since_in_sqlite() {
+    keeps_history_since("sqlite").await;
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn keeps_state_across_restarts_in_sqlite() {
+    keeps_state_across_restarts("sqlite").await;
+}
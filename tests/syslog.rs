@@ -0,0 +1,17 @@
+#![cfg(unix)]
+
+use cdu::syslog_message;
+use log::Level;
+
+#[test]
+fn prefixes_priority_of_daemon_facility() {
+    let pid = std::process::id();
+    assert_eq!(
+        format!("<27>cdu[{}]: cdu::cdu > failed to update", pid),
+        syslog_message(Level::Error, "cdu::cdu", "failed to update")
+    );
+    assert_eq!(
+        format!("<30>cdu[{}]: cdu > updated", pid),
+        syslog_message(Level::Info, "cdu", "updated")
+    );
+}
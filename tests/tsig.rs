@@ -0,0 +1,46 @@
+use cdu::{tsig_sign, TsigAlgorithm};
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// Update of example.com replacing A record and alias of a.example.com with 192.0.2.1. The MAC
+/// was computed apart from cdu with Python's hmac, over the message and TSIG variables of
+/// RFC 8945 section 4.3.3.
+const UNSIGNED: &str = concat!(
+    "123428000001000000030000",
+    "076578616d706c6503636f6d0000060001",
+    "0161076578616d706c6503636f6d00000100ff000000000000",
+    "0161076578616d706c6503636f6d00000500ff000000000000",
+    "0161076578616d706c6503636f6d00000100010000012c0004c0000201",
+);
+
+const TSIG: &str = concat!(
+    "076364752d6b65790000fa00ff00000000003d",
+    "0b686d61632d73686132353600",
+    "00006553f100",
+    "012c",
+    "0020d11d149f5f9e8e72042d7a46e1f386f5d8bce6aa892f6d11246602cfcbe4a636",
+    "123400000000",
+);
+
+#[test]
+fn signs_update_as_known_answer() {
+    let secret = base64::decode("VGhpcyBpcyBhIFRTSUcga2V5IG9mIGNkdSB0ZXN0cyE=").unwrap();
+    let signed = tsig_sign(
+        hex(UNSIGNED),
+        "cdu-key",
+        TsigAlgorithm::HmacSha256,
+        &secret,
+        1_700_000_000,
+    );
+
+    let mut expected = hex(UNSIGNED);
+    // one additional record
+    expected[11] = 1;
+    expected.extend(hex(TSIG));
+    assert_eq!(expected, signed);
+}
@@ -0,0 +1,474 @@
+mod logs;
+mod mock;
+
+use std::fs;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use cdu::{Cdu, Opts, Phase, RunContext, Stage};
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use structopt::StructOpt;
+
+use logs::take_logged;
+use mock::{cdu, failing_powerdns, MockApi, TOKEN};
+
+fn ip(s: &str) -> Option<IpAddr> {
+    Some(s.parse().unwrap())
+}
+
+/// Mock with zone example.com and the A records of the names, at 192.0.2.1 and proxied as given
+fn api_with(records: &[(&str, bool)]) -> MockApi {
+    let api = MockApi::start();
+    api.add_zone("example.com");
+    for (name, proxied) in records {
+        api.add_record(name, "A", "192.0.2.1", *proxied);
+    }
+    api
+}
+
+/// Body of the change sent for the record
+fn change_of(api: &MockApi, name: &str) -> Value {
+    api.changes()
+        .into_iter()
+        .find(|received| received.body["name"] == name)
+        .map(|received| received.body)
+        .unwrap_or_else(|| panic!("no change of {}", name))
+}
+
+#[tokio::test]
+async fn updates_records_and_skips_them_while_up_to_date() {
+    let api = api_with(&[("a.example.com", false)]);
+    let (cdu, events) = cdu(&api, &["--records", "a.example.com"]);
+    cdu.run_with_ip_address(ip("198.51.100.1")).await.unwrap();
+
+    let changes = api.changes();
+    assert_eq!(1, changes.len());
+    assert_eq!("PUT", changes[0].method);
+    assert!(
+        changes[0].path.starts_with("/zones/"),
+        "{}",
+        changes[0].path
+    );
+    assert_eq!(TOKEN, changes[0].token);
+    assert_eq!("198.51.100.1", changes[0].body["content"]);
+    assert_eq!(
+        "198.51.100.1",
+        api.record("a.example.com", "A").unwrap()["content"]
+    );
+
+    // nothing is requested while the IP address stays the same
+    let received = api.received().len();
+    cdu.run_with_ip_address(ip("198.51.100.1")).await.unwrap();
+    assert_eq!(received, api.received().len());
+
+    cdu.run_with_ip_address(ip("198.51.100.2")).await.unwrap();
+    assert_eq!(2, api.changes().len());
+    assert_eq!(
+        "198.51.100.2",
+        api.record("a.example.com", "A").unwrap()["content"]
+    );
+
+    cdu.flush_events().await;
+    assert_eq!(
+        vec![
+            "a.example.com 192.0.2.1 → 198.51.100.1",
+            "a.example.com 198.51.100.1 → 198.51.100.2",
+        ],
+        events
+            .messages("record_updated")
+            .iter()
+            .map(|message| message.split(',').next().unwrap())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(2, events.messages("ip_changed").len());
+}
+
+#[tokio::test]
+async fn keeps_proxy_status_unless_overridden_per_record() {
+    let api = api_with(&[
+        ("a.example.com", true),
+        ("b.example.com", true),
+        ("c.example.com", false),
+    ]);
+    let records = "a.example.com,b.example.com:dns-only,c.example.com:proxied";
+    let (cdu, _) = cdu(&api, &["--records", records, "--ttl", "300"]);
+    cdu.run_with_ip_address(ip("198.51.100.1")).await.unwrap();
+
+    assert_eq!(true, change_of(&api, "a.example.com")["proxied"]);
+    assert_eq!(false, change_of(&api, "b.example.com")["proxied"]);
+    assert_eq!(true, change_of(&api, "c.example.com")["proxied"]);
+    assert_eq!(300, change_of(&api, "a.example.com")["ttl"]);
+}
+
+#[tokio::test]
+async fn updates_records_again_once_proxy_status_or_ttl_changes() {
+    let api = api_with(&[("a.example.com", false)]);
+    let (cdu, _) = cdu(&api, &["--records", "a.example.com"]);
+    cdu.run_with_ip_address(ip("198.51.100.1")).await.unwrap();
+    assert!(change_of(&api, "a.example.com").get("ttl").is_none());
+
+    // reloaded with a TTL, the record is updated although the IP address is the same
+    let reloaded = reloaded(&cdu, &api, &["--records", "a.example.com", "--ttl", "60"]);
+    reloaded
+        .run_with_ip_address(ip("198.51.100.1"))
+        .await
+        .unwrap();
+    let changes = api.changes();
+    assert_eq!(2, changes.len());
+    assert_eq!(60, changes[1].body["ttl"]);
+}
+
+/// Cdu reloaded with the options given, as on SIGHUP
+fn reloaded(cdu: &Cdu, api: &MockApi, args: &[&str]) -> Cdu {
+    let url = api.url();
+    let mut argv = vec![
+        "cdu",
+        "--zone",
+        "example.com",
+        "--api-url",
+        &url,
+        "--token",
+        TOKEN,
+    ];
+    argv.extend(args);
+    cdu.reload(Opts::from_iter(argv))
+}
+
+#[tokio::test]
+async fn fails_over_to_fallback_token_and_keeps_it() {
+    let api = api_with(&[("a.example.com", false)]);
+    api.reject_token("primary");
+    let (cdu, _) = cdu(
+        &api,
+        &[
+            "--records",
+            "a.example.com",
+            "--token",
+            "primary",
+            "--fallback-token",
+            "fallback",
+        ],
+    );
+    cdu.run_with_ip_address(ip("198.51.100.1")).await.unwrap();
+    let received = api.received();
+    assert_eq!("primary", received[0].token);
+    assert!(received[1..].iter().all(|r| r.token == "fallback"));
+    assert_eq!(1, api.changes().len());
+
+    // the fallback token is used right away afterwards
+    let before = received.len();
+    cdu.run_with_ip_address(ip("198.51.100.2")).await.unwrap();
+    assert!(api.received()[before..]
+        .iter()
+        .all(|r| r.token == "fallback"));
+    assert_eq!(
+        "198.51.100.2",
+        api.record("a.example.com", "A").unwrap()["content"]
+    );
+}
+
+#[tokio::test]
+async fn retries_updates_failed_with_server_errors() {
+    let api = api_with(&[("a.example.com", false)]);
+    api.fail_next("PUT", 502);
+    let (cdu, _) = cdu(&api, &["--records", "a.example.com"]);
+    cdu.run_with_ip_address(ip("198.51.100.1")).await.unwrap();
+
+    assert_eq!(2, api.changes().len());
+    assert_eq!(
+        "198.51.100.1",
+        api.record("a.example.com", "A").unwrap()["content"]
+    );
+}
+
+#[tokio::test]
+async fn does_not_retry_updates_rejected_by_cloudflare() {
+    let api = api_with(&[("a.example.com", false)]);
+    api.fail_next("PUT", 400);
+    let (cdu, _) = cdu(&api, &["--records", "a.example.com"]);
+    cdu.run_with_ip_address(ip("198.51.100.1"))
+        .await
+        .unwrap_err();
+
+    assert_eq!(1, api.changes().len());
+    assert_eq!(
+        "192.0.2.1",
+        api.record("a.example.com", "A").unwrap()["content"]
+    );
+}
+
+#[tokio::test]
+async fn falls_back_to_zone_fetched_before_once_lookup_fails() {
+    let api = api_with(&[("a.example.com", false)]);
+    let (cdu, _) = cdu(&api, &["--records", "a.example.com"]);
+    cdu.run_with_ip_address(ip("198.51.100.1")).await.unwrap();
+
+    // zone lookup is the first request of the run
+    api.fail_next("GET", 503);
+    cdu.run_with_ip_address(ip("198.51.100.2")).await.unwrap();
+    assert_eq!(
+        "198.51.100.2",
+        api.record("a.example.com", "A").unwrap()["content"]
+    );
+}
+
+#[tokio::test]
+async fn reports_drift_without_updating_in_observe_mode() {
+    let api = api_with(&[("a.example.com", false)]);
+    let (cdu, events) = cdu(&api, &["--records", "a.example.com", "--observe"]);
+    cdu.run_with_ip_address(ip("198.51.100.1")).await.unwrap();
+    cdu.flush_events().await;
+
+    assert!(api.changes().is_empty());
+    assert_eq!(
+        vec!["a.example.com is 192.0.2.1 but should be 198.51.100.1"],
+        events.messages("drift_detected")
+    );
+}
+
+#[tokio::test]
+async fn matches_names_regardless_of_case_and_duplicates() {
+    let api = api_with(&[("a.example.com", false)]);
+    let (cdu, _) = cdu(&api, &["--records", "A.Example.COM.,a.example.com"]);
+    cdu.run_with_ip_address(ip("198.51.100.1")).await.unwrap();
+
+    assert_eq!(1, api.changes().len());
+    assert_eq!(
+        "198.51.100.1",
+        api.record("a.example.com", "A").unwrap()["content"]
+    );
+}
+
+#[tokio::test]
+async fn parks_records_at_maintenance_address() {
+    let api = api_with(&[("a.example.com", false)]);
+    let (cdu, _) = cdu(
+        &api,
+        &["--records", "a.example.com", "--park", "203.0.113.9"],
+    );
+    cdu.run_with_ip_address(ip("198.51.100.1")).await.unwrap();
+
+    assert_eq!("203.0.113.9", change_of(&api, "a.example.com")["content"]);
+}
+
+#[tokio::test]
+async fn fails_on_missing_record() {
+    let api = api_with(&[("a.example.com", false)]);
+    let (cdu, _) = cdu(&api, &["--records", "a.example.com,b.example.com"]);
+    let e = cdu
+        .run_with_ip_address(ip("198.51.100.1"))
+        .await
+        .unwrap_err();
+
+    assert!(e.to_string().contains("b.example.com"), "{}", e);
+    assert!(api.changes().is_empty());
+}
+
+const CONFIG: &str = r#"
+records:
+  - name: a.example.com
+    ttl: 120
+    proxied: true
+  - name: b.example.com
+    expires_after: 1h
+  - name: c.example.com
+    enabled: false
+"#;
+
+#[tokio::test]
+async fn applies_records_of_configuration_file() {
+    let api = api_with(&[("a.example.com", false), ("c.example.com", false)]);
+    let path = std::env::temp_dir().join(format!("cdu-{}-update.yaml", std::process::id()));
+    fs::write(&path, CONFIG).unwrap();
+    let url = api.url();
+    let argv = [
+        "cdu",
+        "--zone",
+        "example.com",
+        "--api-url",
+        &url,
+        "--token",
+        TOKEN,
+        "--config",
+        path.to_str().unwrap(),
+    ];
+    let res = Opts::from_iter_with_config(argv);
+    fs::remove_file(&path).unwrap();
+    let cdu = Cdu::new(res.unwrap());
+    cdu.run_with_ip_address(ip("198.51.100.1")).await.unwrap();
+
+    let a = change_of(&api, "a.example.com");
+    assert_eq!(120, a["ttl"]);
+    assert_eq!(true, a["proxied"]);
+    // expiring records missing are created, disabled ones are left alone
+    let created = api.record("b.example.com", "A").unwrap();
+    assert_eq!("198.51.100.1", created["content"]);
+    assert_eq!(
+        "192.0.2.1",
+        api.record("c.example.com", "A").unwrap()["content"]
+    );
+    assert_eq!(2, api.changes().len());
+}
+
+/// Payloads logged as they would be sent in dry run
+fn logged_payloads() -> Vec<Value> {
+    take_logged()
+        .iter()
+        .filter(|message| message.starts_with("dry run: "))
+        .map(|message| serde_json::from_str(&message[message.find('{').unwrap()..]).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn logs_payload_of_each_record_in_dry_run_and_plan() {
+    for mode in ["--dry-run", "--plan"] {
+        let api = api_with(&[("a.example.com", true)]);
+        let records = "a.example.com:dns-only";
+        let (cdu, _) = cdu(&api, &["--records", records, "--ttl", "300", mode]);
+        take_logged();
+        cdu.run_with_ip_address(ip("198.51.100.1")).await.unwrap();
+
+        let expected = json!({
+            "name": "a.example.com",
+            "type": "A",
+            "content": "198.51.100.1",
+            "proxied": false,
+            "ttl": 300,
+        });
+        assert_eq!(vec![expected], logged_payloads(), "{}", mode);
+        assert!(api.changes().is_empty(), "{}", mode);
+    }
+}
+
+const VIEWS: &str = r#"
+records:
+  - name: nas.example.com
+    expires_after: 1h
+  - name: nas.example.com
+    provider: powerdns
+    expires_after: 1h
+"#;
+
+/// When hosts of records last reported back, as kept in the status file after a run of the
+/// views of nas.example.com which fails on PowerDNS, given the status file before
+async fn confirmed_after_failing_view(test: &str, status: &str) -> Value {
+    let api = api_with(&[("nas.example.com", false)]);
+    let path = |extension: &str| {
+        let name = format!("cdu-{}-{}.{}", test, std::process::id(), extension);
+        std::env::temp_dir().join(name)
+    };
+    let (config, status_file) = (path("yaml"), path("json"));
+    fs::write(&config, VIEWS).unwrap();
+    fs::write(&status_file, status).unwrap();
+    let (url, powerdns_url) = (api.url(), failing_powerdns());
+    let argv = [
+        "cdu",
+        "--zone",
+        "example.com",
+        "--api-url",
+        &url,
+        "--token",
+        TOKEN,
+        "--config",
+        config.to_str().unwrap(),
+        "--status-file",
+        status_file.to_str().unwrap(),
+        "--powerdns-url",
+        &powerdns_url,
+        "--powerdns-api-key",
+        "key",
+    ];
+    let res = Opts::from_iter_with_config(argv);
+    fs::remove_file(&config).unwrap();
+    let cdu = Cdu::new(res.unwrap());
+    // reported by the host itself, as through the webhook
+    let res = cdu.run_with_ip_address(ip("198.51.100.1")).await;
+    cdu.flush_events().await;
+    let saved = fs::read_to_string(&status_file).unwrap();
+    fs::remove_file(&status_file).unwrap();
+    assert!(res.is_err());
+    serde_json::from_str::<Value>(&saved).unwrap()["confirmed"].clone()
+}
+
+const LAST_REPORTED: &str = "2024-01-01T00:00:00Z";
+
+#[tokio::test]
+async fn keeps_expiry_of_each_view_of_name() {
+    let status = json!({
+        "ip_address": "192.0.2.1",
+        "records": {
+            "nas.example.com": "192.0.2.1",
+            "nas.example.com@powerdns": "192.0.2.1",
+        },
+        "confirmed": {
+            "nas.example.com": LAST_REPORTED,
+            "nas.example.com@powerdns": LAST_REPORTED,
+        },
+    });
+    let confirmed = confirmed_after_failing_view("views", &status.to_string()).await;
+
+    // only the view updated is confirmed by the host reporting back
+    assert_ne!(LAST_REPORTED, confirmed["nas.example.com"]);
+    assert_eq!(LAST_REPORTED, confirmed["nas.example.com@powerdns"]);
+}
+
+#[tokio::test]
+async fn applies_expiry_kept_by_name_to_each_view() {
+    // written before views of a name were told apart
+    let status = json!({
+        "ip_address": "192.0.2.1",
+        "records": {
+            "nas.example.com": "192.0.2.1",
+            "nas.example.com@powerdns": "192.0.2.1",
+        },
+        "confirmed": { "nas.example.com": LAST_REPORTED },
+    });
+    let confirmed = confirmed_after_failing_view("views-migrated", &status.to_string()).await;
+
+    assert_ne!(LAST_REPORTED, confirmed["nas.example.com"]);
+    assert_eq!(LAST_REPORTED, confirmed["nas.example.com@powerdns"]);
+}
+
+/// Verify stage looking up records, which finds addresses of Cloudflare for proxied ones
+#[derive(Clone, Default)]
+struct Lookup(Arc<Mutex<Vec<String>>>);
+
+impl Stage for Lookup {
+    fn run<'a>(&'a self, ctx: &'a mut RunContext) -> BoxFuture<'a, anyhow::Result<()>> {
+        for record in &mut ctx.records {
+            self.0.lock().unwrap().push(record.name.clone());
+            if record.proxied == Some(true) {
+                record.error = Some(anyhow!("{} resolves to Cloudflare", record.name));
+            }
+        }
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[tokio::test]
+async fn leaves_proxied_records_out_of_verification_if_asked() {
+    for skip in [false, true] {
+        let api = api_with(&[("a.example.com", true), ("b.example.com", false)]);
+        let mut args = vec!["--records", "a.example.com,b.example.com"];
+        if skip {
+            args.push("--skip-verify-proxied");
+        }
+        let (mut cdu, _) = cdu(&api, &args);
+        let lookup = Lookup::default();
+        cdu.add_stage(Phase::Verify, Box::new(lookup.clone()));
+        let res = cdu.run_with_ip_address(ip("198.51.100.1")).await;
+
+        let looked_up = lookup.0.lock().unwrap().clone();
+        if skip {
+            res.unwrap();
+            assert_eq!(vec!["b.example.com"], looked_up);
+        } else {
+            assert!(res.unwrap_err().to_string().contains("a.example.com"));
+            assert_eq!(vec!["a.example.com", "b.example.com"], looked_up);
+        }
+        // both are updated either way
+        assert_eq!(2, api.changes().len());
+    }
+}
@@ -0,0 +1,30 @@
+use cdu::pick_zone;
+use cloudflare::endpoints::zone::Status;
+
+#[test]
+fn picks_the_only_zone() {
+    let zones = [("a1", &Status::Pending)];
+    assert_eq!("a1", pick_zone("example.com", &zones).unwrap());
+}
+
+#[test]
+fn prefers_active_zone_over_pending_duplicate() {
+    let zones = [("p1", &Status::Pending), ("a1", &Status::Active)];
+    assert_eq!("a1", pick_zone("example.com", &zones).unwrap());
+}
+
+#[test]
+fn lists_identifiers_of_ambiguous_zones() {
+    let zones = [("a1", &Status::Active), ("a2", &Status::Active)];
+    let e = pick_zone("example.com", &zones).err().unwrap();
+    assert_eq!(
+        "2 zones named example.com, narrow the token down to one of them: a1 (active), a2 (active)",
+        e.to_string()
+    );
+}
+
+#[test]
+fn rejects_missing_zone() {
+    let e = pick_zone("example.com", &[]).err().unwrap();
+    assert_eq!("zone not found: example.com", e.to_string());
+}